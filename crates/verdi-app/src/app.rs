@@ -108,6 +108,16 @@ impl App {
                                     Some(key) => key,
                                     None => return,
                                 };
+
+                                // capture and stats shortcuts
+                                if input.state == glutin::event::ElementState::Pressed {
+                                    match key {
+                                        glutin::event::VirtualKeyCode::F12 => system.capture_screenshot(),
+                                        glutin::event::VirtualKeyCode::F11 => system.toggle_recording(),
+                                        glutin::event::VirtualKeyCode::F10 => system.toggle_stats_overlay(),
+                                        _ => (),
+                                    }
+                                }
                             },
                             glutin::event::WindowEvent::ModifiersChanged(modifiers_state) => {
                                 // todo
@@ -0,0 +1,110 @@
+#version 150
+
+// in
+in vec3 position;
+in vec3 normal; // xy: corner offset, z: 0 spherical, 1 cylindrical
+in vec4 color;
+in vec2 uv;
+
+// out
+out vec4 v_color;
+out vec2 v_uv;
+out float v_fog_density;
+
+// matrices
+uniform mat4 u_model;
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+uniform vec2 u_resolution;
+
+// fog
+uniform bool u_enable_fog;
+uniform float u_fog_start;
+uniform float u_fog_end;
+
+// light
+uniform bool u_enable_lighting;
+
+// uv animation
+uniform float u_time;
+uniform bool u_uv_animated;
+uniform vec2 u_uv_offset;
+uniform vec2 u_uv_scale;
+uniform float u_uv_rotation;
+uniform vec2 u_uv_scroll;
+
+// scales and rotates around the center, then offsets and scrolls over time
+vec2 animate_uv(vec2 uv) {
+    if(!u_uv_animated) {
+        return uv;
+    }
+    float s = sin(u_uv_rotation);
+    float c = cos(u_uv_rotation);
+    vec2 centered = (uv - 0.5) * u_uv_scale;
+    return mat2(c, s, -s, c) * centered + 0.5 + u_uv_offset + u_uv_scroll * u_time;
+}
+
+// Polygon jittering
+vec4 snap(vec4 vertex) {
+    vertex.xyz /= vertex.w; 
+    vertex.xy = floor(u_resolution * vertex.xy) / u_resolution; 
+    vertex.xyz *= vertex.w; 
+
+    return vertex;
+}
+
+float inverse_lerp(float a, float b, float t) {
+    return (t - a) / (b - a);
+}
+
+void main() {
+    vec4 world_vertex = u_model * vec4(position, 1.0);
+    vec4 view_vertex;
+    if(normal.z > 0.5) {
+        // cylindrical: rotates around the world up axis only
+        vec3 camera_right = vec3(u_view[0][0], u_view[1][0], u_view[2][0]);
+        vec3 right = vec3(camera_right.x, 0.0, camera_right.z);
+        right = length(right) > 0.0 ? normalize(right) : vec3(1.0, 0.0, 0.0);
+        world_vertex.xyz += right * normal.x + vec3(0.0, 1.0, 0.0) * normal.y;
+        view_vertex = u_view * world_vertex;
+    }
+    else {
+        // spherical: the quad always faces the camera
+        view_vertex = u_view * world_vertex;
+        view_vertex.xy += normal.xy;
+    }
+    vec4 proj_vertex = u_projection * view_vertex;
+
+    gl_Position = snap(proj_vertex);
+
+    // fog
+    v_fog_density = 0.0;
+    if(u_enable_fog) {
+        float vertex_depth = length(view_vertex);
+        v_fog_density = clamp(inverse_lerp(u_fog_start, u_fog_end, vertex_depth), 0.0, 1.0);
+    }
+
+    // lighting, the quad normal points toward the camera
+    if(u_enable_lighting) {
+        const vec3 light_color = vec3(1.0, 1.0, 1.0);
+
+        // ambient
+        const float ambient_strength = 0.1;
+        vec3 ambient_comp = ambient_strength * light_color;
+
+        // diffuse
+        vec3 v_normal = normalize(transpose(mat3(u_view)) * vec3(0.0, 0.0, -1.0));
+        vec3 u_light = vec3(1.0, 0.0, 0.0);
+        vec3 lighting_dir = normalize(u_light - world_vertex.xyz);
+        float light_mag = max(dot(lighting_dir, v_normal), 0.0);
+        vec3 diffuse_comp = light_mag * light_color;
+
+        v_color = vec4(color.xyz * (ambient_comp + diffuse_comp), color.a);
+    }
+    else {
+        v_color = color;
+    }
+
+    v_uv = animate_uv(uv);
+}
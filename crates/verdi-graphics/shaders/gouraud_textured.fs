@@ -6,11 +6,48 @@ in float v_fog_density;
 
 out vec4 color;
 
+// alpha cutout, nothing is discarded at 0
+uniform float u_alpha_cutoff;
+
+uniform vec4 u_fog_color;
+
+// LOD cross-fade: the outgoing level (positive) and the incoming one (negative) keep complementary pixels
+uniform float u_lod_fade;
+
+const float BAYER[16] = float[16](0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0);
+
+bool lod_discarded() {
+    if(u_lod_fade == 0.0) {
+        return false;
+    }
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    float threshold = (BAYER[(pixel.y & 3) * 4 + (pixel.x & 3)] + 0.5) / 16.0;
+    return u_lod_fade > 0.0 ? threshold < u_lod_fade : threshold >= -u_lod_fade;
+}
+
 uniform sampler2D u_texture;
 
+// flipbook frame (u, v, width, height), empty without flipbook
+uniform vec4 u_uv_frame;
+
+vec2 frame_uv(vec2 uv) {
+    if(u_uv_frame.z == 0.0 || u_uv_frame.w == 0.0) {
+        return uv;
+    }
+    return u_uv_frame.xy + fract(uv) * u_uv_frame.zw;
+}
+
 void main() {
-    vec4 fog_color = vec4(0.3, 0.3, 0.3, 1.0);
+    if(lod_discarded()) {
+        discard;
+    }
+
+    vec2 uv = frame_uv(v_uv);
 
     // with texture
-    color = mix(v_color * texture(u_texture, vec2(v_uv.x, 1.0 - v_uv.y)), fog_color, v_fog_density);
+    color = mix(v_color * texture(u_texture, vec2(uv.x, 1.0 - uv.y)), u_fog_color, v_fog_density);
+
+    if(color.a < u_alpha_cutoff) {
+        discard;
+    }
 }
\ No newline at end of file
@@ -0,0 +1,79 @@
+#version 150
+
+// in
+in vec3 position;
+in vec3 normal;
+in vec4 color;
+in vec2 uv;
+
+// out, the fragment stage does the lighting
+noperspective out vec4 v_color;
+noperspective out vec2 v_uv;
+out vec3 v_world_position;
+out vec3 v_normal;
+out vec3 v_view_normal;
+out float v_fog_density;
+
+// matrices
+uniform mat4 u_model;
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+uniform vec2 u_resolution;
+
+// fog
+uniform bool u_enable_fog;
+uniform float u_fog_start;
+uniform float u_fog_end;
+
+// uv animation
+uniform float u_time;
+uniform bool u_uv_animated;
+uniform vec2 u_uv_offset;
+uniform vec2 u_uv_scale;
+uniform float u_uv_rotation;
+uniform vec2 u_uv_scroll;
+
+// scales and rotates around the center, then offsets and scrolls over time
+vec2 animate_uv(vec2 uv) {
+    if(!u_uv_animated) {
+        return uv;
+    }
+    float s = sin(u_uv_rotation);
+    float c = cos(u_uv_rotation);
+    vec2 centered = (uv - 0.5) * u_uv_scale;
+    return mat2(c, s, -s, c) * centered + 0.5 + u_uv_offset + u_uv_scroll * u_time;
+}
+
+// Polygon jittering
+vec4 snap(vec4 vertex) {
+    vertex.xyz /= vertex.w; 
+    vertex.xy = floor(u_resolution * vertex.xy) / u_resolution; 
+    vertex.xyz *= vertex.w; 
+
+    return vertex;
+}
+
+float inverse_lerp(float a, float b, float t) {
+    return (t - a) / (b - a);
+}
+
+void main() {
+    vec4 world_vertex = u_model * vec4(position, 1.0);
+    vec4 view_vertex = u_view * world_vertex;
+
+    gl_Position = snap(u_projection * view_vertex);
+
+    // fog
+    v_fog_density = 0.0;
+    if(u_enable_fog) {
+        float vertex_depth = length(view_vertex);
+        v_fog_density = clamp(inverse_lerp(u_fog_start, u_fog_end, vertex_depth), 0.0, 1.0);
+    }
+
+    v_color = color;
+    v_uv = animate_uv(uv);
+    v_world_position = world_vertex.xyz;
+    v_normal = normalize(mat3(transpose(inverse(u_model))) * normal);
+    v_view_normal = normalize(mat3(transpose(inverse(u_view * u_model))) * normal);
+}
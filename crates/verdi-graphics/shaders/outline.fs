@@ -0,0 +1,18 @@
+#version 140
+
+in float v_facing;
+in float v_fog_density;
+
+out vec4 color;
+
+uniform vec4 u_fog_color;
+uniform vec4 u_outline_color;
+
+void main() {
+    // only the back of the hull is kept, around the mesh drawn normally
+    if(v_facing > 0.0) {
+        discard;
+    }
+
+    color = mix(u_outline_color, u_fog_color, v_fog_density);
+}
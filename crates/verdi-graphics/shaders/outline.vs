@@ -0,0 +1,59 @@
+#version 150
+
+// in
+in vec3 position;
+in vec3 normal;
+in vec4 color;
+in vec2 uv;
+
+// out
+out float v_facing;
+out float v_fog_density;
+
+// matrices
+uniform mat4 u_model;
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+uniform vec2 u_resolution;
+
+// fog
+uniform bool u_enable_fog;
+uniform float u_fog_start;
+uniform float u_fog_end;
+
+// thickness of the hull, in world units
+uniform float u_outline_width;
+
+// Polygon jittering
+vec4 snap(vec4 vertex) {
+    vertex.xyz /= vertex.w; 
+    vertex.xy = floor(u_resolution * vertex.xy) / u_resolution; 
+    vertex.xyz *= vertex.w; 
+
+    return vertex;
+}
+
+float inverse_lerp(float a, float b, float t) {
+    return (t - a) / (b - a);
+}
+
+void main() {
+    // the inverted hull: the mesh pushed out along its normals
+    vec3 world_normal = normalize(mat3(transpose(inverse(u_model))) * normal);
+    vec4 world_vertex = u_model * vec4(position, 1.0) + vec4(world_normal * u_outline_width, 0.0);
+    vec4 view_vertex = u_view * world_vertex;
+
+    gl_Position = snap(u_projection * view_vertex);
+
+    // fog
+    v_fog_density = 0.0;
+    if(u_enable_fog) {
+        float vertex_depth = length(view_vertex);
+        v_fog_density = clamp(inverse_lerp(u_fog_start, u_fog_end, vertex_depth), 0.0, 1.0);
+    }
+
+    // positive where the hull faces the camera, whatever the winding of the mesh
+    vec3 camera_position = inverse(u_view)[3].xyz;
+    v_facing = dot(world_normal, camera_position - world_vertex.xyz);
+}
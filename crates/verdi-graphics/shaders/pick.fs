@@ -0,0 +1,10 @@
+#version 140
+
+out uint id;
+
+// id of the draw command, 0 is the background
+uniform uint u_pick_id;
+
+void main() {
+    id = u_pick_id;
+}
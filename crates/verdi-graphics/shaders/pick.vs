@@ -0,0 +1,23 @@
+#version 150
+
+in vec3 position;
+
+// matrices
+uniform mat4 u_model;
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+uniform vec2 u_resolution;
+
+// the same jittering as the drawn polygons, so that the ids cover the same pixels
+vec4 snap(vec4 vertex) {
+    vertex.xyz /= vertex.w; 
+    vertex.xy = floor(u_resolution * vertex.xy) / u_resolution; 
+    vertex.xyz *= vertex.w; 
+
+    return vertex;
+}
+
+void main() {
+    gl_Position = snap(u_projection * u_view * u_model * vec4(position, 1.0));
+}
@@ -0,0 +1,22 @@
+#version 140
+
+in vec2 v_uv;
+in float v_fog_density;
+
+out vec4 color;
+
+uniform vec4 u_shadow_color;
+// blob shadows fade from the center
+uniform bool u_blob;
+
+void main() {
+    color = u_shadow_color;
+
+    if(u_blob) {
+        float distance = length(v_uv * 2.0 - 1.0);
+        color.a *= 1.0 - smoothstep(0.3, 1.0, distance);
+    }
+
+    // shadows vanish in the fog
+    color.a *= 1.0 - v_fog_density;
+}
@@ -0,0 +1,55 @@
+#version 150
+
+// in
+in vec3 position;
+in vec3 normal;
+in vec4 color;
+in vec2 uv;
+
+// out
+out vec2 v_uv;
+out float v_fog_density;
+
+// matrices
+uniform mat4 u_model;
+uniform mat4 u_view;
+uniform mat4 u_projection;
+// flattens the mesh on a plane, identity for blob shadows
+uniform mat4 u_shadow_matrix;
+
+uniform vec2 u_resolution;
+
+// fog
+uniform bool u_enable_fog;
+uniform float u_fog_start;
+uniform float u_fog_end;
+
+// Polygon jittering
+vec4 snap(vec4 vertex) {
+    vertex.xyz /= vertex.w; 
+    vertex.xy = floor(u_resolution * vertex.xy) / u_resolution; 
+    vertex.xyz *= vertex.w; 
+
+    return vertex;
+}
+
+float inverse_lerp(float a, float b, float t) {
+    return (t - a) / (b - a);
+}
+
+void main() {
+    vec4 world_vertex = u_shadow_matrix * u_model * vec4(position, 1.0);
+    vec4 view_vertex = u_view * world_vertex;
+    vec4 proj_vertex = u_projection * view_vertex;
+
+    gl_Position = snap(proj_vertex);
+
+    // fog
+    v_fog_density = 0.0;
+    if(u_enable_fog) {
+        float vertex_depth = length(view_vertex.xyz / view_vertex.w);
+        v_fog_density = clamp(inverse_lerp(u_fog_start, u_fog_end, vertex_depth), 0.0, 1.0);
+    }
+
+    v_uv = uv;
+}
@@ -0,0 +1,85 @@
+#version 140
+
+in vec2 v_ndc;
+
+out vec4 color;
+
+// matrices
+uniform mat4 u_view;
+uniform mat4 u_projection;
+
+// 0: gradient, 1: panorama, 2: skybox
+uniform float u_sky_mode;
+
+// gradient
+uniform vec4 u_sky_top;
+uniform vec4 u_sky_horizon;
+uniform vec4 u_sky_bottom;
+
+// panorama (one turn) or skybox (horizontal strip of six faces: +x, -x, +y, -y, +z, -z)
+uniform sampler2D u_sky_texture;
+
+// fog
+uniform bool u_enable_fog;
+uniform vec4 u_fog_color;
+uniform float u_sky_fog_height;
+
+const float PI = 3.14159265;
+
+vec4 gradient(vec3 dir) {
+    if(dir.y > 0.0) {
+        return mix(u_sky_horizon, u_sky_top, dir.y);
+    }
+    return mix(u_sky_horizon, u_sky_bottom, -dir.y);
+}
+
+vec4 panorama(vec3 dir) {
+    // scrolls with the camera yaw
+    float u = atan(dir.x, dir.z) / (2.0 * PI) + 0.5;
+    float v = 0.5 - asin(clamp(dir.y, -1.0, 1.0)) / PI;
+    return texture(u_sky_texture, vec2(u, 1.0 - v));
+}
+
+vec4 skybox(vec3 dir) {
+    vec3 a = abs(dir);
+    float face;
+    vec2 face_uv;
+    if(a.x >= a.y && a.x >= a.z) {
+        face = dir.x > 0.0 ? 0.0 : 1.0;
+        face_uv = vec2(dir.x > 0.0 ? -dir.z : dir.z, -dir.y) / a.x;
+    }
+    else if(a.y >= a.z) {
+        face = dir.y > 0.0 ? 2.0 : 3.0;
+        face_uv = vec2(dir.x, dir.y > 0.0 ? dir.z : -dir.z) / a.y;
+    }
+    else {
+        face = dir.z > 0.0 ? 4.0 : 5.0;
+        face_uv = vec2(dir.z > 0.0 ? dir.x : -dir.x, -dir.y) / a.z;
+    }
+    face_uv = clamp(face_uv * 0.5 + 0.5, 0.0, 1.0);
+
+    float u = (face + face_uv.x) / 6.0;
+    return texture(u_sky_texture, vec2(u, 1.0 - face_uv.y));
+}
+
+void main() {
+    // view direction of the pixel, in world space
+    vec4 view_dir = inverse(u_projection) * vec4(v_ndc, 1.0, 1.0);
+    vec3 dir = normalize(transpose(mat3(u_view)) * (view_dir.xyz / view_dir.w));
+
+    if(u_sky_mode < 0.5) {
+        color = gradient(dir);
+    }
+    else if(u_sky_mode < 1.5) {
+        color = panorama(dir);
+    }
+    else {
+        color = skybox(dir);
+    }
+
+    // the sky fades into the fog at the horizon
+    if(u_enable_fog) {
+        float fog_density = 1.0 - clamp(abs(dir.y) / max(u_sky_fog_height, 0.0001), 0.0, 1.0);
+        color = mix(color, u_fog_color, fog_density);
+    }
+}
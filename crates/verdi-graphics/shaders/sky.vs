@@ -0,0 +1,17 @@
+#version 150
+
+// in
+in vec3 position;
+in vec3 normal;
+in vec4 color;
+in vec2 uv;
+
+// out
+out vec2 v_ndc;
+
+void main() {
+    // the quad covers the whole screen, behind everything
+    gl_Position = vec4(position.xy, 1.0, 1.0);
+
+    v_ndc = position.xy;
+}
@@ -3,11 +3,29 @@
 noperspective in vec4 v_color;
 noperspective in vec2 v_uv;
 
-uniform sampler2D colorTexture;
+uniform sampler2D u_texture;
 
 out vec4 color;
 
+// alpha cutout, nothing is discarded at 0
+uniform float u_alpha_cutoff;
+
+// flipbook frame (u, v, width, height), empty without flipbook
+uniform vec4 u_uv_frame;
+
+vec2 frame_uv(vec2 uv) {
+    if(u_uv_frame.z == 0.0 || u_uv_frame.w == 0.0) {
+        return uv;
+    }
+    return u_uv_frame.xy + fract(uv) * u_uv_frame.zw;
+}
+
 void main() {
+    vec2 uv = frame_uv(v_uv);
     //color = v_color;
-    color = texture(colorTexture, v_uv);
+    color = v_color * texture(u_texture, vec2(uv.x, 1.0 - uv.y));
+
+    if(color.a < u_alpha_cutoff) {
+        discard;
+    }
 }
\ No newline at end of file
@@ -16,6 +16,25 @@ uniform mat4 u_projection;
 
 uniform vec2 u_resolution;
 
+// uv animation
+uniform float u_time;
+uniform bool u_uv_animated;
+uniform vec2 u_uv_offset;
+uniform vec2 u_uv_scale;
+uniform float u_uv_rotation;
+uniform vec2 u_uv_scroll;
+
+// scales and rotates around the center, then offsets and scrolls over time
+vec2 animate_uv(vec2 uv) {
+    if(!u_uv_animated) {
+        return uv;
+    }
+    float s = sin(u_uv_rotation);
+    float c = cos(u_uv_rotation);
+    vec2 centered = (uv - 0.5) * u_uv_scale;
+    return mat2(c, s, -s, c) * centered + 0.5 + u_uv_offset + u_uv_scroll * u_time;
+}
+
 // Polygon jittering
 vec4 snap(vec4 vertex) {
     // convert to normalised device coordinates (NDC)
@@ -38,5 +57,5 @@ void main() {
     gl_Position = proj_vertex;
 
     v_color = color;
-    v_uv = uv;
+    v_uv = animate_uv(uv);
 }
\ No newline at end of file
@@ -0,0 +1,31 @@
+#version 140
+
+noperspective in vec4 v_weights;
+noperspective in vec2 v_uv;
+noperspective in float v_light;
+in float v_fog_density;
+
+out vec4 color;
+
+uniform vec4 u_fog_color;
+
+uniform sampler2D u_texture0;
+uniform sampler2D u_texture1;
+uniform sampler2D u_texture2;
+uniform sampler2D u_texture3;
+// repetitions of the textures over the terrain
+uniform float u_tiling;
+
+void main() {
+    vec2 uv = vec2(v_uv.x, 1.0 - v_uv.y) * u_tiling;
+    float total = max(dot(v_weights, vec4(1.0)), 0.0001);
+
+    vec3 texel = (
+        texture(u_texture0, uv).rgb * v_weights.r +
+        texture(u_texture1, uv).rgb * v_weights.g +
+        texture(u_texture2, uv).rgb * v_weights.b +
+        texture(u_texture3, uv).rgb * v_weights.a
+    ) / total;
+
+    color = mix(vec4(texel * v_light, 1.0), u_fog_color, v_fog_density);
+}
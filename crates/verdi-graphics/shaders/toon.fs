@@ -0,0 +1,57 @@
+#version 140
+
+noperspective in vec4 v_color;
+noperspective in vec2 v_uv;
+in vec3 v_world_position;
+in vec3 v_normal;
+in float v_fog_density;
+
+out vec4 color;
+
+// alpha cutout, nothing is discarded at 0
+uniform float u_alpha_cutoff;
+
+uniform vec4 u_fog_color;
+uniform bool u_enable_lighting;
+
+// LOD cross-fade: the outgoing level (positive) and the incoming one (negative) keep complementary pixels
+uniform float u_lod_fade;
+
+const float BAYER[16] = float[16](0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0);
+
+bool lod_discarded() {
+    if(u_lod_fade == 0.0) {
+        return false;
+    }
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    float threshold = (BAYER[(pixel.y & 3) * 4 + (pixel.x & 3)] + 0.5) / 16.0;
+    return u_lod_fade > 0.0 ? threshold < u_lod_fade : threshold >= -u_lod_fade;
+}
+
+uniform sampler2D u_texture;
+
+// the light intensity from left to right, read along the middle row
+uniform sampler2D u_ramp;
+
+void main() {
+    if(lod_discarded()) {
+        discard;
+    }
+
+    float light = 1.0;
+    if(u_enable_lighting) {
+        const float ambient_strength = 0.1;
+        vec3 u_light = vec3(1.0, 0.0, 0.0);
+        vec3 lighting_dir = normalize(u_light - v_world_position);
+        light = ambient_strength + max(dot(lighting_dir, normalize(v_normal)), 0.0);
+    }
+
+    vec4 band = texture(u_ramp, vec2(clamp(light, 0.0, 0.999), 0.5));
+    vec4 texel = texture(u_texture, vec2(v_uv.x, 1.0 - v_uv.y));
+
+    color = mix(vec4(v_color.xyz * texel.xyz * band.xyz, v_color.w * texel.w), u_fog_color, v_fog_density);
+
+    if(color.a < u_alpha_cutoff) {
+        discard;
+    }
+}
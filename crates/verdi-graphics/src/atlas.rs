@@ -0,0 +1,399 @@
+use std::{path::Path, ops::{Deref, DerefMut}, collections::HashMap};
+
+use image::{RgbaImage, ImageError};
+use mlua::{UserData, UserDataMethods, UserDataFields, Table, prelude::LuaValue};
+use slotmap::Key;
+use verdi_database::{ResourceId, Resource, Assets, Handle};
+
+use crate::image::{Image, ImageHandle};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AtlasError {
+    #[error("Reading atlas descriptor failed")]
+    IoError(#[from] std::io::Error),
+    #[error("Atlas descriptor parsing failed")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Atlas descriptor is missing {0}")]
+    MissingField(&'static str),
+    #[error("Image loading error")]
+    ImageError(#[from] ImageError),
+}
+
+pub type AtlasId = ResourceId;
+
+/// Area of an atlas image, in pixels.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Named regions of an image, and sequences of regions used for animations.
+pub struct Atlas {
+    image: ImageHandle,
+    regions: Vec<Region>,
+    names: HashMap<String, usize>,
+    sequences: HashMap<String, Vec<usize>>,
+    pub id: AtlasId,
+}
+
+impl Resource for Atlas {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Atlas {
+    pub fn new(image: ImageHandle) -> Self {
+        Self {
+            image,
+            regions: Vec::new(),
+            names: HashMap::new(),
+            sequences: HashMap::new(),
+            id: AtlasId::null(),
+        }
+    }
+
+    /// Cuts the image in frames of the same size, from left to right and top to bottom.
+    pub fn from_grid(image: ImageHandle, image_width: u32, image_height: u32, frame_width: u32, frame_height: u32) -> Self {
+        let mut atlas = Atlas::new(image);
+        let frame_width = frame_width.max(1);
+        let frame_height = frame_height.max(1);
+
+        for y in 0..image_height / frame_height {
+            for x in 0..image_width / frame_width {
+                atlas.add_region(
+                    None,
+                    Region {
+                        x: x * frame_width,
+                        y: y * frame_height,
+                        width: frame_width,
+                        height: frame_height,
+                    }
+                );
+            }
+        }
+
+        atlas
+    }
+
+    /// Reads a JSON descriptor in the Aseprite / TexturePacker format, with frames as a hash or an array,
+    /// and animation sequences from the frame tags.
+    /// Returns the path of the atlas image with the atlas.
+    pub fn parse_descriptor<P: AsRef<Path>>(path: P) -> Result<(String, Vec<(String, Region)>, Vec<(String, Vec<usize>)>), AtlasError> {
+        let json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(path.as_ref())?)?;
+
+        let image_name = json["meta"]["image"]
+            .as_str()
+            .ok_or(AtlasError::MissingField("meta.image"))?;
+
+        let image_path = path
+            .as_ref()
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(image_name)
+            .to_string_lossy()
+            .to_string();
+
+        let read_region = |frame: &serde_json::Value| -> Option<Region> {
+            let rect = &frame["frame"];
+            Some(Region {
+                x: rect["x"].as_u64()? as u32,
+                y: rect["y"].as_u64()? as u32,
+                width: rect["w"].as_u64()? as u32,
+                height: rect["h"].as_u64()? as u32,
+            })
+        };
+
+        let mut regions = Vec::new();
+        match &json["frames"] {
+            serde_json::Value::Object(frames) => {
+                for (name, frame) in frames {
+                    let region = read_region(frame).ok_or(AtlasError::MissingField("frame"))?;
+                    regions.push((name.clone(), region));
+                }
+            },
+            serde_json::Value::Array(frames) => {
+                for (index, frame) in frames.iter().enumerate() {
+                    let region = read_region(frame).ok_or(AtlasError::MissingField("frame"))?;
+                    let name = frame["filename"]
+                        .as_str()
+                        .map(|name| name.to_string())
+                        .unwrap_or_else(|| index.to_string());
+                    regions.push((name, region));
+                }
+            },
+            _ => return Err(AtlasError::MissingField("frames")),
+        }
+
+        let mut sequences = Vec::new();
+        if let Some(tags) = json["meta"]["frameTags"].as_array() {
+            for tag in tags {
+                if let (Some(name), Some(from), Some(to)) = (tag["name"].as_str(), tag["from"].as_u64(), tag["to"].as_u64()) {
+                    let mut frames: Vec<usize> = (from as usize..=to as usize).collect();
+                    if tag["direction"].as_str() == Some("reverse") {
+                        frames.reverse();
+                    }
+                    sequences.push((name.to_string(), frames));
+                }
+            }
+        }
+
+        Ok((image_path, regions, sequences))
+    }
+
+    /// Packs the images in a single image, using rows of decreasing height.
+    /// The position of each image in the packed image is returned in the same order.
+    pub fn pack(images: &[RgbaImage], padding: u32) -> (RgbaImage, Vec<Region>) {
+        let area: u32 = images
+            .iter()
+            .map(|image| (image.width() + padding) * (image.height() + padding))
+            .sum();
+        let widest = images.iter().map(|image| image.width() + padding).max().unwrap_or(1);
+        let width = ((area as f32).sqrt().ceil() as u32).max(widest).next_power_of_two();
+
+        let mut order: Vec<usize> = (0..images.len()).collect();
+        order.sort_by_key(|index| std::cmp::Reverse(images[*index].height()));
+
+        let mut regions = vec![Region { x: 0, y: 0, width: 0, height: 0 }; images.len()];
+        let (mut x, mut y, mut row_height) = (0, 0, 0);
+        for index in order {
+            let image = &images[index];
+            if x + image.width() > width {
+                x = 0;
+                y += row_height;
+                row_height = 0;
+            }
+
+            regions[index] = Region {
+                x,
+                y,
+                width: image.width(),
+                height: image.height(),
+            };
+
+            x += image.width() + padding;
+            row_height = row_height.max(image.height() + padding);
+        }
+
+        let mut packed = RgbaImage::new(width, (y + row_height).max(1));
+        for (image, region) in images.iter().zip(regions.iter()) {
+            image::imageops::replace(&mut packed, image, region.x as i64, region.y as i64);
+        }
+
+        (packed, regions)
+    }
+
+    pub fn get_image(&self) -> &ImageHandle {
+        &self.image
+    }
+
+    pub fn add_region(&mut self, name: Option<String>, region: Region) -> usize {
+        self.regions.push(region);
+        let index = self.regions.len() - 1;
+        if let Some(name) = name {
+            self.names.insert(name, index);
+        }
+        index
+    }
+
+    pub fn add_sequence(&mut self, name: String, frames: Vec<usize>) {
+        self.sequences.insert(name, frames);
+    }
+
+    pub fn get_region_count(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn get_region(&self, index: usize) -> Option<&Region> {
+        self.regions.get(index)
+    }
+
+    pub fn find_region(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    pub fn get_sequence(&self, name: &str) -> Option<&Vec<usize>> {
+        self.sequences.get(name)
+    }
+}
+
+/// A region of an image, usable wherever an image is drawn.
+#[derive(Clone)]
+pub struct AtlasFrame {
+    pub image: ImageHandle,
+    pub region: Region,
+    /// (u0, v0, u1, v1), with v going down as for the image rows
+    pub uv: [f32; 4],
+}
+
+impl AtlasFrame {
+    pub fn new(image: ImageHandle, region: Region) -> Self {
+        let (width, height) = image
+            .get_datas()
+            .get::<Image>(image.get_id())
+            .map(|image| image.get_dimensions())
+            .unwrap_or((1, 1));
+
+        let uv = [
+            region.x as f32 / width.max(1) as f32,
+            region.y as f32 / height.max(1) as f32,
+            (region.x + region.width) as f32 / width.max(1) as f32,
+            (region.y + region.height) as f32 / height.max(1) as f32,
+        ];
+
+        Self {
+            image,
+            region,
+            uv,
+        }
+    }
+
+    /// A frame covering the whole image.
+    pub fn from_image(image: ImageHandle) -> Self {
+        let (width, height) = image.get_dimensions();
+        AtlasFrame::new(image, Region { x: 0, y: 0, width, height })
+    }
+
+    /// Interprets a lua value as a frame: either an atlas frame or an image.
+    pub fn from_lua_value(value: &LuaValue) -> Option<Self> {
+        if let LuaValue::UserData(data) = value {
+            if let Ok(frame) = data.borrow::<AtlasFrame>() {
+                return Some(frame.clone());
+            }
+            if let Ok(image) = data.borrow::<ImageHandle>() {
+                return Some(AtlasFrame::from_image(image.clone()));
+            }
+        }
+        None
+    }
+}
+
+impl UserData for AtlasFrame {
+    fn add_fields<'lua, F: UserDataFields<'lua, Self>>(fields: &mut F) {
+        fields.add_field_method_get("x", |_, this| Ok(this.region.x));
+        fields.add_field_method_get("y", |_, this| Ok(this.region.y));
+        fields.add_field_method_get("width", |_, this| Ok(this.region.width));
+        fields.add_field_method_get("height", |_, this| Ok(this.region.height));
+        fields.add_field_method_get("image", |_, this| Ok(this.image.clone()));
+    }
+}
+
+#[derive(Clone)]
+pub struct AtlasHandle(Handle);
+
+impl Deref for AtlasHandle {
+    type Target = Handle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for AtlasHandle {
+    fn deref_mut(&mut self) -> &mut Handle {
+        &mut self.0
+    }
+}
+
+impl AtlasHandle {
+    pub fn new(assets: Assets, id: AtlasId) -> Self {
+        AtlasHandle(assets.new_handle(id))
+    }
+
+    pub fn get_frame_count(&self) -> usize {
+        self.get_datas()
+            .get::<Atlas>(self.get_id())
+            .map(|atlas| atlas.get_region_count())
+            .unwrap_or(0)
+    }
+
+    pub fn get_frame(&self, index: usize) -> Option<AtlasFrame> {
+        let (image, region) = {
+            let datas = self.get_datas();
+            let atlas = datas.get::<Atlas>(self.get_id())?;
+            (atlas.get_image().clone(), *atlas.get_region(index)?)
+        };
+
+        Some(AtlasFrame::new(image, region))
+    }
+
+    pub fn find_frame(&self, name: &str) -> Option<AtlasFrame> {
+        let index = self.get_datas()
+            .get::<Atlas>(self.get_id())?
+            .find_region(name)?;
+
+        self.get_frame(index)
+    }
+
+    pub fn get_sequence(&self, name: &str) -> Option<Vec<AtlasFrame>> {
+        let indices = self.get_datas()
+            .get::<Atlas>(self.get_id())?
+            .get_sequence(name)?
+            .clone();
+
+        Some(
+            indices
+                .into_iter()
+                .filter_map(|index| self.get_frame(index))
+                .collect()
+        )
+    }
+
+    pub fn add_sequence(&mut self, name: String, frames: Vec<usize>) {
+        let atlas_id = self.get_id();
+        if let Some(atlas) = self.get_datas_mut().get_mut::<Atlas>(atlas_id) {
+            atlas.add_sequence(name, frames);
+        }
+    }
+
+    pub fn get_image(&self) -> Option<ImageHandle> {
+        self.get_datas()
+            .get::<Atlas>(self.get_id())
+            .map(|atlas| atlas.get_image().clone())
+    }
+}
+
+impl UserData for AtlasHandle {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("getImage", |_, atlas, ()| {
+            Ok(atlas.get_image())
+        });
+
+        methods.add_method("getFrameCount", |_, atlas, ()| {
+            Ok(atlas.get_frame_count())
+        });
+
+        // frames are found by name, or by index starting at 1
+        methods.add_method("getFrame", |_, atlas, key: LuaValue| {
+            Ok(match key {
+                LuaValue::Integer(index) if index > 0 => atlas.get_frame(index as usize - 1),
+                LuaValue::Number(index) if index >= 1.0 => atlas.get_frame(index as usize - 1),
+                LuaValue::String(name) => atlas.find_frame(name.to_str()?),
+                _ => None,
+            })
+        });
+
+        methods.add_method("getSequence", |_, atlas, name: String| {
+            Ok(atlas.get_sequence(&name))
+        });
+
+        methods.add_method_mut("addSequence", |_, atlas, (name, frames): (String, Table)| {
+            let frames = frames
+                .sequence_values::<usize>()
+                .filter_map(|index| index.ok())
+                .filter(|index| *index > 0)
+                .map(|index| index - 1)
+                .collect();
+            Ok(atlas.add_sequence(name, frames))
+        });
+    }
+}
@@ -0,0 +1,129 @@
+use verdi_math::{Vec2, Vec3, Vec4};
+
+use crate::{
+    atlas::AtlasFrame,
+    image::ImageId,
+    mesh::MeshHandle,
+    uniform::UniformHandle,
+    vertex::Vertex,
+};
+
+/// How a billboard is oriented toward the camera.
+#[derive(Copy, Clone, PartialEq)]
+pub enum BillboardMode {
+    /// Always faces the camera.
+    Spherical,
+    /// Only rotates around the world Y axis, for trees, characters...
+    Cylindrical,
+}
+
+impl From<String> for BillboardMode {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "cylindrical" => BillboardMode::Cylindrical,
+            _ => BillboardMode::Spherical,
+        }
+    }
+}
+
+/// A camera facing quad, centered on its position.
+#[derive(Clone)]
+pub struct Billboard {
+    pub frame: AtlasFrame,
+    pub position: Vec3,
+    pub size: Vec2,
+    pub mode: BillboardMode,
+}
+
+impl Billboard {
+    pub fn new(frame: AtlasFrame, position: Vec3, size: Vec2, mode: BillboardMode) -> Self {
+        Self {
+            frame,
+            position,
+            size,
+            mode,
+        }
+    }
+
+    /// Four vertices at the billboard center.
+    /// The normal stores the corner offset and the mode, used by the billboard program.
+    pub fn vertices(&self, color: Vec4) -> [Vertex; 4] {
+        let half_size = self.size * 0.5;
+        let [u0, v0, u1, v1] = self.frame.uv;
+        let mode = match self.mode {
+            BillboardMode::Spherical => 0.0,
+            BillboardMode::Cylindrical => 1.0,
+        };
+
+        [
+            ([-1.0, 1.0], [u0, v0]),
+            ([-1.0, -1.0], [u0, v1]),
+            ([1.0, 1.0], [u1, v0]),
+            ([1.0, -1.0], [u1, v1]),
+        ].map(|(corner, uv)| Vertex {
+            position: self.position.to_array(),
+            normal: [corner[0] * half_size.x, corner[1] * half_size.y, mode],
+            color: color.to_array(),
+            uv,
+        })
+    }
+
+    pub fn indices(first: u32) -> [u32; 6] {
+        [first, first + 1, first + 2, first + 2, first + 1, first + 3]
+    }
+}
+
+/// A streaming mesh drawing every billboard of a pass sharing the same image.
+pub struct BillboardBatch {
+    pub mesh: MeshHandle,
+    pub texture: UniformHandle,
+}
+
+/// Streaming meshes reused from frame to frame.
+pub struct BillboardBatches {
+    batches: Vec<BillboardBatch>,
+    used: usize,
+}
+
+impl BillboardBatches {
+    pub fn new() -> Self {
+        Self {
+            batches: Vec::new(),
+            used: 0,
+        }
+    }
+
+    /// Returns an unused batch, if there is any left this frame.
+    pub fn next_free(&mut self) -> Option<&BillboardBatch> {
+        let batch = self.batches.get(self.used)?;
+        self.used += 1;
+        Some(batch)
+    }
+
+    pub fn push(&mut self, batch: BillboardBatch) {
+        self.batches.push(batch);
+        self.used = self.batches.len();
+    }
+
+    pub fn next_frame(&mut self) {
+        self.used = 0;
+    }
+
+    pub fn clear(&mut self) {
+        self.batches.clear();
+        self.used = 0;
+    }
+
+    /// Groups the billboards by image, keeping their draw order.
+    pub fn group_by_image(billboards: Vec<Billboard>) -> Vec<(ImageId, Vec<Billboard>)> {
+        let mut groups: Vec<(ImageId, Vec<Billboard>)> = Vec::new();
+        for billboard in billboards {
+            let image_id = billboard.frame.image.get_id();
+            match groups.iter_mut().find(|(id, _)| *id == image_id) {
+                Some((_, group)) => group.push(billboard),
+                None => groups.push((image_id, vec![billboard])),
+            }
+        }
+        groups
+    }
+}
@@ -0,0 +1,127 @@
+use std::{rc::Rc, cell::RefCell};
+use mlua::{Lua, Result, Table};
+
+use verdi_math::prelude::*;
+
+use crate::{
+    prelude::GraphicsChip,
+    sky::Sky,
+};
+
+pub struct BindDebugDraw;
+
+impl BindDebugDraw {
+    fn color(color: Option<Table>) -> Result<Vec4> {
+        match color {
+            Some(color) => Sky::color_from_table(&color),
+            None => Ok(Vec4::ONE),
+        }
+    }
+
+    fn line(gpu: &mut GraphicsChip, start: &Vec3, end: &Vec3, color: &Vec4) {
+        gpu.debug_draw.line(*start, *end, *color);
+    }
+
+    fn aabb(gpu: &mut GraphicsChip, min: &Vec3, max: &Vec3, color: &Vec4) {
+        gpu.debug_draw.aabb(*min, *max, *color);
+    }
+
+    fn sphere(gpu: &mut GraphicsChip, center: &Vec3, radius: f32, color: &Vec4) {
+        gpu.debug_draw.sphere(*center, radius, *color);
+    }
+
+    fn axes(gpu: &mut GraphicsChip, transform: &TransformHandle, size: f32) {
+        let matrix = match transform.get_datas().get::<Transform>(transform.get_id()) {
+            Some(transform) => transform.to_matrix(),
+            None => return,
+        };
+        gpu.debug_draw.axes(&matrix, size);
+    }
+
+    fn grid(gpu: &mut GraphicsChip, size: f32, step: f32, color: &Vec4) {
+        gpu.debug_draw.grid(size, step, *color);
+    }
+
+    fn text(gpu: &mut GraphicsChip, position: &Vec3, text: String, color: &Vec4, size: f32) {
+        gpu.debug_draw.text(*position, text, *color, size);
+    }
+
+    fn set_on_top(gpu: &mut GraphicsChip, on_top: bool) {
+        gpu.debug_draw.on_top = on_top;
+    }
+
+    /// Adds the functions to the debug table, which may already exist.
+    pub fn bind(lua: &Lua, gpu: Rc<RefCell<GraphicsChip>>) -> Result<()> {
+        let globals = lua.globals();
+
+        let module_table = match globals.get::<_, Option<Table>>("debug")? {
+            Some(table) => table,
+            None => lua.create_table()?,
+        };
+
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (start, end, color): (LuaVec3, LuaVec3, Option<Table>)| Ok(
+                    BindDebugDraw::line(&mut gpu.borrow_mut(), &start, &end, &BindDebugDraw::color(color)?)
+                )
+            )?;
+            module_table.set("line3d", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (min, max, color): (LuaVec3, LuaVec3, Option<Table>)| Ok(
+                    BindDebugDraw::aabb(&mut gpu.borrow_mut(), &min, &max, &BindDebugDraw::color(color)?)
+                )
+            )?;
+            module_table.set("box", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (center, radius, color): (LuaVec3, f32, Option<Table>)| Ok(
+                    BindDebugDraw::sphere(&mut gpu.borrow_mut(), &center, radius, &BindDebugDraw::color(color)?)
+                )
+            )?;
+            module_table.set("sphere", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (transform, size): (TransformHandle, Option<f32>)| Ok(
+                    BindDebugDraw::axes(&mut gpu.borrow_mut(), &transform, size.unwrap_or(1.0))
+                )
+            )?;
+            module_table.set("axes", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (size, step, color): (f32, Option<f32>, Option<Table>)| Ok(
+                    BindDebugDraw::grid(&mut gpu.borrow_mut(), size, step.unwrap_or(1.0), &BindDebugDraw::color(color)?)
+                )
+            )?;
+            module_table.set("grid", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (position, text, color, size): (LuaVec3, String, Option<Table>, Option<f32>)| Ok(
+                    BindDebugDraw::text(&mut gpu.borrow_mut(), &position, text, &BindDebugDraw::color(color)?, size.unwrap_or(0.25))
+                )
+            )?;
+            module_table.set("text3d", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, on_top: bool| Ok(BindDebugDraw::set_on_top(&mut gpu.borrow_mut(), on_top)))?;
+            module_table.set("setOnTop", func)?;
+        }
+
+        // add table to globals
+        globals.set("debug", module_table)?;
+
+        Ok(())
+    }
+}
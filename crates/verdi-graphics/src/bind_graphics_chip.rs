@@ -1,5 +1,5 @@
 use std::{rc::Rc, cell::RefCell};
-use mlua::{Lua, Result};
+use mlua::{Lua, Result, Table, prelude::LuaValue};
 
 use verdi_math::prelude::*;
 
@@ -11,6 +11,14 @@ use crate::{
     material::MaterialHandle, 
     camera::CameraHandle, 
     sprite::SpriteHandle,
+    indexed_image::IndexedImageHandle,
+    atlas::{AtlasFrame, AtlasHandle},
+    particle_system::ParticleSystemHandle,
+    sky::{Sky, SkyHandle},
+    shadow::{ShadowHandle, ShadowGround, Plane},
+    budget::{Limits, OverflowBehavior},
+    tilemap::TilemapHandle,
+    terrain::{TerrainHandle, DEFAULT_TERRAIN_CHUNK_SIZE},
 };
 
 pub struct BindGraphicsChip;
@@ -50,6 +58,18 @@ impl<'lua> BindGraphicsChip {
         gpu.borrow_mut().new_image(path).unwrap()
     }
 
+    fn new_image_data(gpu: Rc<RefCell<GraphicsChip>>, width: u32, height: u32) -> ImageHandle {
+        gpu.borrow_mut().new_image_data(width, height)
+    }
+
+    fn new_indexed_image(gpu: Rc<RefCell<GraphicsChip>>, path: &String, palette: &ImageHandle) -> mlua::Result<IndexedImageHandle> {
+        gpu.borrow_mut().new_indexed_image(path, palette).map_err(mlua::Error::external)
+    }
+
+    fn new_indexed_image_data(gpu: Rc<RefCell<GraphicsChip>>, width: u32, height: u32, palette: &ImageHandle) -> IndexedImageHandle {
+        gpu.borrow_mut().new_indexed_image_data(width, height, palette)
+    }
+
     fn new_model(gpu: Rc<RefCell<GraphicsChip>>, path: &String) -> ModelHandle {
         gpu.borrow_mut().new_model(path).unwrap()
     }
@@ -58,18 +78,130 @@ impl<'lua> BindGraphicsChip {
         gpu.borrow_mut().new_mesh().unwrap()
     }
 
-    // fn new_sprite(gpu: Rc<RefCell<GraphicsChip>>, image: ImageHandle) -> SpriteHandle {
-    //     let sprite_id = gpu.borrow_mut().new_sprite(image);
-    //     SpriteHandle { 
-    //         assets: gpu.borrow().assets.clone(), 
-    //         id: sprite_id,
-    //     }
-    // }
+    fn new_cube(gpu: Rc<RefCell<GraphicsChip>>) -> MeshHandle {
+        gpu.borrow_mut().new_cube()
+    }
+
+    fn new_plane(gpu: Rc<RefCell<GraphicsChip>>, subdivisions: Option<u32>) -> MeshHandle {
+        gpu.borrow_mut().new_plane(subdivisions.unwrap_or(0))
+    }
+
+    fn new_sphere(gpu: Rc<RefCell<GraphicsChip>>, rings: Option<u32>, segments: Option<u32>) -> MeshHandle {
+        gpu.borrow_mut().new_sphere(rings.unwrap_or(8), segments.unwrap_or(16))
+    }
+
+    fn new_cylinder(gpu: Rc<RefCell<GraphicsChip>>, segments: Option<u32>) -> MeshHandle {
+        gpu.borrow_mut().new_cylinder(segments.unwrap_or(16))
+    }
+
+    fn new_cone(gpu: Rc<RefCell<GraphicsChip>>, segments: Option<u32>) -> MeshHandle {
+        gpu.borrow_mut().new_cone(segments.unwrap_or(16))
+    }
+
+    fn new_torus(gpu: Rc<RefCell<GraphicsChip>>, rings: Option<u32>, segments: Option<u32>, thickness: Option<f32>) -> MeshHandle {
+        gpu.borrow_mut().new_torus(rings.unwrap_or(8), segments.unwrap_or(16), thickness.unwrap_or(0.15))
+    }
+
+    fn new_heightmap(gpu: Rc<RefCell<GraphicsChip>>, image: &ImageHandle, scale: Option<f32>) -> Option<MeshHandle> {
+        gpu.borrow_mut().new_heightmap(image, scale.unwrap_or(1.0))
+    }
+
+    // by default, one unit per pixel and a quarter of the width in height
+    fn new_terrain(gpu: Rc<RefCell<GraphicsChip>>, image: &ImageHandle, size: Option<f32>, height: Option<f32>, chunk_size: Option<u32>) -> Option<TerrainHandle> {
+        let size = size.unwrap_or_else(|| image.get_dimensions().0.saturating_sub(1).max(1) as f32);
+        gpu.borrow_mut().new_terrain(image, size, height.unwrap_or(size / 4.0), chunk_size.unwrap_or(DEFAULT_TERRAIN_CHUNK_SIZE))
+    }
+
+    fn new_sprite(gpu: Rc<RefCell<GraphicsChip>>, frame: &LuaValue) -> Option<SpriteHandle> {
+        let frame = AtlasFrame::from_lua_value(frame)?;
+        Some(gpu.borrow_mut().new_sprite(&frame))
+    }
+
+    fn new_particle_system(gpu: Rc<RefCell<GraphicsChip>>, frame: &LuaValue, max_particles: usize) -> Option<ParticleSystemHandle> {
+        let frame = AtlasFrame::from_lua_value(frame)?;
+        Some(gpu.borrow_mut().new_particle_system(&frame, max_particles))
+    }
+
+    fn new_gradient_sky(gpu: Rc<RefCell<GraphicsChip>>, top: &Table, horizon: &Table, bottom: &Table) -> mlua::Result<SkyHandle> {
+        Ok(
+            gpu.borrow_mut().new_gradient_sky(
+                Sky::color_from_table(top)?,
+                Sky::color_from_table(horizon)?,
+                Sky::color_from_table(bottom)?
+            )
+        )
+    }
+
+    fn new_panorama_sky(gpu: Rc<RefCell<GraphicsChip>>, image: &ImageHandle) -> SkyHandle {
+        gpu.borrow_mut().new_panorama_sky(image)
+    }
+
+    fn new_skybox(gpu: Rc<RefCell<GraphicsChip>>, faces: Vec<ImageHandle>) -> SkyHandle {
+        match faces.len() {
+            1 => gpu.borrow_mut().new_skybox(&faces[0]),
+            _ => gpu.borrow_mut().new_skybox_from_faces(&faces),
+        }
+    }
+
+    fn new_blob_shadow(gpu: Rc<RefCell<GraphicsChip>>, radius: f32, point: Option<Vec3>, normal: Option<Vec3>) -> ShadowHandle {
+        let plane = Plane::new(point.unwrap_or(Vec3::ZERO), normal.unwrap_or(Vec3::Y));
+        gpu.borrow_mut().new_blob_shadow(radius, ShadowGround::Plane(plane))
+    }
+
+    fn new_planar_shadow(gpu: Rc<RefCell<GraphicsChip>>, light_direction: Vec3, point: Option<Vec3>, normal: Option<Vec3>) -> ShadowHandle {
+        let plane = Plane::new(point.unwrap_or(Vec3::ZERO), normal.unwrap_or(Vec3::Y));
+        gpu.borrow_mut().new_planar_shadow(plane, light_direction)
+    }
+
+    fn new_atlas(gpu: Rc<RefCell<GraphicsChip>>, path: &String) -> mlua::Result<AtlasHandle> {
+        gpu.borrow_mut().new_atlas(path).map_err(mlua::Error::external)
+    }
+
+    fn new_grid_atlas(gpu: Rc<RefCell<GraphicsChip>>, image: &ImageHandle, frame_width: u32, frame_height: u32) -> AtlasHandle {
+        gpu.borrow_mut().new_grid_atlas(image, frame_width, frame_height)
+    }
+
+    fn new_tilemap(gpu: Rc<RefCell<GraphicsChip>>, atlas: &AtlasHandle, tile_width: u32, tile_height: u32, cols: u32, rows: u32) -> TilemapHandle {
+        gpu.borrow_mut().new_tilemap(atlas, tile_width, tile_height, cols, rows)
+    }
+
+    fn new_tiled_map(gpu: Rc<RefCell<GraphicsChip>>, path: &String) -> mlua::Result<TilemapHandle> {
+        gpu.borrow_mut().new_tiled_map(path).map_err(mlua::Error::external)
+    }
+
+    fn pack_atlas(gpu: Rc<RefCell<GraphicsChip>>, images: Table) -> mlua::Result<AtlasHandle> {
+        // either a list of images or images by name
+        let mut named_images = Vec::new();
+        for pair in images.pairs::<LuaValue, ImageHandle>() {
+            let (key, image) = pair?;
+            // keep the list order so that frame indices match
+            let (order, name) = match key {
+                LuaValue::Integer(index) => (index, None),
+                LuaValue::String(name) => (i64::MAX, Some(name.to_str()?.to_string())),
+                _ => (i64::MAX, None),
+            };
+            named_images.push((order, name, image));
+        }
+        named_images.sort_by_key(|(order, _, _)| *order);
+
+        Ok(
+            gpu.borrow_mut().pack_atlas(
+                named_images
+                    .into_iter()
+                    .map(|(_, name, image)| (name, image))
+                    .collect()
+            )
+        )
+    }
 
     fn new_material(gpu: Rc<RefCell<GraphicsChip>>) -> MaterialHandle {
         gpu.borrow_mut().new_gouraud_material()
     }
 
+    fn new_palette_material(gpu: Rc<RefCell<GraphicsChip>>, image: &IndexedImageHandle) -> Option<MaterialHandle> {
+        gpu.borrow_mut().new_palette_material(image)
+    }
+
     fn new_camera(gpu: Rc<RefCell<GraphicsChip>>, transform: TransformHandle) -> CameraHandle {
         gpu.borrow_mut().new_camera(transform)
     }
@@ -82,6 +214,112 @@ impl<'lua> BindGraphicsChip {
     //     )
     // }
 
+    fn capture_screenshot(gpu: &mut GraphicsChip, path: &String) {
+        gpu.capture_screenshot(path);
+    }
+
+    fn start_recording(gpu: &mut GraphicsChip, path: &String, seconds: f32, palette: Option<ImageHandle>) {
+        gpu.start_recording(path, seconds, palette.as_ref());
+    }
+
+    fn stop_recording(gpu: &mut GraphicsChip) {
+        gpu.stop_recording();
+    }
+
+    fn set_capture_palette(gpu: &mut GraphicsChip, palette: Option<ImageHandle>) {
+        gpu.set_capture_palette(palette);
+    }
+
+    fn get_stats(lua: &'lua Lua, gpu: &GraphicsChip) -> Result<Table<'lua>> {
+        let stats = &gpu.stats;
+        let table = lua.create_table()?;
+        table.set("passes", stats.passes)?;
+        table.set("drawCalls", stats.draw_calls)?;
+        table.set("triangles", stats.triangles)?;
+        table.set("vertices", stats.vertices)?;
+        table.set("culled", stats.culled)?;
+        table.set("textureBinds", stats.texture_binds)?;
+        table.set("uniformUploads", stats.uniform_uploads)?;
+        table.set("gpuMeshes", stats.gpu_meshes)?;
+        table.set("gpuImages", stats.gpu_images)?;
+        table.set("gpuIndexedImages", stats.gpu_indexed_images)?;
+        table.set("gpuDepthBuffers", stats.gpu_depth_buffers)?;
+        table.set("gpuPrograms", stats.gpu_programs)?;
+        table.set("gpuMemory", stats.gpu_memory)?;
+
+        Ok(table)
+    }
+
+    fn set_limits(gpu: &mut GraphicsChip, table: Table) -> Result<()> {
+        let limits = Limits {
+            max_triangles: table.get("maxTriangles")?,
+            max_texture_memory: table.get("maxTextureMemory")?,
+            max_texture_size: table.get("maxTextureSize")?,
+            max_colors: table.get("maxColors")?,
+            max_lights: table.get("maxLights")?,
+            on_overflow: table
+                .get::<_, Option<String>>("onOverflow")?
+                .map_or(OverflowBehavior::Warn, OverflowBehavior::from),
+        };
+
+        gpu.set_limits(limits);
+        Ok(())
+    }
+
+    fn get_limits(lua: &'lua Lua, gpu: &GraphicsChip) -> Result<Table<'lua>> {
+        let limits = &gpu.budgets.limits;
+        let table = lua.create_table()?;
+        table.set("maxTriangles", limits.max_triangles)?;
+        table.set("maxTextureMemory", limits.max_texture_memory)?;
+        table.set("maxTextureSize", limits.max_texture_size)?;
+        table.set("maxColors", limits.max_colors)?;
+        table.set("maxLights", limits.max_lights)?;
+        table.set("onOverflow", limits.on_overflow.name())?;
+
+        Ok(table)
+    }
+
+    /// The budgets exceeded during the last frame.
+    fn get_budget_overflows(lua: &'lua Lua, gpu: &GraphicsChip) -> Result<Table<'lua>> {
+        let overflows = lua.create_table()?;
+        for (index, overflow) in gpu.budgets.get_overflows().iter().enumerate() {
+            let table = lua.create_table()?;
+            table.set("budget", overflow.budget.name())?;
+            table.set("used", overflow.used)?;
+            table.set("limit", overflow.limit)?;
+            table.set("started", overflow.started)?;
+            table.set("message", overflow.to_message())?;
+            overflows.set(index + 1, table)?;
+        }
+
+        Ok(overflows)
+    }
+
+    /// The draw command under the pixel, the indices start at 0.
+    fn pick(lua: &'lua Lua, gpu: &mut GraphicsChip, x: u32, y: u32) -> Result<Option<Table<'lua>>> {
+        let entry = match gpu.pick(x, y) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        let table = lua.create_table()?;
+        table.set("pass", entry.pass)?;
+        table.set("command", entry.cmd)?;
+        table.set("mesh", entry.mesh)?;
+        table.set("transform", entry.transform)?;
+        table.set("entity", entry.tag.entity)?;
+        if let Some((model, node)) = entry.tag.node {
+            table.set("model", model)?;
+            table.set("node", node)?;
+        }
+
+        Ok(Some(table))
+    }
+
+    fn set_stats_overlay(gpu: &mut GraphicsChip, enabled: bool) {
+        gpu.set_stats_overlay(enabled);
+    }
+
     fn set_clear_color(gpu: &mut GraphicsChip, color: &Vec4) {
         gpu.set_clear_color(color);
     }
@@ -166,6 +404,33 @@ impl<'lua> BindGraphicsChip {
             let func = lua.create_function(move |_, path: String| Ok(BindGraphicsChip::new_image(gpu.clone(), &path)))?;
             module_table.set("newImage", func)?;
         }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function(
+                move |_, (width, height): (u32, u32)| Ok(
+                    BindGraphicsChip::new_image_data(gpu.clone(), width, height)
+                )
+            )?;
+            module_table.set("newImageData", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function(
+                move |_, (path, palette): (String, ImageHandle)| {
+                    BindGraphicsChip::new_indexed_image(gpu.clone(), &path, &palette)
+                }
+            )?;
+            module_table.set("newIndexedImage", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function(
+                move |_, (width, height, palette): (u32, u32, ImageHandle)| Ok(
+                    BindGraphicsChip::new_indexed_image_data(gpu.clone(), width, height, &palette)
+                )
+            )?;
+            module_table.set("newIndexedImageData", func)?;
+        }
         {
             let gpu = gpu.clone();
             let func = lua.create_function_mut(move |_, path: String| Ok(BindGraphicsChip::new_model(gpu.clone(), &path)))?;
@@ -176,16 +441,157 @@ impl<'lua> BindGraphicsChip {
             let func = lua.create_function_mut(move |_, ()| Ok(BindGraphicsChip::new_mesh(gpu.clone())))?;
             module_table.set("newMesh", func)?;
         }
-        // {
-        //     let gpu = gpu.clone();
-        //     let func = lua.create_function_mut(move |_, image: ImageHandle| Ok(BindGraphicsChip::new_sprite(gpu.clone(), image)))?;
-        //     module_table.set("newSprite", func)?;
-        // }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, ()| Ok(BindGraphicsChip::new_cube(gpu.clone())))?;
+            module_table.set("newCube", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, subdivisions: Option<u32>| Ok(BindGraphicsChip::new_plane(gpu.clone(), subdivisions)))?;
+            module_table.set("newPlane", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (rings, segments): (Option<u32>, Option<u32>)| Ok(
+                    BindGraphicsChip::new_sphere(gpu.clone(), rings, segments)
+                )
+            )?;
+            module_table.set("newSphere", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, segments: Option<u32>| Ok(BindGraphicsChip::new_cylinder(gpu.clone(), segments)))?;
+            module_table.set("newCylinder", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, segments: Option<u32>| Ok(BindGraphicsChip::new_cone(gpu.clone(), segments)))?;
+            module_table.set("newCone", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (rings, segments, thickness): (Option<u32>, Option<u32>, Option<f32>)| Ok(
+                    BindGraphicsChip::new_torus(gpu.clone(), rings, segments, thickness)
+                )
+            )?;
+            module_table.set("newTorus", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (image, scale): (ImageHandle, Option<f32>)| Ok(
+                    BindGraphicsChip::new_heightmap(gpu.clone(), &image, scale)
+                )
+            )?;
+            module_table.set("newHeightmap", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (image, size, height, chunk_size): (ImageHandle, Option<f32>, Option<f32>, Option<u32>)| Ok(
+                    BindGraphicsChip::new_terrain(gpu.clone(), &image, size, height, chunk_size)
+                )
+            )?;
+            module_table.set("newTerrain", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, frame: LuaValue| Ok(BindGraphicsChip::new_sprite(gpu.clone(), &frame)))?;
+            module_table.set("newSprite", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (frame, max_particles): (LuaValue, usize)| Ok(
+                    BindGraphicsChip::new_particle_system(gpu.clone(), &frame, max_particles)
+                )
+            )?;
+            module_table.set("newParticleSystem", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (top, horizon, bottom): (Table, Table, Table)| 
+                    BindGraphicsChip::new_gradient_sky(gpu.clone(), &top, &horizon, &bottom)
+            )?;
+            module_table.set("newGradientSky", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, image: ImageHandle| Ok(BindGraphicsChip::new_panorama_sky(gpu.clone(), &image)))?;
+            module_table.set("newPanoramaSky", func)?;
+        }
+        {
+            // either a strip or six faces
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, faces: mlua::Variadic<ImageHandle>| Ok(BindGraphicsChip::new_skybox(gpu.clone(), faces.to_vec())))?;
+            module_table.set("newSkybox", func)?;
+        }
+        {
+            // the ground defaults to the y = 0 plane
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (radius, point, normal): (f32, Option<LuaVec3>, Option<LuaVec3>)| Ok(
+                    BindGraphicsChip::new_blob_shadow(gpu.clone(), radius, point.map(|p| *p), normal.map(|n| *n))
+                )
+            )?;
+            module_table.set("newBlobShadow", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (light_direction, point, normal): (LuaVec3, Option<LuaVec3>, Option<LuaVec3>)| Ok(
+                    BindGraphicsChip::new_planar_shadow(gpu.clone(), *light_direction, point.map(|p| *p), normal.map(|n| *n))
+                )
+            )?;
+            module_table.set("newPlanarShadow", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, path: String| BindGraphicsChip::new_atlas(gpu.clone(), &path))?;
+            module_table.set("newAtlas", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (image, frame_width, frame_height): (ImageHandle, u32, u32)| Ok(
+                    BindGraphicsChip::new_grid_atlas(gpu.clone(), &image, frame_width, frame_height)
+                )
+            )?;
+            module_table.set("newGridAtlas", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, images: Table| BindGraphicsChip::pack_atlas(gpu.clone(), images))?;
+            module_table.set("packAtlas", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (atlas, tile_width, tile_height, cols, rows): (AtlasHandle, u32, u32, u32, u32)| Ok(
+                    BindGraphicsChip::new_tilemap(gpu.clone(), &atlas, tile_width, tile_height, cols, rows)
+                )
+            )?;
+            module_table.set("newTilemap", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, path: String| BindGraphicsChip::new_tiled_map(gpu.clone(), &path))?;
+            module_table.set("newTiledMap", func)?;
+        }
         {
             let gpu = gpu.clone();
             let func = lua.create_function_mut(move |_, ()| Ok(BindGraphicsChip::new_material(gpu.clone())))?;
             module_table.set("newMaterial", func)?;
         }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, image: IndexedImageHandle| Ok(BindGraphicsChip::new_palette_material(gpu.clone(), &image)))?;
+            module_table.set("newPaletteMaterial", func)?;
+        }
         // {
         //     let gpu = gpu.clone();
         //     let func = lua.create_function_mut(move |_, value: f32| Ok(BindGraphicsChip::new_uniform(gpu.clone(), value)))?;
@@ -198,13 +604,28 @@ impl<'lua> BindGraphicsChip {
         }
         {
             let gpu = gpu.clone();
+            // a named pass declares the resources it reads and writes
             let func = lua.create_function_mut(
-                move |_, ()| Ok( 
-                    gpu.borrow_mut().new_pass()
+                move |_, (name, width, height): (Option<String>, Option<u32>, Option<u32>)| Ok(
+                    match name {
+                        Some(name) => gpu.borrow_mut().new_declared_pass(name, width.zip(height)),
+                        None => gpu.borrow_mut().new_pass(),
+                    }
                 )
             )?;
             module_table.set("newPass", func)?;
         }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function(move |_, ()| Ok(gpu.borrow().get_graph_error()))?;
+            module_table.set("getGraphError", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            // the built-in programs share the global uniforms, a material switches with setProgram
+            let func = lua.create_function(move |_, name: String| Ok(gpu.borrow().globals.global_programs.get(&name).cloned()))?;
+            module_table.set("getProgram", func)?;
+        }
         // Render state
         {
             let gpu = gpu.clone();
@@ -218,6 +639,80 @@ impl<'lua> BindGraphicsChip {
             )?;
             module_table.set("setClearColor", func)?;
         }
+        // Capture
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, path: String| Ok(BindGraphicsChip::capture_screenshot(&mut gpu.borrow_mut(), &path)))?;
+            module_table.set("captureScreenshot", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(
+                move |_, (path, seconds, palette): (String, f32, Option<ImageHandle>)| Ok(
+                    BindGraphicsChip::start_recording(&mut gpu.borrow_mut(), &path, seconds, palette)
+                )
+            )?;
+            module_table.set("startRecording", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, ()| Ok(BindGraphicsChip::stop_recording(&mut gpu.borrow_mut())))?;
+            module_table.set("stopRecording", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, palette: Option<ImageHandle>| Ok(BindGraphicsChip::set_capture_palette(&mut gpu.borrow_mut(), palette)))?;
+            module_table.set("setCapturePalette", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, ()| Ok(gpu.borrow().capture.is_recording()))?;
+            module_table.set("isRecording", func)?;
+        }
+        // Time
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function(move |_, ()| Ok(gpu.borrow().get_time()))?;
+            module_table.set("getTime", func)?;
+        }
+        // Stats
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function(move |lua, ()| BindGraphicsChip::get_stats(lua, &gpu.borrow()))?;
+            module_table.set("getStats", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, enabled: bool| Ok(BindGraphicsChip::set_stats_overlay(&mut gpu.borrow_mut(), enabled)))?;
+            module_table.set("setStatsOverlay", func)?;
+        }
+        // Picking
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |lua, (x, y): (u32, u32)| BindGraphicsChip::pick(lua, &mut gpu.borrow_mut(), x, y))?;
+            module_table.set("pick", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, enabled: bool| Ok(gpu.borrow_mut().enable_picking(enabled)))?;
+            module_table.set("enablePicking", func)?;
+        }
+        // Budgets
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function_mut(move |_, table: Table| BindGraphicsChip::set_limits(&mut gpu.borrow_mut(), table))?;
+            module_table.set("setLimits", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function(move |lua, ()| BindGraphicsChip::get_limits(lua, &gpu.borrow()))?;
+            module_table.set("getLimits", func)?;
+        }
+        {
+            let gpu = gpu.clone();
+            let func = lua.create_function(move |lua, ()| BindGraphicsChip::get_budget_overflows(lua, &gpu.borrow()))?;
+            module_table.set("getBudgetOverflows", func)?;
+        }
         // Draw
         {
             let gpu = gpu.clone();
@@ -0,0 +1,103 @@
+use verdi_math::{Mat4, Vec3, Vec4};
+
+/// Axis aligned box containing a mesh, in the mesh space.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Bounds {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self {
+            min,
+            max,
+        }
+    }
+
+    /// The smallest box containing the points, None without any point.
+    pub fn from_points<I: IntoIterator<Item = Vec3>>(points: I) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(
+            points.fold(Bounds::new(first, first), |bounds, point| {
+                Bounds::new(bounds.min.min(point), bounds.max.max(point))
+            })
+        )
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// Whether a ray crosses the box before the given distance.
+    pub fn intersects_ray(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> bool {
+        let inverse = direction.recip();
+        let t0 = (self.min - origin) * inverse;
+        let t1 = (self.max - origin) * inverse;
+        let near = t0.min(t1).max_element();
+        let far = t0.max(t1).min_element();
+
+        near <= far && far >= 0.0 && near <= max_distance
+    }
+
+    /// Whether the box may be in the view frustum. It is hidden when all its corners are outside of the same clip plane.
+    pub fn is_visible(&self, clip_from_local: &Mat4) -> bool {
+        let corners: Vec<Vec4> = self
+            .corners()
+            .iter()
+            .map(|corner| *clip_from_local * corner.extend(1.0))
+            .collect();
+
+        let outside: [fn(&Vec4) -> bool; 6] = [
+            |p| p.x < -p.w,
+            |p| p.x > p.w,
+            |p| p.y < -p.w,
+            |p| p.y > p.w,
+            |p| p.z < -p.w,
+            |p| p.z > p.w,
+        ];
+
+        !outside
+            .iter()
+            .any(|is_outside| corners.iter().all(is_outside))
+    }
+}
+
+/// Möller-Trumbore intersection, the distance along the ray.
+pub fn ray_triangle(origin: Vec3, direction: Vec3, [a, b, c]: &[Vec3; 3]) -> Option<f32> {
+    const EPSILON: f32 = 1e-6;
+
+    let (edge1, edge2) = (*b - *a, *c - *a);
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < EPSILON {
+        return None;
+    }
+
+    let inverse = 1.0 / determinant;
+    let s = origin - *a;
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(edge1);
+    let v = direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(q) * inverse;
+    (t > EPSILON).then_some(t)
+}
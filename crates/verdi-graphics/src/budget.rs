@@ -0,0 +1,241 @@
+use std::collections::{HashMap, HashSet};
+
+use image::RgbaImage;
+use verdi_database::ResourceId;
+
+/// What happens when a frame goes over a budget.
+#[derive(Copy, Clone, PartialEq)]
+pub enum OverflowBehavior {
+    /// Keeps drawing, the overflow is only reported to verdi.onBudgetOverflow.
+    Warn,
+    /// Skips what goes over the budget.
+    Drop,
+    /// Stops the game.
+    Error,
+}
+
+impl From<String> for OverflowBehavior {
+    fn from(string: String) -> Self {
+        match string.as_str() {
+            "warn" => OverflowBehavior::Warn,
+            "drop" => OverflowBehavior::Drop,
+            "error" => OverflowBehavior::Error,
+            _ => OverflowBehavior::Warn
+        }
+    }
+}
+
+impl OverflowBehavior {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverflowBehavior::Warn => "warn",
+            OverflowBehavior::Drop => "drop",
+            OverflowBehavior::Error => "error",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Budget {
+    Triangles,
+    TextureMemory,
+    TextureSize,
+    Colors,
+    Lights,
+}
+
+impl Budget {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Budget::Triangles => "triangles",
+            Budget::TextureMemory => "textureMemory",
+            Budget::TextureSize => "textureSize",
+            Budget::Colors => "colors",
+            Budget::Lights => "lights",
+        }
+    }
+}
+
+/// The target spec of the game. Every budget is optional.
+#[derive(Copy, Clone)]
+pub struct Limits {
+    /// Triangles drawn per frame, in all passes.
+    pub max_triangles: Option<usize>,
+    /// Bytes of the textures used in a frame.
+    pub max_texture_memory: Option<usize>,
+    /// Largest width or height of a texture.
+    pub max_texture_size: Option<usize>,
+    /// Distinct colors of a texture.
+    pub max_colors: Option<usize>,
+    /// Lights enabled in a frame, in all passes. The built-in programs have a single light, enabled per pass.
+    pub max_lights: Option<usize>,
+    pub on_overflow: OverflowBehavior,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_triangles: None,
+            max_texture_memory: None,
+            max_texture_size: None,
+            max_colors: None,
+            max_lights: None,
+            on_overflow: OverflowBehavior::Warn,
+        }
+    }
+}
+
+impl Limits {
+    pub fn get(&self, budget: Budget) -> Option<usize> {
+        match budget {
+            Budget::Triangles => self.max_triangles,
+            Budget::TextureMemory => self.max_texture_memory,
+            Budget::TextureSize => self.max_texture_size,
+            Budget::Colors => self.max_colors,
+            Budget::Lights => self.max_lights,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        [Budget::Triangles, Budget::TextureMemory, Budget::TextureSize, Budget::Colors, Budget::Lights]
+            .iter()
+            .all(|budget| self.get(*budget).is_none())
+    }
+}
+
+/// A budget exceeded during a frame, with the largest amount used.
+#[derive(Copy, Clone)]
+pub struct BudgetOverflow {
+    pub budget: Budget,
+    pub used: usize,
+    pub limit: usize,
+    /// The budget wasn't exceeded during the frame before.
+    pub started: bool,
+}
+
+impl BudgetOverflow {
+    pub fn to_message(&self) -> String {
+        format!("{} budget exceeded: {} used, {} allowed", self.budget.name(), self.used, self.limit)
+    }
+}
+
+/// Checks the frames against the limits.
+pub struct Budgets {
+    pub limits: Limits,
+    overflows: Vec<BudgetOverflow>,
+    previous: HashSet<Budget>,
+    // distinct colors of the images, with their revision
+    color_counts: HashMap<ResourceId, (u32, usize)>,
+}
+
+impl Budgets {
+    pub fn new() -> Self {
+        Self {
+            limits: Limits::default(),
+            overflows: Vec::new(),
+            previous: HashSet::new(),
+            color_counts: HashMap::new(),
+        }
+    }
+
+    /// Forgets the overflows of the last frame, they are kept until the next one is checked.
+    pub fn next_frame(&mut self) {
+        self.previous = self.overflows.iter().map(|overflow| overflow.budget).collect();
+        self.overflows.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.overflows.clear();
+        self.previous.clear();
+        self.color_counts.clear();
+    }
+
+    /// Records the amount used, and returns false if it must be dropped.
+    pub fn check(&mut self, budget: Budget, used: usize) -> bool {
+        let limit = match self.limits.get(budget) {
+            Some(limit) if used > limit => limit,
+            _ => return true,
+        };
+
+        match self.overflows.iter_mut().find(|overflow| overflow.budget == budget) {
+            Some(overflow) => overflow.used = overflow.used.max(used),
+            None => self.overflows.push(BudgetOverflow { budget, used, limit, started: !self.previous.contains(&budget) }),
+        }
+
+        self.limits.on_overflow != OverflowBehavior::Drop
+    }
+
+    pub fn get_overflows(&self) -> &Vec<BudgetOverflow> {
+        &self.overflows
+    }
+
+    /// The first overflow of the last frame, when the limits are errors.
+    pub fn get_error(&self) -> Option<String> {
+        match self.limits.on_overflow {
+            OverflowBehavior::Error => self.overflows.first().map(|overflow| overflow.to_message()),
+            _ => None,
+        }
+    }
+
+    /// Distinct colors of an image, counted again when it changes.
+    pub fn color_count(&mut self, id: ResourceId, revision: u32, data: &RgbaImage) -> usize {
+        if let Some((counted_revision, count)) = self.color_counts.get(&id) {
+            if *counted_revision == revision {
+                return *count;
+            }
+        }
+
+        let count = data
+            .pixels()
+            .map(|pixel| pixel.0)
+            .collect::<HashSet<[u8; 4]>>()
+            .len();
+
+        self.color_counts.insert(id, (revision, count));
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budgets(on_overflow: OverflowBehavior) -> Budgets {
+        let mut budgets = Budgets::new();
+        budgets.limits = Limits { max_lights: Some(1), on_overflow, ..Default::default() };
+        budgets
+    }
+
+    #[test]
+    fn overflows_are_kept_until_the_next_frame() {
+        let mut budgets = budgets(OverflowBehavior::Error);
+        assert!(budgets.check(Budget::Lights, 1));
+        assert!(budgets.check(Budget::Lights, 2));
+
+        // the error doesn't hide the overflows from the game
+        assert!(budgets.get_error().is_some());
+        assert_eq!(budgets.get_overflows().len(), 1);
+        assert_eq!(budgets.get_overflows()[0].used, 2);
+
+        budgets.next_frame();
+        assert!(budgets.get_overflows().is_empty());
+        assert!(budgets.get_error().is_none());
+    }
+
+    #[test]
+    fn overflows_start_once() {
+        let mut budgets = budgets(OverflowBehavior::Drop);
+        assert!(!budgets.check(Budget::Lights, 2));
+        assert!(budgets.get_overflows()[0].started);
+
+        budgets.next_frame();
+        assert!(!budgets.check(Budget::Lights, 3));
+        assert!(!budgets.get_overflows()[0].started);
+
+        // after a frame within the budget, it starts again
+        budgets.next_frame();
+        budgets.next_frame();
+        assert!(!budgets.check(Budget::Lights, 2));
+        assert!(budgets.get_overflows()[0].started);
+    }
+}
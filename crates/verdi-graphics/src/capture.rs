@@ -0,0 +1,255 @@
+use std::{path::{Path, PathBuf}, fs::File};
+
+use image::{RgbaImage, ImageError, ImageFormat};
+use thiserror::Error;
+
+use crate::indexed_image::IndexedImage;
+
+/// Frames per second of the recordings, at most. The frames of the game are kept at this rate.
+const RECORDING_FPS: f32 = 30.0;
+
+#[derive(Error, Debug)]
+pub enum CaptureError {
+    #[error("Writing capture failed")]
+    IoError(#[from] std::io::Error),
+    #[error("Image encoding error")]
+    ImageError(#[from] ImageError),
+    #[error("GIF encoding error")]
+    GifError(#[from] gif::EncodingError),
+    #[error("Capture is too large for a GIF")]
+    TooLarge,
+}
+
+pub enum RecordingFormat {
+    /// Colors are reduced to the palette if there is one, or quantized for each frame.
+    Gif { palette: Option<Vec<[u8; 4]>> },
+    /// Numbered PNG files, using the path as a prefix.
+    PngSequence,
+}
+
+pub struct Recording {
+    path: PathBuf,
+    format: RecordingFormat,
+    /// The frames kept, with their time since the start of the recording.
+    frames: Vec<(f32, RgbaImage)>,
+    duration: f32,
+    elapsed: f32,
+    /// Time of the next frame to keep.
+    next_frame_time: f32,
+}
+
+impl Recording {
+    pub fn new(path: PathBuf, format: RecordingFormat, seconds: f32) -> Self {
+        Self {
+            path,
+            format,
+            frames: Vec::new(),
+            duration: seconds.max(0.0),
+            elapsed: 0.0,
+            next_frame_time: 0.0,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// The frame is shown for the delta time, the frame rate of the game doesn't change the speed of the recording.
+    fn add_frame(&mut self, frame: &RgbaImage, delta_time: f32) {
+        if self.is_full() {
+            return;
+        }
+
+        if self.elapsed >= self.next_frame_time {
+            self.frames.push((self.elapsed, frame.clone()));
+
+            // a slow game skips the frames it missed
+            let step = 1.0 / RECORDING_FPS;
+            while self.next_frame_time <= self.elapsed {
+                self.next_frame_time += step;
+            }
+        }
+        self.elapsed += delta_time;
+    }
+
+    /// Delays of the GIF frames in hundredths of a second, until the next frame or the end of the recording.
+    fn frame_delays(&self) -> Vec<u16> {
+        let centiseconds = |time: f32| (time * 100.0).round() as i64;
+        self.frames
+            .iter()
+            .enumerate()
+            .map(|(index, (time, _))| {
+                let end = self.frames.get(index + 1).map_or(self.elapsed, |(next_time, _)| *next_time);
+                (centiseconds(end) - centiseconds(*time)).clamp(1, u16::MAX as i64) as u16
+            })
+            .collect()
+    }
+
+    pub fn save(self) -> Result<(), CaptureError> {
+        match &self.format {
+            RecordingFormat::Gif { palette } => self.save_gif(palette.as_deref()),
+            RecordingFormat::PngSequence => self.save_png_sequence(),
+        }
+    }
+
+    fn save_gif(&self, palette: Option<&[[u8; 4]]>) -> Result<(), CaptureError> {
+        let (width, height) = match self.frames.first() {
+            Some((_, frame)) => frame.dimensions(),
+            None => return Ok(()),
+        };
+        let width = u16::try_from(width).map_err(|_| CaptureError::TooLarge)?;
+        let height = u16::try_from(height).map_err(|_| CaptureError::TooLarge)?;
+
+        let global_palette: Vec<u8> = palette
+            .unwrap_or(&[])
+            .iter()
+            .take(256)
+            .flat_map(|color| [color[0], color[1], color[2]])
+            .collect();
+
+        let mut encoder = gif::Encoder::new(File::create(&self.path)?, width, height, &global_palette)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        for ((_, frame), delay) in self.frames.iter().zip(self.frame_delays()) {
+            let mut gif_frame = match palette {
+                Some(palette) => {
+                    let indices: Vec<u8> = frame
+                        .pixels()
+                        .map(|pixel| IndexedImage::closest_index(palette, pixel.0))
+                        .collect();
+                    gif::Frame::from_indexed_pixels(width, height, indices, None)
+                },
+                None => {
+                    let mut pixels = frame.as_raw().clone();
+                    gif::Frame::from_rgba_speed(width, height, &mut pixels, 10)
+                },
+            };
+            gif_frame.delay = delay;
+            encoder.write_frame(&gif_frame)?;
+        }
+
+        Ok(())
+    }
+
+    fn save_png_sequence(&self) -> Result<(), CaptureError> {
+        let stem = self.path.with_extension("");
+        for (index, (_, frame)) in self.frames.iter().enumerate() {
+            let mut path = stem.clone().into_os_string();
+            path.push(format!("_{:04}.png", index));
+            frame.save_with_format(path, ImageFormat::Png)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Screenshots and recordings of the game framebuffer, at its internal resolution.
+pub struct Capture {
+    screenshots: Vec<PathBuf>,
+    recording: Option<Recording>,
+}
+
+impl Capture {
+    pub fn new() -> Self {
+        Self {
+            screenshots: Vec::new(),
+            recording: None,
+        }
+    }
+
+    /// The screenshot is saved at the end of the frame.
+    pub fn request_screenshot<P: AsRef<Path>>(&mut self, path: P) {
+        self.screenshots.push(path.as_ref().to_path_buf());
+    }
+
+    /// Records the next frames. A ".gif" path makes a GIF, any other path a PNG sequence.
+    pub fn start_recording<P: AsRef<Path>>(&mut self, path: P, seconds: f32, palette: Option<Vec<[u8; 4]>>) {
+        let path = path.as_ref().to_path_buf();
+        let is_gif = path
+            .extension()
+            .map_or(false, |extension| extension.eq_ignore_ascii_case("gif"));
+
+        let format = match is_gif {
+            true => RecordingFormat::Gif { palette },
+            false => RecordingFormat::PngSequence,
+        };
+
+        self.stop_recording();
+        self.recording = Some(Recording::new(path, format, seconds));
+    }
+
+    /// Saves the frames recorded so far, in the background.
+    pub fn stop_recording(&mut self) {
+        if let Some(recording) = self.recording.take() {
+            std::thread::spawn(move || {
+                if let Err(e) = recording.save() {
+                    println!("{}", e);
+                }
+            });
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Whether the framebuffer must be read back this frame.
+    pub fn wants_frame(&self) -> bool {
+        !self.screenshots.is_empty() || self.recording.is_some()
+    }
+
+    /// The frame drawn by the game, shown for the delta time.
+    pub fn on_frame(&mut self, frame: RgbaImage, delta_time: f32) {
+        for path in self.screenshots.drain(..) {
+            if let Err(e) = frame.save_with_format(&path, ImageFormat::Png) {
+                println!("{}", e);
+            }
+        }
+
+        let is_full = match &mut self.recording {
+            Some(recording) => {
+                recording.add_frame(&frame, delta_time);
+                recording.is_full()
+            },
+            None => false,
+        };
+
+        if is_full {
+            self.stop_recording();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(fps: f32, seconds: f32) -> Recording {
+        let mut recording = Recording::new(PathBuf::from("test.gif"), RecordingFormat::Gif { palette: None }, seconds);
+        let frame = RgbaImage::new(1, 1);
+        while !recording.is_full() {
+            recording.add_frame(&frame, 1.0 / fps);
+        }
+        recording
+    }
+
+    #[test]
+    fn recordings_last_as_long_at_any_frame_rate() {
+        for fps in [20.0, 60.0, 144.0] {
+            let recording = record(fps, 2.0);
+            let delays = recording.frame_delays();
+            // the last frame is shown for its whole duration
+            let total = delays.iter().map(|delay| *delay as f32).sum::<f32>();
+            assert!(total >= 200.0 && total <= 200.0 + 100.0 / fps + 1.0, "{} at {} fps", total, fps);
+            assert!(delays.len() <= 60 && delays.len() >= 40, "{} frames at {} fps", delays.len(), fps);
+        }
+    }
+
+    #[test]
+    fn frames_are_kept_at_the_recording_rate() {
+        let recording = record(144.0, 1.0);
+        assert!((recording.frames.len() as i32 - RECORDING_FPS as i32).abs() <= 1);
+        let delays = recording.frame_delays();
+        assert!(delays[..delays.len() - 1].iter().all(|delay| (2..=5).contains(delay)), "{:?}", delays);
+    }
+}
@@ -0,0 +1,305 @@
+use verdi_math::{Vec2, Vec3, Vec4, Mat4};
+
+use crate::{
+    mesh::MeshHandle,
+    vertex::Vertex,
+};
+
+/// Glyph height in font units.
+const GLYPH_HEIGHT: f32 = 6.0;
+/// Horizontal space taken by a glyph, in font units.
+const GLYPH_ADVANCE: f32 = 6.0;
+
+#[derive(Copy, Clone)]
+pub struct DebugLine {
+    pub start: Vec3,
+    pub end: Vec3,
+    pub color: Vec4,
+    pub on_top: bool,
+}
+
+#[derive(Clone)]
+pub struct DebugText {
+    pub position: Vec3,
+    pub text: String,
+    pub color: Vec4,
+    pub size: f32,
+    pub on_top: bool,
+}
+
+/// Debug shapes accumulated during a frame, drawn as lines in every pass.
+/// Nothing is recorded in release builds.
+pub struct DebugDraw {
+    lines: Vec<DebugLine>,
+    texts: Vec<DebugText>,
+    /// Shapes added from now are drawn over everything.
+    pub on_top: bool,
+    meshes: Vec<(MeshHandle, bool)>,
+    used: usize,
+}
+
+impl DebugDraw {
+    pub fn new() -> Self {
+        Self {
+            lines: Vec::new(),
+            texts: Vec::new(),
+            on_top: false,
+            meshes: Vec::new(),
+            used: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.texts.is_empty()
+    }
+
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: Vec4) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        self.lines.push(DebugLine { start, end, color, on_top: self.on_top });
+    }
+
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: Vec4) {
+        let corner = |x: bool, y: bool, z: bool| Vec3::new(
+            if x { max.x } else { min.x },
+            if y { max.y } else { min.y },
+            if z { max.z } else { min.z },
+        );
+
+        for (a, b) in [
+            // bottom
+            ((false, false, false), (true, false, false)),
+            ((true, false, false), (true, false, true)),
+            ((true, false, true), (false, false, true)),
+            ((false, false, true), (false, false, false)),
+            // top
+            ((false, true, false), (true, true, false)),
+            ((true, true, false), (true, true, true)),
+            ((true, true, true), (false, true, true)),
+            ((false, true, true), (false, true, false)),
+            // sides
+            ((false, false, false), (false, true, false)),
+            ((true, false, false), (true, true, false)),
+            ((true, false, true), (true, true, true)),
+            ((false, false, true), (false, true, true)),
+        ] {
+            self.line(corner(a.0, a.1, a.2), corner(b.0, b.1, b.2), color);
+        }
+    }
+
+    /// Three circles, one around each axis.
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: Vec4) {
+        const SEGMENTS: usize = 24;
+
+        for axes in [(Vec3::X, Vec3::Y), (Vec3::X, Vec3::Z), (Vec3::Y, Vec3::Z)] {
+            let point = |index: usize| {
+                let angle = index as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (axes.0 * angle.cos() + axes.1 * angle.sin()) * radius
+            };
+
+            for index in 0..SEGMENTS {
+                self.line(point(index), point(index + 1), color);
+            }
+        }
+    }
+
+    /// The X, Y and Z axes of the transform, in red, green and blue.
+    pub fn axes(&mut self, matrix: &Mat4, size: f32) {
+        let origin = matrix.transform_point3(Vec3::ZERO);
+        self.line(origin, origin + matrix.transform_vector3(Vec3::X) * size, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        self.line(origin, origin + matrix.transform_vector3(Vec3::Y) * size, Vec4::new(0.0, 1.0, 0.0, 1.0));
+        self.line(origin, origin + matrix.transform_vector3(Vec3::Z) * size, Vec4::new(0.0, 0.0, 1.0, 1.0));
+    }
+
+    /// A grid on the XZ plane, centered on the origin.
+    pub fn grid(&mut self, size: f32, step: f32, color: Vec4) {
+        if step <= 0.0 {
+            return;
+        }
+
+        let half_size = size * 0.5;
+        let count = (size / step).floor() as i32;
+        for index in 0..=count {
+            let offset = -half_size + index as f32 * step;
+            self.line(Vec3::new(offset, 0.0, -half_size), Vec3::new(offset, 0.0, half_size), color);
+            self.line(Vec3::new(-half_size, 0.0, offset), Vec3::new(half_size, 0.0, offset), color);
+        }
+    }
+
+    /// Text facing the camera, starting at the position. The size is the height of a letter.
+    pub fn text(&mut self, position: Vec3, text: String, color: Vec4, size: f32) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        self.texts.push(DebugText { position, text, color, size, on_top: self.on_top });
+    }
+
+    /// Line vertices for a pass, depth tested and on top.
+    /// The text is oriented with the view matrix of the pass.
+    pub fn build_vertices(&self, view: &Mat4) -> (Vec<Vertex>, Vec<Vertex>) {
+        let mut depth_tested = Vec::new();
+        let mut on_top = Vec::new();
+
+        let mut push_line = |line: &DebugLine| {
+            let vertices = if line.on_top { &mut on_top } else { &mut depth_tested };
+            for position in [line.start, line.end] {
+                vertices.push(Vertex {
+                    position: position.to_array(),
+                    color: line.color.to_array(),
+                    ..Default::default()
+                });
+            }
+        };
+
+        for line in self.lines.iter() {
+            push_line(line);
+        }
+
+        // camera axes in world space
+        let right = view.row(0).truncate().normalize_or_zero();
+        let up = view.row(1).truncate().normalize_or_zero();
+
+        for text in self.texts.iter() {
+            let scale = text.size / GLYPH_HEIGHT;
+            for (index, character) in text.text.chars().enumerate() {
+                let origin = text.position + right * (index as f32 * GLYPH_ADVANCE * scale);
+                for [x1, y1, x2, y2] in DebugDraw::glyph_segments(character) {
+                    push_line(&DebugLine {
+                        start: origin + (right * x1 + up * y1) * scale,
+                        end: origin + (right * x2 + up * y2) * scale,
+                        color: text.color,
+                        on_top: text.on_top,
+                    });
+                }
+            }
+        }
+
+        (depth_tested, on_top)
+    }
+
+    /// Line vertices of a text in pixels, y going down. The size is the height of a letter.
+    pub fn screen_text_vertices(position: Vec2, text: &str, color: Vec4, size: f32) -> Vec<Vertex> {
+        let scale = size / GLYPH_HEIGHT;
+        let mut vertices = Vec::new();
+
+        for (index, character) in text.chars().enumerate() {
+            let origin = Vec2::new(position.x + index as f32 * GLYPH_ADVANCE * scale, position.y + size);
+            for [x1, y1, x2, y2] in DebugDraw::glyph_segments(character) {
+                for (x, y) in [(x1, y1), (x2, y2)] {
+                    vertices.push(Vertex {
+                        position: [origin.x + x * scale, origin.y - y * scale, 0.0],
+                        color: color.to_array(),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        vertices
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.texts.clear();
+        self.on_top = false;
+        self.used = 0;
+    }
+
+    /// Returns an unused mesh of the given mode, if there is any left this frame.
+    pub fn next_free_mesh(&mut self, on_top: bool) -> Option<MeshHandle> {
+        let index = self.meshes[self.used..]
+            .iter()
+            .position(|(_, mesh_on_top)| *mesh_on_top == on_top)?
+            + self.used;
+
+        // keep the used meshes at the front
+        self.meshes.swap(self.used, index);
+        self.used += 1;
+        Some(self.meshes[self.used - 1].0.clone())
+    }
+
+    pub fn push_mesh(&mut self, mesh: MeshHandle, on_top: bool) {
+        self.meshes.push((mesh, on_top));
+        let last = self.meshes.len() - 1;
+        self.meshes.swap(self.used, last);
+        self.used += 1;
+    }
+
+    /// Drops the meshes, when the assets are cleared.
+    pub fn reset(&mut self) {
+        self.clear();
+        self.meshes.clear();
+    }
+
+    /// Segments of a glyph on a 4x6 grid, y going up.
+    fn glyph_segments(character: char) -> Vec<[f32; 4]> {
+        let strokes = match character.to_ascii_uppercase() {
+            '0' => "0040 4046 4606 0600 0046",
+            '1' => "2026 2615 0040",
+            '2' => "0646 4643 4303 0300 0040",
+            '3' => "0646 4640 4000 0343",
+            '4' => "0603 0343 4640",
+            '5' => "4606 0603 0343 4340 4000",
+            '6' => "4606 0600 0040 4043 4303",
+            '7' => "0646 4620",
+            '8' => "0040 4046 4606 0600 0343",
+            '9' => "4303 0306 0646 4640 4000",
+            'A' => "0004 0426 2644 4440 0343",
+            'B' => "0006 0636 3633 0343 4340 4000",
+            'C' => "4606 0600 0040",
+            'D' => "0006 0626 2644 4442 4220 2000",
+            'E' => "4606 0600 0040 0333",
+            'F' => "4606 0600 0333",
+            'G' => "4606 0600 0040 4043 4323",
+            'H' => "0006 4046 0343",
+            'I' => "0646 2026 0040",
+            'J' => "0646 3630 3000 0002",
+            'K' => "0006 0346 0340",
+            'L' => "0600 0040",
+            'M' => "0006 0623 2346 4640",
+            'N' => "0006 0640 4046",
+            'O' => "0040 4046 4606 0600",
+            'P' => "0006 0646 4643 4303",
+            'Q' => "0040 4046 4606 0600 2240",
+            'R' => "0006 0646 4643 4303 2340",
+            'S' => "4616 1605 0504 0413 1333 3342 4241 4130 3000",
+            'T' => "0646 2620",
+            'U' => "0600 0040 4046",
+            'V' => "0620 2046",
+            'W' => "0610 1023 2330 3046",
+            'X' => "0046 0640",
+            'Y' => "0623 2346 2320",
+            'Z' => "0646 4600 0040",
+            '.' => "2021",
+            ',' => "2110",
+            ':' => "2122 2425",
+            '-' => "0343",
+            '+' => "0343 2125",
+            '=' => "0242 0444",
+            '_' => "0040",
+            '/' => "0046",
+            '(' => "3614 1412 1230",
+            ')' => "1634 3432 3210",
+            '!' => "2622 2021",
+            '?' => "0646 4643 4323 2322 2021",
+            '\'' => "2624",
+            _ => "",
+        };
+
+        strokes
+            .split_whitespace()
+            .map(|segment| {
+                let mut coords = segment.chars().map(|c| c.to_digit(10).unwrap_or(0) as f32);
+                [
+                    coords.next().unwrap_or(0.0),
+                    coords.next().unwrap_or(0.0),
+                    coords.next().unwrap_or(0.0),
+                    coords.next().unwrap_or(0.0),
+                ]
+            })
+            .collect()
+    }
+}
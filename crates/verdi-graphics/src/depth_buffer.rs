@@ -1,5 +1,5 @@
 use std::ops::Deref;
-use glium::{Display, framebuffer::DepthRenderBuffer};
+use glium::{Display, framebuffer::{DepthRenderBuffer, DepthAttachment, ToDepthAttachment}, texture::DepthTexture2d};
 use mlua::UserData;
 use slotmap::Key;
 use verdi_database::{ResourceId, Resource, Assets, Handle};
@@ -8,10 +8,16 @@ use crate::gpu_assets::{GpuAsset, GpuAssetError, PrepareAsset, GpuAssets,
 };
 
 
+pub type DepthBufferId = ResourceId;
+
 #[derive(Clone)]
 pub struct DepthBuffer {
     width: u32,
     height: u32,
+    /// Sampled depth buffers are textures that the next passes can read.
+    sampled: bool,
+    /// Depth of each pixel from 0 to 1, from the top row, kept by the software renderer.
+    data: Option<Vec<f32>>,
     pub id: ResourceId,
 }
 
@@ -30,10 +36,33 @@ impl DepthBuffer {
         Self { 
             width: width, 
             height: height,
+            sampled: false,
+            data: None,
             id: ResourceId::null(),
         }
     }
 
+    /// A depth buffer which can be sampled like a texture.
+    pub fn new_texture(width: u32, height: u32) -> Self {
+        Self {
+            sampled: true,
+            ..DepthBuffer::new(width, height)
+        }
+    }
+
+    pub fn set_data(&mut self, data: Vec<f32>) {
+        self.data = Some(data);
+    }
+
+    /// Depth at the pixel, 1 (the far plane) when there is no data.
+    pub fn get_depth(&self, x: u32, y: u32) -> f32 {
+        self.data
+            .as_ref()
+            .and_then(|data| data.get((y * self.width + x) as usize))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
     pub fn get_dimensions(&self) -> (u32, u32) {
         return (self.width, self.height)
     }
@@ -49,6 +78,18 @@ impl DepthBuffer {
 
 impl PrepareAsset for DepthBuffer {
     fn prepare_rendering(&self, ctx: &Display, assets: &Assets, gpu_assets: &GpuAssets) -> Result<Box<dyn GpuAsset>, GpuAssetError> {
+        if self.sampled {
+            return DepthTexture2d::empty_with_format(
+                ctx,
+                glium::texture::DepthFormat::I24,
+                glium::texture::MipmapsOption::NoMipmap,
+                self.width,
+                self.height
+            )
+            .map(|gl_depth| Box::new(GpuDepthBuffer::Texture(gl_depth)) as Box<dyn GpuAsset>)
+            .map_err(|_| GpuAssetError::PreparationFailed);
+        }
+
         if let Ok(gl_depth) = DepthRenderBuffer::new(
             ctx,
             glium::texture::DepthFormat::I24,
@@ -57,7 +98,7 @@ impl PrepareAsset for DepthBuffer {
         ) {
             return Ok(
                 Box::new(
-                    GpuDepthBuffer::new(gl_depth)
+                    GpuDepthBuffer::RenderBuffer(gl_depth)
                 )
             )
         }
@@ -85,17 +126,25 @@ impl DepthBufferHandle {
 
 impl UserData for DepthBufferHandle {}
 
-pub struct GpuDepthBuffer { 
-    gl: DepthRenderBuffer,
+pub enum GpuDepthBuffer {
+    RenderBuffer(DepthRenderBuffer),
+    Texture(DepthTexture2d),
 }
 
 impl GpuDepthBuffer {
-    pub fn new(gl: DepthRenderBuffer) -> Self {    
-        Self { gl }
+    pub fn get_depth_attachment(&self) -> DepthAttachment<'_> {
+        match self {
+            GpuDepthBuffer::RenderBuffer(gl) => gl.to_depth_attachment(),
+            GpuDepthBuffer::Texture(gl) => gl.to_depth_attachment(),
+        }
     }
 
-    pub fn get_gl_depth_buffer(&self) -> &DepthRenderBuffer {
-        &self.gl
+    /// None when the depth buffer can't be sampled.
+    pub fn get_gl_texture(&self) -> Option<&DepthTexture2d> {
+        match self {
+            GpuDepthBuffer::Texture(gl) => Some(gl),
+            GpuDepthBuffer::RenderBuffer(_) => None,
+        }
     }
 }
 
@@ -109,4 +158,12 @@ impl Resource for GpuDepthBuffer {
     }
 }
 
-impl GpuAsset for GpuDepthBuffer {}
\ No newline at end of file
+impl GpuAsset for GpuDepthBuffer {
+    fn estimated_memory(&self) -> usize {
+        let (width, height) = match self {
+            GpuDepthBuffer::RenderBuffer(gl) => gl.get_dimensions(),
+            GpuDepthBuffer::Texture(gl) => gl.dimensions(),
+        };
+        width as usize * height as usize * 4
+    }
+}
\ No newline at end of file
@@ -6,21 +6,38 @@ use crate::{image::ImageHandle, depth_buffer::DepthBufferHandle};
 
 
 
+/// Name of the fragment shader output written into the first color target.
+pub const COLOR_OUTPUT: &str = "color";
+
 pub struct Framebuffer {
-    color_target: ImageHandle,
+    /// The fragment shader outputs and the images they are written to.
+    color_targets: Vec<(String, ImageHandle)>,
     depth_target: DepthBufferHandle,
 }
 
 impl Framebuffer {
     pub fn new(color_target: ImageHandle, depth_target: DepthBufferHandle) -> Self {
         Self {
-            color_target,
+            color_targets: vec![(COLOR_OUTPUT.to_string(), color_target)],
+            depth_target,
+        }
+    }
+
+    /// A framebuffer with multiple render targets, at least one.
+    /// The first one is presented and captured.
+    pub fn with_color_targets(color_targets: Vec<(String, ImageHandle)>, depth_target: DepthBufferHandle) -> Self {
+        Self {
+            color_targets,
             depth_target,
         }
     }
 
     pub fn get_color_target(&self) -> ImageHandle {
-        self.color_target.clone()
+        self.color_targets[0].1.clone()
+    }
+
+    pub fn get_color_targets(&self) -> &Vec<(String, ImageHandle)> {
+        &self.color_targets
     }
 
     pub fn get_depth_target(&self) -> DepthBufferHandle {
@@ -79,4 +96,13 @@ impl FramebufferHandle {
     pub fn new(assets: Assets, id: ResourceId) -> Self {
         FramebufferHandle(assets.new_handle(id))
     }
+
+    /// Size of the color target, in pixels.
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        let color_target = self.get_datas()
+            .get::<Framebuffer>(self.get_id())
+            .map(|framebuffer| framebuffer.get_color_target());
+
+        color_target.map_or((0, 0), |image| image.get_dimensions())
+    }
 }
\ No newline at end of file
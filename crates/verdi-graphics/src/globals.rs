@@ -1,11 +1,14 @@
+use image::{RgbaImage, Rgba};
 use verdi_database::Assets;
-use verdi_math::{Vec2, Mat4};
+use verdi_math::{Vec2, Vec3, Vec4, Mat4};
 
 use crate::{
     program::{Program, ProgramHandle}, 
-    shader::Shader, 
+    shader::{Shader, ShaderId}, 
     uniform::{Uniform, UniformHandle, UniformValue}, 
     pipeline::{Pipeline, PipelineHandle},
+    image::Image,
+    sampler::WrapMode,
 };
 
 /// Indicates where to find some globals (pipelines, shader and uniforms) in the database
@@ -62,6 +65,12 @@ pub struct GlobalUniforms {
     pub enable_fog: UniformHandle,
     pub fog_start: UniformHandle,
     pub fog_end: UniformHandle,
+    pub fog_color: UniformHandle,
+    pub lod_fade: UniformHandle,
+    /// Alpha under which the fragments of a cutout material are discarded, set for each draw.
+    pub alpha_cutoff: UniformHandle,
+    /// Seconds since the game started, animating the materials.
+    pub time: UniformHandle,
     pub identity_mat: UniformHandle, // TODO: temporary
 }
 
@@ -99,6 +108,22 @@ impl GlobalUniforms {
             assets.clone(), 
             assets.add(Box::new(Uniform::new(UniformValue::Float(0.0))))
         );
+        let fog_color = UniformHandle::new(
+            assets.clone(), 
+            assets.add(Box::new(Uniform::new(UniformValue::Vec4(Vec4::new(0.3, 0.3, 0.3, 1.0)))))
+        );
+        let lod_fade = UniformHandle::new(
+            assets.clone(), 
+            assets.add(Box::new(Uniform::new(UniformValue::Float(0.0))))
+        );
+        let alpha_cutoff = UniformHandle::new(
+            assets.clone(), 
+            assets.add(Box::new(Uniform::new(UniformValue::Float(0.0))))
+        );
+        let time = UniformHandle::new(
+            assets.clone(), 
+            assets.add(Box::new(Uniform::new(UniformValue::Float(0.0))))
+        );
         let identity_mat = UniformHandle::new(
             assets.clone(), 
             assets.add(Box::new(Uniform::new(UniformValue::Mat4(Mat4::IDENTITY))))
@@ -113,6 +138,10 @@ impl GlobalUniforms {
             enable_fog,
             fog_start,
             fog_end,
+            fog_color,
+            lod_fade,
+            alpha_cutoff,
+            time,
             identity_mat,
         }
     }
@@ -125,6 +154,17 @@ pub struct GlobalPrograms {
     pub gouraud_textured: ProgramHandle,
     pub std_2d: ProgramHandle,
     pub simple: ProgramHandle,
+    pub palette: ProgramHandle,
+    pub billboard: ProgramHandle,
+    pub sky: ProgramHandle,
+    pub shadow: ProgramHandle,
+    pub terrain: ProgramHandle,
+    pub pick: ProgramHandle,
+    pub flat: ProgramHandle,
+    pub unlit: ProgramHandle,
+    pub toon: ProgramHandle,
+    pub outline: ProgramHandle,
+    pub matcap: ProgramHandle,
 }
 
 impl GlobalPrograms {
@@ -135,10 +175,38 @@ impl GlobalPrograms {
                 gouraud_textured: GlobalPrograms::init_gouraud_textured(assets)?,
                 std_2d: GlobalPrograms::init_std_2d(assets)?,
                 simple: GlobalPrograms::init_simple(assets)?,
+                palette: GlobalPrograms::init_palette(assets)?,
+                billboard: GlobalPrograms::init_billboard(assets)?,
+                sky: GlobalPrograms::init_sky(assets)?,
+                shadow: GlobalPrograms::init_shadow(assets)?,
+                terrain: GlobalPrograms::init_terrain(assets)?,
+                pick: GlobalPrograms::init_pick(assets)?,
+                flat: GlobalPrograms::init_flat(assets)?,
+                unlit: GlobalPrograms::init_unlit(assets)?,
+                toon: GlobalPrograms::init_toon(assets)?,
+                outline: GlobalPrograms::init_outline(assets)?,
+                matcap: GlobalPrograms::init_matcap(assets)?,
             }
         )
     }
 
+    /// The programs a material can switch to, by name.
+    pub fn get(&self, name: &str) -> Option<&ProgramHandle> {
+        match name {
+            "gouraud" => Some(&self.gouraud),
+            "gouraud_textured" => Some(&self.gouraud_textured),
+            "std_2d" => Some(&self.std_2d),
+            "simple" => Some(&self.simple),
+            "palette" => Some(&self.palette),
+            "flat" => Some(&self.flat),
+            "unlit" => Some(&self.unlit),
+            "toon" => Some(&self.toon),
+            "outline" => Some(&self.outline),
+            "matcap" => Some(&self.matcap),
+            _ => None,
+        }
+    }
+
     fn init_gouraud(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
         let vs = Shader::new(
             match std::fs::read_to_string( "./crates/verdi-graphics/shaders/gouraud.vs") {
@@ -262,4 +330,139 @@ impl GlobalPrograms {
             )    
         )
     }
+
+    fn init_palette(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        GlobalPrograms::load_program(assets, "gouraud.vs", "palette.fs")
+    }
+
+    fn init_billboard(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        GlobalPrograms::load_program(assets, "billboard.vs", "gouraud_textured.fs")
+    }
+
+    fn init_sky(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        GlobalPrograms::load_program(assets, "sky.vs", "sky.fs")
+    }
+
+    fn init_shadow(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        GlobalPrograms::load_program(assets, "shadow.vs", "shadow.fs")
+    }
+
+    fn init_terrain(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        GlobalPrograms::load_program(assets, "terrain.vs", "terrain.fs")
+    }
+
+    fn init_pick(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        // the ids are written without any color conversion
+        GlobalPrograms::load_program_with(assets, "pick.vs", "pick.fs", |program| program.with_raw_output())
+    }
+
+    fn init_flat(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        GlobalPrograms::load_program(assets, "lit.vs", "flat.fs")
+    }
+
+    fn init_unlit(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        GlobalPrograms::load_program(assets, "unlit.vs", "gouraud.fs")
+    }
+
+    fn init_toon(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        let ramp = GlobalPrograms::default_texture(assets, GlobalPrograms::default_ramp());
+        let white = GlobalPrograms::default_texture(assets, RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255])));
+
+        GlobalPrograms::load_program_with(assets, "lit.vs", "toon.fs", |program| program
+            .with_default_uniform("u_ramp", ramp)
+            .with_default_uniform("u_texture", white)
+        )
+    }
+
+    fn init_outline(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        let width = UniformHandle::new(
+            assets.clone(), 
+            assets.add(Box::new(Uniform::new(UniformValue::Float(0.03))))
+        );
+        let color = UniformHandle::new(
+            assets.clone(), 
+            assets.add(Box::new(Uniform::new(UniformValue::Vec4(Vec4::new(0.0, 0.0, 0.0, 1.0)))))
+        );
+
+        GlobalPrograms::load_program_with(assets, "outline.vs", "outline.fs", |program| program
+            .with_default_uniform("u_outline_width", width)
+            .with_default_uniform("u_outline_color", color)
+        )
+    }
+
+    fn init_matcap(assets: &mut Assets) -> Result<ProgramHandle, std::io::Error> {
+        let matcap = GlobalPrograms::default_texture(assets, GlobalPrograms::default_matcap());
+        let white = GlobalPrograms::default_texture(assets, RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 255])));
+
+        GlobalPrograms::load_program_with(assets, "lit.vs", "matcap.fs", |program| program
+            .with_default_uniform("u_matcap", matcap)
+            .with_default_uniform("u_texture", white)
+        )
+    }
+
+    /// Loads the shaders of a program, from their file names in the shader folder.
+    fn load_program(assets: &mut Assets, vs_path: &str, fs_path: &str) -> Result<ProgramHandle, std::io::Error> {
+        GlobalPrograms::load_program_with(assets, vs_path, fs_path, |program| program)
+    }
+
+    /// Loads a program, with the settings applied by the function.
+    fn load_program_with<F: FnOnce(Program) -> Program>(assets: &mut Assets, vs_path: &str, fs_path: &str, settings: F) -> Result<ProgramHandle, std::io::Error> {
+        let vs_id = GlobalPrograms::load_shader(assets, vs_path)?;
+        let fs_id = GlobalPrograms::load_shader(assets, fs_path)?;
+
+        Ok(
+            ProgramHandle::new(
+                assets.clone(), 
+                assets.add(Box::new(settings(Program::new(vs_id, fs_id))))
+            )    
+        )
+    }
+
+    fn load_shader(assets: &mut Assets, path: &str) -> Result<ShaderId, std::io::Error> {
+        let src = match std::fs::read_to_string(format!("./crates/verdi-graphics/shaders/{}", path)) {
+            Ok(src) => src,
+            Err(e) => {
+                println!("{}", e);
+                return Err(e);
+            }
+        };
+
+        Ok(assets.add(Box::new(Shader::new(src))))
+    }
+
+    /// A texture the programs sample when the material has none.
+    fn default_texture(assets: &mut Assets, data: RgbaImage) -> UniformHandle {
+        let mut image = Image::from_data(data);
+        image.set_wrap(WrapMode::Clamp, WrapMode::Clamp);
+        let image_id = assets.add(Box::new(image));
+
+        UniformHandle::new(
+            assets.clone(), 
+            assets.add(Box::new(Uniform::new(UniformValue::Texture(image_id))))
+        )
+    }
+
+    /// Three bands of light for the toon shading: shadow, midtone and lit.
+    fn default_ramp() -> RgbaImage {
+        RgbaImage::from_fn(3, 1, |x, _| {
+            let level = [90, 170, 255][x as usize];
+            Rgba([level, level, level, 255])
+        })
+    }
+
+    /// A grey sphere lit from the top left, facing the camera.
+    fn default_matcap() -> RgbaImage {
+        const SIZE: u32 = 32;
+        let light = Vec3::new(-0.4, 0.6, -0.7).normalize();
+
+        RgbaImage::from_fn(SIZE, SIZE, |x, y| {
+            let u = (x as f32 + 0.5) / SIZE as f32 * 2.0 - 1.0;
+            let v = 1.0 - (y as f32 + 0.5) / SIZE as f32 * 2.0;
+            let normal = Vec3::new(u, v, -(1.0 - u * u - v * v).max(0.0).sqrt()).normalize_or_zero();
+
+            let diffuse = normal.dot(light).max(0.0);
+            let level = ((0.15 + 0.7 * diffuse + 0.3 * diffuse.powi(16)).min(1.0) * 255.0) as u8;
+            Rgba([level, level, level, 255])
+        })
+    }
 }
\ No newline at end of file
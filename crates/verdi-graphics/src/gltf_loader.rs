@@ -1,20 +1,22 @@
-use std::{path::Path, rc::Rc, cell::RefCell};
+use std::{path::Path, rc::Rc, cell::RefCell, collections::HashMap};
 
 use gltf::buffer::Data;
 use image::ImageError;
 
 use thiserror::Error;
-use verdi_database::Assets;
+use verdi_database::{Assets, ResourceId};
 use verdi_math::{Mat4, prelude::Math};
 
 use crate::{
     mesh::{Mesh, PrimitiveType, MeshHandle}, 
     image::Image, 
-    material::{Material, MaterialId}, 
+    material::{Material, MaterialId, AlphaMode}, 
     node::Node,
     vertex::Vertex, 
     model::Model, 
     globals::Globals, uniform::{UniformHandle, Uniform, UniformValue}, 
+    lod::DEFAULT_LOD_DISTANCE,
+    morph::{MorphTarget, MorphAnimation, MorphChannel, MorphInterpolation},
 };
 
 #[derive(Error, Debug)]
@@ -83,29 +85,45 @@ impl GltfLoader {
         let mut meshes = vec![];
         for gltf_mesh in gltf.meshes() {
             for gltf_primitive in gltf_mesh.primitives() {
+                let mut mesh = GltfLoader::load_primitive(
+                    gltf_primitive, 
+                    &buffers, 
+                    &materials
+                )?;
+                // the default weights are shared by the primitives of the mesh
+                mesh.morph_weights = gltf_mesh
+                    .weights()
+                    .map(|weights| weights.to_vec())
+                    .unwrap_or_else(|| vec![0.0; mesh.morph_targets.len()]);
+
                 // creates a mesh per gltf primitive
-                meshes.push(
-                    assets.add(
-                        Box::new(
-                            GltfLoader::load_primitive(
-                                gltf_primitive, 
-                                &buffers, 
-                                &materials
-                            )?
-                        )
-                    )
-                );
+                meshes.push(assets.add(Box::new(mesh)));
             }
         }
 
         
 
+        // meshes of the nodes named "<name>_LOD<level>", by name
+        let mut lod_meshes: HashMap<String, Vec<(u32, ResourceId)>> = HashMap::new();
+
+        // index of the model node of each gltf node
+        let mut node_indices: HashMap<usize, usize> = HashMap::new();
+
         for gltf_node in gltf.nodes() {
             let mesh_id = gltf_node
                 .mesh()
                 .map(|mesh| mesh.index())
                 .and_then(|i| meshes.get(i).cloned());
 
+            let lod = gltf_node.name().and_then(GltfLoader::parse_lod_name);
+            if let (Some((name, level)), Some(mesh_id)) = (&lod, mesh_id) {
+                lod_meshes.entry(name.clone()).or_default().push((*level, mesh_id));
+                // the lower details are only drawn through the first level
+                if *level > 0 {
+                    continue;
+                }
+            }
+
             if let Some(mesh_id) = mesh_id {
                 let transform = math
                 .borrow_mut()
@@ -115,19 +133,138 @@ impl GltfLoader {
                     )
                 );
 
+            let (morph_weights, morphed_mesh) = GltfLoader::new_morphed_mesh(mesh_id, assets);
+
+            node_indices.insert(gltf_node.index(), model.nodes.len());
             model.nodes.push( 
                 Node {
                     mesh: Some(MeshHandle::new(assets.clone(), mesh_id)),
                     transform: transform,
                     children: vec![],
+                    morph_dirty: morphed_mesh.is_some(),
+                    morph_weights,
+                    morphed_mesh,
                 }
             );
             }            
         }
 
+        GltfLoader::attach_lods(lod_meshes, assets);
+
+        for gltf_animation in gltf.animations() {
+            if let Some(animation) = GltfLoader::load_morph_animation(gltf_animation, &buffers, &node_indices) {
+                model.animations.push(animation);
+            }
+        }
+
         Ok(model)
     }
 
+    /// The weights of a node drawing the mesh, and the copy of the mesh blended with them.
+    /// Meshes without morph targets are drawn as is.
+    fn new_morphed_mesh(mesh_id: ResourceId, assets: &mut Assets) -> (Vec<f32>, Option<MeshHandle>) {
+        let (weights, morphed) = match assets.get_datas().get::<Mesh>(mesh_id) {
+            Some(mesh) if !mesh.morph_targets.is_empty() => (
+                mesh.morph_weights.clone(),
+                Mesh::new(mesh.vertices.clone(), mesh.indices.clone(), mesh.primitive_type, mesh.material)
+            ),
+            _ => return (Vec::new(), None),
+        };
+
+        (weights, Some(MeshHandle::new(assets.clone(), assets.add(Box::new(morphed)))))
+    }
+
+    /// Keeps the channels of the animation animating morph weights, of the imported nodes.
+    fn load_morph_animation(gltf_animation: gltf::Animation, buffers: &Vec<Data>, node_indices: &HashMap<usize, usize>) -> Option<MorphAnimation> {
+        let mut channels = vec![];
+        for gltf_channel in gltf_animation.channels() {
+            let node = match node_indices.get(&gltf_channel.target().node().index()) {
+                Some(node) => *node,
+                None => continue,
+            };
+
+            let reader = gltf_channel.reader(|buffer| Some(&buffers[buffer.index()]));
+            let values: Vec<f32> = match reader.read_outputs() {
+                Some(gltf::animation::util::ReadOutputs::MorphTargetWeights(weights)) => weights.into_f32().collect(),
+                _ => continue,
+            };
+            let times: Vec<f32> = match reader.read_inputs() {
+                Some(times) => times.collect(),
+                None => continue,
+            };
+
+            let interpolation = match gltf_channel.sampler().interpolation() {
+                gltf::animation::Interpolation::Step => MorphInterpolation::Step,
+                gltf::animation::Interpolation::Linear => MorphInterpolation::Linear,
+                gltf::animation::Interpolation::CubicSpline => MorphInterpolation::CubicSpline,
+            };
+            let values_per_key = if interpolation == MorphInterpolation::CubicSpline { 3 } else { 1 };
+
+            if times.is_empty() {
+                continue;
+            }
+
+            channels.push(
+                MorphChannel {
+                    node,
+                    target_count: values.len() / (times.len() * values_per_key),
+                    times,
+                    values,
+                    interpolation,
+                }
+            );
+        }
+
+        if channels.is_empty() {
+            return None;
+        }
+
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |duration: f32, time| duration.max(*time));
+
+        Some(
+            MorphAnimation {
+                name: gltf_animation.name().map_or_else(|| gltf_animation.index().to_string(), str::to_string),
+                channels,
+                duration,
+            }
+        )
+    }
+
+    /// Splits a node name like "tree_LOD1" into its name and level.
+    fn parse_lod_name(node_name: &str) -> Option<(String, u32)> {
+        let (name, level) = node_name.rsplit_once("_LOD")?;
+        Some((name.to_string(), level.parse().ok()?))
+    }
+
+    /// Adds the lower details to the mesh of the first level, spaced by a default distance.
+    fn attach_lods(lod_meshes: HashMap<String, Vec<(u32, ResourceId)>>, assets: &mut Assets) {
+        for (_, mut levels) in lod_meshes {
+            levels.sort_by_key(|(level, _)| *level);
+            let (base_level, base_id) = match levels.first() {
+                Some(base) => *base,
+                None => continue,
+            };
+
+            let lods: Vec<(MeshHandle, f32)> = levels
+                .iter()
+                .skip(1)
+                .map(|(level, mesh_id)| (
+                    MeshHandle::new(assets.clone(), *mesh_id),
+                    (level - base_level) as f32 * DEFAULT_LOD_DISTANCE
+                ))
+                .collect();
+
+            if let Some(mesh) = assets.get_datas_mut().get_mut::<Mesh>(base_id) {
+                for (lod, distance) in lods {
+                    mesh.lods.add_level(lod, distance);
+                }
+            }
+        }
+    }
+
     fn load_primitive(gltf_primitive: gltf::Primitive, buffers: &Vec<Data>, materials: &Vec<MaterialId>) -> Result<Mesh, GltfError> {
         let reader = gltf_primitive.reader(|buffer| Some(&buffers[buffer.index()]));
 
@@ -167,6 +304,14 @@ impl GltfLoader {
             }
         }
 
+        let morph_targets = reader
+            .read_morph_targets()
+            .map(|(positions, normals, _)| MorphTarget {
+                positions: positions.map(|positions| positions.collect()).unwrap_or_default(),
+                normals: normals.map(|normals| normals.collect()).unwrap_or_default(),
+            })
+            .collect();
+
         let mut index_buffer = None;
         if let Some(indices) = reader.read_indices() {
             index_buffer = Some(indices.into_u32().collect());
@@ -175,14 +320,15 @@ impl GltfLoader {
         let material_id = gltf_primitive.material().index()
             .and_then(|i| materials.get(i).cloned()).unwrap(); // unwrap ??
 
-        Ok(
-            Mesh::new(
-                vertex_buffer,
-                index_buffer,
-                PrimitiveType::Triangles,
-                material_id
-            )
-        )
+        let mut mesh = Mesh::new(
+            vertex_buffer,
+            index_buffer,
+            PrimitiveType::Triangles,
+            material_id
+        );
+        mesh.morph_targets = morph_targets;
+
+        Ok(mesh)
     }
 
     fn load_texture(gltf_texture: gltf::Texture, buffers: &Vec<Data>, folder_path: &Path) -> Result<Image, ImageError> {
@@ -220,6 +366,12 @@ impl GltfLoader {
             material.add_uniform("u_texture", id);
         }
 
+        material.alpha_mode = match gltf_material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Cutout(gltf_material.alpha_cutoff().unwrap_or(0.5)),
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        };
+
         material.to_owned()
     }
 }
\ No newline at end of file
@@ -10,33 +10,59 @@ use thiserror::Error;
 pub enum GpuAssetError {
     #[error("Gpu asset creation failed")]
     PreparationFailed,
-    #[error("Program creation compilation error")]
+    #[error("Program creation compilation error: {0}")]
     ShaderError(#[from] glium::ProgramCreationError),
+    #[error("Framebuffer creation error: {0}")]
+    FramebufferError(#[from] glium::framebuffer::ValidationError),
 }
 
-pub trait GpuAsset: Resource {}
+pub trait GpuAsset: Resource {
+    /// Approximate size in video memory, in bytes.
+    fn estimated_memory(&self) -> usize {
+        0
+    }
+}
 
 pub(crate) trait PrepareAsset {
     fn prepare_rendering(&self, ctx: &Display, assets: &Assets, gpu_assets: &GpuAssets) -> Result<Box<dyn GpuAsset>, GpuAssetError>;
 }
 
-pub struct GpuAssets(SecondaryMap<ResourceId, Box<dyn GpuAsset>>);
+pub struct GpuAssets {
+    assets: SecondaryMap<ResourceId, Box<dyn GpuAsset>>,
+    /// Assets which couldn't be prepared, they aren't tried again.
+    failures: SecondaryMap<ResourceId, ()>,
+}
 
 impl GpuAssets {
     pub fn new() -> Self {
-        Self(SecondaryMap::default())
+        Self {
+            assets: SecondaryMap::default(),
+            failures: SecondaryMap::default(),
+        }
     }
 
     pub fn clear(&mut self) {
-        self.0.clear();
+        self.assets.clear();
+        self.failures.clear();
     }
 
     pub fn add(&mut self, id: ResourceId, gpu_asset: Box<dyn GpuAsset>) {
-        self.0.insert(id, gpu_asset);
+        self.assets.insert(id, gpu_asset);
+    }
+
+    /// Reports the error the first time the asset fails to be prepared.
+    pub fn add_failure(&mut self, id: ResourceId, error: GpuAssetError) {
+        if self.failures.insert(id, ()).is_none() {
+            println!("{}", error);
+        }
+    }
+
+    pub fn has_failed(&self, id: ResourceId) -> bool {
+        self.failures.contains_key(id)
     }
 
     pub fn get<A: Any>(&self, id: ResourceId) -> Option<&A> {
-        match self.0.get(id) {
+        match self.assets.get(id) {
             Some(value) => {
                 return value.as_any().downcast_ref();
             },
@@ -44,8 +70,23 @@ impl GpuAssets {
         };
     }
 
+    /// Number of GPU assets of a given type.
+    pub fn count<A: Any>(&self) -> usize {
+        self.assets
+            .values()
+            .filter(|gpu_asset| gpu_asset.as_any().is::<A>())
+            .count()
+    }
+
+    pub fn estimated_memory(&self) -> usize {
+        self.assets
+            .values()
+            .map(|gpu_asset| gpu_asset.estimated_memory())
+            .sum()
+    }
+
     pub fn get_mut<A: Any>(&mut self, id: ResourceId) -> Option<&mut A> {
-        match self.0.get_mut(id) {
+        match self.assets.get_mut(id) {
             Some(value) => {
                 return value.as_any_mut().downcast_mut();
             },
@@ -6,13 +6,15 @@ use crate::gpu_assets::GpuAsset;
 pub struct GpuImage { 
     gl: SrgbTexture2d,
     sampler: SamplerBehavior,
+    revision: u32,
 }
 
 impl GpuImage {
-    pub fn new(gl: glium::texture::SrgbTexture2d, sampler: SamplerBehavior) -> Self {    
+    pub fn new(gl: glium::texture::SrgbTexture2d, sampler: SamplerBehavior, revision: u32) -> Self {    
         Self {
             gl,
             sampler,
+            revision,
         }
     }
 
@@ -23,6 +25,15 @@ impl GpuImage {
     pub fn get_gl_sampler(&self) -> &SamplerBehavior {
         &self.sampler
     }
+
+    pub fn set_gl_sampler(&mut self, sampler: SamplerBehavior) {
+        self.sampler = sampler;
+    }
+
+    /// Revision of the image this texture was created from.
+    pub fn get_revision(&self) -> u32 {
+        self.revision
+    }
 }
 
 impl Resource for GpuImage {
@@ -35,4 +46,11 @@ impl Resource for GpuImage {
     }
 }
 
-impl GpuAsset for GpuImage {}
\ No newline at end of file
+impl GpuAsset for GpuImage {
+    fn estimated_memory(&self) -> usize {
+        let texels = self.gl.get_width() as usize * self.gl.get_height().unwrap_or(1) as usize;
+        // the mipmaps add a third
+        let texels = if self.gl.get_mipmap_levels() > 1 { texels * 4 / 3 } else { texels };
+        texels * 4
+    }
+}
\ No newline at end of file
@@ -6,13 +6,15 @@ use crate::{gpu_assets::GpuAsset, vertex::Vertex};
 pub struct GpuMesh {
     vertex_buffer: VertexBuffer<Vertex>,
     index_buffer: Option<IndexBuffer<u32>>,
+    revision: u32,
 }
 
 impl GpuMesh {
-    pub fn new(vertex_buffer: VertexBuffer<Vertex>, index_buffer: Option<IndexBuffer<u32>>) -> Self {
+    pub fn new(vertex_buffer: VertexBuffer<Vertex>, index_buffer: Option<IndexBuffer<u32>>, revision: u32) -> Self {
         Self {
             vertex_buffer,
             index_buffer,
+            revision,
         }
     }
 
@@ -23,6 +25,11 @@ impl GpuMesh {
     pub fn get_index_buffer(&self) -> &Option<IndexBuffer<u32>> {
         &self.index_buffer
     }
+
+    /// Revision of the mesh these buffers were created from.
+    pub fn get_revision(&self) -> u32 {
+        self.revision
+    }
 }
 
 impl Resource for GpuMesh {
@@ -35,4 +42,8 @@ impl Resource for GpuMesh {
     }
 }
 
-impl GpuAsset for GpuMesh {}
\ No newline at end of file
+impl GpuAsset for GpuMesh {
+    fn estimated_memory(&self) -> usize {
+        self.vertex_buffer.get_size() + self.index_buffer.as_ref().map_or(0, |index_buffer| index_buffer.get_size())
+    }
+}
\ No newline at end of file
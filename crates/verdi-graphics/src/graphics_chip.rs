@@ -1,17 +1,17 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, rc::Rc, collections::HashSet};
 
 use crate::{
     vertex::Vertex, 
     render_pass::RenderPass, 
-    image::{Image, ImageHandle}, 
+    image::{Image, ImageHandle, ImageId}, 
     model::ModelHandle, 
     gltf_loader::{GltfError, GltfLoader}, 
-    material::{Material, MaterialHandle}, 
+    material::{Material, MaterialHandle, AlphaMode}, 
     globals::Globals, 
     mesh::{Mesh, PrimitiveType, MeshHandle}, 
     render_state::RenderState, 
     pass::PassHandle, 
-    render_graph::RenderGraph, 
+    render_graph::{RenderGraph, PassDeclaration}, 
     camera::{Camera, CameraHandle},
     gpu_assets::{GpuAssets, PrepareAsset}, 
     framebuffer::{FramebufferHandle, Framebuffer}, 
@@ -19,11 +19,31 @@ use crate::{
     gpu_program::GpuProgram, 
     pipeline::Pipeline, 
     program::Program, 
-    gpu_image::GpuImage, gpu_mesh::GpuMesh, uniform::{Uniform, UniformValue},
+    gpu_image::GpuImage, gpu_mesh::GpuMesh, uniform::{Uniform, UniformValue, UniformHandle},
+    mesh_generator::MeshGenerator,
+    indexed_image::{IndexedImage, IndexedImageHandle, GpuIndexedImage},
+    atlas::{Atlas, AtlasHandle, AtlasFrame, AtlasError},
+    sprite::{Sprite, SpriteHandle},
+    particle_system::{ParticleSystem, ParticleSystemHandle},
+    billboard::{Billboard, BillboardBatch, BillboardBatches},
+    sky::{Sky, SkyHandle, SkyMode},
+    sampler::WrapMode,
+    shadow::{Shadow, ShadowHandle, ShadowKind, ShadowGround, Plane, BlobTransforms},
+    debug_draw::DebugDraw,
+    capture::Capture,
+    render_stats::RenderStats,
+    budget::{Budgets, Budget, Limits},
+    render_cmds::DrawCmd,
+    tilemap::{Tilemap, TilemapHandle},
+    tiled_loader::{TiledLoader, TiledError},
+    atlas::Region,
+    terrain::{Terrain, TerrainHandle, TerrainMaterials},
+    picking::{Picking, PickEntry},
 };
 
 use glium::Display;
-use image::ImageError;
+use slotmap::Key;
+use image::{ImageError, RgbaImage};
 use verdi_database::Assets;
 use verdi_math::prelude::*;
 
@@ -37,6 +57,27 @@ pub struct GraphicsChip {
     pub gpu_assets: GpuAssets,
     pub globals: Rc<Globals>,
     pub render_state: RenderState,
+    // used by the geometry built in world space
+    identity_transform: TransformHandle,
+    billboard_batches: BillboardBatches,
+    blob_transforms: BlobTransforms,
+    pub debug_draw: DebugDraw,
+    pub capture: Capture,
+    // the GIF recordings are reduced to these colors unless another palette is given
+    capture_palette: Option<ImageHandle>,
+    pub stats: RenderStats,
+    /// Draws the stats over the game framebuffer.
+    pub show_stats: bool,
+    // cancels the view of the pass drawing the stats
+    stats_transform: TransformHandle,
+    pub budgets: Budgets,
+    // reported once until the graph changes
+    graph_error: Option<String>,
+    pub picking: Picking,
+    // seconds since the game started
+    time: f32,
+    /// Duration of the current frame.
+    delta_time: f32,
     math: Rc<RefCell<Math>>, 
 }
 
@@ -78,6 +119,9 @@ impl GraphicsChip {
             current_offset: 0,
         };
 
+        let identity_transform = math.borrow_mut().new_transform();
+        let stats_transform = math.borrow_mut().new_transform();
+
         Ok(Self { 
             render_graph: Rc::new(RefCell::new(RenderGraph::new())),
             render_passes: Vec::new(),
@@ -87,15 +131,619 @@ impl GraphicsChip {
             gpu_assets: GpuAssets::new(),
             globals,
             render_state: RenderState::new(),
+            identity_transform,
+            billboard_batches: BillboardBatches::new(),
+            blob_transforms: BlobTransforms::new(),
+            debug_draw: DebugDraw::new(),
+            capture: Capture::new(),
+            stats: RenderStats::default(),
+            show_stats: false,
+            stats_transform,
+            budgets: Budgets::new(),
+            graph_error: None,
+            capture_palette: None,
+            picking: Picking::default(),
+            time: 0.0,
+            delta_time: 0.0,
             math,
         })
     }
 
+    /// Orders the declared passes and allocates their targets, before the other steps see the passes.
+    fn compile_render_graph(&mut self) {
+        let error = self.render_graph
+            .borrow_mut()
+            .compile(&mut self.assets)
+            .err()
+            .map(|error| error.to_string());
+
+        if error.is_some() && error != self.graph_error {
+            if let Some(error) = &error {
+                println!("{}", error);
+            }
+        }
+        self.graph_error = error;
+    }
+
+    /// Replaces the meshes by their level of detail at the camera distance, or culls them.
+    fn select_lods(&mut self) {
+        let render_graph = self.render_graph.clone();
+        for pass in render_graph.borrow_mut().get_passes_mut().iter_mut() {
+            let camera_position = pass.render_state.view.inverse().w_axis.truncate();
+
+            for cmd in pass.take_cmds() {
+                let draws = {
+                    let asset_datas = self.assets.get_datas();
+                    match asset_datas.get::<Mesh>(cmd.mesh.get_id()) {
+                        Some(mesh) if cmd.perspective && !mesh.lods.is_empty() => {
+                            let position = cmd.transform
+                                .get_datas()
+                                .get::<Transform>(cmd.transform.get_id())
+                                .map_or(Vec3::ZERO, |transform| transform.to_matrix().w_axis.truncate());
+
+                            Some(mesh.lods.select(position.distance(camera_position)))
+                        },
+                        _ => None,
+                    }
+                };
+
+                let draws = match draws {
+                    Some(draws) => draws,
+                    None => {
+                        pass.push_cmd(cmd);
+                        continue;
+                    },
+                };
+
+                if draws.is_empty() {
+                    self.stats.culled += 1;
+                }
+
+                for draw in draws {
+                    pass.push_cmd(
+                        DrawCmd {
+                            mesh: draw.mesh.unwrap_or_else(|| cmd.mesh.clone()),
+                            transform: cmd.transform.clone(),
+                            perspective: cmd.perspective,
+                            material: cmd.material.clone(),
+                            lod_fade: draw.fade,
+                            pick: cmd.pick.clone(),
+                        }
+                    );
+                }
+            }
+        }
+    }
+
+    /// Removes the draw commands of the meshes whose bounds are out of the view.
+    fn cull_meshes(&mut self) {
+        let render_graph = self.render_graph.clone();
+        for pass in render_graph.borrow_mut().get_passes_mut().iter_mut() {
+            let (width, height) = pass.get_dimensions();
+            if width == 0 || height == 0 {
+                continue;
+            }
+            let clip_from_world = Camera::perspective_matrix(width, height) * pass.render_state.view;
+
+            let asset_datas = self.assets.get_datas();
+            let stats = &mut self.stats;
+            pass.retain_cmds(|cmd| {
+                let bounds = match asset_datas.get::<Mesh>(cmd.mesh.get_id()) {
+                    Some(mesh) if cmd.perspective => match mesh.bounds {
+                        Some(bounds) => bounds,
+                        None => return true,
+                    },
+                    _ => return true,
+                };
+
+                let model = cmd.transform
+                    .get_datas()
+                    .get::<Transform>(cmd.transform.get_id())
+                    .map_or(Mat4::IDENTITY, |transform| transform.to_matrix());
+
+                let visible = bounds.is_visible(&(clip_from_world * model));
+                if !visible {
+                    stats.culled += 1;
+                }
+                visible
+            });
+        }
+    }
+
+    /// Selects the flipbook frame of the drawn materials at the current time.
+    fn animate_materials(&mut self) {
+        let mut material_ids = HashSet::new();
+        {
+            let asset_datas = self.assets.get_datas();
+            for pass in self.render_graph.borrow().get_passes().iter() {
+                for cmd in pass.get_cmds() {
+                    let material_id = match &cmd.material {
+                        Some(material) => material.get_id(),
+                        None => match asset_datas.get::<Mesh>(cmd.mesh.get_id()) {
+                            Some(mesh) => mesh.material,
+                            None => continue,
+                        },
+                    };
+                    material_ids.insert(material_id);
+                }
+            }
+        }
+
+        let mut asset_datas = self.assets.get_datas_mut();
+        for material_id in material_ids {
+            let frame = match asset_datas
+                .get::<Material>(material_id)
+                .and_then(|material| material.uv_animation.as_ref()) {
+                Some(uv_animation) if !uv_animation.frames.is_empty() => {
+                    (uv_animation.frame.get_id(), uv_animation.frame_at(self.time))
+                },
+                _ => continue,
+            };
+
+            if let Some(uniform) = asset_datas.get_mut::<Uniform>(frame.0) {
+                uniform.value = UniformValue::Vec4(frame.1);
+            }
+        }
+    }
+
+    /// Builds the streaming meshes drawing the billboards of each pass, one per image.
+    fn batch_billboards(&mut self) {
+        let render_graph = self.render_graph.clone();
+        for pass in render_graph.borrow_mut().get_passes_mut().iter_mut() {
+            for (image_id, billboards) in BillboardBatches::group_by_image(pass.take_billboards()) {
+                let mut vertices = Vec::with_capacity(billboards.len() * 4);
+                let mut indices = Vec::with_capacity(billboards.len() * 6);
+                for (index, billboard) in billboards.iter().enumerate() {
+                    vertices.extend_from_slice(&billboard.vertices(Vec4::ONE));
+                    indices.extend_from_slice(&Billboard::indices(index as u32 * 4));
+                }
+
+                let (mesh, texture) = self.next_billboard_batch();
+                {
+                    let mut datas = self.assets.get_datas_mut();
+                    if let Some(mesh) = datas.get_mut::<Mesh>(mesh.get_id()) {
+                        mesh.vertices = vertices;
+                        mesh.indices = Some(indices);
+                        mesh.set_modified();
+                    }
+                    if let Some(texture) = datas.get_mut::<Uniform>(texture.get_id()) {
+                        texture.value = UniformValue::Texture(image_id);
+                    }
+                }
+
+                pass.add_draw_cmd(mesh, self.identity_transform.clone(), true);
+            }
+        }
+    }
+
+    /// Adds the draw commands of the shadows requested in each pass.
+    fn resolve_shadows(&mut self) {
+        let render_graph = self.render_graph.clone();
+        for pass in render_graph.borrow_mut().get_passes_mut().iter_mut() {
+            for shadow_draw in pass.take_shadows() {
+                let blob = {
+                    let datas = self.assets.get_datas();
+                    let shadow = match datas.get::<Shadow>(shadow_draw.shadow.get_id()) {
+                        Some(shadow) if shadow.enabled => shadow,
+                        _ => continue,
+                    };
+
+                    match &shadow.kind {
+                        ShadowKind::Planar { .. } => {
+                            pass.add_draw_cmd_with_material(
+                                shadow_draw.mesh.clone(),
+                                shadow_draw.transform.clone(),
+                                shadow.material.clone()
+                            );
+                            continue;
+                        },
+                        ShadowKind::Blob { .. } => {
+                            let position = shadow_draw.transform
+                                .get_datas()
+                                .get::<Transform>(shadow_draw.transform.get_id())
+                                .map_or(Vec3::ZERO, |transform| transform.get_position());
+
+                            match shadow.blob_transform(position) {
+                                Some(blob_transform) => (shadow.quad.clone(), blob_transform),
+                                None => continue,
+                            }
+                        },
+                    }
+                };
+
+                let (quad, blob_transform) = blob;
+                let mut transform = match self.blob_transforms.next_free() {
+                    Some(transform) => transform,
+                    None => {
+                        let transform = self.math.borrow_mut().new_transform();
+                        self.blob_transforms.push(transform.clone());
+                        transform
+                    },
+                };
+
+                let transform_id = transform.get_id();
+                if let Some(transform) = transform.get_datas_mut().get_mut::<Transform>(transform_id) {
+                    *transform = blob_transform;
+                }
+
+                pass.add_draw_cmd(quad, transform, true);
+            }
+        }
+    }
+
+    /// Adds the debug lines of the frame at the end of each pass.
+    fn build_debug_lines(&mut self) {
+        if self.debug_draw.is_empty() {
+            return;
+        }
+
+        let render_graph = self.render_graph.clone();
+        for pass in render_graph.borrow_mut().get_passes_mut().iter_mut() {
+            let (depth_tested, on_top) = self.debug_draw.build_vertices(&pass.render_state.view);
+            for (vertices, on_top) in [(depth_tested, false), (on_top, true)] {
+                if vertices.is_empty() {
+                    continue;
+                }
+
+                let mesh = self.next_debug_mesh(on_top);
+                let mesh_id = mesh.get_id();
+                if let Some(mesh) = self.assets.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+                    mesh.vertices = vertices;
+                    mesh.set_modified();
+                }
+
+                pass.add_draw_cmd(mesh, self.identity_transform.clone(), true);
+            }
+        }
+    }
+
+    /// Draws the stats of the last frame at the end of the last pass on the game framebuffer.
+    fn build_stats_overlay(&mut self, stats: &RenderStats) {
+        if !self.show_stats {
+            return;
+        }
+
+        let framebuffer_id = match &self.framebuffer {
+            Some(framebuffer) => framebuffer.get_id(),
+            None => return,
+        };
+
+        let render_graph = self.render_graph.clone();
+        let mut render_graph = render_graph.borrow_mut();
+        let pass = match render_graph
+            .get_passes_mut()
+            .iter_mut()
+            .rev()
+            .find(|pass| pass.get_framebuffer().is_some_and(|framebuffer| framebuffer.get_id() == framebuffer_id)) {
+            Some(pass) => pass,
+            None => return,
+        };
+
+        let vertices: Vec<Vertex> = stats
+            .to_lines()
+            .iter()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                DebugDraw::screen_text_vertices(Vec2::new(4.0, 4.0 + index as f32 * 10.0), line, Vec4::new(1.0, 1.0, 0.0, 1.0), 6.0)
+            })
+            .collect();
+
+        let mesh = self.next_debug_mesh(true);
+        if let Some(mesh) = self.assets.get_datas_mut().get_mut::<Mesh>(mesh.get_id()) {
+            mesh.vertices = vertices;
+            mesh.set_modified();
+        }
+
+        // the vertices are in pixels, with the orthographic projection
+        let stats_transform_id = self.stats_transform.get_id();
+        if let Some(transform) = self.stats_transform.get_datas_mut().get_mut::<Transform>(stats_transform_id) {
+            *transform = Transform::from_matrix(pass.render_state.view.inverse());
+        }
+
+        pass.add_draw_cmd(mesh, self.stats_transform.clone(), false);
+    }
+
+    /// Keeps the draw commands of the game framebuffer which can be picked, as they will be rendered.
+    fn collect_pick_entries(&mut self) {
+        let framebuffer_id = match &self.framebuffer {
+            Some(framebuffer) => framebuffer.get_id(),
+            None => return,
+        };
+
+        let asset_datas = self.assets.get_datas();
+        let dimensions = asset_datas
+            .get::<Framebuffer>(framebuffer_id)
+            .and_then(|framebuffer| asset_datas.get::<Image>(framebuffer.get_color_target().get_id()))
+            .map_or((0, 0), |image| image.get_dimensions());
+
+        let perspective_matrix = Camera::perspective_matrix(dimensions.0, dimensions.1);
+        let ortho_matrix = Camera::orthographic_matrix(0.0, dimensions.0 as f32, dimensions.1 as f32, 0.0, -10.0, 10.0);
+
+        let mut entries = Vec::new();
+        for (pass_index, pass) in self.render_graph.borrow().get_passes().iter().enumerate() {
+            if pass.get_framebuffer().is_none_or(|framebuffer| framebuffer.get_id() != framebuffer_id) {
+                continue;
+            }
+
+            for (cmd_index, cmd) in pass.get_cmds().iter().enumerate() {
+                // lines and points have no surface to pick
+                match asset_datas.get::<Mesh>(cmd.mesh.get_id()) {
+                    Some(mesh) if mesh.primitive_type == PrimitiveType::Triangles => (),
+                    _ => continue,
+                }
+
+                let model = cmd.transform
+                    .get_datas()
+                    .get::<Transform>(cmd.transform.get_id())
+                    .map_or(Mat4::IDENTITY, |transform| transform.to_matrix());
+
+                entries.push(PickEntry {
+                    pass: pass_index,
+                    cmd: cmd_index,
+                    mesh: cmd.mesh.clone(),
+                    transform: cmd.transform.clone(),
+                    model,
+                    view: pass.render_state.view,
+                    projection: if cmd.perspective { perspective_matrix } else { ortho_matrix },
+                    tag: cmd.pick.clone(),
+                });
+            }
+        }
+        drop(asset_datas);
+
+        self.picking.set_entries(entries, dimensions);
+    }
+
+    /// Draws the blended commands after the opaque ones, from back to front.
+    /// The 2D ones and the ones drawn on top come last, in their order.
+    fn sort_blended_cmds(&mut self) {
+        let asset_datas = self.assets.get_datas();
+
+        let render_graph = self.render_graph.clone();
+        for pass in render_graph.borrow_mut().get_passes_mut().iter_mut() {
+            let view = pass.render_state.view;
+
+            let mut blended = Vec::new();
+            let mut overlays = Vec::new();
+            for cmd in pass.take_cmds() {
+                let mesh = asset_datas.get::<Mesh>(cmd.mesh.get_id());
+                let material = mesh
+                    .map(|mesh| cmd.material.as_ref().map_or(mesh.material, |material| material.get_id()))
+                    .and_then(|material_id| asset_datas.get::<Material>(material_id));
+
+                match material {
+                    Some(material) if material.alpha_mode == AlphaMode::Blend => {
+                        if !cmd.perspective || !material.depth_test {
+                            overlays.push(cmd);
+                            continue;
+                        }
+
+                        let center = mesh
+                            .and_then(|mesh| mesh.bounds)
+                            .map_or(Vec3::ZERO, |bounds| (bounds.min + bounds.max) * 0.5);
+                        let model = cmd.transform
+                            .get_datas()
+                            .get::<Transform>(cmd.transform.get_id())
+                            .map_or(Mat4::IDENTITY, |transform| transform.to_matrix());
+
+                        // the camera looks towards +z
+                        let depth = (view * model).transform_point3(center).z;
+                        blended.push((depth, cmd));
+                    },
+                    _ => pass.push_cmd(cmd),
+                }
+            }
+
+            blended.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+            for cmd in blended.into_iter().map(|(_, cmd)| cmd).chain(overlays) {
+                pass.push_cmd(cmd);
+            }
+        }
+    }
+
+    /// Checks the frame against the limits, dropping the draw commands over budget if asked to.
+    fn enforce_budgets(&mut self) {
+        self.budgets.next_frame();
+        if self.budgets.limits.is_empty() {
+            return;
+        }
+
+        let budgets = &mut self.budgets;
+        let asset_datas = self.assets.get_datas();
+
+        let mut triangles = 0;
+        let mut texture_memory = 0;
+        let mut textures = HashSet::new();
+        let mut lights = 0;
+
+        let render_graph = self.render_graph.clone();
+        for pass in render_graph.borrow_mut().get_passes_mut().iter_mut() {
+            pass.retain_cmds(|cmd| {
+                let mesh = match asset_datas.get::<Mesh>(cmd.mesh.get_id()) {
+                    Some(mesh) => mesh,
+                    None => return true,
+                };
+
+                let mesh_triangles = match mesh.primitive_type {
+                    PrimitiveType::Triangles => mesh.indices.as_ref().map_or(mesh.vertices.len(), |indices| indices.len()) / 3,
+                    _ => 0,
+                };
+                let mut keep = budgets.check(Budget::Triangles, triangles + mesh_triangles);
+
+                let material_id = cmd.material
+                    .as_ref()
+                    .map_or(mesh.material, |material| material.get_id());
+
+                let mut new_textures = Vec::new();
+                let mut new_texture_memory = 0;
+                if let Some(material) = asset_datas.get::<Material>(material_id) {
+                    for (_, uniform) in material.get_uniforms().iter().flatten() {
+                        let texture = match asset_datas.get::<Uniform>(uniform.get_id()).map(|uniform| uniform.value) {
+                            Some(UniformValue::Texture(id)) => asset_datas
+                                .get::<Image>(id)
+                                .map(|image| (id, image.get_dimensions(), 4, image.get_data().as_ref().map(|data| (image.get_revision(), data)))),
+                            Some(UniformValue::IndexedTexture(id)) => asset_datas
+                                .get::<IndexedImage>(id)
+                                .map(|image| (id, image.get_dimensions(), 1, None)),
+                            _ => None,
+                        };
+
+                        let (id, (width, height), bytes_per_pixel, data) = match texture {
+                            Some(texture) => texture,
+                            None => continue,
+                        };
+
+                        keep &= budgets.check(Budget::TextureSize, width.max(height) as usize);
+                        if let Some((revision, data)) = data {
+                            let colors = budgets.color_count(id, revision, data);
+                            keep &= budgets.check(Budget::Colors, colors);
+                        }
+
+                        if !textures.contains(&id) && !new_textures.contains(&id) {
+                            new_textures.push(id);
+                            new_texture_memory += (width * height) as usize * bytes_per_pixel;
+                        }
+                    }
+                }
+                keep &= budgets.check(Budget::TextureMemory, texture_memory + new_texture_memory);
+
+                if keep {
+                    triangles += mesh_triangles;
+                    texture_memory += new_texture_memory;
+                    textures.extend(new_textures);
+                }
+
+                keep
+            });
+
+            // the light of a pass counts when it draws something
+            if pass.render_state.enable_lighting && !pass.get_cmds().is_empty() {
+                if budgets.check(Budget::Lights, lights + 1) {
+                    lights += 1;
+                }
+                else {
+                    pass.render_state.enable_lighting = false;
+                }
+            }
+        }
+    }
+
+    /// Counts the GPU assets, once they are prepared.
+    fn collect_gpu_asset_stats(&mut self) {
+        self.stats.gpu_meshes = self.gpu_assets.count::<GpuMesh>() as u32;
+        self.stats.gpu_images = self.gpu_assets.count::<GpuImage>() as u32;
+        self.stats.gpu_indexed_images = self.gpu_assets.count::<GpuIndexedImage>() as u32;
+        self.stats.gpu_depth_buffers = self.gpu_assets.count::<GpuDepthBuffer>() as u32;
+        self.stats.gpu_programs = self.gpu_assets.count::<GpuProgram>() as u32;
+        self.stats.gpu_memory = self.gpu_assets.estimated_memory();
+    }
+
+    fn next_debug_mesh(&mut self, on_top: bool) -> MeshHandle {
+        if let Some(mesh) = self.debug_draw.next_free_mesh(on_top) {
+            return mesh;
+        }
+
+        let mut material = Material::new(
+            self.globals.global_programs.simple.clone(), 
+            &self.globals.global_uniforms
+        );
+        material.depth_test = !on_top;
+        material.depth_write = !on_top;
+        material.alpha_mode = AlphaMode::Blend;
+
+        let material_id = self.assets.add(
+            Box::new(material)
+        );
+
+        let mesh = MeshHandle::new(
+            self.assets.clone(),
+            self.assets.add(
+                Box::new(
+                    Mesh::new(
+                        Vec::new(),
+                        None,
+                        PrimitiveType::Lines,
+                        material_id
+                    )
+                )
+            )
+        );
+
+        self.debug_draw.push_mesh(mesh.clone(), on_top);
+        mesh
+    }
+
+    fn next_billboard_batch(&mut self) -> (MeshHandle, UniformHandle) {
+        if let Some(batch) = self.billboard_batches.next_free() {
+            return (batch.mesh.clone(), batch.texture.clone());
+        }
+
+        let texture = UniformHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Uniform::new(UniformValue::Texture(ImageId::null()))))
+        );
+
+        let mut material = Material::new(
+            self.globals.global_programs.billboard.clone(), 
+            &self.globals.global_uniforms
+        );
+        material.alpha_mode = AlphaMode::Blend;
+        material.add_uniform("u_enable_fog", self.globals.global_uniforms.enable_fog.clone());
+        material.add_uniform("u_fog_start", self.globals.global_uniforms.fog_start.clone());
+        material.add_uniform("u_fog_end", self.globals.global_uniforms.fog_end.clone());
+        material.add_uniform("u_enable_lighting", self.globals.global_uniforms.enable_lighting.clone());
+        material.add_uniform("u_texture", texture.clone());
+
+        let material_id = self.assets.add(
+            Box::new(material)
+        );
+
+        let mesh = MeshHandle::new(
+            self.assets.clone(),
+            self.assets.add(
+                Box::new(
+                    Mesh::new(
+                        Vec::new(),
+                        Some(Vec::new()),
+                        PrimitiveType::Triangles,
+                        material_id
+                    )
+                )
+            )
+        );
+
+        self.billboard_batches.push(
+            BillboardBatch {
+                mesh: mesh.clone(),
+                texture: texture.clone(),
+            }
+        );
+
+        (mesh, texture)
+    }
+
     pub fn get_framebuffer(&self) -> Option<FramebufferHandle> {
         self.framebuffer.clone()
     }
 
+    /// The draw command of the last frame under the pixel of the game framebuffer.
+    /// The ids are rendered on the GPU from the next frame on, and the pixel is read back here.
+    pub fn pick(&mut self, x: u32, y: u32) -> Option<PickEntry> {
+        self.picking.enabled = true;
+        self.picking
+            .pick(x, y, &self.assets.get_datas())
+            .cloned()
+    }
+
+    /// Renders the ids of the draw commands every frame, or picks them on the CPU.
+    pub fn enable_picking(&mut self, enabled: bool) {
+        self.picking.enabled = enabled;
+    }
+
     pub fn on_game_start(&mut self) {
+        self.time = 0.0;
+        self.advance_time(0.0);
+
         let color_target = self.new_empty_image(320, 240);
         let depth_target  = self.new_depth_buffer(320, 240);
         let framebuffer = self.new_framebuffer(color_target, depth_target);
@@ -103,10 +751,36 @@ impl GraphicsChip {
         self.framebuffer = Some(framebuffer.clone());
     }
 
+    /// Moves the global time forward, animating the materials.
+    pub fn advance_time(&mut self, delta_time: f32) {
+        self.time += delta_time;
+        self.delta_time = delta_time;
+
+        let time_id = self.globals.global_uniforms.time.get_id();
+        if let Some(uniform) = self.assets.get_datas_mut().get_mut::<Uniform>(time_id) {
+            uniform.value = UniformValue::Float(self.time);
+        }
+    }
+
+    pub fn get_time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn get_delta_time(&self) -> f32 {
+        self.delta_time
+    }
+
     pub fn on_game_shutdown(&mut self) {
         self.assets.clear();
         self.gpu_assets.clear();
         self.render_passes.clear();
+        self.billboard_batches.clear();
+        self.debug_draw.reset();
+        self.budgets.clear();
+        self.render_graph.borrow_mut().reset();
+        self.graph_error = None;
+        self.capture_palette = None;
+        self.picking = Picking::default();
     }
 
     pub fn new_frame(&mut self) {
@@ -123,50 +797,141 @@ impl GraphicsChip {
     pub fn frame_ends(&mut self) {
         self.render_passes.clear();   
         self.render_graph.borrow_mut().clear();
+        self.billboard_batches.next_frame();
+        self.blob_transforms.next_frame();
+        self.debug_draw.clear();
         //self.buffer_state.next_frame();
     }
 
+    /// Builds the geometry generated on the CPU for the passes.
+    /// Must be called once per frame, before rendering with any backend.
+    pub fn prepare_frame(&mut self) {
+        // the stats of the last frame stay readable until now
+        let last_stats = std::mem::take(&mut self.stats);
+
+        self.compile_render_graph();
+        self.select_lods();
+        self.cull_meshes();
+        self.resolve_shadows();
+        self.batch_billboards();
+        self.build_debug_lines();
+        self.animate_materials();
+        self.sort_blended_cmds();
+        self.enforce_budgets();
+        self.collect_pick_entries();
+        self.build_stats_overlay(&last_stats);
+    }
+
     pub fn prepare_gpu_assets(&mut self, ctx: &Display) {
+        self.prepare_frame();
+
         // fonction à revoir commplètement. Le gros point noir du moteur pour l'instant.
         let asset_datas = self.assets.get_datas();
 
-        for pass in self.render_graph.borrow().get_passes().iter() {
+        let render_graph = self.render_graph.clone();
+        for pass in render_graph.borrow_mut().get_passes_mut().iter_mut() {
+            // the commands using an asset which failed are skipped
+            let mut prepared = Vec::with_capacity(pass.get_cmds().len());
+
             for cmd in pass.get_cmds() {
-                if self.gpu_assets.get::<GpuMesh>(cmd.mesh.get_id()).is_none() {
-                    let mesh = asset_datas
-                        .get::<Mesh>(cmd.mesh.get_id())
-                        .expect("Missing primitive resource");
+                let mut failed = false;
 
+                let mesh = asset_datas
+                    .get::<Mesh>(cmd.mesh.get_id())
+                    .expect("Missing primitive resource");
+
+                let up_to_date = self.gpu_assets
+                    .get::<GpuMesh>(cmd.mesh.get_id())
+                    .map_or(false, |gpu_mesh| gpu_mesh.get_revision() == mesh.get_revision());
+
+                if !up_to_date {
                     // construct gpu primitive
                     match mesh.prepare_rendering(ctx, &self.assets, &self.gpu_assets) {
                         Ok(gpu_mesh) => self.gpu_assets.add(cmd.mesh.get_id(), gpu_mesh),
                         Err(_) => todo!(),
                     }
+                }
 
-                    // construct gpu objects needed by the material
-                    if let Some(material) = self.assets.get_datas().get::<Material>(mesh.material) {
-                        for uniform_handle in material.get_uniforms() {
-                            if let Some(uniform_handle) = uniform_handle {
-                                if let Some(uniform) = uniform_handle.1.get_datas().get::<Uniform>(uniform_handle.1.get_id()) {
-                                    match uniform.get_value() {
-                                        UniformValue::Texture(id) => {
-                                            if let Some(texture) = asset_datas.get::<Image>(*id) {                      
+                // construct gpu objects needed by the material, or update them if they were modified
+                let material_id = cmd.material
+                    .as_ref()
+                    .map_or(mesh.material, |material| material.get_id());
+
+                if let Some(material) = self.assets.get_datas().get::<Material>(material_id) {
+                    let program_id = material.program.get_id();
+                    if self.gpu_assets.get::<GpuProgram>(program_id).is_none() && !self.gpu_assets.has_failed(program_id) {
+                        if let Some(program) = asset_datas.get::<Program>(program_id) {
+                            match program.prepare_rendering(ctx, &self.assets, &self.gpu_assets) {
+                                Ok(gpu_program) => self.gpu_assets.add(program_id, gpu_program),
+                                Err(error) => self.gpu_assets.add_failure(program_id, error),
+                            }
+                        }
+                    }
+                    failed |= self.gpu_assets.has_failed(program_id);
+
+                    for uniform_handle in material.get_uniforms() {
+                        if let Some(uniform_handle) = uniform_handle {
+                            if let Some(uniform) = uniform_handle.1.get_datas().get::<Uniform>(uniform_handle.1.get_id()) {
+                                match uniform.get_value() {
+                                    UniformValue::Texture(id) => {
+                                        if let Some(texture) = asset_datas.get::<Image>(*id) {
+                                            let up_to_date = self.gpu_assets
+                                                .get::<GpuImage>(*id)
+                                                .map_or(false, |gpu_image| gpu_image.get_revision() == texture.get_revision());
+
+                                            if !up_to_date && !self.gpu_assets.has_failed(*id) {
+                                                match texture.prepare_rendering(ctx, &self.assets, &self.gpu_assets) {
+                                                    Ok(gpu_image) => self.gpu_assets.add(*id, gpu_image),
+                                                    Err(error) => self.gpu_assets.add_failure(*id, error),
+                                                }
+                                            }
+                                            else if let Some(gpu_image) = self.gpu_assets.get_mut::<GpuImage>(*id) {
+                                                // sampler settings don't need a new texture
+                                                gpu_image.set_gl_sampler(texture.get_gl_sampler());
+                                            }
+                                            failed |= self.gpu_assets.has_failed(*id);
+                                        }
+                                    },
+                                    UniformValue::IndexedTexture(id) => {
+                                        if let Some(texture) = asset_datas.get::<IndexedImage>(*id) {
+                                            let up_to_date = self.gpu_assets
+                                                .get::<GpuIndexedImage>(*id)
+                                                .map_or(false, |gpu_image| gpu_image.get_revision() == texture.get_revision());
+
+                                            if !up_to_date && !self.gpu_assets.has_failed(*id) {
                                                 match texture.prepare_rendering(ctx, &self.assets, &self.gpu_assets) {
                                                     Ok(gpu_image) => self.gpu_assets.add(*id, gpu_image),
-                                                    Err(_) => todo!(),
+                                                    Err(error) => self.gpu_assets.add_failure(*id, error),
                                                 }
                                             }
-                                        },
-                                        _ => {
-                                            continue;
+                                            failed |= self.gpu_assets.has_failed(*id);
                                         }
+                                    },
+                                    UniformValue::DepthTexture(id) => {
+                                        if self.gpu_assets.get::<GpuDepthBuffer>(*id).is_none() && !self.gpu_assets.has_failed(*id) {
+                                            if let Some(depth) = asset_datas.get::<DepthBuffer>(*id) {
+                                                match depth.prepare_rendering(ctx, &self.assets, &self.gpu_assets) {
+                                                    Ok(gpu_depth) => self.gpu_assets.add(*id, gpu_depth),
+                                                    Err(error) => self.gpu_assets.add_failure(*id, error),
+                                                }
+                                            }
+                                        }
+                                        failed |= self.gpu_assets.has_failed(*id);
+                                    },
+                                    _ => {
+                                        continue;
                                     }
                                 }
                             }
                         }
-                    }     
-                }                
-            }     
+                    }
+                }
+
+                prepared.push(!failed);
+            }
+
+            let mut prepared = prepared.into_iter();
+            pass.retain_cmds(|_| prepared.next().unwrap_or(false));
         }
 
         if let Some(pipeline) = self.assets.get_datas().get::<Pipeline>(self.globals.global_pipelines.default_pipeline.get_id()) {
@@ -180,29 +945,57 @@ impl GraphicsChip {
             }
         }
 
-        if let Some(framebuffer_handle) = &self.framebuffer {
-            if let Some(framebuffer) = framebuffer_handle.get_datas().get::<Framebuffer>(framebuffer_handle.get_id()) {
-                let color_handle = framebuffer.get_color_target();
-                let depth_handle = framebuffer.get_depth_target();
-                let color_id = color_handle.get_id();
-                let depth_id = depth_handle.get_id();
-                if self.gpu_assets.get::<GpuImage>(color_id).is_none() {
-                    if let Some(image) = self.assets.get_datas().get::<Image>(color_id) {
-                        match image.prepare_rendering(ctx, &self.assets, &self.gpu_assets)  {
-                            Ok(gpu_image) => self.gpu_assets.add(color_id, gpu_image),
-                            Err(_) => todo!(),
-                        }
+        if let Some(framebuffer) = &self.framebuffer {
+            GraphicsChip::prepare_framebuffer(ctx, &self.assets, &mut self.gpu_assets, framebuffer);
+        }
+
+        // the targets allocated by the render graph
+        for pass in self.render_graph.borrow().get_passes().iter() {
+            if let Some(framebuffer) = pass.get_framebuffer() {
+                GraphicsChip::prepare_framebuffer(ctx, &self.assets, &mut self.gpu_assets, &framebuffer);
+            }
+        }
+
+        // without its program, the picking stays on the CPU
+        if self.picking.enabled {
+            let program_id = self.globals.global_programs.pick.get_id();
+            if self.gpu_assets.get::<GpuProgram>(program_id).is_none() && !self.gpu_assets.has_failed(program_id) {
+                if let Some(program) = asset_datas.get::<Program>(program_id) {
+                    match program.prepare_rendering(ctx, &self.assets, &self.gpu_assets) {
+                        Ok(gpu_program) => self.gpu_assets.add(program_id, gpu_program),
+                        Err(error) => self.gpu_assets.add_failure(program_id, error),
                     }
                 }
-                if self.gpu_assets.get::<GpuDepthBuffer>(depth_id).is_none() {
-                    if let Some(depth) = self.assets.get_datas().get::<DepthBuffer>(depth_id) {
-                        match depth.prepare_rendering(ctx, &self.assets, &self.gpu_assets)  {
-                            Ok(gpu_depth) => self.gpu_assets.add(depth_id, gpu_depth),
-                            Err(_) => todo!(),
+            }
+        }
+
+        drop(asset_datas);
+        self.collect_gpu_asset_stats();
+    }
+
+    /// The targets which fail are reported once, and the passes drawing into them are skipped by the renderer.
+    fn prepare_framebuffer(ctx: &Display, assets: &Assets, gpu_assets: &mut GpuAssets, framebuffer_handle: &FramebufferHandle) {
+        if let Some(framebuffer) = framebuffer_handle.get_datas().get::<Framebuffer>(framebuffer_handle.get_id()) {
+            let depth_id = framebuffer.get_depth_target().get_id();
+            for (_, color_target) in framebuffer.get_color_targets() {
+                let color_id = color_target.get_id();
+                if gpu_assets.get::<GpuImage>(color_id).is_none() && !gpu_assets.has_failed(color_id) {
+                    if let Some(image) = assets.get_datas().get::<Image>(color_id) {
+                        match image.prepare_rendering(ctx, assets, gpu_assets)  {
+                            Ok(gpu_image) => gpu_assets.add(color_id, gpu_image),
+                            Err(error) => gpu_assets.add_failure(color_id, error),
                         }
                     }
                 }
             }
+            if gpu_assets.get::<GpuDepthBuffer>(depth_id).is_none() && !gpu_assets.has_failed(depth_id) {
+                if let Some(depth) = assets.get_datas().get::<DepthBuffer>(depth_id) {
+                    match depth.prepare_rendering(ctx, assets, gpu_assets)  {
+                        Ok(gpu_depth) => gpu_assets.add(depth_id, gpu_depth),
+                        Err(error) => gpu_assets.add_failure(depth_id, error),
+                    }
+                }
+            }
         }
     }
 
@@ -307,6 +1100,43 @@ impl GraphicsChip {
         )
     }
 
+    pub fn new_image_data(&mut self, width: u32, height: u32) -> ImageHandle {
+        let image = Image::from_data(RgbaImage::new(width, height));
+        ImageHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(image))
+        )
+    }
+
+    pub fn new_indexed_image(&mut self, path: &String, palette: &ImageHandle) -> Result<IndexedImageHandle, ImageError> {
+        let palette_uniform = self.new_palette_uniform(palette);
+        let image = {
+            let datas = self.assets.get_datas();
+            let palette_colors = datas
+                .get::<Image>(palette.get_id())
+                .and_then(|palette| palette.get_data().clone())
+                .unwrap_or_default();
+
+            IndexedImage::from_path(path, &palette_colors, palette_uniform)?
+        };
+
+        Ok(
+            IndexedImageHandle::new(
+                self.assets.clone(),
+                self.assets.add(Box::new(image))
+            )
+        )
+    }
+
+    pub fn new_indexed_image_data(&mut self, width: u32, height: u32, palette: &ImageHandle) -> IndexedImageHandle {
+        let palette_uniform = self.new_palette_uniform(palette);
+        let image = IndexedImage::new(width, height, palette_uniform);
+        IndexedImageHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(image))
+        )
+    }
+
     pub fn new_depth_buffer(&mut self, width: u32, height: u32) -> DepthBufferHandle {
         let depth_buffer = DepthBuffer::new(width, height);
         DepthBufferHandle::new(
@@ -363,71 +1193,519 @@ impl GraphicsChip {
         ))
     }
 
-    // pub fn new_sprite(&mut self, image: ImageHandle) -> SpriteId {
-    //     let mut dimensions = (100, 100);
-    //     if let Some(image_ref) = self.assets.borrow().get::<Image>(image.id) {
-    //         dimensions = image_ref.get_dimensions();
-    //     }
-        
-    //     let vertex_buffer:Vec<Vertex> = vec![
-    //         Vertex {
-    //             position: [0.0, 0.0, 0.0],
-    //             normal: [0.0, 0.0, 0.0],
-    //             uv: [0.0, 0.0],
-    //             color: [0.0, 0.0, 0.0, 0.0],
-    //         },
-    //         Vertex {
-    //             position: [0.0, dimensions.1 as f32, 0.0],
-    //             normal: [0.0, 0.0, 0.0],
-    //             uv: [0.0, 1.0],
-    //             color: [0.0, 0.0, 0.0, 0.0],
-    //         },
-    //         Vertex {
-    //             position: [dimensions.0 as f32, 0.0, 0.0],
-    //             normal: [0.0, 0.0, 0.0],
-    //             uv: [1.0, 0.0],
-    //             color: [0.0, 0.0, 0.0, 0.0],
-    //         },
-    //         Vertex {
-    //             position: [dimensions.0 as f32, dimensions.1 as f32, 0.0],
-    //             normal: [0.0, 0.0, 0.0],
-    //             uv: [1.0, 1.0],
-    //             color: [0.0, 0.0, 0.0, 0.0],
-    //         },
-    //     ];
-    //     let index_buffer = vec![0, 1, 2, 2, 1, 3];
-
-    //     let mut material = Material::new(
-    //         self.globals.global_materials.std_2d, 
-    //         &self.globals.global_uniforms
-    //     );
-
-    //     let tex_uniform_id = self.assets.borrow_mut().add(
-    //         Box::new(
-    //             Uniform::new(image)
-    //         )
-    //     );
-    //     material.add_uniform("u_texture", tex_uniform_id);
+    pub fn new_cube(&mut self) -> MeshHandle {
+        let (vertices, indices) = MeshGenerator::cube();
+        self.new_generated_mesh(vertices, indices)
+    }
 
-    //     let material_id = self.assets.borrow_mut().add(
-    //         Box::new(material)
-    //     );
+    pub fn new_plane(&mut self, subdivisions: u32) -> MeshHandle {
+        let (vertices, indices) = MeshGenerator::plane(subdivisions);
+        self.new_generated_mesh(vertices, indices)
+    }
 
-    //     // TODO: reuse same mesh
-    //     let quad = self.assets.borrow_mut().add(
-    //         Box::new(
-    //             Mesh::new(
-    //                 vertex_buffer,
-    //                 Some(index_buffer),
-    //                 PrimitiveType::Triangles,
-    //                 material_id
-    //             )
-    //         )
-    //     );
-    //     let sprite = Sprite::new(image.id, quad);
+    pub fn new_sphere(&mut self, rings: u32, segments: u32) -> MeshHandle {
+        let (vertices, indices) = MeshGenerator::sphere(rings, segments);
+        self.new_generated_mesh(vertices, indices)
+    }
 
-    //     self.assets.borrow_mut().add(Box::new(sprite))
-    // }
+    pub fn new_cylinder(&mut self, segments: u32) -> MeshHandle {
+        let (vertices, indices) = MeshGenerator::cylinder(segments);
+        self.new_generated_mesh(vertices, indices)
+    }
+
+    pub fn new_cone(&mut self, segments: u32) -> MeshHandle {
+        let (vertices, indices) = MeshGenerator::cone(segments);
+        self.new_generated_mesh(vertices, indices)
+    }
+
+    pub fn new_torus(&mut self, rings: u32, segments: u32, thickness: f32) -> MeshHandle {
+        let (vertices, indices) = MeshGenerator::torus(rings, segments, thickness);
+        self.new_generated_mesh(vertices, indices)
+    }
+
+    pub fn new_heightmap(&mut self, image: &ImageHandle, scale: f32) -> Option<MeshHandle> {
+        let (vertices, indices) = {
+            let datas = self.assets.get_datas();
+            let rgba_image = datas
+                .get::<Image>(image.get_id())?
+                .get_data()
+                .as_ref()?;
+
+            MeshGenerator::heightmap(rgba_image, scale)
+        };
+
+        Some(self.new_generated_mesh(vertices, indices))
+    }
+
+    /// A terrain of the given width, elevated by the luminance of the image up to the given height.
+    pub fn new_terrain(&mut self, image: &ImageHandle, size: f32, height: f32, chunk_size: u32) -> Option<TerrainHandle> {
+        let data = self.assets
+            .get_datas()
+            .get::<Image>(image.get_id())?
+            .get_data()
+            .clone()?;
+
+        let global_uniforms = &self.globals.global_uniforms;
+
+        let mut colors = Material::new(self.globals.global_programs.gouraud.clone(), global_uniforms);
+        colors.add_uniform("u_enable_fog", global_uniforms.enable_fog.clone());
+        colors.add_uniform("u_fog_start", global_uniforms.fog_start.clone());
+        colors.add_uniform("u_fog_end", global_uniforms.fog_end.clone());
+        colors.add_uniform("u_enable_lighting", global_uniforms.enable_lighting.clone());
+
+        let mut new_uniform = |value| UniformHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Uniform::new(value)))
+        );
+        let splat_textures = [
+            new_uniform(UniformValue::Texture(image.get_id())),
+            new_uniform(UniformValue::Texture(image.get_id())),
+            new_uniform(UniformValue::Texture(image.get_id())),
+            new_uniform(UniformValue::Texture(image.get_id())),
+        ];
+        let tiling = new_uniform(UniformValue::Float(1.0));
+
+        let mut splat = Material::new(self.globals.global_programs.terrain.clone(), global_uniforms);
+        splat.add_uniform("u_enable_fog", global_uniforms.enable_fog.clone());
+        splat.add_uniform("u_fog_start", global_uniforms.fog_start.clone());
+        splat.add_uniform("u_fog_end", global_uniforms.fog_end.clone());
+        splat.add_uniform("u_enable_lighting", global_uniforms.enable_lighting.clone());
+        splat.add_uniform("u_texture0", splat_textures[0].clone());
+        splat.add_uniform("u_texture1", splat_textures[1].clone());
+        splat.add_uniform("u_texture2", splat_textures[2].clone());
+        splat.add_uniform("u_texture3", splat_textures[3].clone());
+        splat.add_uniform("u_tiling", tiling.clone());
+
+        let materials = TerrainMaterials {
+            colors: self.assets.add(Box::new(colors)),
+            splat: self.assets.add(Box::new(splat)),
+            splat_textures,
+            tiling,
+        };
+
+        let transform = self.math.borrow_mut().new_transform();
+        let mut terrain = Terrain::from_heightmap(&data, size, height, chunk_size, materials, transform);
+
+        for chunk in 0..terrain.get_chunk_count() {
+            let (vertices, indices, bounds) = terrain.chunk_geometry(chunk);
+            let mut mesh = Mesh::new(vertices, Some(indices), PrimitiveType::Triangles, terrain.get_material());
+            mesh.bounds = bounds;
+
+            terrain.add_chunk(
+                MeshHandle::new(
+                    self.assets.clone(),
+                    self.assets.add(Box::new(mesh))
+                )
+            );
+        }
+
+        Some(
+            TerrainHandle::new(
+                self.assets.clone(),
+                self.assets.add(Box::new(terrain))
+            )
+        )
+    }
+
+    pub fn new_sprite(&mut self, frame: &AtlasFrame) -> SpriteHandle {
+        let index_buffer = vec![0, 1, 2, 2, 1, 3];
+
+        let texture = UniformHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Uniform::new(UniformValue::Texture(frame.image.get_id()))))
+        );
+
+        let mut material = Material::new(
+            self.globals.global_programs.std_2d.clone(), 
+            &self.globals.global_uniforms
+        );
+        material.alpha_mode = AlphaMode::Blend;
+        material.add_uniform("u_texture", texture.clone());
+
+        let material_id = self.assets.add(
+            Box::new(material)
+        );
+
+        let quad_id = self.assets.add(
+            Box::new(
+                Mesh::new(
+                    Sprite::quad_vertices(frame),
+                    Some(index_buffer),
+                    PrimitiveType::Triangles,
+                    material_id
+                )
+            )
+        );
+
+        SpriteHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Sprite::new(frame.image.get_id(), quad_id, texture)))
+        )
+    }
+
+    /// Creates a particle system drawing its particles with the given frame.
+    pub fn new_particle_system(&mut self, frame: &AtlasFrame, max_particles: usize) -> ParticleSystemHandle {
+        let texture = UniformHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Uniform::new(UniformValue::Texture(frame.image.get_id()))))
+        );
+
+        let mut material = Material::new(
+            self.globals.global_programs.billboard.clone(), 
+            &self.globals.global_uniforms
+        );
+        material.alpha_mode = AlphaMode::Blend;
+        material.add_uniform("u_enable_fog", self.globals.global_uniforms.enable_fog.clone());
+        material.add_uniform("u_fog_start", self.globals.global_uniforms.fog_start.clone());
+        material.add_uniform("u_fog_end", self.globals.global_uniforms.fog_end.clone());
+        material.add_uniform("u_texture", texture);
+
+        let material_id = self.assets.add(
+            Box::new(material)
+        );
+
+        // filled by the simulation
+        let mesh = MeshHandle::new(
+            self.assets.clone(),
+            self.assets.add(
+                Box::new(
+                    Mesh::new(
+                        Vec::new(),
+                        Some(Vec::new()),
+                        PrimitiveType::Triangles,
+                        material_id
+                    )
+                )
+            )
+        );
+
+        let transform = self.math.borrow_mut().new_transform();
+
+        ParticleSystemHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(ParticleSystem::new(frame.clone(), max_particles, mesh, transform)))
+        )
+    }
+
+    pub fn new_gradient_sky(&mut self, top: Vec4, horizon: Vec4, bottom: Vec4) -> SkyHandle {
+        let sky = self.new_sky(SkyMode::Gradient, None);
+        let mut handle = sky.clone();
+        handle.set_colors(top, horizon, bottom);
+        sky
+    }
+
+    /// The image covers a whole turn around the camera and repeats horizontally.
+    pub fn new_panorama_sky(&mut self, image: &ImageHandle) -> SkyHandle {
+        image.clone().set_wrap(WrapMode::Repeat, WrapMode::Clamp);
+        self.new_sky(SkyMode::Panorama, Some(image))
+    }
+
+    /// The image is a horizontal strip of six square faces: +x, -x, +y, -y, +z, -z.
+    pub fn new_skybox(&mut self, strip: &ImageHandle) -> SkyHandle {
+        strip.clone().set_wrap(WrapMode::Clamp, WrapMode::Clamp);
+        self.new_sky(SkyMode::Skybox, Some(strip))
+    }
+
+    /// Packs six faces into a strip, ordered +x, -x, +y, -y, +z, -z.
+    pub fn new_skybox_from_faces(&mut self, faces: &[ImageHandle]) -> SkyHandle {
+        let (face_width, face_height) = faces
+            .first()
+            .map(|face| face.get_dimensions())
+            .unwrap_or((1, 1));
+
+        let mut strip = self.new_image_data(face_width * 6, face_height);
+        for (index, face) in faces.iter().take(6).enumerate() {
+            strip.paste(face, (index as u32 * face_width) as i64, 0);
+        }
+
+        self.new_skybox(&strip)
+    }
+
+    fn new_sky(&mut self, mode: SkyMode, texture: Option<&ImageHandle>) -> SkyHandle {
+        let new_uniform = |assets: &mut Assets, value: UniformValue| UniformHandle::new(
+            assets.clone(),
+            assets.add(Box::new(Uniform::new(value)))
+        );
+
+        let top = new_uniform(&mut self.assets, UniformValue::Vec4(Vec4::new(0.2, 0.4, 0.8, 1.0)));
+        let horizon = new_uniform(&mut self.assets, UniformValue::Vec4(Vec4::new(0.7, 0.8, 0.9, 1.0)));
+        let bottom = new_uniform(&mut self.assets, UniformValue::Vec4(Vec4::new(0.3, 0.3, 0.3, 1.0)));
+        let fog_height = new_uniform(&mut self.assets, UniformValue::Float(0.2));
+        let sky_mode = new_uniform(&mut self.assets, UniformValue::Float(mode.to_uniform()));
+
+        let mut material = Material::new(
+            self.globals.global_programs.sky.clone(), 
+            &self.globals.global_uniforms
+        );
+        material.add_uniform("u_enable_fog", self.globals.global_uniforms.enable_fog.clone());
+        material.add_uniform("u_sky_mode", sky_mode);
+        material.add_uniform("u_sky_top", top.clone());
+        material.add_uniform("u_sky_horizon", horizon.clone());
+        material.add_uniform("u_sky_bottom", bottom.clone());
+        material.add_uniform("u_sky_fog_height", fog_height.clone());
+        if let Some(texture) = texture {
+            let texture = new_uniform(&mut self.assets, UniformValue::Texture(texture.get_id()));
+            material.add_uniform("u_sky_texture", texture);
+        }
+        // behind everything
+        material.depth_test = false;
+        material.depth_write = false;
+
+        let material_id = self.assets.add(
+            Box::new(material)
+        );
+
+        let quad = MeshHandle::new(
+            self.assets.clone(),
+            self.assets.add(
+                Box::new(
+                    Mesh::new(
+                        Sky::quad_vertices(),
+                        Some(vec![0, 1, 2, 2, 1, 3]),
+                        PrimitiveType::Triangles,
+                        material_id
+                    )
+                )
+            )
+        );
+
+        let transform = self.math.borrow_mut().new_transform();
+
+        SkyHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Sky::new(quad, transform, top, horizon, bottom, fog_height)))
+        )
+    }
+
+    /// A soft disc projected on the ground below the object.
+    pub fn new_blob_shadow(&mut self, radius: f32, ground: ShadowGround) -> ShadowHandle {
+        self.new_shadow(
+            ShadowKind::Blob {
+                radius,
+                max_distance: 10.0,
+                ground,
+            }
+        )
+    }
+
+    /// The mesh flattened on the plane along the light direction.
+    pub fn new_planar_shadow(&mut self, plane: Plane, light_direction: Vec3) -> ShadowHandle {
+        self.new_shadow(
+            ShadowKind::Planar {
+                plane,
+                light_direction,
+            }
+        )
+    }
+
+    fn new_shadow(&mut self, kind: ShadowKind) -> ShadowHandle {
+        let (is_blob, shadow_matrix) = match &kind {
+            ShadowKind::Blob { .. } => (true, Mat4::IDENTITY),
+            ShadowKind::Planar { plane, light_direction } => (false, Shadow::planar_matrix(plane, *light_direction)),
+        };
+
+        let color = UniformHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Uniform::new(UniformValue::Vec4(Vec4::new(0.0, 0.0, 0.0, 0.5)))))
+        );
+        let shadow_matrix = UniformHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Uniform::new(UniformValue::Mat4(shadow_matrix))))
+        );
+        let blob = UniformHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Uniform::new(UniformValue::Bool(is_blob))))
+        );
+
+        let mut material = Material::new(
+            self.globals.global_programs.shadow.clone(), 
+            &self.globals.global_uniforms
+        );
+        material.add_uniform("u_enable_fog", self.globals.global_uniforms.enable_fog.clone());
+        material.add_uniform("u_fog_start", self.globals.global_uniforms.fog_start.clone());
+        material.add_uniform("u_fog_end", self.globals.global_uniforms.fog_end.clone());
+        material.add_uniform("u_shadow_color", color.clone());
+        material.add_uniform("u_shadow_matrix", shadow_matrix.clone());
+        material.add_uniform("u_blob", blob);
+        // shadows are blended over the ground
+        material.alpha_mode = AlphaMode::Blend;
+
+        let material = MaterialHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(material))
+        );
+
+        let quad = MeshHandle::new(
+            self.assets.clone(),
+            self.assets.add(
+                Box::new(
+                    Mesh::new(
+                        Shadow::quad_vertices(),
+                        Some(vec![0, 1, 2, 2, 1, 3]),
+                        PrimitiveType::Triangles,
+                        material.get_id()
+                    )
+                )
+            )
+        );
+
+        ShadowHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Shadow::new(kind, material, quad, color, shadow_matrix)))
+        )
+    }
+
+    /// Loads an atlas from a JSON descriptor and the image it references.
+    pub fn new_atlas(&mut self, path: &String) -> Result<AtlasHandle, AtlasError> {
+        let (image_path, regions, sequences) = Atlas::parse_descriptor(path)?;
+        let image = self.new_image(&image_path)?;
+
+        let mut atlas = Atlas::new(image);
+        for (name, region) in regions {
+            atlas.add_region(Some(name), region);
+        }
+        for (name, frames) in sequences {
+            atlas.add_sequence(name, frames);
+        }
+
+        Ok(
+            AtlasHandle::new(
+                self.assets.clone(),
+                self.assets.add(Box::new(atlas))
+            )
+        )
+    }
+
+    pub fn new_grid_atlas(&mut self, image: &ImageHandle, frame_width: u32, frame_height: u32) -> AtlasHandle {
+        let (width, height) = image.get_dimensions();
+        let atlas = Atlas::from_grid(image.clone(), width, height, frame_width, frame_height);
+
+        AtlasHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(atlas))
+        )
+    }
+
+    /// Combines the images in a single one, to be drawn without changing textures.
+    pub fn pack_atlas(&mut self, images: Vec<(Option<String>, ImageHandle)>) -> AtlasHandle {
+        let pixels: Vec<RgbaImage> = {
+            let datas = self.assets.get_datas();
+            images
+                .iter()
+                .map(|(_, image)| {
+                    datas
+                        .get::<Image>(image.get_id())
+                        .and_then(|image| image.get_data().clone())
+                        .unwrap_or_else(|| RgbaImage::new(1, 1))
+                })
+                .collect()
+        };
+
+        let (packed, regions) = Atlas::pack(&pixels, 1);
+        let packed_image = ImageHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Image::from_data(packed)))
+        );
+
+        let mut atlas = Atlas::new(packed_image);
+        for ((name, _), region) in images.into_iter().zip(regions) {
+            atlas.add_region(name, region);
+        }
+
+        AtlasHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(atlas))
+        )
+    }
+
+    /// Creates a map with a single empty layer.
+    pub fn new_tilemap(&mut self, atlas: &AtlasHandle, tile_width: u32, tile_height: u32, cols: u32, rows: u32) -> TilemapHandle {
+        let mut tilemap = self.create_tilemap(atlas, tile_width, tile_height, cols, rows);
+        tilemap.edit(|tilemap| tilemap.add_layer(String::new()));
+        tilemap
+    }
+
+    fn create_tilemap(&mut self, atlas: &AtlasHandle, tile_width: u32, tile_height: u32, cols: u32, rows: u32) -> TilemapHandle {
+        let image_id = atlas.get_image().map_or(ImageId::null(), |image| image.get_id());
+        let texture = UniformHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Uniform::new(UniformValue::Texture(image_id))))
+        );
+
+        let mut material = Material::new(
+            self.globals.global_programs.std_2d.clone(), 
+            &self.globals.global_uniforms
+        );
+        material.alpha_mode = AlphaMode::Blend;
+        material.add_uniform("u_texture", texture);
+
+        let material_id = self.assets.add(Box::new(material));
+        let transforms = self.math.borrow().get_assets().clone();
+
+        TilemapHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Tilemap::new(atlas.clone(), material_id, transforms, tile_width, tile_height, cols, rows)))
+        )
+    }
+
+    /// Loads a map made with the Tiled editor. Its tilesets are packed in a single atlas.
+    pub fn new_tiled_map(&mut self, path: &String) -> Result<TilemapHandle, TiledError> {
+        let map = TiledLoader::load(path)?;
+
+        let mut images = Vec::new();
+        for tileset in map.tilesets.iter() {
+            let image = Image::from_path(&tileset.image_path)?;
+            images.push(image.get_data().clone().unwrap_or_else(|| RgbaImage::new(1, 1)));
+        }
+
+        let (packed, offsets) = Atlas::pack(&images, 1);
+        let image = ImageHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Image::from_data(packed)))
+        );
+
+        // the tile ids follow the atlas frames
+        let mut atlas = Atlas::new(image);
+        for (tileset, offset) in map.tilesets.iter().zip(offsets) {
+            for region in tileset.regions() {
+                atlas.add_region(
+                    None,
+                    Region {
+                        x: offset.x + region.x,
+                        y: offset.y + region.y,
+                        ..region
+                    }
+                );
+            }
+        }
+
+        let atlas = AtlasHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(atlas))
+        );
+
+        let mut tilemap = self.create_tilemap(&atlas, map.tile_width, map.tile_height, map.cols, map.rows);
+        tilemap.edit(move |tilemap| {
+            for layer in map.layers {
+                let index = tilemap.add_layer(layer.name);
+                for (cell, tile) in layer.tiles.into_iter().enumerate() {
+                    let (x, y) = (cell as u32 % map.cols, cell as u32 / map.cols);
+                    tilemap.set_tile(index, x, y, tile);
+                }
+
+                if let Some(tile_layer) = tilemap.get_layer_mut(index) {
+                    tile_layer.offset = layer.offset;
+                    tile_layer.parallax = layer.parallax;
+                    tile_layer.visible = layer.visible;
+                    tile_layer.properties = layer.properties;
+                }
+            }
+            tilemap.object_layers = map.object_layers;
+            tilemap.tile_properties = map.tile_properties;
+        });
+
+        Ok(tilemap)
+    }
 
     pub fn new_gouraud_material(&mut self) -> MaterialHandle {
         let mut material = Material::new(
@@ -447,11 +1725,46 @@ impl GraphicsChip {
         )
     }
 
+    /// A gouraud material drawing an indexed image through its palette.
+    pub fn new_palette_material(&mut self, image: &IndexedImageHandle) -> Option<MaterialHandle> {
+        let palette_uniform = self.assets
+            .get_datas()
+            .get::<IndexedImage>(image.get_id())?
+            .get_palette()
+            .clone();
+
+        let indices_uniform = UniformHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Uniform::new(UniformValue::IndexedTexture(image.get_id()))))
+        );
+
+        let mut material = Material::new(
+            self.globals.global_programs.palette.clone(), 
+            &self.globals.global_uniforms
+        );
+        material.add_uniform("u_enable_fog", self.globals.global_uniforms.enable_fog.clone());
+        material.add_uniform("u_fog_start", self.globals.global_uniforms.fog_start.clone());
+        material.add_uniform("u_fog_end", self.globals.global_uniforms.fog_end.clone());
+        material.add_uniform("u_enable_lighting", self.globals.global_uniforms.enable_lighting.clone());
+        material.add_uniform("u_indices", indices_uniform);
+        material.add_uniform("u_palette", palette_uniform);
+
+        Some(
+            MaterialHandle::new(
+                self.assets.clone(),
+                self.assets.add(
+                 Box::new(material)
+                )
+            )
+        )
+    }
+
     pub fn new_2d_material(&mut self) -> MaterialHandle {
-        let material = Material::new(
+        let mut material = Material::new(
             self.globals.global_programs.std_2d.clone(), 
             &self.globals.global_uniforms
         );
+        material.alpha_mode = AlphaMode::Blend;
         MaterialHandle::new(
             self.assets.clone(),
             self.assets.add(
@@ -495,6 +1808,62 @@ impl GraphicsChip {
         }
     }
 
+    /// A pass drawing into the resources it declares, its targets are allocated by the render graph.
+    /// Without dimensions, it has the size of the game framebuffer.
+    pub fn new_declared_pass(&mut self, name: String, dimensions: Option<(u32, u32)>) -> PassHandle {
+        let dimensions = dimensions.unwrap_or_else(|| {
+            self.framebuffer
+                .as_ref()
+                .map_or((320, 240), |framebuffer| framebuffer.get_dimensions())
+        });
+
+        PassHandle {
+            graph: self.render_graph.clone(),
+            id: self.render_graph.borrow_mut().create_declared_pass(PassDeclaration::new(name, dimensions)),
+        }
+    }
+
+    /// The error of the last compilation of the render graph, if it failed.
+    pub fn get_graph_error(&self) -> Option<String> {
+        self.graph_error.clone()
+    }
+
+    /// Saves the game framebuffer as a PNG at the end of the frame.
+    pub fn capture_screenshot(&mut self, path: &String) {
+        self.capture.request_screenshot(path);
+    }
+
+    /// Records the game framebuffer, as a GIF reduced to the palette colors if there is one.
+    /// Without a palette, the one set by the game is used.
+    pub fn start_recording(&mut self, path: &String, seconds: f32, palette: Option<&ImageHandle>) {
+        let palette_colors = palette.or(self.capture_palette.as_ref()).and_then(|palette| {
+            self.assets
+                .get_datas()
+                .get::<Image>(palette.get_id())?
+                .get_data()
+                .as_ref()
+                .map(|data| data.pixels().map(|pixel| pixel.0).collect())
+        });
+
+        self.capture.start_recording(path, seconds, palette_colors);
+    }
+
+    pub fn stop_recording(&mut self) {
+        self.capture.stop_recording();
+    }
+
+    pub fn set_capture_palette(&mut self, palette: Option<ImageHandle>) {
+        self.capture_palette = palette;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.budgets.limits = limits;
+    }
+
+    pub fn set_stats_overlay(&mut self, enabled: bool) {
+        self.show_stats = enabled;
+    }
+
     pub fn set_clear_color(&mut self, color: &Vec4) {
         self.render_state.clear_color = *color;
     }
@@ -589,6 +1958,31 @@ impl GraphicsChip {
         stream_buffer.data.clone_from_slice(&cmd.data);
     }
 
+    fn new_palette_uniform(&mut self, palette: &ImageHandle) -> UniformHandle {
+        UniformHandle::new(
+            self.assets.clone(),
+            self.assets.add(Box::new(Uniform::new(UniformValue::Texture(palette.get_id()))))
+        )
+    }
+
+    fn new_generated_mesh(&mut self, vertices: Vec<Vertex>, indices: Vec<u32>) -> MeshHandle {
+        let material = self.new_gouraud_material();
+
+        MeshHandle::new(
+            self.assets.clone(),
+            self.assets.add(
+                Box::new(
+                    Mesh::new(
+                        vertices,
+                        Some(indices),
+                        PrimitiveType::Triangles,
+                        material.get_id()
+                    )
+                )
+            )
+        )
+    }
+
     pub fn flush_stream_buffer(&mut self) {
         self.render_passes.push(
             RenderPass {
@@ -1,14 +1,15 @@
   
-use std::{path::Path, ops::Deref};
-use glium::{Display, uniforms::{SamplerBehavior, MinifySamplerFilter, MagnifySamplerFilter}};
-use image::{io::Reader as ImageReader, RgbaImage, ImageError};
-use mlua::UserData;
+use std::{path::Path, ops::{Deref, DerefMut}};
+use glium::{Display, texture::MipmapsOption, uniforms::SamplerBehavior};
+use image::{io::Reader as ImageReader, RgbaImage, ImageError, Rgba, ImageFormat};
+use mlua::{UserData, UserDataMethods, Function};
 use slotmap::Key;
 use verdi_database::{ResourceId, Resource, Assets, Handle};
 
 use crate::{
     gpu_assets::{GpuAsset, GpuAssetError, PrepareAsset, GpuAssets}, 
-    gpu_image::GpuImage
+    gpu_image::GpuImage, 
+    sampler::{Sampler, FilterMode, WrapMode, MipmapMode},
 };
 
 pub type ImageId = ResourceId;
@@ -18,6 +19,8 @@ pub struct Image {
     width: u32,
     height: u32,
     data: Option<RgbaImage>,
+    revision: u32,
+    sampler: Sampler,
     pub id: ImageId,
 }
 
@@ -37,6 +40,8 @@ impl Image {
             width: width, 
             height: height,
             data: None,
+            revision: 0,
+            sampler: Sampler::default(),
             id: ImageId::null(),
         }
     }
@@ -51,6 +56,8 @@ impl Image {
             width: dim.0, 
             height: dim.1,
             data: Some(rgba8_img),
+            revision: 0,
+            sampler: Sampler::default(),
             id: ImageId::null(),
         })
     }
@@ -69,14 +76,122 @@ impl Image {
             width: dim.0, 
             height: dim.1,
             data: Some(rgba8_img),
+            revision: 0,
+            sampler: Sampler::default(),
             id: ImageId::null(),
         })
     }
 
+    pub fn from_data(data: RgbaImage) -> Self {
+        let dim = data.dimensions();
+
+        Self {
+            width: dim.0,
+            height: dim.1,
+            data: Some(data),
+            revision: 0,
+            sampler: Sampler::default(),
+            id: ImageId::null(),
+        }
+    }
+
     pub fn get_data(&self) -> &Option<RgbaImage> {
         &self.data
     }
 
+    /// Gives a mutable access to the pixels, allocating them if needed.
+    /// The image will be uploaded again to the GPU.
+    pub fn get_data_mut(&mut self) -> &mut RgbaImage {
+        self.revision = self.revision.wrapping_add(1);
+        let (width, height) = (self.width, self.height);
+        self.data.get_or_insert_with(|| RgbaImage::new(width, height))
+    }
+
+    /// Incremented each time the pixels are modified.
+    pub fn get_revision(&self) -> u32 {
+        self.revision
+    }
+
+    pub fn get_sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
+    pub fn get_gl_sampler(&self) -> SamplerBehavior {
+        if self.data.is_none() {
+            // an empty texture has no mipmaps to sample from
+            return Sampler {
+                mipmap_mode: MipmapMode::None,
+                .. self.sampler
+            }.get_gl_sampler();
+        }
+
+        self.sampler.get_gl_sampler()
+    }
+
+    pub fn set_filter(&mut self, min_filter: FilterMode, mag_filter: FilterMode) {
+        self.sampler.min_filter = min_filter;
+        self.sampler.mag_filter = mag_filter;
+    }
+
+    pub fn set_wrap(&mut self, wrap_u: WrapMode, wrap_v: WrapMode) {
+        self.sampler.wrap_u = wrap_u;
+        self.sampler.wrap_v = wrap_v;
+    }
+
+    pub fn set_mipmap_mode(&mut self, mipmap_mode: MipmapMode) {
+        if self.sampler.has_mipmaps() != (mipmap_mode != MipmapMode::None) {
+            // mipmaps have to be generated or dropped with a new texture
+            self.revision = self.revision.wrapping_add(1);
+        }
+        self.sampler.mipmap_mode = mipmap_mode;
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        self.data
+            .as_ref()
+            .and_then(|data| data.get_pixel_checked(x, y))
+            .map(|pixel| pixel.0)
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, color: [u8; 4]) {
+        if x < self.width && y < self.height {
+            self.get_data_mut().put_pixel(x, y, Rgba(color));
+        }
+    }
+
+    /// Rotates the pixels between first and last (included), read from left to right and top to bottom.
+    /// Used to animate palettes.
+    pub fn cycle_colors(&mut self, first: u32, last: u32, steps: i32) {
+        let count = (self.width * self.height) as usize;
+        let first = first as usize;
+        let last = (last as usize).min(count.saturating_sub(1));
+        if first >= last {
+            return;
+        }
+
+        let data = self.get_data_mut();
+        let mut pixels: Vec<Rgba<u8>> = data.pixels().copied().collect();
+        let range = &mut pixels[first..=last];
+        let steps = steps.rem_euclid(range.len() as i32) as usize;
+        range.rotate_right(steps);
+
+        for (pixel, color) in data.pixels_mut().zip(pixels) {
+            *pixel = color;
+        }
+    }
+
+    /// Copies the pixels of another image at the given position, clipping what is outside.
+    pub fn paste(&mut self, other: &RgbaImage, x: i64, y: i64) {
+        image::imageops::replace(self.get_data_mut(), other, x, y);
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+        match &self.data {
+            Some(data) => data.save_with_format(path, ImageFormat::Png),
+            None => RgbaImage::new(self.width, self.height).save_with_format(path, ImageFormat::Png),
+        }
+    }
+
     pub fn get_dimensions(&self) -> (u32, u32) {
         return (self.width, self.height)
     }
@@ -92,27 +207,32 @@ impl Image {
 
 impl PrepareAsset for Image {
     fn prepare_rendering(&self, ctx: &Display, assets: &Assets, gpu_assets: &GpuAssets) -> Result<Box<dyn GpuAsset>, GpuAssetError> {
+        // OpenGL has no empty texture
+        if self.width == 0 || self.height == 0 {
+            return Err(GpuAssetError::PreparationFailed);
+        }
+
         if let Some(datas) = &self.get_data() {
             let raw_image = glium::texture::RawImage2d::from_raw_rgba_reversed(
                 &datas.as_raw(), 
                 self.get_dimensions()
             );
 
-            let gl = glium::texture::SrgbTexture2d::new(
-                ctx, 
-                raw_image
-            ).unwrap();
-
-            // TODO: to be settable
-            let sampler = SamplerBehavior {
-                minify_filter: MinifySamplerFilter::Nearest,
-                magnify_filter: MagnifySamplerFilter::Nearest,
-                .. Default::default()
+            let mipmaps = if self.sampler.has_mipmaps() {
+                MipmapsOption::AutoGeneratedMipmaps
+            } else {
+                MipmapsOption::NoMipmap
             };
 
+            let gl = glium::texture::SrgbTexture2d::with_mipmaps(
+                ctx, 
+                raw_image,
+                mipmaps
+            ).map_err(|_| GpuAssetError::PreparationFailed)?;
+
             return Ok(
                 Box::new(
-                    GpuImage::new(gl, sampler)
+                    GpuImage::new(gl, self.get_gl_sampler(), self.revision)
                 )
             )
         }
@@ -121,17 +241,11 @@ impl PrepareAsset for Image {
                 ctx, 
                 self.width, 
                 self.height
-            ).unwrap();
-
-            let sampler = SamplerBehavior {
-                minify_filter: MinifySamplerFilter::Nearest,
-                magnify_filter: MagnifySamplerFilter::Nearest,
-                .. Default::default()
-            };
+            ).map_err(|_| GpuAssetError::PreparationFailed)?;
 
             return Ok(
                 Box::new(
-                    GpuImage::new(gl, sampler)
+                    GpuImage::new(gl, self.get_gl_sampler(), self.revision)
                 )
             )
         }
@@ -149,10 +263,199 @@ impl Deref for ImageHandle {
     }
 }
 
+impl DerefMut for ImageHandle {
+    fn deref_mut(&mut self) -> &mut Handle {
+        &mut self.0
+    }
+}
+
 impl ImageHandle {
     pub fn new(assets: Assets, id: ImageId) -> Self {
         ImageHandle(assets.new_handle(id))
     }
+
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        self.get_datas()
+            .get::<Image>(self.get_id())
+            .map(|image| image.get_dimensions())
+            .unwrap_or((0, 0))
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> (f32, f32, f32, f32) {
+        let pixel = self.get_datas()
+            .get::<Image>(self.get_id())
+            .and_then(|image| image.get_pixel(x, y))
+            .unwrap_or([0, 0, 0, 0]);
+
+        (
+            pixel[0] as f32 / 255.0,
+            pixel[1] as f32 / 255.0,
+            pixel[2] as f32 / 255.0,
+            pixel[3] as f32 / 255.0,
+        )
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, r: f32, g: f32, b: f32, a: f32) {
+        let image_id = self.get_id();
+        if let Some(image) = self.get_datas_mut().get_mut::<Image>(image_id) {
+            image.set_pixel(x, y, ImageHandle::to_rgba8(r, g, b, a));
+        }
+    }
+
+    /// Calls the function for each pixel with (x, y, r, g, b, a) and stores the returned color.
+    pub fn map_pixels(&self, func: Function) -> mlua::Result<()> {
+        let (width, height) = self.get_dimensions();
+
+        // work on a copy so that the function can access the image
+        let mut pixels = match self.get_datas().get::<Image>(self.get_id()) {
+            Some(image) => image.get_data().clone().unwrap_or_else(|| RgbaImage::new(width, height)),
+            None => return Ok(()),
+        };
+
+        for (x, y, pixel) in pixels.enumerate_pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let (r, g, b, a): (f32, f32, f32, f32) = func.call((
+                x, 
+                y, 
+                r as f32 / 255.0, 
+                g as f32 / 255.0, 
+                b as f32 / 255.0, 
+                a as f32 / 255.0
+            ))?;
+            *pixel = Rgba(ImageHandle::to_rgba8(r, g, b, a));
+        }
+
+        // the image isn't borrowed by the function any more
+        let mut assets = self.get_assets().clone();
+        if let Some(image) = assets.get_datas_mut().get_mut::<Image>(self.get_id()) {
+            *image.get_data_mut() = pixels;
+        }
+
+        Ok(())
+    }
+
+    pub fn paste(&mut self, other: &ImageHandle, x: i64, y: i64) {
+        let other_pixels = match self.get_datas().get::<Image>(other.get_id()) {
+            Some(other) => other.get_data().clone(),
+            None => None,
+        };
+
+        let image_id = self.get_id();
+        if let Some(other_pixels) = other_pixels {
+            if let Some(image) = self.get_datas_mut().get_mut::<Image>(image_id) {
+                image.paste(&other_pixels, x, y);
+            }
+        }
+    }
+
+    pub fn set_filter(&mut self, min_filter: FilterMode, mag_filter: FilterMode) {
+        let image_id = self.get_id();
+        if let Some(image) = self.get_datas_mut().get_mut::<Image>(image_id) {
+            image.set_filter(min_filter, mag_filter);
+        }
+    }
+
+    pub fn set_wrap(&mut self, wrap_u: WrapMode, wrap_v: WrapMode) {
+        let image_id = self.get_id();
+        if let Some(image) = self.get_datas_mut().get_mut::<Image>(image_id) {
+            image.set_wrap(wrap_u, wrap_v);
+        }
+    }
+
+    pub fn set_mipmap_mode(&mut self, mipmap_mode: MipmapMode) {
+        let image_id = self.get_id();
+        if let Some(image) = self.get_datas_mut().get_mut::<Image>(image_id) {
+            image.set_mipmap_mode(mipmap_mode);
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+        match self.get_datas().get::<Image>(self.get_id()) {
+            Some(image) => image.save(path),
+            None => Ok(()),
+        }
+    }
+
+    fn to_rgba8(r: f32, g: f32, b: f32, a: f32) -> [u8; 4] {
+        [
+            (r.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (g.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (b.clamp(0.0, 1.0) * 255.0).round() as u8,
+            (a.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]
+    }
 }
 
-impl UserData for ImageHandle {}
\ No newline at end of file
+impl UserData for ImageHandle {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("getWidth", |_, image, ()| {
+            Ok(image.get_dimensions().0)
+        });
+
+        methods.add_method("getHeight", |_, image, ()| {
+            Ok(image.get_dimensions().1)
+        });
+
+        methods.add_method("getPixel", |_, image, (x, y): (u32, u32)| {
+            Ok(image.get_pixel(x, y))
+        });
+
+        methods.add_method_mut("setPixel", |_, image, (x, y, r, g, b, a): (u32, u32, f32, f32, f32, Option<f32>)| {
+            Ok(image.set_pixel(x, y, r, g, b, a.unwrap_or(1.0)))
+        });
+
+        methods.add_method("mapPixels", |_, image, func: Function| {
+            image.map_pixels(func)
+        });
+
+        methods.add_method_mut("paste", |_, image, (other, x, y): (ImageHandle, Option<i64>, Option<i64>)| {
+            Ok(image.paste(&other, x.unwrap_or(0), y.unwrap_or(0)))
+        });
+
+        methods.add_method_mut("setFilter", |_, image, (min, mag): (String, Option<String>)| {
+            let mag = mag.unwrap_or_else(|| min.clone());
+            Ok(image.set_filter(FilterMode::from(min), FilterMode::from(mag)))
+        });
+
+        methods.add_method_mut("setWrap", |_, image, (u, v): (String, Option<String>)| {
+            let v = v.unwrap_or_else(|| u.clone());
+            Ok(image.set_wrap(WrapMode::from(u), WrapMode::from(v)))
+        });
+
+        methods.add_method_mut("setMipmapFilter", |_, image, mode: String| {
+            Ok(image.set_mipmap_mode(MipmapMode::from(mode)))
+        });
+
+        methods.add_method("save", |_, image, path: String| {
+            image.save(path).map_err(mlua::Error::external)
+        });
+    }
+}
+#[cfg(test)]
+mod tests {
+    use mlua::Lua;
+
+    use super::*;
+
+    #[test]
+    fn map_pixels_can_read_the_image() {
+        let mut assets = Assets::new();
+        let mut data = RgbaImage::new(2, 1);
+        data.put_pixel(1, 0, Rgba([255, 0, 0, 255]));
+        let image = ImageHandle::new(assets.clone(), assets.add(Box::new(Image::from_data(data))));
+
+        let lua = Lua::new();
+        lua.globals().set("image", image.clone()).unwrap();
+        lua.load(r#"
+            image:mapPixels(function(x, y, r, g, b, a)
+                local left = image:getPixel(0, y)
+                return left, image:getWidth() / 2, r, a
+            end)
+        "#)
+        .exec()
+        .unwrap();
+
+        assert_eq!(image.get_pixel(0, 0), (0.0, 1.0, 0.0, 0.0));
+        assert_eq!(image.get_pixel(1, 0), (0.0, 1.0, 1.0, 1.0));
+    }
+}
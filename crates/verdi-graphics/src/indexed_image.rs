@@ -0,0 +1,319 @@
+use std::{path::Path, ops::{Deref, DerefMut}, borrow::Cow};
+use glium::{
+    Display,
+    texture::{Texture2d, RawImage2d, ClientFormat, UncompressedFloatFormat, MipmapsOption},
+    uniforms::{SamplerBehavior, MinifySamplerFilter, MagnifySamplerFilter, SamplerWrapFunction},
+};
+use image::{RgbaImage, ImageError};
+use mlua::{UserData, UserDataMethods};
+use slotmap::Key;
+use verdi_database::{ResourceId, Resource, Assets, Handle};
+
+use crate::{
+    gpu_assets::{GpuAsset, GpuAssetError, PrepareAsset, GpuAssets},
+    image::{Image, ImageHandle},
+    uniform::{UniformHandle, Uniform, UniformValue},
+};
+
+pub type IndexedImageId = ResourceId;
+
+/// An image storing 8-bit indices into a palette image.
+/// Palette entries are read from left to right and top to bottom.
+pub struct IndexedImage {
+    width: u32,
+    height: u32,
+    indices: Vec<u8>,
+    palette: UniformHandle,
+    revision: u32,
+    pub id: IndexedImageId,
+}
+
+impl Resource for IndexedImage {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl IndexedImage {
+    pub fn new(width: u32, height: u32, palette: UniformHandle) -> Self {
+        Self {
+            width,
+            height,
+            indices: vec![0; (width * height) as usize],
+            palette,
+            revision: 0,
+            id: IndexedImageId::null(),
+        }
+    }
+
+    /// Loads an image and converts each color to the index of the closest palette entry.
+    pub fn from_path<P: AsRef<Path>>(path: P, palette_colors: &RgbaImage, palette: UniformHandle) -> Result<Self, ImageError> {
+        let rgba8_img = image::open(path)?.to_rgba8();
+        let (width, height) = rgba8_img.dimensions();
+        let colors: Vec<[u8; 4]> = palette_colors.pixels().map(|pixel| pixel.0).collect();
+
+        let indices = rgba8_img
+            .pixels()
+            .map(|pixel| IndexedImage::closest_index(&colors, pixel.0))
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            indices,
+            palette,
+            revision: 0,
+            id: IndexedImageId::null(),
+        })
+    }
+
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        return (self.width, self.height)
+    }
+
+    pub fn get_palette(&self) -> &UniformHandle {
+        &self.palette
+    }
+
+    /// Incremented each time the indices are modified.
+    pub fn get_revision(&self) -> u32 {
+        self.revision
+    }
+
+    pub fn get_index(&self, x: u32, y: u32) -> Option<u8> {
+        if x < self.width && y < self.height {
+            return Some(self.indices[(y * self.width + x) as usize]);
+        }
+        None
+    }
+
+    pub fn set_index(&mut self, x: u32, y: u32, index: u8) {
+        if x < self.width && y < self.height {
+            self.indices[(y * self.width + x) as usize] = index;
+            self.revision = self.revision.wrapping_add(1);
+        }
+    }
+
+    pub(crate) fn closest_index(colors: &[[u8; 4]], color: [u8; 4]) -> u8 {
+        colors
+            .iter()
+            .take(256)
+            .enumerate()
+            .min_by_key(|(_, entry)| {
+                entry
+                    .iter()
+                    .zip(color.iter())
+                    .map(|(a, b)| (*a as i32 - *b as i32).pow(2))
+                    .sum::<i32>()
+            })
+            .map(|(index, _)| index as u8)
+            .unwrap_or(0)
+    }
+}
+
+impl PrepareAsset for IndexedImage {
+    fn prepare_rendering(&self, ctx: &Display, _assets: &Assets, _gpu_assets: &GpuAssets) -> Result<Box<dyn GpuAsset>, GpuAssetError> {
+        // rows are uploaded bottom to top, as for rgba images
+        let reversed: Vec<u8> = self.indices
+            .chunks(self.width.max(1) as usize)
+            .rev()
+            .flatten()
+            .copied()
+            .collect();
+
+        let raw_image = RawImage2d {
+            data: Cow::Owned(reversed),
+            width: self.width,
+            height: self.height,
+            format: ClientFormat::U8,
+        };
+
+        let gl = Texture2d::with_format(
+            ctx,
+            raw_image,
+            UncompressedFloatFormat::U8,
+            MipmapsOption::NoMipmap
+        ).map_err(|_| GpuAssetError::PreparationFailed)?;
+
+        // indices can't be interpolated
+        let sampler = SamplerBehavior {
+            minify_filter: MinifySamplerFilter::Nearest,
+            magnify_filter: MagnifySamplerFilter::Nearest,
+            wrap_function: (
+                SamplerWrapFunction::Repeat,
+                SamplerWrapFunction::Repeat,
+                SamplerWrapFunction::Repeat,
+            ),
+            .. Default::default()
+        };
+
+        Ok(
+            Box::new(
+                GpuIndexedImage::new(gl, sampler, self.revision)
+            )
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct IndexedImageHandle(Handle);
+
+impl Deref for IndexedImageHandle {
+    type Target = Handle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for IndexedImageHandle {
+    fn deref_mut(&mut self) -> &mut Handle {
+        &mut self.0
+    }
+}
+
+impl IndexedImageHandle {
+    pub fn new(assets: Assets, id: IndexedImageId) -> Self {
+        IndexedImageHandle(assets.new_handle(id))
+    }
+
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        self.get_datas()
+            .get::<IndexedImage>(self.get_id())
+            .map(|image| image.get_dimensions())
+            .unwrap_or((0, 0))
+    }
+
+    pub fn get_index(&self, x: u32, y: u32) -> Option<u8> {
+        self.get_datas()
+            .get::<IndexedImage>(self.get_id())
+            .and_then(|image| image.get_index(x, y))
+    }
+
+    pub fn set_index(&mut self, x: u32, y: u32, index: u8) {
+        let image_id = self.get_id();
+        if let Some(image) = self.get_datas_mut().get_mut::<IndexedImage>(image_id) {
+            image.set_index(x, y, index);
+        }
+    }
+
+    pub fn get_palette(&self) -> Option<ImageHandle> {
+        let palette = self.get_datas()
+            .get::<IndexedImage>(self.get_id())?
+            .get_palette()
+            .clone();
+
+        let datas = self.get_datas();
+        match datas.get::<Uniform>(palette.get_id())?.get_value() {
+            UniformValue::Texture(palette_id) => Some(ImageHandle::new(self.get_assets().clone(), *palette_id)),
+            _ => None,
+        }
+    }
+
+    /// Swaps the palette. Every material using this image is updated.
+    pub fn set_palette(&mut self, palette: &ImageHandle) {
+        let palette_uniform = match self.get_datas().get::<IndexedImage>(self.get_id()) {
+            Some(image) => image.get_palette().clone(),
+            None => return,
+        };
+
+        if let Some(uniform) = self.get_datas_mut().get_mut::<Uniform>(palette_uniform.get_id()) {
+            uniform.value = UniformValue::Texture(palette.get_id());
+        }
+    }
+
+    /// Rotates the palette entries between first and last (included) by the given steps.
+    pub fn cycle_palette(&mut self, first: u32, last: u32, steps: i32) {
+        if let Some(palette) = self.get_palette() {
+            let palette_id = palette.get_id();
+            if let Some(image) = self.get_datas_mut().get_mut::<Image>(palette_id) {
+                image.cycle_colors(first, last, steps);
+            }
+        }
+    }
+}
+
+impl UserData for IndexedImageHandle {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("getWidth", |_, image, ()| {
+            Ok(image.get_dimensions().0)
+        });
+
+        methods.add_method("getHeight", |_, image, ()| {
+            Ok(image.get_dimensions().1)
+        });
+
+        methods.add_method("getIndex", |_, image, (x, y): (u32, u32)| {
+            Ok(image.get_index(x, y))
+        });
+
+        methods.add_method_mut("setIndex", |_, image, (x, y, index): (u32, u32, u8)| {
+            Ok(image.set_index(x, y, index))
+        });
+
+        methods.add_method("getPalette", |_, image, ()| {
+            Ok(image.get_palette())
+        });
+
+        methods.add_method_mut("setPalette", |_, image, palette: ImageHandle| {
+            Ok(image.set_palette(&palette))
+        });
+
+        methods.add_method_mut("cyclePalette", |_, image, (first, last, steps): (u32, u32, Option<i32>)| {
+            Ok(image.cycle_palette(first, last, steps.unwrap_or(1)))
+        });
+    }
+}
+
+pub struct GpuIndexedImage {
+    gl: Texture2d,
+    sampler: SamplerBehavior,
+    revision: u32,
+}
+
+impl GpuIndexedImage {
+    pub fn new(gl: Texture2d, sampler: SamplerBehavior, revision: u32) -> Self {
+        Self {
+            gl,
+            sampler,
+            revision,
+        }
+    }
+
+    pub fn get_gl_texture(&self) -> &Texture2d {
+        &self.gl
+    }
+
+    pub fn get_gl_sampler(&self) -> &SamplerBehavior {
+        &self.sampler
+    }
+
+    /// Revision of the indexed image this texture was created from.
+    pub fn get_revision(&self) -> u32 {
+        self.revision
+    }
+}
+
+impl Resource for GpuIndexedImage {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl GpuAsset for GpuIndexedImage {
+    fn estimated_memory(&self) -> usize {
+        let texels = self.gl.get_width() as usize * self.gl.get_height().unwrap_or(1) as usize;
+        // the mipmaps add a third
+        let texels = if self.gl.get_mipmap_levels() > 1 { texels * 4 / 3 } else { texels };
+        // one byte per index
+        texels
+    }
+}
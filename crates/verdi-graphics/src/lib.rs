@@ -5,10 +5,14 @@ pub mod prelude {
         graphics_chip::GraphicsChip,
         bind_graphics_chip::BindGraphicsChip,
         renderer::Renderer,
+        software_renderer::SoftwareRenderer,
         framebuffer::FramebufferHandle,
         globals::Globals,
         pass::PassHandle,
+        bind_debug_draw::BindDebugDraw,
     };
+    // the images returned by the software renderer
+    pub use image::RgbaImage;
 }
 
 mod graphics_chip;
@@ -18,9 +22,13 @@ mod sprite_vertex;
 mod render_pass;
 mod draw_command;
 mod renderer;
+mod software_renderer;
 mod renderable;
 mod image;
+mod indexed_image;
+mod sampler;
 mod mesh;
+mod mesh_generator;
 mod model;
 mod assets;
 mod gpu_assets;
@@ -42,5 +50,24 @@ mod pipeline;
 mod render_cmds;
 mod render_graph;
 mod sprite;
+mod atlas;
+mod particle_system;
+mod billboard;
+mod sky;
+mod shadow;
+mod debug_draw;
+mod bind_debug_draw;
+mod capture;
+mod render_stats;
+mod budget;
+mod lod;
+mod tilemap;
+mod tiled_loader;
+mod bounds;
+mod terrain;
+mod uv_animation;
+mod morph;
+mod light_bake;
 mod framebuffer;
-mod depth_buffer;
\ No newline at end of file
+mod depth_buffer;
+mod picking;
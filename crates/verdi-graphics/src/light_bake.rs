@@ -0,0 +1,415 @@
+use mlua::{Table, prelude::LuaValue};
+use verdi_database::AssetDatas;
+use verdi_math::{Mat3, Mat4, Vec3, prelude::{LuaVec3, TransformHandle, Transform}};
+
+use crate::{
+    bounds::{Bounds, ray_triangle},
+    mesh::{Mesh, MeshHandle},
+    model::{Model, ModelHandle},
+    vertex::Vertex,
+};
+
+/// A light used when baking the lighting into the vertex colors.
+#[derive(Clone, Copy)]
+pub enum BakeLight {
+    Directional {
+        /// Direction in which the light travels.
+        direction: Vec3,
+        color: Vec3,
+    },
+    Point {
+        position: Vec3,
+        color: Vec3,
+        /// The light fades linearly to nothing at this distance.
+        range: f32,
+    },
+}
+
+impl BakeLight {
+    /// { type = "directional", direction = vec3 } or { type = "point", position = vec3, range = 10 },
+    /// with an optional color = { r, g, b } and intensity.
+    pub fn from_lua_table(table: &Table) -> mlua::Result<Self> {
+        let color = table
+            .get::<_, Option<Vec<f32>>>("color")?
+            .map_or(Vec3::ONE, |color| Vec3::new(
+                color.first().copied().unwrap_or(1.0),
+                color.get(1).copied().unwrap_or(1.0),
+                color.get(2).copied().unwrap_or(1.0)
+            ));
+        let color = color * table.get::<_, Option<f32>>("intensity")?.unwrap_or(1.0);
+
+        match table.get::<_, Option<String>>("type")?.as_deref() {
+            Some("point") => Ok(
+                BakeLight::Point {
+                    position: *table.get::<_, LuaVec3>("position")?,
+                    color,
+                    range: table.get::<_, Option<f32>>("range")?.unwrap_or(10.0),
+                }
+            ),
+            _ => Ok(
+                BakeLight::Directional {
+                    direction: *table.get::<_, LuaVec3>("direction")?,
+                    color,
+                }
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct BakeSettings {
+    pub ambient: Vec3,
+    /// Darkens the ambient light with rays cast around the vertices.
+    pub ambient_occlusion: bool,
+    pub ao_samples: u32,
+    pub ao_distance: f32,
+    /// Casts a ray towards each light.
+    pub shadows: bool,
+}
+
+impl Default for BakeSettings {
+    fn default() -> Self {
+        Self {
+            ambient: Vec3::splat(0.1),
+            ambient_occlusion: false,
+            ao_samples: 16,
+            ao_distance: 1.0,
+            shadows: false,
+        }
+    }
+}
+
+impl BakeSettings {
+    /// { ambient = 0.1 or { r, g, b }, ao = false, aoSamples = 16, aoDistance = 1, shadows = false }
+    pub fn from_lua_table(table: &Table) -> mlua::Result<Self> {
+        let default = BakeSettings::default();
+        let ambient = match table.get::<_, LuaValue>("ambient")? {
+            LuaValue::Number(value) => Vec3::splat(value as f32),
+            LuaValue::Integer(value) => Vec3::splat(value as f32),
+            LuaValue::Table(color) => Vec3::new(color.get(1)?, color.get(2)?, color.get(3)?),
+            _ => default.ambient,
+        };
+
+        Ok(
+            Self {
+                ambient,
+                ambient_occlusion: table.get::<_, Option<bool>>("ao")?.unwrap_or(default.ambient_occlusion),
+                ao_samples: table.get::<_, Option<u32>>("aoSamples")?.unwrap_or(default.ao_samples),
+                ao_distance: table.get::<_, Option<f32>>("aoDistance")?.unwrap_or(default.ao_distance),
+                shadows: table.get::<_, Option<bool>>("shadows")?.unwrap_or(default.shadows),
+            }
+        )
+    }
+}
+
+/// Triangles of a mesh in world space, with their bounds.
+struct OccluderMesh {
+    bounds: Bounds,
+    triangles: Vec<[Vec3; 3]>,
+}
+
+/// The scene geometry blocking the rays.
+#[derive(Default)]
+pub struct Occluders {
+    meshes: Vec<OccluderMesh>,
+}
+
+impl Occluders {
+    pub fn add_mesh(&mut self, mesh: &Mesh, model: Mat4) {
+        let triangles: Vec<[Vec3; 3]> = mesh
+            .get_triangles()
+            .iter()
+            .map(|triangle| triangle.map(|position| model.transform_point3(position)))
+            .collect();
+
+        if let Some(bounds) = Bounds::from_points(triangles.iter().flatten().copied()) {
+            self.meshes.push(OccluderMesh { bounds, triangles });
+        }
+    }
+
+    /// Whether a triangle is hit by the ray before the given distance.
+    pub fn is_occluded(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> bool {
+        self.meshes
+            .iter()
+            .filter(|mesh| mesh.bounds.intersects_ray(origin, direction, max_distance))
+            .any(|mesh| {
+                mesh.triangles
+                    .iter()
+                    .any(|triangle| ray_triangle(origin, direction, triangle).is_some_and(|t| t < max_distance))
+            })
+    }
+}
+
+/// The world matrix of a transform, identity without any.
+pub fn transform_matrix(transform: &Option<TransformHandle>) -> Mat4 {
+    transform
+        .as_ref()
+        .and_then(|transform| transform
+            .get_datas()
+            .get::<Transform>(transform.get_id())
+            .map(|transform| transform.to_matrix())
+        )
+        .unwrap_or(Mat4::IDENTITY)
+}
+
+/// Reads the occluders of the bake options: models, meshes, or { mesh, transform } tables.
+pub fn occluders_from_lua(value: LuaValue) -> mlua::Result<Vec<(MeshHandle, Mat4)>> {
+    let mut occluders = vec![];
+    let table = match value {
+        LuaValue::Table(table) => table,
+        _ => return Ok(occluders),
+    };
+
+    for value in table.sequence_values::<LuaValue>() {
+        match value? {
+            LuaValue::UserData(data) => {
+                if let Ok(model) = data.borrow::<ModelHandle>() {
+                    occluders.extend(LightBaker::model_meshes(&model));
+                }
+                else if let Ok(mesh) = data.borrow::<MeshHandle>() {
+                    occluders.push((mesh.clone(), Mat4::IDENTITY));
+                }
+            },
+            LuaValue::Table(occluder) => {
+                let mesh: MeshHandle = occluder.get(1)?;
+                let transform: Option<TransformHandle> = occluder.get(2)?;
+                occluders.push((mesh, transform_matrix(&transform)));
+            },
+            _ => (),
+        }
+    }
+
+    Ok(occluders)
+}
+
+/// Computes the lighting of static vertices on the CPU.
+/// The result only depends on the inputs: the occlusion rays follow fixed directions.
+pub struct LightBaker {
+    pub lights: Vec<BakeLight>,
+    pub settings: BakeSettings,
+    pub occluders: Occluders,
+}
+
+impl LightBaker {
+    pub fn new(lights: Vec<BakeLight>, settings: BakeSettings, occluders: &[(MeshHandle, Mat4)], datas: &AssetDatas) -> Self {
+        let mut baker = Self {
+            lights,
+            settings,
+            occluders: Occluders::default(),
+        };
+
+        for (mesh, model) in occluders {
+            if let Some(mesh) = datas.get::<Mesh>(mesh.get_id()) {
+                baker.occluders.add_mesh(mesh, *model);
+            }
+        }
+
+        baker
+    }
+
+    /// The meshes of the nodes of a model, with their world matrix.
+    pub fn model_meshes(model: &ModelHandle) -> Vec<(MeshHandle, Mat4)> {
+        match model.get_datas().get::<Model>(model.get_id()) {
+            Some(model) => model.nodes
+                .iter()
+                .filter_map(|node| Some((node.mesh.clone()?, transform_matrix(&Some(node.transform.clone())))))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Multiplies the colors of the vertices by their lighting.
+    pub fn bake(&self, vertices: &mut [Vertex], model: Mat4) {
+        let normal_matrix = Mat3::from_mat4(model.inverse().transpose());
+        let ao_directions = LightBaker::sphere_directions(self.settings.ao_samples);
+
+        for vertex in vertices.iter_mut() {
+            let position = model.transform_point3(Vec3::from(vertex.position));
+            let normal = (normal_matrix * Vec3::from(vertex.normal)).normalize_or_zero();
+
+            let light = self.light_vertex(position, normal, &ao_directions);
+            let color = Vec3::from_slice(&vertex.color[..3]) * light;
+            vertex.color = color.extend(vertex.color[3]).to_array();
+        }
+    }
+
+    fn light_vertex(&self, position: Vec3, normal: Vec3, ao_directions: &[Vec3]) -> Vec3 {
+        // the rays start a bit above the surface so that it doesn't hide itself
+        let origin = position + normal * 0.001;
+
+        let mut light = self.settings.ambient;
+        if self.settings.ambient_occlusion {
+            light *= self.ambient_visibility(origin, normal, ao_directions);
+        }
+
+        for bake_light in self.lights.iter() {
+            let (to_light, distance, color) = match *bake_light {
+                BakeLight::Directional { direction, color } => (-direction.normalize_or_zero(), f32::INFINITY, color),
+                BakeLight::Point { position: light_position, color, range } => {
+                    let offset = light_position - position;
+                    let distance = offset.length();
+                    let attenuation = if range > 0.0 { (1.0 - distance / range).max(0.0) } else { 1.0 };
+                    (offset.normalize_or_zero(), distance, color * attenuation)
+                },
+            };
+
+            // vertices without normal face every light
+            let diffuse = if normal == Vec3::ZERO { 1.0 } else { normal.dot(to_light).max(0.0) };
+            if diffuse == 0.0 || color == Vec3::ZERO {
+                continue;
+            }
+
+            if self.settings.shadows && self.occluders.is_occluded(origin, to_light, distance) {
+                continue;
+            }
+
+            light += color * diffuse;
+        }
+
+        light
+    }
+
+    /// Share of the rays around the normal that escape.
+    fn ambient_visibility(&self, origin: Vec3, normal: Vec3, directions: &[Vec3]) -> f32 {
+        let rays: Vec<Vec3> = directions
+            .iter()
+            .map(|direction| if normal != Vec3::ZERO && direction.dot(normal) < 0.0 { -*direction } else { *direction })
+            .collect();
+
+        if rays.is_empty() {
+            return 1.0;
+        }
+
+        let escaped = rays
+            .iter()
+            .filter(|direction| !self.occluders.is_occluded(origin, **direction, self.settings.ao_distance))
+            .count();
+
+        escaped as f32 / rays.len() as f32
+    }
+
+    /// Directions evenly spread on the sphere, along a Fibonacci spiral.
+    fn sphere_directions(count: u32) -> Vec<Vec3> {
+        let golden_angle = std::f32::consts::PI * (3.0 - 5.0_f32.sqrt());
+        (0..count)
+            .map(|i| {
+                let y = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+                let radius = (1.0 - y * y).max(0.0).sqrt();
+                let theta = golden_angle * i as f32;
+                Vec3::new(theta.cos() * radius, y, theta.sin() * radius)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use slotmap::Key;
+    use verdi_database::ResourceId;
+
+    use crate::{mesh::PrimitiveType, mesh_generator::MeshGenerator};
+
+    use super::*;
+
+    /// A horizontal quad between two corners, at the height of the first.
+    fn quad(min: Vec3, max: Vec3) -> Mesh {
+        let vertices = [(min.x, min.z), (max.x, min.z), (max.x, max.z), (min.x, max.z)]
+            .map(|(x, z)| Vertex { position: [x, min.y, z], ..Default::default() })
+            .to_vec();
+
+        Mesh::new(vertices, Some(vec![0, 2, 1, 0, 3, 2]), PrimitiveType::Triangles, ResourceId::null())
+    }
+
+    fn baker(lights: Vec<BakeLight>, settings: BakeSettings, occluders: &[Mesh]) -> LightBaker {
+        let mut baker = LightBaker { lights, settings, occluders: Occluders::default() };
+        for occluder in occluders {
+            baker.occluders.add_mesh(occluder, Mat4::IDENTITY);
+        }
+
+        baker
+    }
+
+    /// Bakes a plane of 3x3 vertices at the origin, facing up.
+    fn bake_plane(baker: &LightBaker) -> Vec<Vertex> {
+        let (mut vertices, _) = MeshGenerator::plane(1);
+        baker.bake(&mut vertices, Mat4::IDENTITY);
+        vertices
+    }
+
+    fn assert_light(vertex: &Vertex, expected: f32) {
+        for channel in &vertex.color[..3] {
+            assert!((channel - expected).abs() < 1e-4, "vertex at {:?} has color {:?}, expected {}", vertex.position, vertex.color, expected);
+        }
+        assert_eq!(vertex.color[3], 1.0);
+    }
+
+    fn sun(direction: Vec3) -> BakeLight {
+        BakeLight::Directional { direction, color: Vec3::ONE }
+    }
+
+    #[test]
+    fn directional_light_follows_the_angle() {
+        let settings = BakeSettings::default();
+
+        for vertex in bake_plane(&baker(vec![sun(Vec3::NEG_Y)], settings, &[])) {
+            assert_light(&vertex, 1.1);
+        }
+
+        // at 60 degrees from the normal, the light is halved
+        let slanted = Vec3::new(60.0_f32.to_radians().sin(), -60.0_f32.to_radians().cos(), 0.0);
+        for vertex in bake_plane(&baker(vec![sun(slanted)], settings, &[])) {
+            assert_light(&vertex, 0.6);
+        }
+
+        // from below, only the ambient light remains
+        for vertex in bake_plane(&baker(vec![sun(Vec3::Y)], settings, &[])) {
+            assert_light(&vertex, 0.1);
+        }
+    }
+
+    #[test]
+    fn occluder_casts_a_shadow() {
+        let occluder = quad(Vec3::new(-1.0, 1.0, -1.0), Vec3::new(-0.25, 1.0, 1.0));
+        let settings = BakeSettings { shadows: true, ..Default::default() };
+
+        for vertex in bake_plane(&baker(vec![sun(Vec3::NEG_Y)], settings, std::slice::from_ref(&occluder))) {
+            let expected = if vertex.position[0] < -0.25 { 0.1 } else { 1.1 };
+            assert_light(&vertex, expected);
+        }
+
+        // without shadows, the occluder is ignored
+        for vertex in bake_plane(&baker(vec![sun(Vec3::NEG_Y)], BakeSettings::default(), &[occluder])) {
+            assert_light(&vertex, 1.1);
+        }
+    }
+
+    #[test]
+    fn ambient_occlusion_darkens_covered_vertices() {
+        let settings = BakeSettings {
+            ambient: Vec3::ONE,
+            ambient_occlusion: true,
+            ao_samples: 64,
+            ao_distance: 1.0,
+            ..Default::default()
+        };
+
+        for vertex in bake_plane(&baker(Vec::new(), settings, &[])) {
+            assert_light(&vertex, 1.0);
+        }
+
+        // a ceiling beyond the occlusion distance doesn't count
+        let far = quad(Vec3::new(-10.0, 2.0, -10.0), Vec3::new(10.0, 2.0, 10.0));
+        for vertex in bake_plane(&baker(Vec::new(), settings, &[far])) {
+            assert_light(&vertex, 1.0);
+        }
+
+        // a close ceiling blocks the rays going up steeply, the same way for every vertex
+        let close = quad(Vec3::new(-10.0, 0.5, -10.0), Vec3::new(10.0, 0.5, 10.0));
+        let vertices = bake_plane(&baker(Vec::new(), settings, &[close]));
+        let visibility = vertices[0].color[0];
+        assert!(visibility > 0.0 && visibility < 1.0, "visibility {}", visibility);
+        for vertex in vertices.iter() {
+            assert_light(vertex, visibility);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use crate::mesh::MeshHandle;
+
+/// Distance between two levels imported without any other information.
+pub const DEFAULT_LOD_DISTANCE: f32 = 16.0;
+
+/// A lower detail version of a mesh, used from a camera distance.
+#[derive(Clone)]
+pub struct Lod {
+    pub mesh: MeshHandle,
+    pub distance: f32,
+}
+
+/// The levels of detail of a mesh, the mesh itself being the most detailed one.
+#[derive(Clone)]
+pub struct LodGroup {
+    /// Sorted by distance.
+    levels: Vec<Lod>,
+    /// Width of the distance band where two levels are cross-faded with a dither pattern. 0 disables it.
+    pub fade: f32,
+    /// The mesh isn't drawn from this distance.
+    pub cull_distance: Option<f32>,
+}
+
+/// A level to draw. A positive fade keeps part of the pixels of the outgoing level,
+/// a negative one the complementary part for the incoming level.
+pub struct LodDraw {
+    /// None for the mesh itself.
+    pub mesh: Option<MeshHandle>,
+    pub fade: f32,
+}
+
+impl LodGroup {
+    pub fn new() -> Self {
+        Self {
+            levels: Vec::new(),
+            fade: 0.0,
+            cull_distance: None,
+        }
+    }
+
+    /// Whether the mesh is always drawn at full detail.
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty() && self.cull_distance.is_none()
+    }
+
+    pub fn add_level(&mut self, mesh: MeshHandle, distance: f32) {
+        self.levels.push(Lod { mesh, distance });
+        self.levels.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    }
+
+    pub fn get_levels(&self) -> &Vec<Lod> {
+        &self.levels
+    }
+
+    pub fn clear(&mut self) {
+        self.levels.clear();
+    }
+
+    /// Sets the distances of the levels, from the first lower detail one.
+    pub fn set_distances(&mut self, distances: &[f32]) {
+        for (level, distance) in self.levels.iter_mut().zip(distances.iter()) {
+            level.distance = *distance;
+        }
+        self.levels.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    }
+
+    /// The levels to draw at a camera distance. Nothing is drawn when the mesh is culled.
+    pub fn select(&self, distance: f32) -> Vec<LodDraw> {
+        if self.cull_distance.map_or(false, |cull_distance| distance >= cull_distance) {
+            return Vec::new();
+        }
+
+        let level = self.levels
+            .iter()
+            .take_while(|lod| distance >= lod.distance)
+            .count();
+        let mesh = |level: usize| if level == 0 { None } else { Some(self.levels[level - 1].mesh.clone()) };
+
+        if let Some(next) = self.levels.get(level) {
+            let fade_start = next.distance - self.fade;
+            if self.fade > 0.0 && distance > fade_start {
+                let fade = (distance - fade_start) / self.fade;
+                return vec![
+                    LodDraw { mesh: mesh(level), fade },
+                    LodDraw { mesh: Some(next.mesh.clone()), fade: -fade },
+                ];
+            }
+        }
+
+        vec![LodDraw { mesh: mesh(level), fade: 0.0 }]
+    }
+}
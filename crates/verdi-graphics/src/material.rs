@@ -4,21 +4,78 @@ use mlua::{UserData, UserDataMethods, prelude::LuaValue};
 use slotmap::Key;
 use verdi_database::{ResourceId, Resource, Assets, Handle};
 
+use verdi_math::{Vec2, Vec4};
+
 use crate::{
-    program::ProgramHandle, 
+    program::{Program, ProgramHandle}, 
     globals::GlobalUniforms, 
     uniform::{Uniform, UniformHandle, UniformValue},
+    image::{ImageHandle, ImageId},
+    atlas::AtlasFrame,
+    uv_animation::UvAnimation,
 };
 
 const MAX_UNIFORMS: usize = 64;
 
+/// The uniforms which can sample the targets of the render graph, the names of the uniforms being static.
+pub const TARGET_UNIFORMS: [&str; 6] = [
+    "u_texture",
+    "u_depth_texture",
+    "u_normal_texture",
+    "u_id_texture",
+    "u_texture0",
+    "u_texture1",
+];
+
 pub type MaterialId = ResourceId;
 
+/// How the fragments of a material are combined with the target.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum AlphaMode {
+    /// The alpha is ignored.
+    Opaque,
+    /// The fragments with an alpha under the threshold are discarded, the others are opaque.
+    Cutout(f32),
+    /// Blended over the opaque geometry, after it and from back to front, without writing the depth.
+    Blend,
+}
+
+impl AlphaMode {
+    pub fn from_name(name: &str, cutoff: Option<f32>) -> Option<Self> {
+        match name {
+            "opaque" => Some(AlphaMode::Opaque),
+            "cutout" => Some(AlphaMode::Cutout(cutoff.unwrap_or(0.5))),
+            "blend" => Some(AlphaMode::Blend),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlphaMode::Opaque => "opaque",
+            AlphaMode::Cutout(_) => "cutout",
+            AlphaMode::Blend => "blend",
+        }
+    }
+
+    /// The alpha under which the fragments are discarded, 0 keeping them all.
+    pub fn get_cutoff(&self) -> f32 {
+        match self {
+            AlphaMode::Cutout(cutoff) => *cutoff,
+            _ => 0.0,
+        }
+    }
+}
+
 /// A material defines the program and uniforms to use when rendering a mesh.
 #[derive(Clone)]
 pub struct Material {
     pub program: ProgramHandle,
     uniforms: Vec<Option<(&'static str, UniformHandle)>>,
+    pub depth_test: bool,
+    pub depth_write: bool,
+    pub alpha_mode: AlphaMode,
+    pub uv_animation: Option<UvAnimation>,
     pub id: MaterialId,
 }
 
@@ -40,10 +97,18 @@ impl Material {
         uniforms[1] = Some(("u_view", global_uniforms.view_matrix.clone()));
         uniforms[2] = Some(("u_projection", global_uniforms.projection_matrix.clone()));
         uniforms[3] = Some(("u_resolution", global_uniforms.resolution.clone()));
+        uniforms[4] = Some(("u_fog_color", global_uniforms.fog_color.clone()));
+        uniforms[5] = Some(("u_lod_fade", global_uniforms.lod_fade.clone()));
+        uniforms[6] = Some(("u_time", global_uniforms.time.clone()));
+        uniforms[7] = Some(("u_alpha_cutoff", global_uniforms.alpha_cutoff.clone()));
 
         Self {
             program,
             uniforms,
+            depth_test: true,
+            depth_write: true,
+            alpha_mode: AlphaMode::Opaque,
+            uv_animation: None,
             id: MaterialId::null(),
         }
     }
@@ -58,9 +123,38 @@ impl Material {
         self
     }
 
+    pub fn find_uniform(&self, name: &str) -> Option<&UniformHandle> {
+        self.uniforms
+            .iter()
+            .flatten()
+            .find(|(uniform_name, _)| *uniform_name == name)
+            .map(|(_, uniform)| uniform)
+    }
+
+    /// Replaces the uniform with the same name, or adds it.
+    pub fn set_uniform(&mut self, name: &'static str, uniform_handle: UniformHandle) -> &mut Self {
+        let existing = self.uniforms
+            .iter_mut()
+            .flatten()
+            .find(|(uniform_name, _)| *uniform_name == name);
+
+        match existing {
+            Some(uniform) => uniform.1 = uniform_handle,
+            None => {
+                self.add_uniform(name, uniform_handle);
+            },
+        }
+        self
+    }
+
     pub fn get_uniforms(&self) -> &Vec<Option<(&'static str, UniformHandle)>> {
         &self.uniforms
     }
+
+    /// The blended materials never write the depth.
+    pub fn writes_depth(&self) -> bool {
+        self.depth_write && self.alpha_mode != AlphaMode::Blend
+    }
 }
 
 pub struct GlUniformValues<'a> {
@@ -83,6 +177,7 @@ impl<'material> glium::uniforms::Uniforms for GlUniformValues<'material> {
     }
 }
 
+#[derive(Clone)]
 pub struct MaterialHandle(Handle);
 
 impl Deref for MaterialHandle {
@@ -103,10 +198,244 @@ impl MaterialHandle {
     pub fn new(assets: Assets, id: MaterialId) -> Self{
         MaterialHandle(assets.new_handle(id))
     }
+
+    /// The uv animation of the material, added with its uniforms the first time.
+    fn get_uv_animation(&mut self) -> Option<UvAnimation> {
+        let material_id = self.get_id();
+        let uv_animation = self.get_datas()
+            .get::<Material>(material_id)?
+            .uv_animation
+            .clone();
+
+        match uv_animation {
+            Some(uv_animation) => Some(uv_animation),
+            None => {
+                let uv_animation = UvAnimation::new(self.get_assets_mut());
+                let mut datas = self.get_datas_mut();
+                let material = datas.get_mut::<Material>(material_id)?;
+                for (name, uniform) in uv_animation.get_uniforms() {
+                    material.set_uniform(name, uniform);
+                }
+                material.uv_animation = Some(uv_animation.clone());
+                Some(uv_animation)
+            },
+        }
+    }
+
+    fn set_uniform_value(&mut self, uniform: &UniformHandle, value: UniformValue) {
+        if let Some(uniform) = self.get_datas_mut().get_mut::<Uniform>(uniform.get_id()) {
+            uniform.value = value;
+        }
+    }
+
+    /// Replaces the uniform with a new one, the previous one may be shared with other materials.
+    fn set_new_uniform(&mut self, name: &'static str, value: UniformValue) {
+        let uniform = UniformHandle::new(
+            self.get_assets().clone(),
+            self.get_assets_mut().add(Box::new(Uniform::new(value)))
+        );
+
+        let material_id = self.get_id();
+        if let Some(material) = self.get_datas_mut().get_mut::<Material>(material_id) {
+            material.set_uniform(name, uniform);
+        }
+    }
+
+    pub fn set_texture(&mut self, image: &ImageHandle) {
+        self.set_new_uniform("u_texture", UniformValue::Texture(image.get_id()));
+    }
+
+    /// Draws with another program, the uniforms it needs and the material lacks get their default.
+    pub fn set_program(&mut self, program: &ProgramHandle) {
+        let defaults = program
+            .get_datas()
+            .get::<Program>(program.get_id())
+            .map(|program| program.defaults.clone())
+            .unwrap_or_default();
+
+        let material_id = self.get_id();
+        if let Some(material) = self.get_datas_mut().get_mut::<Material>(material_id) {
+            material.program = program.clone();
+            for (name, uniform) in defaults {
+                if material.find_uniform(name).is_none() {
+                    material.add_uniform(name, uniform);
+                }
+            }
+        }
+    }
+
+    pub fn set_alpha_mode(&mut self, alpha_mode: AlphaMode) {
+        let material_id = self.get_id();
+        if let Some(material) = self.get_datas_mut().get_mut::<Material>(material_id) {
+            material.alpha_mode = alpha_mode;
+        }
+    }
+
+    pub fn get_alpha_mode(&self) -> Option<AlphaMode> {
+        self.get_datas()
+            .get::<Material>(self.get_id())
+            .map(|material| material.alpha_mode)
+    }
+
+    /// The light intensity of the toon program, from left to right.
+    pub fn set_ramp(&mut self, image: &ImageHandle) {
+        self.set_new_uniform("u_ramp", UniformValue::Texture(image.get_id()));
+    }
+
+    /// The lit sphere of the matcap program.
+    pub fn set_matcap(&mut self, image: &ImageHandle) {
+        self.set_new_uniform("u_matcap", UniformValue::Texture(image.get_id()));
+    }
+
+    /// The hull of the outline program, its width being in world units.
+    /// Without a color, the current one is kept.
+    pub fn set_outline(&mut self, width: f32, color: Option<Vec4>) {
+        self.set_new_uniform("u_outline_width", UniformValue::Float(width));
+        if let Some(color) = color {
+            self.set_new_uniform("u_outline_color", UniformValue::Vec4(color));
+        }
+    }
+
+    /// Samples a target of the render graph, which sets the returned uniform when it allocates the target.
+    pub fn set_render_target_texture(&mut self, name: &'static str) -> UniformHandle {
+        let material_id = self.get_id();
+        // the uniform is kept from one frame to the next
+        if let Some(uniform) = self.get_datas().get::<Material>(material_id).and_then(|material| material.find_uniform(name).cloned()) {
+            return uniform;
+        }
+
+        let uniform = UniformHandle::new(
+            self.get_assets().clone(),
+            self.get_assets_mut().add(Box::new(Uniform::new(UniformValue::Texture(ImageId::null()))))
+        );
+
+        if let Some(material) = self.get_datas_mut().get_mut::<Material>(material_id) {
+            material.set_uniform(name, uniform.clone());
+        }
+        uniform
+    }
+
+    pub fn set_uv_offset(&mut self, offset: Vec2) {
+        if let Some(uv_animation) = self.get_uv_animation() {
+            self.set_uniform_value(&uv_animation.offset, UniformValue::Vec2(offset));
+        }
+    }
+
+    pub fn set_uv_scale(&mut self, scale: Vec2) {
+        if let Some(uv_animation) = self.get_uv_animation() {
+            self.set_uniform_value(&uv_animation.scale, UniformValue::Vec2(scale));
+        }
+    }
+
+    /// Rotation of the texture around its center, in radians.
+    pub fn set_uv_rotation(&mut self, rotation: f32) {
+        if let Some(uv_animation) = self.get_uv_animation() {
+            self.set_uniform_value(&uv_animation.rotation, UniformValue::Float(rotation));
+        }
+    }
+
+    /// Speed of the texture scrolling, in uvs per second.
+    pub fn set_uv_scroll(&mut self, speed: Vec2) {
+        if let Some(uv_animation) = self.get_uv_animation() {
+            self.set_uniform_value(&uv_animation.scroll, UniformValue::Vec2(speed));
+        }
+    }
+
+    /// Plays the frames in loop at the given rate, drawing with the image of the first one.
+    /// Without any frame, the whole texture is shown again.
+    pub fn set_flipbook(&mut self, frames: &[AtlasFrame], frame_rate: f32) {
+        if let Some(first) = frames.first() {
+            self.set_texture(&first.image);
+        }
+
+        let uv_animation = match self.get_uv_animation() {
+            Some(uv_animation) => uv_animation,
+            None => return,
+        };
+        self.set_uniform_value(&uv_animation.frame, UniformValue::Vec4(Vec4::ZERO));
+
+        let material_id = self.get_id();
+        if let Some(uv_animation) = self.get_datas_mut()
+            .get_mut::<Material>(material_id)
+            .and_then(|material| material.uv_animation.as_mut()) {
+            uv_animation.frames = frames.iter().map(|frame| frame.uv).collect();
+            uv_animation.frame_rate = frame_rate;
+        }
+    }
+
+    /// Enables or disables the uv animation, keeping its settings.
+    pub fn set_uv_animated(&mut self, animated: bool) {
+        if let Some(uv_animation) = self.get_uv_animation() {
+            self.set_uniform_value(&uv_animation.animated, UniformValue::Bool(animated));
+        }
+    }
 }
 
 impl UserData for MaterialHandle {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("setTexture", |_, material, image: ImageHandle| {
+            Ok(material.set_texture(&image))
+        });
+
+        // "opaque", "cutout" with its threshold, or "blend"
+        methods.add_method_mut("setAlphaMode", |_, material, (mode, cutoff): (String, Option<f32>)| {
+            match AlphaMode::from_name(&mode, cutoff) {
+                Some(alpha_mode) => Ok(material.set_alpha_mode(alpha_mode)),
+                None => Err(mlua::Error::RuntimeError(format!("Unknown alpha mode {}", mode))),
+            }
+        });
+
+        methods.add_method("getAlphaMode", |_, material, ()| {
+            let alpha_mode = material.get_alpha_mode().unwrap_or(AlphaMode::Opaque);
+            Ok((alpha_mode.name(), alpha_mode.get_cutoff()))
+        });
+
+        // the built-in programs are found with graphics.getProgram
+        methods.add_method_mut("setProgram", |_, material, program: ProgramHandle| {
+            Ok(material.set_program(&program))
+        });
+
+        methods.add_method_mut("setRamp", |_, material, image: ImageHandle| {
+            Ok(material.set_ramp(&image))
+        });
+
+        methods.add_method_mut("setMatcap", |_, material, image: ImageHandle| {
+            Ok(material.set_matcap(&image))
+        });
+
+        methods.add_method_mut("setOutline", |_, material, (width, r, g, b, a): (f32, Option<f32>, Option<f32>, Option<f32>, Option<f32>)| {
+            let color = r.map(|r| Vec4::new(r, g.unwrap_or(r), b.unwrap_or(r), a.unwrap_or(1.0)));
+            Ok(material.set_outline(width, color))
+        });
+
+        methods.add_method_mut("setUvOffset", |_, material, (u, v): (f32, f32)| {
+            Ok(material.set_uv_offset(Vec2::new(u, v)))
+        });
+
+        methods.add_method_mut("setUvScale", |_, material, (u, v): (f32, Option<f32>)| {
+            Ok(material.set_uv_scale(Vec2::new(u, v.unwrap_or(u))))
+        });
+
+        methods.add_method_mut("setUvRotation", |_, material, rotation: f32| {
+            Ok(material.set_uv_rotation(rotation))
+        });
+
+        methods.add_method_mut("setUvScroll", |_, material, (u, v): (f32, f32)| {
+            Ok(material.set_uv_scroll(Vec2::new(u, v)))
+        });
+
+        methods.add_method_mut("setFlipbook", |_, material, (frames, frame_rate): (Vec<LuaValue>, Option<f32>)| {
+            let frames: Vec<AtlasFrame> = frames
+                .iter()
+                .filter_map(AtlasFrame::from_lua_value)
+                .collect();
+            Ok(material.set_flipbook(&frames, frame_rate.unwrap_or(10.0)))
+        });
+
+        methods.add_method_mut("setUvAnimated", |_, material, animated: bool| {
+            Ok(material.set_uv_animated(animated))
+        });
+
         methods.add_method_mut("addUniform", |_, material, (name, value): (String, LuaValue)| {
             let mut uniform = None;
             {
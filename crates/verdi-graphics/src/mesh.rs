@@ -5,11 +5,18 @@ use mlua::{UserData, UserDataMethods, Table};
 use slotmap::Key;
 use verdi_database::{ResourceId, Resource, Assets, Handle};
 
+use verdi_math::{Mat4, Vec3, prelude::TransformHandle};
+
 use crate::{
     vertex::Vertex, 
-    material::MaterialId, 
+    material::{Material, MaterialId, MaterialHandle}, 
+    uniform::{Uniform, UniformHandle, UniformValue},
+    light_bake::{LightBaker, BakeLight, BakeSettings, transform_matrix, occluders_from_lua},
     gpu_mesh::GpuMesh, 
     gpu_assets::{GpuAsset, GpuAssetError, PrepareAsset, GpuAssets}, 
+    lod::LodGroup,
+    bounds::Bounds,
+    morph::MorphTarget,
 };
 
 use thiserror::Error;
@@ -58,6 +65,13 @@ pub struct Mesh {
     pub indices: Option<Vec<u32>>,
     pub primitive_type: PrimitiveType,
     pub material: MaterialId, // toutes les instances d'un même mesh devront utiliser un même matériau
+    pub lods: LodGroup,
+    /// Meshes with bounds are skipped when they are out of the view.
+    pub bounds: Option<Bounds>,
+    pub morph_targets: Vec<MorphTarget>,
+    /// Default weights of the morph targets, copied by the nodes drawing the mesh.
+    pub morph_weights: Vec<f32>,
+    revision: u32,
     pub id: MeshId,
 }
 
@@ -83,9 +97,43 @@ impl Mesh {
             indices,
             primitive_type,
             material,
+            lods: LodGroup::new(),
+            bounds: None,
+            morph_targets: Vec::new(),
+            morph_weights: Vec::new(),
+            revision: 0,
             id: MeshId::null(),
         }
     }
+
+    /// Must be called after modifying the vertices or the indices so that they are uploaded again to the GPU.
+    pub fn set_modified(&mut self) {
+        self.revision = self.revision.wrapping_add(1);
+    }
+
+    /// Incremented each time the geometry is modified.
+    pub fn get_revision(&self) -> u32 {
+        self.revision
+    }
+
+    /// The positions of the triangles in the mesh space, none for points and lines.
+    pub fn get_triangles(&self) -> Vec<[Vec3; 3]> {
+        if self.primitive_type != PrimitiveType::Triangles {
+            return Vec::new();
+        }
+
+        let position = |index: u32| self.vertices.get(index as usize).map(|vertex| Vec3::from(vertex.position));
+        let triangle = |indices: &[u32]| Some([position(indices[0])?, position(indices[1])?, position(indices[2])?]);
+
+        match &self.indices {
+            Some(indices) => indices.chunks_exact(3).filter_map(triangle).collect(),
+            None => (0..self.vertices.len() as u32)
+                .collect::<Vec<u32>>()
+                .chunks_exact(3)
+                .filter_map(triangle)
+                .collect(),
+        }
+    }
 }
 
 impl PrepareAsset for Mesh {
@@ -104,14 +152,14 @@ impl PrepareAsset for Mesh {
 
             return Ok(
                 Box::new(
-                    GpuMesh::new(vertex_buffer, Some(index_buffer))
+                    GpuMesh::new(vertex_buffer, Some(index_buffer), self.revision)
                 )
             );
         }
 
         Ok(
             Box::new(
-                GpuMesh::new(vertex_buffer, None)
+                GpuMesh::new(vertex_buffer, None, self.revision)
             )
         )
     }
@@ -156,6 +204,7 @@ impl MeshHandle {
                     }
                 }
             }
+            mesh.set_modified();
         }
     }
 
@@ -175,6 +224,15 @@ impl MeshHandle {
                     }
                 }
             }
+            mesh.set_modified();
+        }
+    }
+
+    pub fn set_material(&mut self, material: &MaterialHandle) {
+        let mesh_id = self.get_id();
+        if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id)
+        {
+            mesh.material = material.get_id();
         }
     }
 
@@ -183,6 +241,83 @@ impl MeshHandle {
         if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id)
         {
             mesh.primitive_type = primitive_type;
+            mesh.set_modified();
+        }
+    }
+}
+
+impl MeshHandle {
+    /// Uses a lower detail mesh from a camera distance.
+    pub fn add_lod(&mut self, lod: &MeshHandle, distance: f32) {
+        let mesh_id = self.get_id();
+        if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+            mesh.lods.add_level(lod.clone(), distance);
+        }
+    }
+
+    pub fn clear_lods(&mut self) {
+        let mesh_id = self.get_id();
+        if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+            mesh.lods.clear();
+        }
+    }
+
+    pub fn set_lod_distances(&mut self, distances: &[f32]) {
+        let mesh_id = self.get_id();
+        if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+            mesh.lods.set_distances(distances);
+        }
+    }
+
+    pub fn set_lod_fade(&mut self, fade: f32) {
+        let mesh_id = self.get_id();
+        if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+            mesh.lods.fade = fade.max(0.0);
+        }
+    }
+
+    pub fn set_cull_distance(&mut self, distance: Option<f32>) {
+        let mesh_id = self.get_id();
+        if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+            mesh.lods.cull_distance = distance;
+        }
+    }
+
+    /// Multiplies the vertex colors by the lighting baked with the mesh placed by the matrix,
+    /// then draws the mesh without the dynamic lighting.
+    /// Baking again multiplies the colors again.
+    pub fn bake_lighting(&mut self, baker: &LightBaker, model: Mat4) {
+        let mesh_id = self.get_id();
+        if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+            baker.bake(&mut mesh.vertices, model);
+            mesh.set_modified();
+        }
+
+        self.disable_lighting();
+    }
+
+    /// Gives the mesh its own copy of its material, ignoring the lighting of the passes.
+    fn disable_lighting(&mut self) {
+        let mesh_id = self.get_id();
+        let material = match self.get_datas().get::<Mesh>(mesh_id) {
+            Some(mesh) => self.get_datas().get::<Material>(mesh.material).cloned(),
+            None => return,
+        };
+
+        let mut material = match material {
+            Some(material) if material.find_uniform("u_enable_lighting").is_some() => material,
+            _ => return,
+        };
+
+        let unlit = UniformHandle::new(
+            self.get_assets().clone(),
+            self.get_assets_mut().add(Box::new(Uniform::new(UniformValue::Bool(false))))
+        );
+        material.set_uniform("u_enable_lighting", unlit);
+        let material_id = self.get_assets_mut().add(Box::new(material));
+
+        if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+            mesh.material = material_id;
         }
     }
 }
@@ -197,8 +332,60 @@ impl UserData for MeshHandle {
             Ok(mesh.set_indices(indices))
         });
 
+        methods.add_method_mut("setMaterial", |_, mesh, material: MaterialHandle| {
+            Ok(mesh.set_material(&material))
+        });
+
+        methods.add_method("getMaterial", |_, mesh, ()| {
+            Ok(mesh.get_datas()
+                .get::<Mesh>(mesh.get_id())
+                .map(|data| MaterialHandle::new(mesh.get_assets().clone(), data.material))
+            )
+        });
+
         methods.add_method_mut("setPrimitiveType", |_, mesh, primitive_string: String| {
             Ok(mesh.set_primitive_type(PrimitiveType::from(primitive_string)))
         });
+
+        methods.add_method_mut("addLod", |_, mesh, (lod, distance): (MeshHandle, f32)| {
+            Ok(mesh.add_lod(&lod, distance))
+        });
+
+        methods.add_method_mut("clearLods", |_, mesh, ()| {
+            Ok(mesh.clear_lods())
+        });
+
+        methods.add_method_mut("setLodDistances", |_, mesh, distances: Vec<f32>| {
+            Ok(mesh.set_lod_distances(&distances))
+        });
+
+        methods.add_method_mut("setLodFade", |_, mesh, fade: f32| {
+            Ok(mesh.set_lod_fade(fade))
+        });
+
+        methods.add_method_mut("setCullDistance", |_, mesh, distance: Option<f32>| {
+            Ok(mesh.set_cull_distance(distance))
+        });
+
+        methods.add_method_mut("bakeLighting", |_, mesh, (lights, options): (Vec<Table>, Option<Table>)| {
+            let lights = lights
+                .iter()
+                .map(BakeLight::from_lua_table)
+                .collect::<mlua::Result<Vec<BakeLight>>>()?;
+
+            let (settings, transform, mut occluders) = match &options {
+                Some(options) => (
+                    BakeSettings::from_lua_table(options)?,
+                    transform_matrix(&options.get::<_, Option<TransformHandle>>("transform")?),
+                    occluders_from_lua(options.get("occluders")?)?,
+                ),
+                None => (BakeSettings::default(), Mat4::IDENTITY, Vec::new()),
+            };
+            // the mesh shadows itself
+            occluders.push((mesh.clone(), transform));
+
+            let baker = LightBaker::new(lights, settings, &occluders, &mesh.get_datas());
+            Ok(mesh.bake_lighting(&baker, transform))
+        });
     }
 }
\ No newline at end of file
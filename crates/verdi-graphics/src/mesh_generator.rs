@@ -0,0 +1,283 @@
+use std::f32::consts::PI;
+
+use image::RgbaImage;
+use verdi_math::{Vec2, Vec3};
+
+use crate::vertex::Vertex;
+
+/// Procedural generation of common shapes.
+/// Every shape fits in a unit cube centered on the origin, with normals and uvs.
+pub struct MeshGenerator;
+
+impl MeshGenerator {
+    pub fn cube() -> (Vec<Vertex>, Vec<u32>) {
+        // (normal, u axis, v axis) for each face
+        let faces = [
+            (Vec3::X, Vec3::NEG_Z, Vec3::NEG_Y),
+            (Vec3::NEG_X, Vec3::Z, Vec3::NEG_Y),
+            (Vec3::Y, Vec3::X, Vec3::Z),
+            (Vec3::NEG_Y, Vec3::X, Vec3::NEG_Z),
+            (Vec3::Z, Vec3::X, Vec3::NEG_Y),
+            (Vec3::NEG_Z, Vec3::NEG_X, Vec3::NEG_Y),
+        ];
+
+        let mut vertices = Vec::with_capacity(24);
+        let mut indices = Vec::with_capacity(36);
+
+        for (normal, u_axis, v_axis) in faces {
+            let first = vertices.len() as u32;
+            for (u, v) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                let position = 0.5 * normal + (u - 0.5) * u_axis + (v - 0.5) * v_axis;
+                vertices.push(MeshGenerator::vertex(position, normal, Vec2::new(u, v)));
+            }
+            indices.extend_from_slice(&[first, first + 2, first + 1, first, first + 3, first + 2]);
+        }
+
+        (vertices, indices)
+    }
+
+    /// A plane in the XZ plane, facing up.
+    pub fn plane(subdivisions: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let cells = subdivisions + 1;
+
+        MeshGenerator::grid(cells, cells, |u, v| {
+            Vec3::new(u - 0.5, 0.0, v - 0.5)
+        })
+    }
+
+    pub fn sphere(rings: u32, segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let rings = rings.max(2);
+        let segments = segments.max(3);
+
+        let (mut vertices, mut indices) = MeshGenerator::grid(segments, rings, |u, v| {
+            let theta = u * 2.0 * PI;
+            let phi = v * PI;
+            0.5 * Vec3::new(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin())
+        });
+
+        // the rows go downwards, which winds the grid inwards
+        MeshGenerator::flip_winding(&mut indices);
+
+        // on a sphere, the normal is the normalized position
+        for vertex in vertices.iter_mut() {
+            vertex.normal = Vec3::from(vertex.position).normalize_or_zero().to_array();
+        }
+
+        (vertices, indices)
+    }
+
+    pub fn cylinder(segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+        MeshGenerator::revolution(segments, 0.5, 0.5)
+    }
+
+    pub fn cone(segments: u32) -> (Vec<Vertex>, Vec<u32>) {
+        MeshGenerator::revolution(segments, 0.5, 0.0)
+    }
+
+    /// A torus lying in the XZ plane. `thickness` is the radius of the tube.
+    pub fn torus(rings: u32, segments: u32, thickness: f32) -> (Vec<Vertex>, Vec<u32>) {
+        let rings = rings.max(3);
+        let segments = segments.max(3);
+        let radius = 0.5 - thickness;
+
+        let (mut vertices, indices) = MeshGenerator::grid(segments, rings, |u, v| {
+            let theta = u * 2.0 * PI;
+            let phi = v * 2.0 * PI;
+            let distance = radius + thickness * phi.cos();
+            Vec3::new(distance * theta.cos(), thickness * phi.sin(), distance * theta.sin())
+        });
+
+        for vertex in vertices.iter_mut() {
+            let position = Vec3::from(vertex.position);
+            let center = radius * Vec3::new(position.x, 0.0, position.z).normalize_or_zero();
+            vertex.normal = (position - center).normalize_or_zero().to_array();
+        }
+
+        (vertices, indices)
+    }
+
+    /// A grid with one vertex per pixel, elevated by the luminance of the image.
+    pub fn heightmap(image: &RgbaImage, scale: f32) -> (Vec<Vertex>, Vec<u32>) {
+        let (width, height) = image.dimensions();
+        let width = width.max(2);
+        let height = height.max(2);
+
+        let elevation = |x: u32, y: u32| {
+            let x = x.min(image.width().saturating_sub(1));
+            let y = y.min(image.height().saturating_sub(1));
+            match image.get_pixel_checked(x, y) {
+                Some(pixel) => {
+                    let luminance = 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32;
+                    scale * luminance / 255.0
+                },
+                None => 0.0,
+            }
+        };
+
+        let (mut vertices, indices) = MeshGenerator::grid(width - 1, height - 1, |u, v| {
+            let x = (u * (width - 1) as f32).round() as u32;
+            let y = (v * (height - 1) as f32).round() as u32;
+            Vec3::new(u - 0.5, elevation(x, y), v - 0.5)
+        });
+
+        // normals from central differences
+        let step_x = 1.0 / (width - 1) as f32;
+        let step_z = 1.0 / (height - 1) as f32;
+        for y in 0..height {
+            for x in 0..width {
+                let dx = elevation(x + 1, y) - elevation(x.saturating_sub(1), y);
+                let dz = elevation(x, y + 1) - elevation(x, y.saturating_sub(1));
+                let normal = Vec3::new(-dx / (2.0 * step_x), 1.0, -dz / (2.0 * step_z)).normalize_or_zero();
+                vertices[(y * width + x) as usize].normal = normal.to_array();
+            }
+        }
+
+        (vertices, indices)
+    }
+}
+
+// Private impl
+impl MeshGenerator {
+    fn vertex(position: Vec3, normal: Vec3, uv: Vec2) -> Vertex {
+        Vertex {
+            position: position.to_array(),
+            normal: normal.to_array(),
+            uv: uv.to_array(),
+            ..Default::default()
+        }
+    }
+
+    /// Builds a (columns + 1) x (rows + 1) grid of vertices, placed by the given function
+    /// of the normalized grid coordinates. Normals are computed from the grid neighbours.
+    fn grid<F: Fn(f32, f32) -> Vec3>(columns: u32, rows: u32, position: F) -> (Vec<Vertex>, Vec<u32>) {
+        let columns = columns.max(1);
+        let rows = rows.max(1);
+        let stride = columns + 1;
+
+        let mut vertices = Vec::with_capacity((stride * (rows + 1)) as usize);
+        for row in 0..=rows {
+            for column in 0..=columns {
+                let u = column as f32 / columns as f32;
+                let v = row as f32 / rows as f32;
+
+                let du = 1.0 / columns as f32;
+                let dv = 1.0 / rows as f32;
+                let tangent = position((u + du).min(1.0), v) - position((u - du).max(0.0), v);
+                let bitangent = position(u, (v + dv).min(1.0)) - position(u, (v - dv).max(0.0));
+                let normal = bitangent.cross(tangent).normalize_or_zero();
+
+                vertices.push(MeshGenerator::vertex(position(u, v), normal, Vec2::new(u, v)));
+            }
+        }
+
+        let mut indices = Vec::with_capacity((columns * rows * 6) as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let i0 = row * stride + column;
+                let i1 = i0 + 1;
+                let i2 = i0 + stride;
+                let i3 = i2 + 1;
+                indices.extend_from_slice(&[i0, i2, i1, i1, i2, i3]);
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    /// Swaps the last two vertices of every triangle.
+    fn flip_winding(indices: &mut [u32]) {
+        for triangle in indices.chunks_exact_mut(3) {
+            triangle.swap(1, 2);
+        }
+    }
+
+    /// A surface of revolution around the Y axis, with caps.
+    fn revolution(segments: u32, bottom_radius: f32, top_radius: f32) -> (Vec<Vertex>, Vec<u32>) {
+        let segments = segments.max(3);
+        let slope = bottom_radius - top_radius;
+
+        let (mut vertices, mut indices) = MeshGenerator::grid(segments, 1, |u, v| {
+            let theta = u * 2.0 * PI;
+            let radius = top_radius + v * slope;
+            Vec3::new(radius * theta.cos(), 0.5 - v, radius * theta.sin())
+        });
+        MeshGenerator::flip_winding(&mut indices);
+
+        // side normals are tilted by the slope of the surface
+        for vertex in vertices.iter_mut() {
+            let position = Vec3::from(vertex.position);
+            let radial = Vec3::new(position.x, 0.0, position.z).normalize_or_zero();
+            let radial = if radial == Vec3::ZERO {
+                let theta = vertex.uv[0] * 2.0 * PI;
+                Vec3::new(theta.cos(), 0.0, theta.sin())
+            } else {
+                radial
+            };
+            vertex.normal = (radial + Vec3::new(0.0, slope, 0.0)).normalize_or_zero().to_array();
+        }
+
+        // caps
+        for (y, radius, normal) in [(0.5, top_radius, Vec3::Y), (-0.5, bottom_radius, Vec3::NEG_Y)] {
+            if radius <= 0.0 {
+                continue;
+            }
+
+            let center = vertices.len() as u32;
+            vertices.push(MeshGenerator::vertex(Vec3::new(0.0, y, 0.0), normal, Vec2::new(0.5, 0.5)));
+
+            for segment in 0..=segments {
+                let theta = segment as f32 / segments as f32 * 2.0 * PI;
+                let (sin, cos) = theta.sin_cos();
+                vertices.push(
+                    MeshGenerator::vertex(
+                        Vec3::new(radius * cos, y, radius * sin),
+                        normal,
+                        Vec2::new(0.5 + 0.5 * cos, 0.5 + 0.5 * sin)
+                    )
+                );
+            }
+
+            for segment in 0..segments {
+                let current = center + 1 + segment;
+                if normal.y > 0.0 {
+                    indices.extend_from_slice(&[center, current + 1, current]);
+                } else {
+                    indices.extend_from_slice(&[center, current, current + 1]);
+                }
+            }
+        }
+
+        (vertices, indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every triangle must wind counter-clockwise around its outward normal.
+    fn assert_outward((vertices, indices): (Vec<Vertex>, Vec<u32>)) {
+        for triangle in indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+            let face_normal = (Vec3::from(b.position) - Vec3::from(a.position))
+                .cross(Vec3::from(c.position) - Vec3::from(a.position));
+            let normal = Vec3::from(a.normal) + Vec3::from(b.normal) + Vec3::from(c.normal);
+
+            // degenerate triangles at the poles have no winding
+            if face_normal.length() > 1e-6 {
+                assert!(face_normal.dot(normal) > 0.0, "triangle {:?} is wound inwards", triangle);
+            }
+        }
+    }
+
+    #[test]
+    fn shapes_wind_outwards() {
+        assert_outward(MeshGenerator::cube());
+        assert_outward(MeshGenerator::plane(3));
+        assert_outward(MeshGenerator::sphere(8, 12));
+        assert_outward(MeshGenerator::cylinder(12));
+        assert_outward(MeshGenerator::cone(12));
+        assert_outward(MeshGenerator::torus(8, 12, 0.2));
+        assert_outward(MeshGenerator::heightmap(&RgbaImage::from_pixel(4, 4, image::Rgba([128, 128, 128, 255])), 1.0));
+    }
+}
@@ -1,16 +1,24 @@
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 
-use mlua::{UserData, UserDataMethods};
+use mlua::{UserData, UserDataMethods, Table, prelude::LuaValue};
 use slotmap::Key;
 use verdi_database::{ResourceId, Resource, Assets, Handle};
 
-use crate::node::Node;
+use crate::{
+    node::Node, 
+    mesh::{Mesh, MeshHandle},
+    morph::{MorphTarget, MorphAnimation, MorphPlayback},
+    light_bake::{LightBaker, BakeLight, BakeSettings, occluders_from_lua},
+};
 
 pub type ModelId = ResourceId;
 
 #[derive(Clone)]
 pub struct Model {
     pub nodes: Vec<Node>,
+    /// Animations of the morph weights of the nodes.
+    pub animations: Vec<MorphAnimation>,
+    pub playback: Option<MorphPlayback>,
     pub id: ModelId,
 }
 
@@ -28,10 +36,59 @@ impl Model {
     pub fn new() -> Self {
         Self {
             nodes: Vec::new(),
+            animations: Vec::new(),
+            playback: None,
             id: ModelId::null(),
         }
     }
 
+    pub fn set_morph_weight(&mut self, node: usize, target: usize, weight: f32) {
+        if let Some(node) = self.nodes.get_mut(node) {
+            if let Some(current) = node.morph_weights.get_mut(target) {
+                if *current != weight {
+                    *current = weight;
+                    node.morph_dirty = true;
+                }
+            }
+        }
+    }
+
+    pub fn find_animation(&self, name: &str) -> Option<usize> {
+        self.animations.iter().position(|animation| animation.name == name)
+    }
+
+    /// Advances the animation played and applies its weights to the nodes.
+    pub fn update(&mut self, delta_time: f32) {
+        let playback = match self.playback.as_mut() {
+            Some(playback) => playback,
+            None => return,
+        };
+        let animation = match self.animations.get(playback.animation) {
+            Some(animation) => animation,
+            None => return,
+        };
+
+        playback.time += delta_time;
+        if playback.looping && animation.duration > 0.0 {
+            playback.time %= animation.duration;
+        }
+        else {
+            playback.time = playback.time.min(animation.duration);
+        }
+
+        let time = playback.time;
+        let weights: Vec<(usize, Vec<f32>)> = animation.channels
+            .iter()
+            .map(|channel| (channel.node, channel.sample(time)))
+            .collect();
+
+        for (node, weights) in weights {
+            for (target, weight) in weights.into_iter().enumerate() {
+                self.set_morph_weight(node, target, weight);
+            }
+        }
+    }
+
     pub fn get_node(&self, index: usize) -> Option<&Node> {
         self.nodes.get(index)
     }
@@ -58,6 +115,12 @@ impl Deref for ModelHandle {
     }
 }
 
+impl DerefMut for ModelHandle {
+    fn deref_mut(&mut self) -> &mut Handle {
+        &mut self.0
+    }
+}
+
 impl ModelHandle {
     pub fn new(assets: Assets, id: ModelId) -> Self{
         ModelHandle(assets.new_handle(id))
@@ -90,6 +153,78 @@ impl ModelHandle {
     }
 }
 
+impl ModelHandle {
+    /// Gives a mutable access to the model.
+    pub fn edit<R, F: FnOnce(&mut Model) -> R>(&mut self, func: F) -> Option<R> {
+        let model_id = self.get_id();
+        self.get_datas_mut()
+            .get_mut::<Model>(model_id)
+            .map(func)
+    }
+
+    /// Blends the meshes of the nodes whose morph weights changed.
+    pub fn blend_morphs(&mut self) {
+        let dirty: Vec<(usize, MeshHandle, MeshHandle, Vec<f32>)> = match self.get_datas().get::<Model>(self.get_id()) {
+            Some(model) => model.nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, node)| node.morph_dirty)
+                .filter_map(|(index, node)| Some((index, node.mesh.clone()?, node.morphed_mesh.clone()?, node.morph_weights.clone())))
+                .collect(),
+            None => return,
+        };
+
+        for (index, mesh, morphed_mesh, weights) in dirty {
+            let vertices = self.get_datas()
+                .get::<Mesh>(mesh.get_id())
+                .map(|mesh| MorphTarget::blend(&mesh.vertices, &mesh.morph_targets, &weights));
+
+            if let Some(vertices) = vertices {
+                if let Some(morphed_mesh) = self.get_datas_mut().get_mut::<Mesh>(morphed_mesh.get_id()) {
+                    morphed_mesh.vertices = vertices;
+                    morphed_mesh.set_modified();
+                }
+            }
+
+            self.edit(|model| model.nodes[index].morph_dirty = false);
+        }
+    }
+
+    /// Plays an animation by name or by index, from the start.
+    pub fn play_animation(&mut self, animation: &LuaValue, looping: bool) {
+        self.edit(|model| {
+            let index = match animation {
+                LuaValue::String(name) => name.to_str().ok().and_then(|name| model.find_animation(name)),
+                LuaValue::Integer(index) => Some(*index as usize),
+                LuaValue::Number(index) => Some(*index as usize),
+                _ => None,
+            };
+
+            model.playback = index
+                .filter(|index| *index < model.animations.len())
+                .map(|animation| MorphPlayback {
+                    animation,
+                    time: 0.0,
+                    looping,
+                });
+            // the first frame is applied at once
+            model.update(0.0);
+        });
+    }
+
+    /// Applies a change to the mesh of each node.
+    fn for_each_mesh<F: FnMut(&mut MeshHandle)>(&self, mut f: F) {
+        let meshes: Vec<MeshHandle> = match self.get_datas().get::<Model>(self.get_id()) {
+            Some(model) => model.nodes.iter().filter_map(|node| node.mesh.clone()).collect(),
+            None => return,
+        };
+
+        for mut mesh in meshes {
+            f(&mut mesh);
+        }
+    }
+}
+
 impl UserData for ModelHandle {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         methods.add_method("getNumNodes", |_, model, ()| {
@@ -99,5 +234,86 @@ impl UserData for ModelHandle {
         methods.add_method("getNode", |_, model, index: usize| {
             Ok(model.get_node(index)) // TODO: warning -> copy of the node
         });
+
+        // the levels of detail of every node
+        methods.add_method("setLodDistances", |_, model, distances: Vec<f32>| {
+            Ok(model.for_each_mesh(|mesh| mesh.set_lod_distances(&distances)))
+        });
+
+        methods.add_method("setLodFade", |_, model, fade: f32| {
+            Ok(model.for_each_mesh(|mesh| mesh.set_lod_fade(fade)))
+        });
+
+        methods.add_method("setCullDistance", |_, model, distance: Option<f32>| {
+            Ok(model.for_each_mesh(|mesh| mesh.set_cull_distance(distance)))
+        });
+
+        // morph targets, the node and target indices start at 0
+        methods.add_method_mut("setMorphWeight", |_, model, (node, target, weight): (usize, usize, f32)| {
+            model.edit(|model| model.set_morph_weight(node, target, weight));
+            Ok(())
+        });
+
+        methods.add_method("getMorphWeight", |_, model, (node, target): (usize, usize)| {
+            Ok(model
+                .get_datas()
+                .get::<Model>(model.get_id())
+                .and_then(|model| model.nodes.get(node))
+                .and_then(|node| node.morph_weights.get(target).copied())
+            )
+        });
+
+        methods.add_method("getMorphTargetCount", |_, model, node: usize| {
+            Ok(model
+                .get_datas()
+                .get::<Model>(model.get_id())
+                .and_then(|model| model.nodes.get(node))
+                .map_or(0, |node| node.morph_weights.len())
+            )
+        });
+
+        methods.add_method("getAnimations", |_, model, ()| {
+            Ok(model
+                .get_datas()
+                .get::<Model>(model.get_id())
+                .map(|model| model.animations.iter().map(|animation| animation.name.clone()).collect::<Vec<String>>())
+                .unwrap_or_default()
+            )
+        });
+
+        methods.add_method_mut("playAnimation", |_, model, (animation, looping): (LuaValue, Option<bool>)| {
+            Ok(model.play_animation(&animation, looping.unwrap_or(true)))
+        });
+
+        methods.add_method_mut("stopAnimation", |_, model, ()| {
+            model.edit(|model| model.playback = None);
+            Ok(())
+        });
+
+        methods.add_method_mut("update", |_, model, delta_time: f32| {
+            model.edit(|model| model.update(delta_time));
+            Ok(())
+        });
+
+        // bakes every node, placed by its transform, and shadowed by the others
+        methods.add_method_mut("bakeLighting", |_, model, (lights, options): (Vec<Table>, Option<Table>)| {
+            let lights = lights
+                .iter()
+                .map(BakeLight::from_lua_table)
+                .collect::<mlua::Result<Vec<BakeLight>>>()?;
+
+            let (settings, mut occluders) = match &options {
+                Some(options) => (BakeSettings::from_lua_table(options)?, occluders_from_lua(options.get("occluders")?)?),
+                None => (BakeSettings::default(), Vec::new()),
+            };
+            let meshes = LightBaker::model_meshes(model);
+            occluders.extend(meshes.iter().cloned());
+
+            let baker = LightBaker::new(lights, settings, &occluders, &model.get_datas());
+            for (mut mesh, transform) in meshes {
+                mesh.bake_lighting(&baker, transform);
+            }
+            Ok(())
+        });
     }
 }
\ No newline at end of file
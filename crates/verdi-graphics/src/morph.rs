@@ -0,0 +1,133 @@
+use verdi_math::Vec3;
+
+use crate::vertex::Vertex;
+
+/// Displacements of the vertices of a mesh, blended by a weight.
+#[derive(Clone, Default)]
+pub struct MorphTarget {
+    pub positions: Vec<[f32; 3]>,
+    /// Empty when the target doesn't move the normals.
+    pub normals: Vec<[f32; 3]>,
+}
+
+impl MorphTarget {
+    /// Adds the weighted displacements of the targets to the vertices.
+    pub fn blend(vertices: &[Vertex], targets: &[MorphTarget], weights: &[f32]) -> Vec<Vertex> {
+        let mut blended = vertices.to_vec();
+
+        for (target, weight) in targets.iter().zip(weights.iter()) {
+            if *weight == 0.0 {
+                continue;
+            }
+
+            for (vertex, delta) in blended.iter_mut().zip(target.positions.iter()) {
+                vertex.position = (Vec3::from(vertex.position) + Vec3::from(*delta) * *weight).to_array();
+            }
+            for (vertex, delta) in blended.iter_mut().zip(target.normals.iter()) {
+                vertex.normal = (Vec3::from(vertex.normal) + Vec3::from(*delta) * *weight).to_array();
+            }
+        }
+
+        if targets.iter().any(|target| !target.normals.is_empty()) {
+            for vertex in blended.iter_mut() {
+                vertex.normal = Vec3::from(vertex.normal).normalize_or_zero().to_array();
+            }
+        }
+
+        blended
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MorphInterpolation {
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+/// Keyframes of the morph weights of a node.
+#[derive(Clone)]
+pub struct MorphChannel {
+    /// Index of the node in the model.
+    pub node: usize,
+    pub times: Vec<f32>,
+    /// The weights of every target for each keyframe.
+    /// Cubic splines store an in-tangent, a value and an out-tangent per keyframe.
+    pub values: Vec<f32>,
+    pub target_count: usize,
+    pub interpolation: MorphInterpolation,
+}
+
+impl MorphChannel {
+    fn keyframe(&self, index: usize) -> &[f32] {
+        let (stride, offset) = match self.interpolation {
+            MorphInterpolation::CubicSpline => (self.target_count * 3, self.target_count),
+            _ => (self.target_count, 0),
+        };
+        let start = index * stride + offset;
+        self.values.get(start..start + self.target_count).unwrap_or(&[])
+    }
+
+    fn tangent(&self, index: usize, out: bool) -> &[f32] {
+        let start = index * self.target_count * 3 + if out { self.target_count * 2 } else { 0 };
+        self.values.get(start..start + self.target_count).unwrap_or(&[])
+    }
+
+    /// The weights at the given time, clamped to the keyframes.
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let (first, last) = match (self.times.first(), self.times.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Vec::new(),
+        };
+
+        if time <= first {
+            return self.keyframe(0).to_vec();
+        }
+        if time >= last {
+            return self.keyframe(self.times.len() - 1).to_vec();
+        }
+
+        let next = self.times.iter().position(|key_time| *key_time > time).unwrap_or(self.times.len() - 1);
+        let previous = next - 1;
+        let delta = self.times[next] - self.times[previous];
+        let t = if delta > 0.0 { (time - self.times[previous]) / delta } else { 0.0 };
+
+        let (from, to) = (self.keyframe(previous), self.keyframe(next));
+        match self.interpolation {
+            MorphInterpolation::Step => from.to_vec(),
+            MorphInterpolation::Linear => from
+                .iter()
+                .zip(to.iter())
+                .map(|(from, to)| from + (to - from) * t)
+                .collect(),
+            MorphInterpolation::CubicSpline => {
+                let (out_tangent, in_tangent) = (self.tangent(previous, true), self.tangent(next, false));
+                let (t2, t3) = (t * t, t * t * t);
+                (0..self.target_count)
+                    .map(|i| {
+                        (2.0 * t3 - 3.0 * t2 + 1.0) * from.get(i).copied().unwrap_or(0.0)
+                            + (t3 - 2.0 * t2 + t) * delta * out_tangent.get(i).copied().unwrap_or(0.0)
+                            + (-2.0 * t3 + 3.0 * t2) * to.get(i).copied().unwrap_or(0.0)
+                            + (t3 - t2) * delta * in_tangent.get(i).copied().unwrap_or(0.0)
+                    })
+                    .collect()
+            },
+        }
+    }
+}
+
+/// An animation of the morph weights, imported from the weight channels of a glTF animation.
+#[derive(Clone)]
+pub struct MorphAnimation {
+    pub name: String,
+    pub channels: Vec<MorphChannel>,
+    pub duration: f32,
+}
+
+/// The animation played by a model.
+#[derive(Clone, Copy)]
+pub struct MorphPlayback {
+    pub animation: usize,
+    pub time: f32,
+    pub looping: bool,
+}
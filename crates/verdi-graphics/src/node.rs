@@ -9,6 +9,12 @@ pub struct Node {
     pub mesh: Option<MeshHandle>,
     pub transform: TransformHandle,
     pub children: Vec<Node>,
+    /// Weights of the morph targets of the mesh, for this node only.
+    pub morph_weights: Vec<f32>,
+    /// The mesh blended with the weights of the node, drawn instead of the mesh.
+    pub morphed_mesh: Option<MeshHandle>,
+    /// The weights changed since the last blending.
+    pub morph_dirty: bool,
 }
 
 impl Node {
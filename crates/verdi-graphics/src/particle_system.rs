@@ -0,0 +1,546 @@
+use std::ops::{Deref, DerefMut};
+
+use mlua::{UserData, UserDataMethods, Table, prelude::LuaValue};
+use slotmap::Key;
+use verdi_database::{ResourceId, Resource, Assets, Handle};
+use verdi_math::{Vec3, Vec4, Quat, prelude::{TransformHandle, LuaVec3}};
+
+use crate::{
+    mesh::{MeshHandle, Mesh},
+    atlas::AtlasFrame,
+    vertex::Vertex,
+};
+
+pub type ParticleSystemId = ResourceId;
+
+/// Small deterministic random number generator (xorshift64*).
+#[derive(Clone)]
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        // splitmix64 spreads close seeds over the whole state
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        state ^= state >> 31;
+
+        // xorshift would stay at zero forever
+        Self(if state == 0 { 0x9E37_79B9_7F4A_7C15 } else { state })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// A number in [0, 1[
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    fn unit_vector(&mut self) -> Vec3 {
+        let z = self.range(-1.0, 1.0);
+        let angle = self.range(0.0, std::f32::consts::TAU);
+        let radius = (1.0 - z * z).max(0.0).sqrt();
+        Vec3::new(radius * angle.cos(), radius * angle.sin(), z)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum EmitterShape {
+    Point,
+    Box(Vec3),
+    Sphere(f32),
+}
+
+impl EmitterShape {
+    fn sample(&self, random: &mut Random) -> Vec3 {
+        match self {
+            EmitterShape::Point => Vec3::ZERO,
+            EmitterShape::Box(size) => Vec3::new(
+                random.range(-0.5, 0.5) * size.x,
+                random.range(-0.5, 0.5) * size.y,
+                random.range(-0.5, 0.5) * size.z,
+            ),
+            EmitterShape::Sphere(radius) => {
+                let direction = Random::unit_vector(random);
+                direction * *radius * random.next_f32().cbrt()
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct Particle {
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub age: f32,
+    pub lifetime: f32,
+}
+
+/// CPU simulated particles, drawn as camera facing quads in a single mesh.
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    max_particles: usize,
+    frames: Vec<AtlasFrame>,
+    pub position: Vec3,
+    pub shape: EmitterShape,
+    pub emission_rate: f32,
+    pub lifetime: (f32, f32),
+    pub direction: Vec3,
+    pub spread: f32,
+    pub speed: (f32, f32),
+    pub gravity: Vec3,
+    pub sizes: Vec<f32>,
+    pub colors: Vec<Vec4>,
+    emission_accumulator: f32,
+    seed: u64,
+    random: Random,
+    mesh: MeshHandle,
+    transform: TransformHandle,
+    pub id: ParticleSystemId,
+}
+
+impl Resource for ParticleSystem {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl ParticleSystem {
+    pub fn new(frame: AtlasFrame, max_particles: usize, mesh: MeshHandle, transform: TransformHandle) -> Self {
+        Self {
+            particles: Vec::with_capacity(max_particles),
+            max_particles,
+            frames: vec![frame],
+            position: Vec3::ZERO,
+            shape: EmitterShape::Point,
+            emission_rate: 10.0,
+            lifetime: (1.0, 1.0),
+            direction: Vec3::Y,
+            spread: 0.0,
+            speed: (1.0, 1.0),
+            gravity: Vec3::ZERO,
+            sizes: vec![1.0],
+            colors: vec![Vec4::ONE],
+            emission_accumulator: 0.0,
+            seed: 0,
+            random: Random::new(0),
+            mesh,
+            transform,
+            id: ParticleSystemId::null(),
+        }
+    }
+
+    pub fn get_particles(&self) -> &Vec<Particle> {
+        &self.particles
+    }
+
+    pub fn get_mesh(&self) -> &MeshHandle {
+        &self.mesh
+    }
+
+    pub fn get_transform(&self) -> &TransformHandle {
+        &self.transform
+    }
+
+    /// Frames used along the particles lifetime.
+    /// They are refused unless they come from the image the system was created with.
+    pub fn set_frames(&mut self, frames: Vec<AtlasFrame>) -> bool {
+        let image_id = self.frames[0].image.get_id();
+        if frames.iter().any(|frame| frame.image.get_id() != image_id) {
+            return false;
+        }
+
+        if !frames.is_empty() {
+            self.frames = frames;
+        }
+        true
+    }
+
+    pub fn get_frames(&self) -> &Vec<AtlasFrame> {
+        &self.frames
+    }
+
+    /// Restarts the simulation: same seed, same particles.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.reset();
+    }
+
+    pub fn reset(&mut self) {
+        self.particles.clear();
+        self.emission_accumulator = 0.0;
+        self.random = Random::new(self.seed);
+    }
+
+    pub fn emit(&mut self, count: usize) {
+        for _ in 0..count {
+            if self.particles.len() >= self.max_particles {
+                break;
+            }
+
+            let particle = self.spawn();
+            self.particles.push(particle);
+        }
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        for particle in self.particles.iter_mut() {
+            particle.age += delta_time;
+            particle.velocity += self.gravity * delta_time;
+            particle.position += particle.velocity * delta_time;
+        }
+        self.particles.retain(|particle| particle.age < particle.lifetime);
+
+        self.emission_accumulator += self.emission_rate * delta_time;
+        let count = self.emission_accumulator.floor();
+        self.emission_accumulator -= count;
+        self.emit(count as usize);
+    }
+
+    /// Four vertices per particle, positioned at the particle center.
+    /// The normal stores the offset of the corner, applied in view space by the billboard program.
+    pub fn build_vertices(&self) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = Vec::with_capacity(self.particles.len() * 4);
+        let mut indices = Vec::with_capacity(self.particles.len() * 6);
+
+        for particle in self.particles.iter() {
+            let t = (particle.age / particle.lifetime).clamp(0.0, 1.0);
+            let half_size = 0.5 * ParticleSystem::interpolate(&self.sizes, t, |a, b, t| a + (b - a) * t);
+            let color = ParticleSystem::interpolate(&self.colors, t, |a, b, t| a.lerp(b, t));
+            let frame_index = ((t * self.frames.len() as f32) as usize).min(self.frames.len() - 1);
+            let [u0, v0, u1, v1] = self.frames[frame_index].uv;
+
+            let first = vertices.len() as u32;
+            for (corner, uv) in [
+                ([-1.0, 1.0], [u0, v0]),
+                ([-1.0, -1.0], [u0, v1]),
+                ([1.0, 1.0], [u1, v0]),
+                ([1.0, -1.0], [u1, v1]),
+            ] {
+                vertices.push(Vertex {
+                    position: particle.position.to_array(),
+                    normal: [corner[0] * half_size, corner[1] * half_size, 0.0],
+                    color: color.to_array(),
+                    uv,
+                });
+            }
+            indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 1, first + 3]);
+        }
+
+        (vertices, indices)
+    }
+
+    fn spawn(&mut self) -> Particle {
+        let position = self.position + self.shape.sample(&mut self.random);
+
+        // random direction in a cone around the emission direction
+        let cos_angle = self.random.range(self.spread.cos(), 1.0);
+        let sin_angle = (1.0 - cos_angle * cos_angle).max(0.0).sqrt();
+        let angle = self.random.range(0.0, std::f32::consts::TAU);
+        let local_direction = Vec3::new(sin_angle * angle.cos(), sin_angle * angle.sin(), cos_angle);
+        let direction = Quat::from_rotation_arc(Vec3::Z, self.direction.normalize_or_zero()) * local_direction;
+
+        let speed = self.random.range(self.speed.0, self.speed.1);
+        let lifetime = self.random.range(self.lifetime.0, self.lifetime.1).max(f32::EPSILON);
+
+        Particle {
+            position,
+            velocity: direction * speed,
+            age: 0.0,
+            lifetime,
+        }
+    }
+
+    fn interpolate<T: Copy, F: Fn(T, T, f32) -> T>(keys: &[T], t: f32, lerp: F) -> T {
+        if keys.len() == 1 {
+            return keys[0];
+        }
+
+        let position = t * (keys.len() - 1) as f32;
+        let index = (position as usize).min(keys.len() - 2);
+        lerp(keys[index], keys[index + 1], position - index as f32)
+    }
+}
+
+#[derive(Clone)]
+pub struct ParticleSystemHandle(Handle);
+
+impl Deref for ParticleSystemHandle {
+    type Target = Handle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for ParticleSystemHandle {
+    fn deref_mut(&mut self) -> &mut Handle {
+        &mut self.0
+    }
+}
+
+impl ParticleSystemHandle {
+    pub fn new(assets: Assets, id: ParticleSystemId) -> Self {
+        ParticleSystemHandle(assets.new_handle(id))
+    }
+
+    /// Simulates the particles and updates the mesh drawing them.
+    pub fn update(&mut self, delta_time: f32) {
+        let system_id = self.get_id();
+        let (mesh_id, vertices, indices) = match self.get_datas_mut().get_mut::<ParticleSystem>(system_id) {
+            Some(system) => {
+                system.update(delta_time);
+                let (vertices, indices) = system.build_vertices();
+                (system.get_mesh().get_id(), vertices, indices)
+            },
+            None => return,
+        };
+
+        if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+            mesh.vertices = vertices;
+            mesh.indices = Some(indices);
+            mesh.set_modified();
+        }
+    }
+
+    pub fn get_particle_count(&self) -> usize {
+        self.get_datas()
+            .get::<ParticleSystem>(self.get_id())
+            .map(|system| system.get_particles().len())
+            .unwrap_or(0)
+    }
+
+    pub fn get_particle(&self, index: usize) -> Option<Particle> {
+        self.get_datas()
+            .get::<ParticleSystem>(self.get_id())?
+            .get_particles()
+            .get(index)
+            .copied()
+    }
+
+    /// Gives a mutable access to the system settings.
+    pub fn edit<F: FnOnce(&mut ParticleSystem)>(&mut self, func: F) {
+        let system_id = self.get_id();
+        if let Some(system) = self.get_datas_mut().get_mut::<ParticleSystem>(system_id) {
+            func(system);
+        }
+    }
+}
+
+impl UserData for ParticleSystemHandle {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("update", |_, system, delta_time: f32| {
+            Ok(system.update(delta_time))
+        });
+
+        methods.add_method_mut("emit", |_, system, count: usize| {
+            Ok(system.edit(|system| system.emit(count)))
+        });
+
+        methods.add_method_mut("reset", |_, system, ()| {
+            Ok(system.edit(|system| system.reset()))
+        });
+
+        methods.add_method_mut("setSeed", |_, system, seed: u64| {
+            Ok(system.edit(|system| system.set_seed(seed)))
+        });
+
+        methods.add_method_mut("setPosition", |_, system, position: LuaVec3| {
+            Ok(system.edit(|system| system.position = *position))
+        });
+
+        methods.add_method_mut("setEmitterShape", |_, system, (shape, x, y, z): (String, Option<f32>, Option<f32>, Option<f32>)| {
+            let shape = match shape.as_str() {
+                "box" => {
+                    let x = x.unwrap_or(1.0);
+                    EmitterShape::Box(Vec3::new(x, y.unwrap_or(x), z.unwrap_or(x)))
+                },
+                "sphere" => EmitterShape::Sphere(x.unwrap_or(1.0)),
+                _ => EmitterShape::Point,
+            };
+            Ok(system.edit(|system| system.shape = shape))
+        });
+
+        methods.add_method_mut("setEmissionRate", |_, system, rate: f32| {
+            Ok(system.edit(|system| system.emission_rate = rate.max(0.0)))
+        });
+
+        methods.add_method_mut("setLifetime", |_, system, (min, max): (f32, Option<f32>)| {
+            Ok(system.edit(|system| system.lifetime = (min, max.unwrap_or(min))))
+        });
+
+        methods.add_method_mut("setDirection", |_, system, direction: LuaVec3| {
+            Ok(system.edit(|system| system.direction = *direction))
+        });
+
+        methods.add_method_mut("setSpread", |_, system, angle: f32| {
+            Ok(system.edit(|system| system.spread = angle))
+        });
+
+        methods.add_method_mut("setSpeed", |_, system, (min, max): (f32, Option<f32>)| {
+            Ok(system.edit(|system| system.speed = (min, max.unwrap_or(min))))
+        });
+
+        methods.add_method_mut("setGravity", |_, system, gravity: LuaVec3| {
+            Ok(system.edit(|system| system.gravity = *gravity))
+        });
+
+        // sizes over lifetime
+        methods.add_method_mut("setSizes", |_, system, sizes: mlua::Variadic<f32>| {
+            Ok({
+                if !sizes.is_empty() {
+                    system.edit(|system| system.sizes = sizes.to_vec());
+                }
+            })
+        });
+
+        // colors over lifetime, as r, g, b, a components
+        methods.add_method_mut("setColors", |_, system, components: mlua::Variadic<f32>| {
+            let colors: Vec<Vec4> = components
+                .chunks_exact(4)
+                .map(|c| Vec4::new(c[0], c[1], c[2], c[3]))
+                .collect();
+            Ok({
+                if !colors.is_empty() {
+                    system.edit(|system| system.colors = colors);
+                }
+            })
+        });
+
+        // frames over lifetime, usually an atlas sequence
+        methods.add_method_mut("setFrames", |_, system, frames: Table| {
+            let frames = frames
+                .sequence_values::<LuaValue>()
+                .filter_map(|frame| frame.ok())
+                .filter_map(|frame| AtlasFrame::from_lua_value(&frame))
+                .collect();
+
+            let mut accepted = true;
+            system.edit(|system| accepted = system.set_frames(frames));
+            match accepted {
+                true => Ok(()),
+                false => Err(mlua::Error::RuntimeError("The frames must come from the image of the particle system".to_string())),
+            }
+        });
+
+        methods.add_method("getParticleCount", |_, system, ()| {
+            Ok(system.get_particle_count())
+        });
+
+        // position, velocity and age of a particle, starting at 1
+        methods.add_method("getParticle", |_, system, index: usize| {
+            let particle = system.get_particle(index.saturating_sub(1));
+            Ok((
+                particle.map(|particle| LuaVec3(particle.position)),
+                particle.map(|particle| LuaVec3(particle.velocity)),
+                particle.map(|particle| particle.age),
+            ))
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbaImage;
+    use verdi_math::prelude::Math;
+
+    use crate::{image::{Image, ImageHandle}, mesh::PrimitiveType};
+
+    use super::*;
+
+    fn new_system(assets: &mut Assets, math: &mut Math, image: &ImageHandle) -> ParticleSystemHandle {
+        let mesh = MeshHandle::new(
+            assets.clone(),
+            assets.add(Box::new(Mesh::new(Vec::new(), Some(Vec::new()), PrimitiveType::Triangles, ResourceId::null())))
+        );
+        let system = ParticleSystem::new(AtlasFrame::from_image(image.clone()), 64, mesh, math.new_transform());
+
+        ParticleSystemHandle::new(assets.clone(), assets.add(Box::new(system)))
+    }
+
+    fn new_image(assets: &mut Assets) -> ImageHandle {
+        ImageHandle::new(assets.clone(), assets.add(Box::new(Image::from_data(RgbaImage::new(4, 4)))))
+    }
+
+    fn simulate(system: &mut ParticleSystemHandle, seed: u64) -> Vec<Particle> {
+        system.edit(|system| {
+            system.set_seed(seed);
+            system.shape = EmitterShape::Sphere(1.0);
+            system.spread = 1.0;
+            system.speed = (1.0, 2.0);
+            system.lifetime = (1.0, 3.0);
+            system.emission_rate = 20.0;
+        });
+        for _ in 0..30 {
+            system.update(1.0 / 30.0);
+        }
+
+        (0..system.get_particle_count())
+            .filter_map(|index| system.get_particle(index))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_particles() {
+        let mut assets = Assets::new();
+        let mut math = Math::new();
+        let image = new_image(&mut assets);
+        let mut first = new_system(&mut assets, &mut math, &image);
+        let mut second = new_system(&mut assets, &mut math, &image);
+
+        let particles = simulate(&mut first, 42);
+        assert_eq!(particles.len(), 20);
+        assert!(particles == simulate(&mut second, 42));
+        assert!(particles != simulate(&mut second, 43));
+
+        // a reset replays the same particles
+        assert!(particles == simulate(&mut first, 42));
+    }
+
+    #[test]
+    fn any_seed_gives_distinct_particles() {
+        // this seed used to cancel the state of the generator
+        let mut random = Random::new(0x9E37_79B9_7F4A_7C15);
+        let values: Vec<u64> = (0..4).map(|_| random.next_u64()).collect();
+        assert!(values.windows(2).all(|pair| pair[0] != pair[1]));
+
+        let mut assets = Assets::new();
+        let mut math = Math::new();
+        let image = new_image(&mut assets);
+        let mut system = new_system(&mut assets, &mut math, &image);
+        let particles = simulate(&mut system, 0x9E37_79B9_7F4A_7C15);
+        assert!(particles.windows(2).all(|pair| pair[0].velocity != pair[1].velocity));
+    }
+
+    #[test]
+    fn frames_from_another_image_are_refused() {
+        let mut assets = Assets::new();
+        let mut math = Math::new();
+        let image = new_image(&mut assets);
+        let other = new_image(&mut assets);
+        let mut system = new_system(&mut assets, &mut math, &image);
+
+        let same_image = vec![AtlasFrame::from_image(image.clone()), AtlasFrame::from_image(image.clone())];
+        let other_image = vec![AtlasFrame::from_image(other)];
+
+        let mut accepted = (false, false);
+        system.edit(|system| {
+            accepted.0 = system.set_frames(same_image);
+            accepted.1 = system.set_frames(other_image);
+        });
+        assert_eq!(accepted, (true, false));
+    }
+}
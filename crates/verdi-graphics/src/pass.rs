@@ -1,17 +1,25 @@
 use std::{cell::RefCell, rc::Rc};
 
-use mlua::{UserData, UserDataMethods};
-use verdi_math::prelude::{TransformHandle, Transform};
+use mlua::{UserData, UserDataMethods, prelude::LuaValue};
+use verdi_math::{Vec2, Vec4, prelude::{TransformHandle, Transform, LuaVec3}};
 
 use crate::{
-    render_cmds::DrawCmd, 
+    render_cmds::{DrawCmd, PickTag}, 
     mesh::MeshHandle, 
-    render_graph::RenderGraph, 
+    render_graph::{RenderGraph, PassDeclaration, ResourceRead}, 
     model::{ModelHandle, Model}, 
     render_state::RenderState, 
     camera::{CameraHandle, Camera}, 
     sprite::{SpriteHandle, Sprite}, 
-    framebuffer::FramebufferHandle
+    particle_system::{ParticleSystemHandle, ParticleSystem}, 
+    framebuffer::{FramebufferHandle, COLOR_OUTPUT}, 
+    billboard::{Billboard, BillboardMode}, 
+    atlas::AtlasFrame,
+    sky::{SkyHandle, Sky},
+    shadow::{ShadowHandle, ShadowDraw},
+    material::{MaterialHandle, TARGET_UNIFORMS},
+    tilemap::TilemapHandle,
+    terrain::TerrainHandle,
 };
 
 pub struct CmdQueue {
@@ -33,37 +41,177 @@ impl CmdQueue {
 pub type PassId = u32;
 
 pub struct Pass {
-    framebuffer: FramebufferHandle,
+    /// Allocated by the render graph for the declared passes.
+    framebuffer: Option<FramebufferHandle>,
+    declaration: Option<PassDeclaration>,
     cmd_queue: CmdQueue,
+    billboards: Vec<Billboard>,
+    shadows: Vec<ShadowDraw>,
+    // given to the next draw commands
+    entity: Option<u64>,
     pub render_state: RenderState,
 }
 
 impl Pass {
     pub fn new(framebuffer: FramebufferHandle) -> Self {
         Self {
-            framebuffer,
+            framebuffer: Some(framebuffer),
+            declaration: None,
             cmd_queue: CmdQueue::new(),
+            billboards: Vec::new(),
+            shadows: Vec::new(),
+            entity: None,
             render_state: RenderState::new(),
         }
     }
 
-    pub fn get_framebuffer(&self) -> FramebufferHandle {
+    pub fn with_declaration(declaration: PassDeclaration) -> Self {
+        Self {
+            framebuffer: None,
+            declaration: Some(declaration),
+            cmd_queue: CmdQueue::new(),
+            billboards: Vec::new(),
+            shadows: Vec::new(),
+            entity: None,
+            render_state: RenderState::new(),
+        }
+    }
+
+    /// None until the render graph is compiled, for the declared passes.
+    pub fn get_framebuffer(&self) -> Option<FramebufferHandle> {
         self.framebuffer.clone()
     }
 
+    pub fn set_framebuffer(&mut self, framebuffer: FramebufferHandle) {
+        self.framebuffer = Some(framebuffer);
+    }
+
+    pub fn get_declaration(&self) -> Option<&PassDeclaration> {
+        self.declaration.as_ref()
+    }
+
+    pub fn get_declaration_mut(&mut self) -> Option<&mut PassDeclaration> {
+        self.declaration.as_mut()
+    }
+
+    pub fn get_name(&self) -> String {
+        self.declaration
+            .as_ref()
+            .map_or_else(String::new, |declaration| declaration.name.clone())
+    }
+
+    /// Size of the targets, known before they are allocated.
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        match (&self.declaration, &self.framebuffer) {
+            (Some(declaration), _) => declaration.dimensions,
+            (None, Some(framebuffer)) => framebuffer.get_dimensions(),
+            (None, None) => (0, 0),
+        }
+    }
+
+    /// Whether the pass is presented to the screen.
+    /// The passes without declaration, or without any resource written, always are.
+    pub fn is_output(&self) -> bool {
+        match &self.declaration {
+            Some(declaration) => declaration.output || declaration.writes().next().is_none(),
+            None => true,
+        }
+    }
+
     pub fn add_draw_cmd(&mut self, mesh: MeshHandle, transform: TransformHandle, perspective: bool) {
         let cmd = DrawCmd {
             mesh,
             transform,
             perspective,
+            material: None,
+            lod_fade: 0.0,
+            pick: PickTag { entity: self.entity, node: None },
+        };
+
+        self.cmd_queue.push_cmd(cmd);
+    }
+
+    /// Draws the mesh of a node, which is reported when picked.
+    pub fn add_node_draw_cmd(&mut self, mesh: MeshHandle, transform: TransformHandle, model: ModelHandle, node: usize) {
+        let cmd = DrawCmd {
+            mesh,
+            transform,
+            perspective: true,
+            material: None,
+            lod_fade: 0.0,
+            pick: PickTag { entity: self.entity, node: Some((model, node)) },
+        };
+
+        self.cmd_queue.push_cmd(cmd);
+    }
+
+    /// The next draw commands belong to the entity, when they are picked.
+    pub fn set_entity(&mut self, entity: Option<u64>) {
+        self.entity = entity;
+    }
+
+    /// Draws the mesh with another material than its own.
+    pub fn add_draw_cmd_with_material(&mut self, mesh: MeshHandle, transform: TransformHandle, material: MaterialHandle) {
+        let cmd = DrawCmd {
+            mesh,
+            transform,
+            perspective: true,
+            material: Some(material),
+            lod_fade: 0.0,
+            pick: PickTag { entity: self.entity, node: None },
         };
 
         self.cmd_queue.push_cmd(cmd);
     }
 
+    /// Billboards are batched into streaming meshes before rendering.
+    pub fn add_billboard(&mut self, billboard: Billboard) {
+        self.billboards.push(billboard);
+    }
+
+    pub fn take_billboards(&mut self) -> Vec<Billboard> {
+        std::mem::take(&mut self.billboards)
+    }
+
+    /// Shadows are resolved into draw commands before rendering, after the other draws.
+    pub fn add_shadow(&mut self, shadow: ShadowDraw) {
+        self.shadows.push(shadow);
+    }
+
+    pub fn take_shadows(&mut self) -> Vec<ShadowDraw> {
+        std::mem::take(&mut self.shadows)
+    }
+
+    /// The sky is drawn before everything else.
+    pub fn set_sky(&mut self, quad: MeshHandle, transform: TransformHandle) {
+        let cmd = DrawCmd {
+            mesh: quad,
+            transform,
+            perspective: true,
+            material: None,
+            lod_fade: 0.0,
+            pick: PickTag { entity: self.entity, node: None },
+        };
+
+        self.cmd_queue.cmds.insert(0, cmd);
+    }
+
     pub fn get_cmds(&self) -> &Vec<DrawCmd> {
         &self.cmd_queue.cmds
     }
+
+    pub fn push_cmd(&mut self, cmd: DrawCmd) {
+        self.cmd_queue.push_cmd(cmd);
+    }
+
+    pub fn take_cmds(&mut self) -> Vec<DrawCmd> {
+        std::mem::take(&mut self.cmd_queue.cmds)
+    }
+
+    /// Keeps the draw commands for which the predicate is true, in order.
+    pub fn retain_cmds<F: FnMut(&DrawCmd) -> bool>(&mut self, predicate: F) {
+        self.cmd_queue.cmds.retain(predicate);
+    }
 }
 
 pub struct PassHandle {
@@ -84,26 +232,47 @@ impl UserData for PassHandle {
                 }
             })
         });
-        methods.add_method_mut("drawModel", |_, pass, model: ModelHandle| {
+        methods.add_method_mut("drawModel", |_, pass, (mut model, shadow): (ModelHandle, Option<ShadowHandle>)| {
+            model.blend_morphs();
+
             Ok({
                 if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
                     if let Some(model_ref) = model.get_assets().get_datas().get::<Model>(model.get_id()) {
-                        for node in model_ref.get_nodes().iter() {
-                            if let Some(mesh) = &node.mesh {
-                                pass.add_draw_cmd(
+                        for (index, node) in model_ref.get_nodes().iter().enumerate() {
+                            if let Some(mesh) = node.morphed_mesh.as_ref().or(node.mesh.as_ref()) {
+                                pass.add_node_draw_cmd(
                                     mesh.clone(), 
                                     node.transform.clone(), 
-                                    true
+                                    model.clone(),
+                                    index
                                 );
+                                if let Some(shadow) = &shadow {
+                                    pass.add_shadow(
+                                        ShadowDraw {
+                                            shadow: shadow.clone(),
+                                            mesh: mesh.clone(),
+                                            transform: node.transform.clone(),
+                                        }
+                                    );
+                                }
                             }
                         }
                     }
                 }
             })
         });
-        methods.add_method_mut("drawMesh", |_, pass, (mesh, transform): (MeshHandle, TransformHandle)| {
+        methods.add_method_mut("drawMesh", |_, pass, (mesh, transform, shadow): (MeshHandle, TransformHandle, Option<ShadowHandle>)| {
             Ok({
                 if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
+                    if let Some(shadow) = shadow {
+                        pass.add_shadow(
+                            ShadowDraw {
+                                shadow,
+                                mesh: mesh.clone(),
+                                transform: transform.clone(),
+                            }
+                        );
+                    }
                     pass.add_draw_cmd(
                         mesh, 
                         transform, 
@@ -112,19 +281,132 @@ impl UserData for PassHandle {
                 }
             })
         });
-        // methods.add_method_mut("drawSprite", |_, pass, (sprite, transform): (SpriteHandle, TransformHandle)| {
-        //     Ok({
-        //         if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
-        //             if let Some(sprite_ref) = sprite.get_assets().get::<Sprite>(sprite.get_id()) {
-        //                 pass.add_draw_cmd(
-        //                     sprite_ref.quad_id, 
-        //                     transform.get_id(), 
-        //                     false
-        //                 );
-        //             }
-        //         }
-        //     })
-        // });
+        methods.add_method_mut("drawSprite", |_, pass, (sprite, transform): (SpriteHandle, TransformHandle)| {
+            Ok({
+                if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
+                    if let Some(sprite_ref) = sprite.get_datas().get::<Sprite>(sprite.get_id()) {
+                        pass.add_draw_cmd(
+                            MeshHandle::new(sprite.get_assets().clone(), sprite_ref.quad_id), 
+                            transform, 
+                            false
+                        );
+                    }
+                }
+            })
+        });
+        methods.add_method_mut("drawTerrain", |_, pass, mut terrain: TerrainHandle| {
+            Ok({
+                if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
+                    // the chunks out of the view are culled with their bounds
+                    if let (meshes, Some(transform)) = terrain.get_chunks() {
+                        for mesh in meshes {
+                            pass.add_draw_cmd(mesh, transform.clone(), true);
+                        }
+                    }
+                }
+            })
+        });
+        // the map is scrolled by a position in pixels, each layer following it with its parallax
+        methods.add_method_mut("drawTilemap", |_, pass, (mut tilemap, x, y): (TilemapHandle, Option<f32>, Option<f32>)| {
+            Ok({
+                if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
+                    let viewport = pass.get_dimensions();
+                    let scroll = Vec2::new(x.unwrap_or(0.0), y.unwrap_or(0.0));
+                    for (mesh, transform) in tilemap.get_visible_chunks(scroll, viewport) {
+                        pass.add_draw_cmd(mesh, transform, false);
+                    }
+                }
+            })
+        });
+        methods.add_method_mut("drawBillboard", |_, pass, (frame, position, size, mode): (LuaValue, LuaVec3, Option<f32>, Option<String>)| {
+            Ok({
+                if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
+                    if let Some(frame) = AtlasFrame::from_lua_value(&frame) {
+                        // the size is the height, the width follows the frame aspect ratio
+                        let height = size.unwrap_or(1.0);
+                        let aspect_ratio = frame.region.width as f32 / frame.region.height.max(1) as f32;
+                        pass.add_billboard(
+                            Billboard::new(
+                                frame, 
+                                *position, 
+                                Vec2::new(height * aspect_ratio, height), 
+                                mode.map_or(BillboardMode::Spherical, BillboardMode::from)
+                            )
+                        );
+                    }
+                }
+            })
+        });
+        methods.add_method_mut("drawParticles", |_, pass, particles: ParticleSystemHandle| {
+            Ok({
+                if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
+                    if let Some(system_ref) = particles.get_datas().get::<ParticleSystem>(particles.get_id()) {
+                        // all the particles are batched in a single mesh
+                        if !system_ref.get_particles().is_empty() {
+                            pass.add_draw_cmd(
+                                system_ref.get_mesh().clone(), 
+                                system_ref.get_transform().clone(), 
+                                true
+                            );
+                        }
+                    }
+                }
+            })
+        });
+        methods.add_method_mut("setSky", |_, pass, sky: SkyHandle| {
+            Ok({
+                if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
+                    if let Some(sky_ref) = sky.get_datas().get::<Sky>(sky.get_id()) {
+                        pass.set_sky(sky_ref.quad.clone(), sky_ref.transform.clone());
+                    }
+                }
+            })
+        });
+        // several outputs of the fragment shader can be written, into as many resources
+        methods.add_method_mut("writeColor", |_, pass, (name, output): (String, Option<String>)| {
+            Ok({
+                if let Some(declaration) = pass.graph.borrow_mut().get_pass_mut(pass.id).and_then(Pass::get_declaration_mut) {
+                    declaration.write_color(output.unwrap_or_else(|| COLOR_OUTPUT.to_string()), name);
+                }
+            })
+        });
+        methods.add_method_mut("writeDepth", |_, pass, name: String| {
+            Ok({
+                if let Some(declaration) = pass.graph.borrow_mut().get_pass_mut(pass.id).and_then(Pass::get_declaration_mut) {
+                    declaration.depth = Some(name);
+                }
+            })
+        });
+        // the resource is sampled by the material, as its texture by default
+        methods.add_method_mut("read", |_, pass, (name, material, uniform): (String, Option<MaterialHandle>, Option<String>)| {
+            let uniform_name = match uniform.as_deref().map(|uniform| TARGET_UNIFORMS.iter().find(|name| **name == uniform)) {
+                Some(Some(uniform_name)) => *uniform_name,
+                Some(None) => return Err(mlua::Error::RuntimeError(format!("{} can't receive a render target", uniform.unwrap_or_default()))),
+                None => TARGET_UNIFORMS[0],
+            };
+
+            Ok({
+                if let Some(declaration) = pass.graph.borrow_mut().get_pass_mut(pass.id).and_then(Pass::get_declaration_mut) {
+                    let uniform = material.map(|mut material| material.set_render_target_texture(uniform_name));
+                    declaration.reads.push(ResourceRead { name, uniform });
+                }
+            })
+        });
+        methods.add_method_mut("setOutput", |_, pass, value: bool| {
+            Ok({
+                if let Some(declaration) = pass.graph.borrow_mut().get_pass_mut(pass.id).and_then(Pass::get_declaration_mut) {
+                    declaration.output = value;
+                }
+            })
+        });
+        // the draws are reported with the entity when picked
+        methods.add_method_mut("setEntity", |_, pass, entity: Option<u64>| {
+            Ok({
+                if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
+                    pass.set_entity(entity);
+                }
+            })
+        });
         methods.add_method_mut("enableLighting", |_, pass, value: bool| {
             Ok({
                 if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
@@ -153,5 +435,12 @@ impl UserData for PassHandle {
                 }
             })
         });
+        methods.add_method_mut("setFogColor", |_, pass, (r, g, b, a): (f32, f32, f32, Option<f32>)| {
+            Ok({
+                if let Some(pass) = pass.graph.borrow_mut().get_pass_mut(pass.id) {
+                    pass.render_state.fog_color = Vec4::new(r, g, b, a.unwrap_or(1.0));
+                }
+            })
+        });
     }
 }
\ No newline at end of file
@@ -0,0 +1,172 @@
+use glium::{
+    framebuffer::DepthRenderBuffer,
+    texture::{DepthFormat, MipmapsOption, UncompressedUintFormat, UnsignedTexture2d},
+    Display, Rect,
+};
+use verdi_database::AssetDatas;
+use verdi_math::{prelude::TransformHandle, Mat4, Vec3};
+
+use crate::{
+    mesh::{Mesh, MeshHandle},
+    render_cmds::PickTag,
+    bounds::ray_triangle,
+};
+
+/// A draw command of the last frame, which can be picked.
+#[derive(Clone)]
+pub struct PickEntry {
+    /// Index of the pass in the render graph.
+    pub pass: usize,
+    /// Index of the draw command in the pass.
+    pub cmd: usize,
+    pub mesh: MeshHandle,
+    pub transform: TransformHandle,
+    pub model: Mat4,
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub tag: PickTag,
+}
+
+/// The integer target receiving the ids, with its depth buffer.
+pub struct PickTarget {
+    pub ids: UnsignedTexture2d,
+    pub depth: DepthRenderBuffer,
+}
+
+impl PickTarget {
+    fn new(ctx: &Display, (width, height): (u32, u32)) -> Option<Self> {
+        let ids = UnsignedTexture2d::empty_with_format(ctx, UncompressedUintFormat::U32, MipmapsOption::NoMipmap, width, height).ok()?;
+        let depth = DepthRenderBuffer::new(ctx, DepthFormat::I24, width, height).ok()?;
+
+        Some(Self { ids, depth })
+    }
+
+    /// The id at the pixel, from the top left corner.
+    fn read_id(&self, x: u32, y: u32) -> u32 {
+        // OpenGL rows go from bottom to top
+        let rect = Rect { left: x, bottom: self.ids.height() - 1 - y, width: 1, height: 1 };
+        let pixels: Vec<Vec<u32>> = self.ids
+            .main_level()
+            .first_layer()
+            .into_image(None)
+            .unwrap()
+            .raw_read(&rect);
+
+        pixels.first().and_then(|row| row.first()).copied().unwrap_or(0)
+    }
+}
+
+/// Finds the draw command under a pixel of the game framebuffer.
+/// The GPU renders the ids of the commands into an integer target, and the pixel is read back when picked.
+/// Without it the triangles of the commands are intersected on the CPU.
+#[derive(Default)]
+pub struct Picking {
+    /// Renders the ids on the GPU, enabled by the first pick.
+    pub enabled: bool,
+    entries: Vec<PickEntry>,
+    dimensions: (u32, u32),
+    /// Target of the ids, with the size of the game framebuffer.
+    target: Option<PickTarget>,
+    /// Whether the target holds the ids of the entries, 0 for the background and the entry index plus one.
+    rendered: bool,
+    /// The target couldn't be created, the picking stays on the CPU.
+    failed: bool,
+}
+
+impl Picking {
+    /// The ids rendered for the previous entries are discarded.
+    pub fn set_entries(&mut self, entries: Vec<PickEntry>, dimensions: (u32, u32)) {
+        self.entries = entries;
+        self.dimensions = dimensions;
+        self.rendered = false;
+    }
+
+    pub fn get_entries(&self) -> &Vec<PickEntry> {
+        &self.entries
+    }
+
+    pub fn get_dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    /// Creates the target of the ids, again when the game framebuffer is resized.
+    pub fn prepare_target(&mut self, ctx: &Display) {
+        if self.failed || self.target.as_ref().map(|target| target.ids.dimensions()) == Some(self.dimensions) {
+            return;
+        }
+
+        self.target = PickTarget::new(ctx, self.dimensions);
+        if self.target.is_none() {
+            println!("Pick target creation failed, picking on the CPU");
+            self.failed = true;
+        }
+    }
+
+    pub fn get_target(&self) -> Option<&PickTarget> {
+        self.target.as_ref()
+    }
+
+    pub fn set_rendered(&mut self) {
+        self.rendered = true;
+    }
+
+    /// The id written for the entry.
+    pub fn encode_id(index: usize) -> u32 {
+        index as u32 + 1
+    }
+
+    /// The entry drawn at the pixel, from the top left corner.
+    pub fn pick(&self, x: u32, y: u32, datas: &AssetDatas) -> Option<&PickEntry> {
+        let (width, height) = self.dimensions;
+        if x >= width || y >= height {
+            return None;
+        }
+
+        match &self.target {
+            Some(target) if self.rendered => match target.read_id(x, y) {
+                0 => None,
+                id => self.entries.get(id as usize - 1),
+            },
+            _ => self.ray_cast(x, y, datas),
+        }
+    }
+
+    /// The closest triangle crossed by the ray of the pixel, as the depth test would keep it.
+    fn ray_cast(&self, x: u32, y: u32, datas: &AssetDatas) -> Option<&PickEntry> {
+        let (width, height) = self.dimensions;
+        let ndc_x = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (y as f32 + 0.5) / height as f32 * 2.0;
+
+        let mut closest: Option<(f32, &PickEntry)> = None;
+        for entry in self.entries.iter() {
+            let mesh = match datas.get::<Mesh>(entry.mesh.get_id()) {
+                Some(mesh) => mesh,
+                None => continue,
+            };
+
+            let clip_from_world = entry.projection * entry.view;
+            let world_from_clip = clip_from_world.inverse();
+            let near = world_from_clip.project_point3(Vec3::new(ndc_x, ndc_y, -1.0));
+            let far = world_from_clip.project_point3(Vec3::new(ndc_x, ndc_y, 1.0));
+            let direction = (far - near).normalize_or_zero();
+            let max_distance = near.distance(far);
+
+            let hit = mesh
+                .get_triangles()
+                .iter()
+                .map(|triangle| triangle.map(|position| entry.model.transform_point3(position)))
+                .filter_map(|triangle| ray_triangle(near, direction, &triangle))
+                .filter(|distance| *distance <= max_distance)
+                .reduce(f32::min);
+
+            if let Some(distance) = hit {
+                let depth = clip_from_world.project_point3(near + direction * distance).z;
+                if closest.is_none_or(|(closest_depth, _)| depth < closest_depth) {
+                    closest = Some((depth, entry));
+                }
+            }
+        }
+
+        closest.map(|(_, entry)| entry)
+    }
+}
@@ -1,16 +1,21 @@
 use std::ops::Deref;
 
 use glium::Display;
+use mlua::UserData;
 use slotmap::Key;
 use verdi_database::{ResourceId, Resource, Assets, Handle};
 
-use crate::{shader::{ShaderId, Shader}, gpu_assets::{PrepareAsset, GpuAssets, GpuAsset, GpuAssetError}, gpu_program::GpuProgram};
+use crate::{shader::{ShaderId, Shader}, gpu_assets::{PrepareAsset, GpuAssets, GpuAsset, GpuAssetError}, gpu_program::GpuProgram, uniform::UniformHandle};
 
 pub type ProgramId = ResourceId;
 
 pub struct Program {
     pub vs: ShaderId,
     pub fs: ShaderId,
+    /// Writes the fragment outputs as they are, even into sRGB targets.
+    pub raw_output: bool,
+    /// Uniforms given to the materials switching to this program, unless they have their own.
+    pub defaults: Vec<(&'static str, UniformHandle)>,
     pub id: ProgramId,
 }
 
@@ -29,20 +34,40 @@ impl Program {
         Self {
             vs,
             fs,
+            raw_output: false,
+            defaults: Vec::new(),
             id: ProgramId::null(),
         }
     }
+
+    pub fn with_raw_output(mut self) -> Self {
+        self.raw_output = true;
+        self
+    }
+
+    pub fn with_default_uniform(mut self, name: &'static str, uniform: UniformHandle) -> Self {
+        self.defaults.push((name, uniform));
+        self
+    }
 }
 
 impl PrepareAsset for Program {
     fn prepare_rendering(&self, display: &Display, assets: &Assets, gpu_assets: &GpuAssets) -> Result<Box<dyn GpuAsset>, GpuAssetError> {
         if let Some(vs) = assets.get_datas().get::<Shader>(self.vs) {
             if let Some(fs) = assets.get_datas().get::<Shader>(self.fs) {
-                let gl_program = glium::Program::from_source(
-                    display, 
-                    vs.get_source(), 
-                    fs.get_source(), 
-                    None
+                let gl_program = glium::Program::new(
+                    display,
+                    glium::program::ProgramCreationInput::SourceCode {
+                        vertex_shader: vs.get_source(),
+                        tessellation_control_shader: None,
+                        tessellation_evaluation_shader: None,
+                        geometry_shader: None,
+                        fragment_shader: fs.get_source(),
+                        transform_feedback_varyings: None,
+                        // glium converts the outputs to sRGB unless the program already did
+                        outputs_srgb: self.raw_output,
+                        uses_point_size: false,
+                    }
                 )?;
 
                 return Ok(
@@ -72,4 +97,6 @@ impl ProgramHandle {
     pub fn new(assets: Assets, id: ProgramId) -> Self {
         ProgramHandle(assets.new_handle(id))
     }
-}
\ No newline at end of file
+}
+
+impl UserData for ProgramHandle {}
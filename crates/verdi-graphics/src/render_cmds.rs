@@ -1,6 +1,15 @@
 use verdi_math::prelude::TransformHandle;
 
-use crate::mesh::MeshHandle;
+use crate::{mesh::MeshHandle, material::MaterialHandle, model::ModelHandle};
+
+/// What a draw command belongs to, reported when it is picked.
+#[derive(Clone, Default)]
+pub struct PickTag {
+    /// Set by the game, usually the id of an entity.
+    pub entity: Option<u64>,
+    /// The model and the index of its node.
+    pub node: Option<(ModelHandle, usize)>,
+}
 
 pub trait RenderCmd {
     fn execute(&self);
@@ -12,6 +21,11 @@ pub struct DrawCmd {
     pub mesh: MeshHandle,
     pub transform: TransformHandle,
     pub perspective: bool,
+    // replaces the mesh material
+    pub material: Option<MaterialHandle>,
+    // dithered cross-fade between two levels of detail, 0 when the mesh is opaque
+    pub lod_fade: f32,
+    pub pick: PickTag,
 }
 
 impl RenderCmd for DrawCmd {
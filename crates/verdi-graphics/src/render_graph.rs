@@ -1,13 +1,161 @@
-use crate::{pass::{Pass, PassId}, framebuffer::FramebufferHandle};
+use std::collections::HashMap;
+
+use thiserror::Error;
+use verdi_database::{Assets, ResourceId};
+
+use crate::{
+    pass::{Pass, PassId},
+    framebuffer::{Framebuffer, FramebufferHandle, COLOR_OUTPUT},
+    image::{Image, ImageHandle},
+    depth_buffer::{DepthBuffer, DepthBufferHandle},
+    uniform::{Uniform, UniformHandle, UniformValue},
+};
+
+#[derive(Error, Debug)]
+pub enum RenderGraphError {
+    #[error("Render graph: passes {0:?} depend on each other")]
+    Cycle(Vec<String>),
+    #[error("Render graph: pass {0} reads {1} which no pass writes")]
+    UnwrittenResource(String, String),
+    #[error("Render graph: pass {0} writes {1} which is already written by another pass")]
+    MultipleWriters(String, String),
+}
+
+/// A named resource read by a pass.
+#[derive(Clone)]
+pub struct ResourceRead {
+    pub name: String,
+    /// Texture uniform receiving the target of the resource, color or depth.
+    pub uniform: Option<UniformHandle>,
+}
+
+/// The named resources of a pass, its framebuffer is allocated by the graph.
+#[derive(Clone)]
+pub struct PassDeclaration {
+    pub name: String,
+    /// The fragment shader outputs and the resources they are written to.
+    pub colors: Vec<(String, String)>,
+    pub depth: Option<String>,
+    pub reads: Vec<ResourceRead>,
+    /// Size of the targets written by the pass.
+    pub dimensions: (u32, u32),
+    /// Output passes are kept even when none of their resources is read.
+    pub output: bool,
+}
+
+impl PassDeclaration {
+    pub fn new(name: String, dimensions: (u32, u32)) -> Self {
+        Self {
+            name,
+            colors: Vec::new(),
+            depth: None,
+            reads: Vec::new(),
+            dimensions,
+            output: false,
+        }
+    }
+
+    /// Writes the output of the fragment shader into the resource, replacing the resource of the output.
+    pub fn write_color(&mut self, output: String, resource: String) {
+        match self.colors.iter_mut().find(|(name, _)| *name == output) {
+            Some(color) => color.1 = resource,
+            None => self.colors.push((output, resource)),
+        }
+    }
+
+    pub fn writes(&self) -> impl Iterator<Item = &String> {
+        self.colors
+            .iter()
+            .map(|(_, resource)| resource)
+            .chain(self.depth.iter())
+    }
+}
+
+/// Targets allocated by the graph, reused by the resources whose lifetimes don't overlap
+/// and kept from one frame to the next.
+#[derive(Default)]
+struct TargetPool {
+    colors: Vec<((u32, u32), ImageHandle)>,
+    depths: Vec<((u32, u32), DepthBufferHandle)>,
+    framebuffers: HashMap<(Vec<(String, ResourceId)>, ResourceId), FramebufferHandle>,
+}
+
+impl TargetPool {
+    fn acquire_color(&mut self, assets: &mut Assets, dimensions: (u32, u32), in_use: &mut Vec<bool>) -> usize {
+        let free = self.colors
+            .iter()
+            .enumerate()
+            .position(|(index, (size, _))| *size == dimensions && !in_use.get(index).copied().unwrap_or(false));
+
+        let index = free.unwrap_or_else(|| {
+            let id = assets.add(Box::new(Image::new(dimensions.0, dimensions.1)));
+            self.colors.push((dimensions, ImageHandle::new(assets.clone(), id)));
+            self.colors.len() - 1
+        });
+
+        in_use.resize(self.colors.len(), false);
+        in_use[index] = true;
+        index
+    }
+
+    fn acquire_depth(&mut self, assets: &mut Assets, dimensions: (u32, u32), in_use: &mut Vec<bool>) -> usize {
+        let free = self.depths
+            .iter()
+            .enumerate()
+            .position(|(index, (size, _))| *size == dimensions && !in_use.get(index).copied().unwrap_or(false));
+
+        let index = free.unwrap_or_else(|| {
+            // the next passes can sample the depth
+            let id = assets.add(Box::new(DepthBuffer::new_texture(dimensions.0, dimensions.1)));
+            self.depths.push((dimensions, DepthBufferHandle::new(assets.clone(), id)));
+            self.depths.len() - 1
+        });
+
+        in_use.resize(self.depths.len(), false);
+        in_use[index] = true;
+        index
+    }
+
+    fn framebuffer(&mut self, assets: &mut Assets, colors: &[(String, usize)], depth: usize) -> FramebufferHandle {
+        let colors: Vec<(String, ImageHandle)> = colors
+            .iter()
+            .map(|(output, color)| (output.clone(), self.colors[*color].1.clone()))
+            .collect();
+        let depth = self.depths[depth].1.clone();
+
+        let key = colors
+            .iter()
+            .map(|(output, color)| (output.clone(), color.get_id()))
+            .collect();
+
+        self.framebuffers
+            .entry((key, depth.get_id()))
+            .or_insert_with(|| {
+                let id = assets.add(Box::new(Framebuffer::with_color_targets(colors, depth)));
+                FramebufferHandle::new(assets.clone(), id)
+            })
+            .clone()
+    }
+}
+
+/// Physical targets of a resource during the frame.
+#[derive(Default, Clone, Copy)]
+struct Allocation {
+    color: Option<usize>,
+    depth: Option<usize>,
+    last_use: usize,
+}
 
 pub struct RenderGraph {
     passes: Vec<Pass>,
+    pool: TargetPool,
 }
 
 impl Default for RenderGraph {
     fn default() -> Self {
-        Self { 
+        Self {
             passes: Vec::default(),
+            pool: TargetPool::default(),
         }
     }
 }
@@ -22,15 +170,219 @@ impl RenderGraph {
         (self.passes.len() - 1) as PassId
     }
 
+    /// Adds a pass drawing into the resources it declares.
+    pub fn create_declared_pass(&mut self, declaration: PassDeclaration) -> PassId {
+        self.passes.push(Pass::with_declaration(declaration));
+        (self.passes.len() - 1) as PassId
+    }
+
     pub fn get_pass_mut(&mut self, id: PassId) -> Option<&mut Pass> {
         self.passes.get_mut(id as usize)
     }
-    
+
     pub fn get_passes(&self) -> &Vec<Pass> {
         &self.passes
     }
 
+    pub fn get_passes_mut(&mut self) -> &mut Vec<Pass> {
+        &mut self.passes
+    }
+
     pub fn clear(&mut self) {
         self.passes.clear();
     }
-}
\ No newline at end of file
+
+    /// Forgets the pooled targets, when the assets are cleared.
+    pub fn reset(&mut self) {
+        self.passes.clear();
+        self.pool = TargetPool::default();
+    }
+
+    /// Orders the passes so that the resources are written before being read,
+    /// removes the passes whose resources are never read, and allocates their framebuffers.
+    /// The passes in error are removed and the others are still compiled, the first error is returned.
+    /// The pass ids are invalid afterwards.
+    pub fn compile(&mut self, assets: &mut Assets) -> Result<(), RenderGraphError> {
+        let mut errors = Vec::new();
+        let mut valid = vec![true; self.passes.len()];
+
+        // a resource is written by a single pass, the first declared
+        let mut writers: HashMap<String, usize> = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            if let Some(declaration) = pass.get_declaration() {
+                for name in declaration.writes() {
+                    if writers.contains_key(name) {
+                        errors.push(RenderGraphError::MultipleWriters(declaration.name.clone(), name.clone()));
+                        valid[index] = false;
+                    }
+                    else {
+                        writers.insert(name.clone(), index);
+                    }
+                }
+            }
+        }
+
+        let mut dependencies: Vec<Vec<usize>> = vec![Vec::new(); self.passes.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            let declaration = match pass.get_declaration() {
+                Some(declaration) => declaration,
+                None => continue,
+            };
+            for read in declaration.reads.iter() {
+                match writers.get(&read.name) {
+                    Some(writer) if *writer != index => dependencies[index].push(*writer),
+                    // a pass can't sample the target it renders to
+                    Some(_) => {
+                        errors.push(RenderGraphError::Cycle(vec![declaration.name.clone()]));
+                        valid[index] = false;
+                    },
+                    None => {
+                        errors.push(RenderGraphError::UnwrittenResource(declaration.name.clone(), read.name.clone()));
+                        valid[index] = false;
+                    },
+                }
+            }
+        }
+
+        // the passes depending on a pass in error are skipped as well
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in 0..self.passes.len() {
+                if valid[index] && dependencies[index].iter().any(|dependency| !valid[*dependency]) {
+                    valid[index] = false;
+                    changed = true;
+                }
+            }
+        }
+
+        // the passes presented to the screen keep alive the passes they depend on
+        let mut live = vec![false; self.passes.len()];
+        let mut stack: Vec<usize> = (0..self.passes.len())
+            .filter(|index| valid[*index] && self.passes[*index].is_output())
+            .collect();
+        while let Some(index) = stack.pop() {
+            if live[index] || !valid[index] {
+                continue;
+            }
+            live[index] = true;
+            stack.extend(dependencies[index].iter().copied());
+        }
+
+        let mut order: Vec<usize> = Vec::new();
+        let mut scheduled = vec![false; self.passes.len()];
+        loop {
+            // in submission order among the passes ready to run
+            let next = (0..self.passes.len()).find(|index| {
+                live[*index]
+                    && !scheduled[*index]
+                    && dependencies[*index].iter().all(|dependency| scheduled[*dependency])
+            });
+
+            match next {
+                Some(index) => {
+                    scheduled[index] = true;
+                    order.push(index);
+                },
+                None => break,
+            }
+        }
+
+        let blocked: Vec<String> = (0..self.passes.len())
+            .filter(|index| live[*index] && !scheduled[*index])
+            .map(|index| self.passes[index].get_name())
+            .collect();
+        if !blocked.is_empty() {
+            errors.insert(0, RenderGraphError::Cycle(blocked));
+        }
+
+        self.allocate(assets, &order);
+
+        let mut passes: Vec<Option<Pass>> = std::mem::take(&mut self.passes).into_iter().map(Some).collect();
+        self.passes = order
+            .iter()
+            .filter_map(|index| passes[*index].take())
+            .collect();
+
+        match errors.into_iter().next() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Gives the passes their framebuffers, reusing the targets of the resources no longer read.
+    fn allocate(&mut self, assets: &mut Assets, order: &[usize]) {
+        let mut allocations: HashMap<String, Allocation> = HashMap::new();
+        for (position, index) in order.iter().enumerate() {
+            if let Some(declaration) = self.passes[*index].get_declaration() {
+                for name in declaration.writes().chain(declaration.reads.iter().map(|read| &read.name)) {
+                    allocations.entry(name.clone()).or_default().last_use = position;
+                }
+            }
+        }
+
+        let mut colors_in_use = Vec::new();
+        let mut depths_in_use = Vec::new();
+
+        for (position, index) in order.iter().enumerate() {
+            let declaration = match self.passes[*index].get_declaration() {
+                Some(declaration) => declaration.clone(),
+                None => continue,
+            };
+            let dimensions = declaration.dimensions;
+
+            // the targets without name only live during the pass
+            let colors: Vec<(String, usize)> = match declaration.colors.is_empty() {
+                true => vec![(COLOR_OUTPUT.to_string(), self.pool.acquire_color(assets, dimensions, &mut colors_in_use))],
+                false => declaration.colors
+                    .iter()
+                    .map(|(output, name)| {
+                        let color = self.pool.acquire_color(assets, dimensions, &mut colors_in_use);
+                        allocations.entry(name.clone()).or_default().color = Some(color);
+                        (output.clone(), color)
+                    })
+                    .collect(),
+            };
+            let depth = match &declaration.depth {
+                Some(name) => {
+                    let depth = self.pool.acquire_depth(assets, dimensions, &mut depths_in_use);
+                    allocations.entry(name.clone()).or_default().depth = Some(depth);
+                    depth
+                },
+                None => self.pool.acquire_depth(assets, dimensions, &mut depths_in_use),
+            };
+
+            let framebuffer = self.pool.framebuffer(assets, &colors, depth);
+            self.passes[*index].set_framebuffer(framebuffer);
+
+            for read in declaration.reads.iter() {
+                let value = match allocations.get(&read.name) {
+                    Some(Allocation { color: Some(color), .. }) => UniformValue::Texture(self.pool.colors[*color].1.get_id()),
+                    Some(Allocation { depth: Some(depth), .. }) => UniformValue::DepthTexture(self.pool.depths[*depth].1.get_id()),
+                    _ => continue,
+                };
+
+                if let Some(uniform) = &read.uniform {
+                    if let Some(uniform) = assets.get_datas_mut().get_mut::<Uniform>(uniform.get_id()) {
+                        uniform.value = value;
+                    }
+                }
+            }
+
+            if declaration.colors.is_empty() {
+                colors_in_use[colors[0].1] = false;
+            }
+            if declaration.depth.is_none() {
+                depths_in_use[depth] = false;
+            }
+            for allocation in allocations.values().filter(|allocation| allocation.last_use == position) {
+                if let Some(color) = allocation.color {
+                    colors_in_use[color] = false;
+                }
+                if let Some(depth) = allocation.depth {
+                    depths_in_use[depth] = false;
+                }
+            }
+        }
+    }
+}
@@ -7,6 +7,7 @@ pub struct RenderState {
     pub enable_fog: bool,
     pub fog_start: f32,
     pub fog_end: f32,
+    pub fog_color: Vec4,
 }
 
 /// A struct defining some global render state.
@@ -19,6 +20,7 @@ impl RenderState {
             enable_fog: false,
             fog_start: 0.0, 
             fog_end: 0.0,
+            fog_color: Vec4::new(0.3, 0.3, 0.3, 1.0),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,47 @@
+use crate::mesh::PrimitiveType;
+
+/// Counters of the last rendered frame.
+#[derive(Copy, Clone, Default)]
+pub struct RenderStats {
+    pub passes: u32,
+    pub draw_calls: u32,
+    pub triangles: u32,
+    pub vertices: u32,
+    /// Objects skipped before drawing because they could not be seen.
+    pub culled: u32,
+    pub texture_binds: u32,
+    pub uniform_uploads: u32,
+    pub gpu_meshes: u32,
+    pub gpu_images: u32,
+    pub gpu_indexed_images: u32,
+    pub gpu_depth_buffers: u32,
+    pub gpu_programs: u32,
+    /// Estimated video memory used by the GPU assets, in bytes.
+    pub gpu_memory: usize,
+}
+
+impl RenderStats {
+    /// Counts a draw call of a mesh. The element count is the number of indices, or vertices if there are none.
+    pub fn add_draw_call(&mut self, primitive_type: PrimitiveType, vertex_count: usize, element_count: usize) {
+        self.draw_calls += 1;
+        self.vertices += vertex_count as u32;
+        if primitive_type == PrimitiveType::Triangles {
+            self.triangles += (element_count / 3) as u32;
+        }
+    }
+
+    /// The lines shown by the overlay.
+    pub fn to_lines(&self) -> Vec<String> {
+        vec![
+            format!("PASSES {}", self.passes),
+            format!("DRAWS {}", self.draw_calls),
+            format!("TRIS {}", self.triangles),
+            format!("VERTS {}", self.vertices),
+            format!("CULLED {}", self.culled),
+            format!("TEX BINDS {}", self.texture_binds),
+            format!("UNIFORMS {}", self.uniform_uploads),
+            format!("MESHES {} IMAGES {}", self.gpu_meshes, self.gpu_images + self.gpu_indexed_images),
+            format!("GPU MEM {}K", self.gpu_memory / 1024),
+        ]
+    }
+}
@@ -1,6 +1,9 @@
 use glium::{
-    framebuffer::SimpleFrameBuffer, uniforms, BlitMask, BlitTarget, Display, Frame, Rect, Surface,
+    framebuffer::{SimpleFrameBuffer, MultiOutputFrameBuffer}, uniforms, BlitMask, BlitTarget, Display, Frame, Rect, Surface,
+    texture::{RawImage2d, SrgbTexture2d},
 };
+use image::{RgbaImage, imageops};
+use verdi_database::AssetDatas;
 use verdi_math::{prelude::Transform, Mat4, Vec2};
 
 use crate::{
@@ -10,11 +13,15 @@ use crate::{
     gpu_image::GpuImage,
     gpu_mesh::GpuMesh,
     gpu_program::GpuProgram,
-    material::{GlUniformValues, Material},
+    material::{GlUniformValues, Material, AlphaMode},
     mesh::Mesh,
-    pipeline::Pipeline,
     prelude::GraphicsChip,
-    uniform::{Uniform, UniformValue}, image::Image,
+    uniform::{Uniform, UniformValue}, image::{Image, ImageId},
+    gpu_assets::{GpuAssets, GpuAssetError},
+    globals::GlobalUniforms,
+    render_stats::RenderStats,
+    pass::Pass,
+    picking::Picking,
 };
 
 // Le renderer pourrait être plus bas niveau.
@@ -29,183 +36,97 @@ impl Renderer {
         let global_uniforms = &gpu.globals.global_uniforms;
         let gpu_assets = &gpu.gpu_assets;
 
+        // framebuffers which can't be created, reported once the assets are no longer borrowed
+        let mut failures = Vec::new();
+
         for pass in gpu.render_graph.borrow().get_passes().iter() {
+            let framebuffer_id = match pass.get_framebuffer() {
+                Some(framebuffer) => framebuffer.get_id(),
+                None => continue,
+            };
+
+            // the passes whose targets failed to be prepared are skipped
+            if gpu_assets.has_failed(framebuffer_id) {
+                continue;
+            }
+
             let mut asset_datas = gpu.assets.get_datas_mut();
-            let framebuffer = asset_datas
-                .get::<Framebuffer>(pass.get_framebuffer().get_id())
-                .expect("Framebuffer missing");
-
-            let gpu_color = gpu_assets
-                .get::<GpuImage>(framebuffer.get_color_target().get_id())
-                .unwrap();
-            let gpu_depth = gpu_assets
-                .get::<GpuDepthBuffer>(framebuffer.get_depth_target().get_id())
-                .unwrap();
-
-            let color = asset_datas
-                .get::<Image>(framebuffer.get_color_target().get_id())
-                .unwrap();
-
-            // create a framebuffer to draw into
-            let mut gl_framebuffer = SimpleFrameBuffer::with_depth_buffer(
-                ctx,
-                gpu_color.get_gl_texture(),
-                gpu_depth.get_gl_depth_buffer(),
-            )
-            .unwrap();
+            let (color_ids, depth_id): (Vec<(String, ImageId)>, _) = match asset_datas.get::<Framebuffer>(framebuffer_id) {
+                Some(framebuffer) => (
+                    framebuffer
+                        .get_color_targets()
+                        .iter()
+                        .map(|(output, image)| (output.clone(), image.get_id()))
+                        .collect(),
+                    framebuffer.get_depth_target().get_id(),
+                ),
+                None => continue,
+            };
+
+            let gpu_depth = match gpu_assets.get::<GpuDepthBuffer>(depth_id) {
+                Some(gpu_depth) => gpu_depth,
+                None => continue,
+            };
+
+            let target_dimensions = match color_ids.first().and_then(|(_, id)| asset_datas.get::<Image>(*id)) {
+                Some(image) => image.get_dimensions(),
+                None => continue,
+            };
+
+            let gpu_colors: Option<Vec<(&str, &SrgbTexture2d)>> = color_ids
+                .iter()
+                .map(|(output, id)| gpu_assets.get::<GpuImage>(*id).map(|gpu_image| (output.as_str(), gpu_image.get_gl_texture())))
+                .collect();
+            let gpu_colors = match gpu_colors {
+                Some(gpu_colors) => gpu_colors,
+                None => continue,
+            };
+
+            gpu.stats.passes += 1;
 
             let clear_color = gpu.render_state.clear_color;
-            gl_framebuffer.clear_color_and_depth(
-                (clear_color.x, clear_color.y, clear_color.z, clear_color.w),
-                1.0,
-            );
-
-            let target_dimensions = color.get_dimensions();
-
-            // perspective matrix
-            let perspective_matrix =
-            Camera::perspective_matrix(target_dimensions.0, target_dimensions.1);
-
-            // ortho matrix
-            let ortho_matrix = Camera::orthographic_matrix(
-                0.0,
-                target_dimensions.0 as f32,
-                target_dimensions.1 as f32,
-                0.0,
-                -10.0,
-                10.0,
-            );
-
-            for cmd in pass.get_cmds() {
-                // get transform
-                let transform_datas = cmd.transform.get_datas();
-                let transform = transform_datas
-                    .get::<Transform>(cmd.transform.get_id())
-                    .expect("Transform missing");
-
-                //let mut asset_datas = gpu.assets.get_datas_mut();
-                asset_datas
-                    .get_mut::<Uniform>(global_uniforms.resolution.get_id())
-                    .expect("Resolution uniform missing")
-                    .value = UniformValue::Vec2(Vec2::new(
-                    target_dimensions.0 as f32,
-                    target_dimensions.1 as f32,
-                ));
-
-                // model matrix
-                asset_datas
-                    .get_mut::<Uniform>(global_uniforms.model_matrix.get_id())
-                    .expect("Model matrix uniform missing")
-                    .value = UniformValue::Mat4(transform.to_matrix());
-
-                // view matrix
-                asset_datas
-                    .get_mut::<Uniform>(global_uniforms.view_matrix.get_id())
-                    .expect("View matrix uniform missing")
-                    .value = UniformValue::Mat4(pass.render_state.view);
-
-                // projection matrix
-                asset_datas
-                    .get_mut::<Uniform>(global_uniforms.projection_matrix.get_id())
-                    .expect("Perspective matrix uniform missing")
-                    .value = UniformValue::Mat4(if cmd.perspective {
-                    perspective_matrix
-                } else {
-                    ortho_matrix
-                });
-
-                asset_datas
-                    .get_mut::<Uniform>(global_uniforms.enable_lighting.get_id())
-                    .expect("Enable lighting uniform missing")
-                    .value = UniformValue::Bool(pass.render_state.enable_lighting);
-
-                asset_datas
-                    .get_mut::<Uniform>(global_uniforms.enable_fog.get_id())
-                    .expect("Enable fog uniform missing")
-                    .value = UniformValue::Bool(pass.render_state.enable_fog);
-
-                asset_datas
-                    .get_mut::<Uniform>(global_uniforms.fog_start.get_id())
-                    .expect("Fog start uniform missing")
-                    .value = UniformValue::Float(pass.render_state.fog_start);
-
-                asset_datas
-                    .get_mut::<Uniform>(global_uniforms.fog_end.get_id())
-                    .expect("Fog end uniform missing")
-                    .value = UniformValue::Float(pass.render_state.fog_end);
-
-                //let asset_datas = gpu.assets.get_datas();
-                let mesh = asset_datas
-                    .get::<Mesh>(cmd.mesh.get_id())
-                    .expect("Mesh resource not found");
-
-                let gpu_mesh = gpu_assets
-                    .get::<GpuMesh>(cmd.mesh.get_id())
-                    .expect("Gpu mesh not found");
-
-                let material = asset_datas
-                    .get::<Material>(mesh.material)
-                    .expect("Material not found");
-
-                let mut uniform_values = [None; 64];
-
-                for (uniform_value, uniform_handle) in
-                    uniform_values.iter_mut().zip(material.get_uniforms())
-                {
-                    if let Some((name, handle)) = uniform_handle.clone() {
-                        if let Some(uniform) = asset_datas.get::<Uniform>(handle.get_id()) {
-                            *uniform_value = Some((name, uniform.get_gl_value(gpu_assets)));
-                        }
-                    } else {
-                        break;
-                    }
+            let clear_color = (clear_color.x, clear_color.y, clear_color.z, clear_color.w);
+
+            // create a framebuffer to draw into, the fragment outputs go to the targets with the same name
+            if gpu_colors.len() > 1 {
+                match MultiOutputFrameBuffer::with_depth_buffer(ctx, gpu_colors.iter().copied(), gpu_depth.get_depth_attachment()) {
+                    Ok(mut gl_framebuffer) => {
+                        gl_framebuffer.clear_color_and_depth(clear_color, 1.0);
+                        Renderer::draw_cmds(&mut gl_framebuffer, pass, &mut asset_datas, gpu_assets, global_uniforms, &mut gpu.stats, target_dimensions);
+                    },
+                    Err(error) => {
+                        failures.push((framebuffer_id, error));
+                        continue;
+                    },
                 }
-
-                let gl_uniform_values = GlUniformValues { uniform_values };
-
-                let pipeline = asset_datas
-                    .get::<Pipeline>(gpu.globals.global_pipelines.default_pipeline.get_id())
-                    .expect("Pipeline not found");
-
-                let gpu_program = gpu_assets
-                    .get::<GpuProgram>(pipeline.get_program().get_id())
-                    .expect("GPU Program not found");
-
-                let draw_params = glium::DrawParameters {
-                    depth: glium::Depth {
-                        test: glium::draw_parameters::DepthTest::IfLess,
-                        write: true,
-                        ..Default::default()
+            }
+            else {
+                match SimpleFrameBuffer::with_depth_buffer(ctx, gpu_colors[0].1, gpu_depth.get_depth_attachment()) {
+                    Ok(mut gl_framebuffer) => {
+                        gl_framebuffer.clear_color_and_depth(clear_color, 1.0);
+                        Renderer::draw_cmds(&mut gl_framebuffer, pass, &mut asset_datas, gpu_assets, global_uniforms, &mut gpu.stats, target_dimensions);
+                    },
+                    Err(error) => {
+                        failures.push((framebuffer_id, error));
+                        continue;
                     },
-                    blend: glium::draw_parameters::Blend::alpha_blending(),
-                    ..Default::default()
-                };
-
-                if let Some(gl_index_buffer) = &gpu_mesh.get_index_buffer() {
-                    gl_framebuffer
-                        .draw(
-                            gpu_mesh.get_vertex_buffer(),
-                            gl_index_buffer,
-                            gpu_program.get_gl_program(),
-                            &gl_uniform_values,
-                            &draw_params,
-                        )
-                        .unwrap();
-                } else {
-                    gl_framebuffer
-                        .draw(
-                            gpu_mesh.get_vertex_buffer(),
-                            glium::index::NoIndices(glium::index::PrimitiveType::from(
-                                mesh.primitive_type,
-                            )),
-                            gpu_program.get_gl_program(),
-                            &gl_uniform_values,
-                            &draw_params,
-                        )
-                        .unwrap();
                 }
             }
 
+            // the targets read by other passes stay off screen
+            if !pass.is_output() {
+                continue;
+            }
+
+            // the first color target is presented
+            let gl_framebuffer = match SimpleFrameBuffer::with_depth_buffer(ctx, gpu_colors[0].1, gpu_depth.get_depth_attachment()) {
+                Ok(gl_framebuffer) => gl_framebuffer,
+                Err(error) => {
+                    failures.push((framebuffer_id, error));
+                    continue;
+                },
+            };
+
             let scale = frame.get_dimensions().1 as f32 / target_dimensions.1 as f32;
             let new_width = target_dimensions.0 as f32 * scale;
             let new_x_pos = (frame.get_dimensions().0 as f32 - new_width) as f32 / 2.0;
@@ -228,6 +149,318 @@ impl Renderer {
                 BlitMask::color_and_depth(),
             );
         }
+
+        for (framebuffer_id, error) in failures {
+            gpu.gpu_assets.add_failure(framebuffer_id, GpuAssetError::from(error));
+        }
+
+        self.render_pick_ids(ctx, gpu);
+        self.capture_framebuffer(gpu);
+    }
+
+    /// Draws the id of each draw command into the pick target, read back when a pixel is picked.
+    fn render_pick_ids(&self, ctx: &Display, gpu: &mut GraphicsChip) {
+        if !gpu.picking.enabled || gpu.picking.get_entries().is_empty() {
+            return;
+        }
+
+        let gpu_assets = &gpu.gpu_assets;
+        let gpu_program = match gpu_assets.get::<GpuProgram>(gpu.globals.global_programs.pick.get_id()) {
+            Some(program) => program,
+            None => return,
+        };
+
+        gpu.picking.prepare_target(ctx);
+
+        let picking = &gpu.picking;
+        let target = match picking.get_target() {
+            Some(target) => target,
+            None => return,
+        };
+
+        let mut gl_framebuffer = match SimpleFrameBuffer::with_depth_buffer(ctx, &target.ids, &target.depth) {
+            Ok(gl_framebuffer) => gl_framebuffer,
+            Err(_) => return,
+        };
+
+        // 0 is the background
+        target.ids
+            .main_level()
+            .first_layer()
+            .into_image(None)
+            .unwrap()
+            .raw_clear_buffer([0u32; 4]);
+        gl_framebuffer.clear_depth(1.0);
+
+        let (width, height) = picking.get_dimensions();
+        let draw_params = glium::DrawParameters {
+            depth: glium::Depth {
+                test: glium::draw_parameters::DepthTest::IfLess,
+                write: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        for (index, entry) in picking.get_entries().iter().enumerate() {
+            let gpu_mesh = match gpu_assets.get::<GpuMesh>(entry.mesh.get_id()) {
+                Some(gpu_mesh) => gpu_mesh,
+                None => continue,
+            };
+
+            let gl_uniforms = glium::uniform! {
+                u_model: entry.model.to_cols_array_2d(),
+                u_view: entry.view.to_cols_array_2d(),
+                u_projection: entry.projection.to_cols_array_2d(),
+                u_resolution: [width as f32, height as f32],
+                u_pick_id: Picking::encode_id(index),
+            };
+
+            if let Some(gl_index_buffer) = &gpu_mesh.get_index_buffer() {
+                gl_framebuffer
+                    .draw(gpu_mesh.get_vertex_buffer(), gl_index_buffer, gpu_program.get_gl_program(), &gl_uniforms, &draw_params)
+                    .unwrap();
+            } else {
+                gl_framebuffer
+                    .draw(
+                        gpu_mesh.get_vertex_buffer(),
+                        glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+                        gpu_program.get_gl_program(),
+                        &gl_uniforms,
+                        &draw_params,
+                    )
+                    .unwrap();
+            }
+        }
+
+        drop(gl_framebuffer);
+        gpu.picking.set_rendered();
+    }
+
+    /// Draws the commands of the pass into a framebuffer with one or multiple outputs.
+    fn draw_cmds<S: Surface>(
+        surface: &mut S,
+        pass: &Pass,
+        asset_datas: &mut AssetDatas,
+        gpu_assets: &GpuAssets,
+        global_uniforms: &GlobalUniforms,
+        stats: &mut RenderStats,
+        target_dimensions: (u32, u32),
+    ) {
+        // perspective matrix
+        let perspective_matrix =
+        Camera::perspective_matrix(target_dimensions.0, target_dimensions.1);
+
+        // ortho matrix
+        let ortho_matrix = Camera::orthographic_matrix(
+            0.0,
+            target_dimensions.0 as f32,
+            target_dimensions.1 as f32,
+            0.0,
+            -10.0,
+            10.0,
+        );
+
+        for cmd in pass.get_cmds() {
+            // get transform
+            let transform_datas = cmd.transform.get_datas();
+            let transform = transform_datas
+                .get::<Transform>(cmd.transform.get_id())
+                .expect("Transform missing");
+
+            //let mut asset_datas = gpu.assets.get_datas_mut();
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.resolution.get_id())
+                .expect("Resolution uniform missing")
+                .value = UniformValue::Vec2(Vec2::new(
+                target_dimensions.0 as f32,
+                target_dimensions.1 as f32,
+            ));
+
+            // model matrix
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.model_matrix.get_id())
+                .expect("Model matrix uniform missing")
+                .value = UniformValue::Mat4(transform.to_matrix());
+
+            // view matrix
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.view_matrix.get_id())
+                .expect("View matrix uniform missing")
+                .value = UniformValue::Mat4(pass.render_state.view);
+
+            // projection matrix
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.projection_matrix.get_id())
+                .expect("Perspective matrix uniform missing")
+                .value = UniformValue::Mat4(if cmd.perspective {
+                perspective_matrix
+            } else {
+                ortho_matrix
+            });
+
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.enable_lighting.get_id())
+                .expect("Enable lighting uniform missing")
+                .value = UniformValue::Bool(pass.render_state.enable_lighting);
+
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.enable_fog.get_id())
+                .expect("Enable fog uniform missing")
+                .value = UniformValue::Bool(pass.render_state.enable_fog);
+
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.fog_start.get_id())
+                .expect("Fog start uniform missing")
+                .value = UniformValue::Float(pass.render_state.fog_start);
+
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.fog_end.get_id())
+                .expect("Fog end uniform missing")
+                .value = UniformValue::Float(pass.render_state.fog_end);
+
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.fog_color.get_id())
+                .expect("Fog color uniform missing")
+                .value = UniformValue::Vec4(pass.render_state.fog_color);
+
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.lod_fade.get_id())
+                .expect("LOD fade uniform missing")
+                .value = UniformValue::Float(cmd.lod_fade);
+
+            let alpha_mode = asset_datas
+                .get::<Mesh>(cmd.mesh.get_id())
+                .map(|mesh| cmd.material.as_ref().map_or(mesh.material, |material| material.get_id()))
+                .and_then(|material_id| asset_datas.get::<Material>(material_id))
+                .map_or(AlphaMode::Opaque, |material| material.alpha_mode);
+
+            asset_datas
+                .get_mut::<Uniform>(global_uniforms.alpha_cutoff.get_id())
+                .expect("Alpha cutoff uniform missing")
+                .value = UniformValue::Float(alpha_mode.get_cutoff());
+
+            //let asset_datas = gpu.assets.get_datas();
+            let mesh = asset_datas
+                .get::<Mesh>(cmd.mesh.get_id())
+                .expect("Mesh resource not found");
+
+            let gpu_mesh = gpu_assets
+                .get::<GpuMesh>(cmd.mesh.get_id())
+                .expect("Gpu mesh not found");
+
+            let material_id = cmd.material
+                .as_ref()
+                .map_or(mesh.material, |material| material.get_id());
+
+            let material = asset_datas
+                .get::<Material>(material_id)
+                .expect("Material not found");
+
+            let mut uniform_values = [None; 64];
+
+            for (uniform_value, uniform_handle) in
+                uniform_values.iter_mut().zip(material.get_uniforms())
+            {
+                if let Some((name, handle)) = uniform_handle.clone() {
+                    if let Some(uniform) = asset_datas.get::<Uniform>(handle.get_id()) {
+                        *uniform_value = Some((name, uniform.get_gl_value(gpu_assets)));
+                    }
+                } else {
+                    break;
+                }
+            }
+
+            for (_, value) in uniform_values.iter().flatten() {
+                stats.uniform_uploads += 1;
+                if let uniforms::UniformValue::Texture2d(..) | uniforms::UniformValue::SrgbTexture2d(..) | uniforms::UniformValue::DepthTexture2d(..) = value {
+                    stats.texture_binds += 1;
+                }
+            }
+
+            let gl_uniform_values = GlUniformValues { uniform_values };
+
+            let gpu_program = gpu_assets
+                .get::<GpuProgram>(material.program.get_id())
+                .expect("GPU Program not found");
+
+            let draw_params = glium::DrawParameters {
+                depth: glium::Depth {
+                    test: if material.depth_test {
+                        glium::draw_parameters::DepthTest::IfLess
+                    } else {
+                        glium::draw_parameters::DepthTest::Overwrite
+                    },
+                    write: material.writes_depth(),
+                    ..Default::default()
+                },
+                // the cutout fragments are discarded by the programs
+                blend: if alpha_mode == AlphaMode::Blend {
+                    glium::draw_parameters::Blend::alpha_blending()
+                } else {
+                    Default::default()
+                },
+                ..Default::default()
+            };
+
+            stats.add_draw_call(
+                mesh.primitive_type,
+                mesh.vertices.len(),
+                mesh.indices.as_ref().map_or(mesh.vertices.len(), |indices| indices.len())
+            );
+
+            if let Some(gl_index_buffer) = &gpu_mesh.get_index_buffer() {
+                surface
+                    .draw(
+                        gpu_mesh.get_vertex_buffer(),
+                        gl_index_buffer,
+                        gpu_program.get_gl_program(),
+                        &gl_uniform_values,
+                        &draw_params,
+                    )
+                    .unwrap();
+            } else {
+                surface
+                    .draw(
+                        gpu_mesh.get_vertex_buffer(),
+                        glium::index::NoIndices(glium::index::PrimitiveType::from(
+                            mesh.primitive_type,
+                        )),
+                        gpu_program.get_gl_program(),
+                        &gl_uniform_values,
+                        &draw_params,
+                    )
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Reads the game framebuffer back when a capture needs it.
+    fn capture_framebuffer(&self, gpu: &mut GraphicsChip) {
+        if !gpu.capture.wants_frame() {
+            return;
+        }
+
+        let framebuffer_handle = match gpu.get_framebuffer() {
+            Some(framebuffer) => framebuffer,
+            None => return,
+        };
+
+        let color_id = match gpu.assets.get_datas().get::<Framebuffer>(framebuffer_handle.get_id()) {
+            Some(framebuffer) => framebuffer.get_color_target().get_id(),
+            None => return,
+        };
+
+        let raw_image: RawImage2d<u8> = match gpu.gpu_assets.get::<GpuImage>(color_id) {
+            Some(gpu_color) => gpu_color.get_gl_texture().read(),
+            None => return,
+        };
+
+        if let Some(image) = RgbaImage::from_raw(raw_image.width, raw_image.height, raw_image.data.into_owned()) {
+            // OpenGL rows go from bottom to top
+            let delta_time = gpu.get_delta_time();
+            gpu.capture.on_frame(imageops::flip_vertical(&image), delta_time);
+        }
     }
 
     pub fn blit_buffers_to_frame(&self, framebuffer: &SimpleFrameBuffer, frame: &mut Frame) {
@@ -0,0 +1,119 @@
+use glium::uniforms::{SamplerBehavior, MinifySamplerFilter, MagnifySamplerFilter, SamplerWrapFunction};
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum FilterMode {
+    Nearest,
+    Linear,
+}
+
+impl From<String> for FilterMode {
+    fn from(string: String) -> Self {
+        match string.as_str() {
+            "nearest" => return FilterMode::Nearest,
+            "linear" => return FilterMode::Linear,
+            _ => FilterMode::Nearest
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum WrapMode {
+    Repeat,
+    Clamp,
+    Mirrored,
+}
+
+impl From<String> for WrapMode {
+    fn from(string: String) -> Self {
+        match string.as_str() {
+            "repeat" => return WrapMode::Repeat,
+            "clamp" => return WrapMode::Clamp,
+            "mirrored" => return WrapMode::Mirrored,
+            _ => WrapMode::Repeat
+        }
+    }
+}
+
+impl From<WrapMode> for SamplerWrapFunction {
+    fn from(w: WrapMode) -> Self {
+        match w {
+            WrapMode::Repeat => SamplerWrapFunction::Repeat,
+            WrapMode::Clamp => SamplerWrapFunction::Clamp,
+            WrapMode::Mirrored => SamplerWrapFunction::Mirror,
+        }
+    }
+}
+
+/// How to pick between mipmap levels. `None` disables mipmaps generation.
+#[derive(Copy, Clone, PartialEq)]
+pub enum MipmapMode {
+    None,
+    Nearest,
+    Linear,
+}
+
+impl From<String> for MipmapMode {
+    fn from(string: String) -> Self {
+        match string.as_str() {
+            "none" => return MipmapMode::None,
+            "nearest" => return MipmapMode::Nearest,
+            "linear" => return MipmapMode::Linear,
+            _ => MipmapMode::None
+        }
+    }
+}
+
+/// Defines how an image is sampled when used as a texture.
+#[derive(Copy, Clone, PartialEq)]
+pub struct Sampler {
+    pub min_filter: FilterMode,
+    pub mag_filter: FilterMode,
+    pub mipmap_mode: MipmapMode,
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            min_filter: FilterMode::Nearest,
+            mag_filter: FilterMode::Nearest,
+            mipmap_mode: MipmapMode::None,
+            wrap_u: WrapMode::Mirrored,
+            wrap_v: WrapMode::Mirrored,
+        }
+    }
+}
+
+impl Sampler {
+    pub fn has_mipmaps(&self) -> bool {
+        self.mipmap_mode != MipmapMode::None
+    }
+
+    pub fn get_gl_sampler(&self) -> SamplerBehavior {
+        let minify_filter = match (self.min_filter, self.mipmap_mode) {
+            (FilterMode::Nearest, MipmapMode::None) => MinifySamplerFilter::Nearest,
+            (FilterMode::Linear, MipmapMode::None) => MinifySamplerFilter::Linear,
+            (FilterMode::Nearest, MipmapMode::Nearest) => MinifySamplerFilter::NearestMipmapNearest,
+            (FilterMode::Linear, MipmapMode::Nearest) => MinifySamplerFilter::LinearMipmapNearest,
+            (FilterMode::Nearest, MipmapMode::Linear) => MinifySamplerFilter::NearestMipmapLinear,
+            (FilterMode::Linear, MipmapMode::Linear) => MinifySamplerFilter::LinearMipmapLinear,
+        };
+
+        let magnify_filter = match self.mag_filter {
+            FilterMode::Nearest => MagnifySamplerFilter::Nearest,
+            FilterMode::Linear => MagnifySamplerFilter::Linear,
+        };
+
+        SamplerBehavior {
+            minify_filter,
+            magnify_filter,
+            wrap_function: (
+                SamplerWrapFunction::from(self.wrap_u),
+                SamplerWrapFunction::from(self.wrap_v),
+                SamplerWrapFunction::Mirror,
+            ),
+            .. Default::default()
+        }
+    }
+}
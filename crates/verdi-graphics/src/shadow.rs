@@ -0,0 +1,394 @@
+use std::ops::{Deref, DerefMut};
+
+use mlua::{UserData, UserDataMethods};
+use slotmap::Key;
+use verdi_database::{ResourceId, Resource, Assets, Handle};
+use verdi_math::{Vec3, Vec4, Mat4, Quat, prelude::{TransformHandle, Transform, LuaVec3}};
+
+use crate::{
+    mesh::{MeshHandle, Mesh},
+    material::MaterialHandle,
+    uniform::{Uniform, UniformHandle, UniformValue},
+    vertex::Vertex,
+};
+
+pub type ShadowId = ResourceId;
+
+/// Small offset keeping the shadows above the surface they are drawn on.
+const SHADOW_OFFSET: f32 = 0.01;
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3) -> Self {
+        Self {
+            point,
+            normal: normal.normalize_or_zero(),
+        }
+    }
+
+    /// Distance along the ray to the plane, if it is in front of the origin.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        let denom = direction.dot(self.normal);
+        if denom.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let t = (self.point - origin).dot(self.normal) / denom;
+        if t >= 0.0 { Some(t) } else { None }
+    }
+}
+
+/// Where blob shadows are projected.
+#[derive(Clone)]
+pub enum ShadowGround {
+    Plane(Plane),
+    Mesh(MeshHandle, TransformHandle),
+}
+
+impl ShadowGround {
+    /// Nearest hit point and surface normal along the ray.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3) -> Option<(Vec3, Vec3)> {
+        match self {
+            ShadowGround::Plane(plane) => {
+                let t = plane.raycast(origin, direction)?;
+                Some((origin + direction * t, plane.normal))
+            },
+            ShadowGround::Mesh(mesh, transform) => {
+                let matrix = transform
+                    .get_datas()
+                    .get::<Transform>(transform.get_id())
+                    .map_or(Mat4::IDENTITY, |transform| transform.to_matrix());
+
+                let datas = mesh.get_datas();
+                let mesh = datas.get::<Mesh>(mesh.get_id())?;
+                let indices: Vec<u32> = match &mesh.indices {
+                    Some(indices) => indices.clone(),
+                    None => (0..mesh.vertices.len() as u32).collect(),
+                };
+
+                let mut nearest: Option<(f32, Vec3)> = None;
+                for triangle in indices.chunks_exact(3) {
+                    let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|index| {
+                        let position = mesh.vertices.get(index as usize).map_or([0.0; 3], |vertex| vertex.position);
+                        matrix.transform_point3(Vec3::from(position))
+                    });
+
+                    if let Some(t) = ShadowGround::raycast_triangle(origin, direction, a, b, c) {
+                        if nearest.map_or(true, |(nearest_t, _)| t < nearest_t) {
+                            let mut normal = (b - a).cross(c - a).normalize_or_zero();
+                            // the normal faces the object
+                            if normal.dot(direction) > 0.0 {
+                                normal = -normal;
+                            }
+                            nearest = Some((t, normal));
+                        }
+                    }
+                }
+
+                nearest.map(|(t, normal)| (origin + direction * t, normal))
+            },
+        }
+    }
+
+    /// Möller-Trumbore ray / triangle intersection.
+    fn raycast_triangle(origin: Vec3, direction: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = direction.cross(edge2);
+        let det = edge1.dot(p);
+        if det.abs() < f32::EPSILON {
+            return None;
+        }
+
+        let inv_det = 1.0 / det;
+        let s = origin - a;
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(edge1);
+        let v = direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(q) * inv_det;
+        if t >= 0.0 { Some(t) } else { None }
+    }
+}
+
+#[derive(Clone)]
+pub enum ShadowKind {
+    /// A soft disc on the ground below the object.
+    Blob {
+        radius: f32,
+        max_distance: f32,
+        ground: ShadowGround,
+    },
+    /// The mesh flattened on a plane along the light direction.
+    Planar {
+        plane: Plane,
+        light_direction: Vec3,
+    },
+}
+
+/// Cheap shadow settings, given to a draw call to cast a shadow.
+pub struct Shadow {
+    pub kind: ShadowKind,
+    pub enabled: bool,
+    pub material: MaterialHandle,
+    pub quad: MeshHandle,
+    pub color: UniformHandle,
+    pub shadow_matrix: UniformHandle,
+    pub id: ShadowId,
+}
+
+impl Resource for Shadow {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Shadow {
+    pub fn new(
+        kind: ShadowKind,
+        material: MaterialHandle,
+        quad: MeshHandle,
+        color: UniformHandle,
+        shadow_matrix: UniformHandle
+    ) -> Self {
+        Self {
+            kind,
+            enabled: true,
+            material,
+            quad,
+            color,
+            shadow_matrix,
+            id: ShadowId::null(),
+        }
+    }
+
+    /// Matrix flattening any point on the plane, along the light direction.
+    pub fn planar_matrix(plane: &Plane, light_direction: Vec3) -> Mat4 {
+        let plane_vec = plane.normal.extend(-plane.normal.dot(plane.point) - SHADOW_OFFSET);
+        let mut light = light_direction.normalize_or_zero();
+        // keep w positive after the projection
+        if light.dot(plane.normal) < 0.0 {
+            light = -light;
+        }
+        let light_vec = light.extend(0.0);
+        let dot = plane_vec.dot(light_vec);
+
+        Mat4::from_diagonal(Vec4::splat(dot)) - Mat4::from_cols(
+            light_vec * plane_vec.x,
+            light_vec * plane_vec.y,
+            light_vec * plane_vec.z,
+            light_vec * plane_vec.w,
+        )
+    }
+
+    /// Transform of the blob quad below the given position, if the ground is close enough.
+    pub fn blob_transform(&self, position: Vec3) -> Option<Transform> {
+        match &self.kind {
+            ShadowKind::Blob { radius, max_distance, ground } => {
+                let (hit, normal) = ground.raycast(position, Vec3::NEG_Y)?;
+                if hit.distance(position) > *max_distance {
+                    return None;
+                }
+
+                let mut transform = Transform::new();
+                transform.set_position(hit + normal * SHADOW_OFFSET);
+                let rotation = Quat::from_rotation_arc(Vec3::Y, normal);
+                let (axis, angle) = rotation.to_axis_angle();
+                transform.set_rotation(angle, axis);
+                transform.set_scale(Vec3::splat(*radius * 2.0));
+                Some(transform)
+            },
+            ShadowKind::Planar { .. } => None,
+        }
+    }
+
+    /// A unit quad on the XZ plane.
+    pub fn quad_vertices() -> Vec<Vertex> {
+        [([-0.5, -0.5], [0.0, 0.0]), ([-0.5, 0.5], [0.0, 1.0]), ([0.5, -0.5], [1.0, 0.0]), ([0.5, 0.5], [1.0, 1.0])]
+            .iter()
+            .map(|([x, z], uv)| Vertex {
+                position: [*x, 0.0, *z],
+                normal: [0.0, 1.0, 0.0],
+                uv: *uv,
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+/// Shadow requested along a draw call, resolved before rendering.
+#[derive(Clone)]
+pub struct ShadowDraw {
+    pub shadow: ShadowHandle,
+    pub mesh: MeshHandle,
+    pub transform: TransformHandle,
+}
+
+/// Transforms of the blob shadows, reused from frame to frame.
+pub struct BlobTransforms {
+    transforms: Vec<TransformHandle>,
+    used: usize,
+}
+
+impl BlobTransforms {
+    pub fn new() -> Self {
+        Self {
+            transforms: Vec::new(),
+            used: 0,
+        }
+    }
+
+    pub fn next_free(&mut self) -> Option<TransformHandle> {
+        let transform = self.transforms.get(self.used)?.clone();
+        self.used += 1;
+        Some(transform)
+    }
+
+    pub fn push(&mut self, transform: TransformHandle) {
+        self.transforms.push(transform);
+        self.used = self.transforms.len();
+    }
+
+    pub fn next_frame(&mut self) {
+        self.used = 0;
+    }
+}
+
+#[derive(Clone)]
+pub struct ShadowHandle(Handle);
+
+impl Deref for ShadowHandle {
+    type Target = Handle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for ShadowHandle {
+    fn deref_mut(&mut self) -> &mut Handle {
+        &mut self.0
+    }
+}
+
+impl ShadowHandle {
+    pub fn new(assets: Assets, id: ShadowId) -> Self {
+        ShadowHandle(assets.new_handle(id))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.get_datas()
+            .get::<Shadow>(self.get_id())
+            .map_or(false, |shadow| shadow.enabled)
+    }
+
+    pub fn set_color(&mut self, color: Vec4) {
+        let color_id = match self.get_datas().get::<Shadow>(self.get_id()) {
+            Some(shadow) => shadow.color.get_id(),
+            None => return,
+        };
+
+        if let Some(uniform) = self.get_datas_mut().get_mut::<Uniform>(color_id) {
+            uniform.value = UniformValue::Vec4(color);
+        }
+    }
+
+    /// Modifies the shadow settings, and updates the projection of planar shadows.
+    pub fn edit<F: FnOnce(&mut Shadow)>(&mut self, func: F) {
+        let shadow_id = self.get_id();
+        let mut datas = self.get_datas_mut();
+
+        let planar_matrix = match datas.get_mut::<Shadow>(shadow_id) {
+            Some(shadow) => {
+                func(shadow);
+                match &shadow.kind {
+                    ShadowKind::Planar { plane, light_direction } => Some((
+                        shadow.shadow_matrix.get_id(),
+                        Shadow::planar_matrix(plane, *light_direction)
+                    )),
+                    ShadowKind::Blob { .. } => None,
+                }
+            },
+            None => return,
+        };
+
+        if let Some((uniform_id, matrix)) = planar_matrix {
+            if let Some(uniform) = datas.get_mut::<Uniform>(uniform_id) {
+                uniform.value = UniformValue::Mat4(matrix);
+            }
+        }
+    }
+}
+
+impl UserData for ShadowHandle {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("setEnabled", |_, shadow, enabled: bool| {
+            Ok(shadow.edit(|shadow| shadow.enabled = enabled))
+        });
+
+        methods.add_method("isEnabled", |_, shadow, ()| {
+            Ok(shadow.is_enabled())
+        });
+
+        methods.add_method_mut("setColor", |_, shadow, (r, g, b, a): (f32, f32, f32, Option<f32>)| {
+            Ok(shadow.set_color(Vec4::new(r, g, b, a.unwrap_or(1.0))))
+        });
+
+        methods.add_method_mut("setRadius", |_, shadow, value: f32| {
+            Ok(shadow.edit(|shadow| {
+                if let ShadowKind::Blob { radius, .. } = &mut shadow.kind {
+                    *radius = value;
+                }
+            }))
+        });
+
+        methods.add_method_mut("setMaxDistance", |_, shadow, value: f32| {
+            Ok(shadow.edit(|shadow| {
+                if let ShadowKind::Blob { max_distance, .. } = &mut shadow.kind {
+                    *max_distance = value;
+                }
+            }))
+        });
+
+        methods.add_method_mut("setPlane", |_, shadow, (point, normal): (LuaVec3, LuaVec3)| {
+            Ok(shadow.edit(|shadow| {
+                match &mut shadow.kind {
+                    ShadowKind::Planar { plane, .. } => *plane = Plane::new(*point, *normal),
+                    ShadowKind::Blob { ground, .. } => *ground = ShadowGround::Plane(Plane::new(*point, *normal)),
+                }
+            }))
+        });
+
+        methods.add_method_mut("setGroundMesh", |_, shadow, (mesh, transform): (MeshHandle, TransformHandle)| {
+            Ok(shadow.edit(|shadow| {
+                if let ShadowKind::Blob { ground, .. } = &mut shadow.kind {
+                    *ground = ShadowGround::Mesh(mesh, transform);
+                }
+            }))
+        });
+
+        methods.add_method_mut("setLightDirection", |_, shadow, direction: LuaVec3| {
+            Ok(shadow.edit(|shadow| {
+                if let ShadowKind::Planar { light_direction, .. } = &mut shadow.kind {
+                    *light_direction = *direction;
+                }
+            }))
+        });
+    }
+}
@@ -0,0 +1,166 @@
+use std::ops::{Deref, DerefMut};
+
+use mlua::{UserData, UserDataMethods, Table};
+use slotmap::Key;
+use verdi_database::{ResourceId, Resource, Assets, Handle};
+use verdi_math::{Vec4, prelude::TransformHandle};
+
+use crate::{
+    mesh::MeshHandle,
+    uniform::{Uniform, UniformHandle, UniformValue},
+    vertex::Vertex,
+};
+
+pub type SkyId = ResourceId;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum SkyMode {
+    /// Vertical gradient from the bottom to the top, through the horizon.
+    Gradient,
+    /// A single texture covering a whole turn, scrolling with the camera yaw.
+    Panorama,
+    /// Six faces stored in a horizontal strip: +x, -x, +y, -y, +z, -z.
+    Skybox,
+}
+
+impl SkyMode {
+    pub fn to_uniform(&self) -> f32 {
+        match self {
+            SkyMode::Gradient => 0.0,
+            SkyMode::Panorama => 1.0,
+            SkyMode::Skybox => 2.0,
+        }
+    }
+}
+
+/// A background drawn before everything else in a pass.
+pub struct Sky {
+    pub quad: MeshHandle,
+    pub transform: TransformHandle,
+    pub top: UniformHandle,
+    pub horizon: UniformHandle,
+    pub bottom: UniformHandle,
+    pub fog_height: UniformHandle,
+    pub id: SkyId,
+}
+
+impl Resource for Sky {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Sky {
+    pub fn new(
+        quad: MeshHandle,
+        transform: TransformHandle,
+        top: UniformHandle,
+        horizon: UniformHandle,
+        bottom: UniformHandle,
+        fog_height: UniformHandle
+    ) -> Self {
+        Self {
+            quad,
+            transform,
+            top,
+            horizon,
+            bottom,
+            fog_height,
+            id: SkyId::null(),
+        }
+    }
+
+    /// A quad covering the screen, in normalized device coordinates.
+    pub fn quad_vertices() -> Vec<Vertex> {
+        [[-1.0, 1.0], [-1.0, -1.0], [1.0, 1.0], [1.0, -1.0]]
+            .iter()
+            .map(|[x, y]| Vertex {
+                position: [*x, *y, 0.0],
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Reads a color from a table of 3 or 4 components.
+    pub fn color_from_table(table: &Table) -> mlua::Result<Vec4> {
+        Ok(
+            Vec4::new(
+                table.get(1)?,
+                table.get(2)?,
+                table.get(3)?,
+                table.get::<_, Option<f32>>(4)?.unwrap_or(1.0)
+            )
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct SkyHandle(Handle);
+
+impl Deref for SkyHandle {
+    type Target = Handle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for SkyHandle {
+    fn deref_mut(&mut self) -> &mut Handle {
+        &mut self.0
+    }
+}
+
+impl SkyHandle {
+    pub fn new(assets: Assets, id: SkyId) -> Self {
+        SkyHandle(assets.new_handle(id))
+    }
+
+    pub fn set_colors(&mut self, top: Vec4, horizon: Vec4, bottom: Vec4) {
+        let (top_id, horizon_id, bottom_id) = match self.get_datas().get::<Sky>(self.get_id()) {
+            Some(sky) => (sky.top.get_id(), sky.horizon.get_id(), sky.bottom.get_id()),
+            None => return,
+        };
+
+        let mut datas = self.get_datas_mut();
+        for (id, color) in [(top_id, top), (horizon_id, horizon), (bottom_id, bottom)] {
+            if let Some(uniform) = datas.get_mut::<Uniform>(id) {
+                uniform.value = UniformValue::Vec4(color);
+            }
+        }
+    }
+
+    /// Height of the band above and below the horizon faded into the fog, between 0 and 1.
+    pub fn set_fog_height(&mut self, height: f32) {
+        let fog_height_id = match self.get_datas().get::<Sky>(self.get_id()) {
+            Some(sky) => sky.fog_height.get_id(),
+            None => return,
+        };
+
+        if let Some(uniform) = self.get_datas_mut().get_mut::<Uniform>(fog_height_id) {
+            uniform.value = UniformValue::Float(height);
+        }
+    }
+}
+
+impl UserData for SkyHandle {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("setColors", |_, sky, (top, horizon, bottom): (Table, Table, Table)| {
+            Ok(
+                sky.set_colors(
+                    Sky::color_from_table(&top)?,
+                    Sky::color_from_table(&horizon)?,
+                    Sky::color_from_table(&bottom)?
+                )
+            )
+        });
+
+        methods.add_method_mut("setFogHeight", |_, sky, height: f32| {
+            Ok(sky.set_fog_height(height))
+        });
+    }
+}
@@ -0,0 +1,1301 @@
+use std::collections::HashMap;
+
+use image::RgbaImage;
+use verdi_math::{prelude::Transform, Mat3, Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
+
+use crate::{
+    camera::Camera,
+    framebuffer::Framebuffer,
+    depth_buffer::DepthBuffer,
+    image::Image,
+    indexed_image::IndexedImage,
+    material::{Material, AlphaMode},
+    mesh::{Mesh, PrimitiveType},
+    prelude::GraphicsChip,
+    render_state::RenderState,
+    sampler::{FilterMode, WrapMode},
+    uniform::{Uniform, UniformValue},
+    uv_animation::UvAnimation,
+    vertex::Vertex,
+};
+
+/// The built-in programs emulated on the CPU. Materials using another program are skipped.
+#[derive(Copy, Clone, PartialEq)]
+enum Shading {
+    Gouraud,
+    GouraudTextured,
+    Std2d,
+    Simple,
+    Palette,
+    Billboard,
+    Sky,
+    Shadow,
+    Terrain,
+    Flat,
+    Unlit,
+    Toon,
+    Outline,
+    Matcap,
+}
+
+/// A vertex after the vertex stage, in clip space.
+#[derive(Copy, Clone)]
+struct ClipVertex {
+    position: Vec4,
+    color: Vec4,
+    uv: Vec2,
+    fog_density: f32,
+    light: f32,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        ClipVertex {
+            position: self.position.lerp(other.position, t),
+            color: self.color.lerp(other.color, t),
+            uv: self.uv.lerp(other.uv, t),
+            fog_density: self.fog_density + (other.fog_density - self.fog_density) * t,
+            light: self.light + (other.light - self.light) * t,
+        }
+    }
+}
+
+/// A vertex in window space, rows going from top to bottom.
+#[derive(Copy, Clone)]
+struct ScreenVertex {
+    x: f32,
+    y: f32,
+    depth: f32,
+    inv_w: f32,
+    vertex: ClipVertex,
+}
+
+/// Values interpolated for a pixel.
+struct Fragment {
+    color: Vec4,
+    uv: Vec2,
+    fog_density: f32,
+    light: f32,
+}
+
+/// Color and depth pixels of a pass target.
+struct Target {
+    width: u32,
+    height: u32,
+    color: Vec<Vec4>,
+    depth: Vec<f32>,
+}
+
+impl Target {
+    fn new(width: u32, height: u32, clear_color: Vec4) -> Self {
+        let size = width as usize * height as usize;
+        Self {
+            width,
+            height,
+            color: vec![clear_color; size],
+            depth: vec![1.0; size],
+        }
+    }
+
+    fn to_image(&self) -> RgbaImage {
+        let mut image = RgbaImage::new(self.width, self.height);
+        for (pixel, color) in image.pixels_mut().zip(self.color.iter()) {
+            let color = (color.clamp(Vec4::ZERO, Vec4::ONE) * 255.0).round();
+            pixel.0 = [color.x as u8, color.y as u8, color.z as u8, color.w as u8];
+        }
+        image
+    }
+}
+
+/// Everything needed to draw one command.
+struct DrawState<'a> {
+    shading: Shading,
+    model: Mat4,
+    view: Mat4,
+    projection: Mat4,
+    resolution: Vec2,
+    enable_lighting: bool,
+    enable_fog: bool,
+    fog_start: f32,
+    fog_end: f32,
+    fog_color: Vec4,
+    depth_test: bool,
+    depth_write: bool,
+    alpha_mode: AlphaMode,
+    lod_fade: f32,
+    uniforms: HashMap<&'static str, UniformValue>,
+    texture: Option<&'a Image>,
+    indices: Option<&'a IndexedImage>,
+    palette: Option<&'a Image>,
+    splat_textures: [Option<&'a Image>; 4],
+    ramp: Option<&'a Image>,
+    matcap: Option<&'a Image>,
+}
+
+impl<'a> DrawState<'a> {
+    fn float(&self, name: &str) -> f32 {
+        match self.uniforms.get(name) {
+            Some(UniformValue::Float(value)) => *value,
+            _ => 0.0,
+        }
+    }
+
+    fn vec2(&self, name: &str) -> Vec2 {
+        match self.uniforms.get(name) {
+            Some(UniformValue::Vec2(value)) => *value,
+            _ => Vec2::ZERO,
+        }
+    }
+
+    fn vec4(&self, name: &str) -> Vec4 {
+        match self.uniforms.get(name) {
+            Some(UniformValue::Vec4(value)) => *value,
+            _ => Vec4::ZERO,
+        }
+    }
+
+    fn mat4(&self, name: &str) -> Mat4 {
+        match self.uniforms.get(name) {
+            Some(UniformValue::Mat4(value)) => *value,
+            _ => Mat4::IDENTITY,
+        }
+    }
+
+    fn bool(&self, name: &str) -> bool {
+        matches!(self.uniforms.get(name), Some(UniformValue::Bool(true)))
+    }
+}
+
+/// Rasterizes the render graph on the CPU, without any GPU context.
+/// It emulates the built-in programs: Gouraud, flat, toon and matcap shading, outlines, vertex snapping,
+/// affine texturing, palettes, fog, depth test, alpha cutout and blending, on triangles, lines and points.
+/// The toon and flat programs are lit for each vertex and the matcap is looked up for each vertex.
+/// Custom programs are not supported, textures are not mipmapped
+/// and colors are not converted between sRGB and linear.
+/// Only the first color target of the framebuffers is drawn, the depth is kept for the CPU.
+/// The other color targets of a pass with several outputs keep their previous pixels,
+/// and `DepthTexture` uniforms are never sampled, since only custom programs declare them.
+pub struct SoftwareRenderer {}
+
+impl SoftwareRenderer {
+    /// Draws every pass into the pixels of its color target,
+    /// and returns the image of the game framebuffer.
+    pub fn render(&mut self, gpu: &mut GraphicsChip) -> Option<RgbaImage> {
+        let render_graph = gpu.render_graph.clone();
+
+        for pass in render_graph.borrow().get_passes().iter() {
+            gpu.stats.passes += 1;
+
+            let (color_id, depth_id, target) = {
+                let asset_datas = gpu.assets.get_datas();
+                let framebuffer = match pass.get_framebuffer().and_then(|framebuffer| asset_datas.get::<Framebuffer>(framebuffer.get_id())) {
+                    Some(framebuffer) => framebuffer,
+                    None => continue,
+                };
+                let color_id = framebuffer.get_color_target().get_id();
+                let depth_id = framebuffer.get_depth_target().get_id();
+                let dimensions = match asset_datas.get::<Image>(color_id) {
+                    Some(color) => color.get_dimensions(),
+                    None => continue,
+                };
+
+                let mut target = Target::new(dimensions.0, dimensions.1, gpu.render_state.clear_color);
+
+                for cmd in pass.get_cmds() {
+                    let model = match cmd.transform.get_datas().get::<Transform>(cmd.transform.get_id()) {
+                        Some(transform) => transform.to_matrix(),
+                        None => continue,
+                    };
+
+                    let mesh = match asset_datas.get::<Mesh>(cmd.mesh.get_id()) {
+                        Some(mesh) => mesh,
+                        None => continue,
+                    };
+
+                    let material_id = cmd.material
+                        .as_ref()
+                        .map_or(mesh.material, |material| material.get_id());
+
+                    let material = match asset_datas.get::<Material>(material_id) {
+                        Some(material) => material,
+                        None => continue,
+                    };
+
+                    let shading = match self.shading(gpu, material) {
+                        Some(shading) => shading,
+                        None => continue,
+                    };
+
+                    let mut uniforms = HashMap::new();
+                    for (name, handle) in material.get_uniforms().iter().flatten() {
+                        if let Some(uniform) = asset_datas.get::<Uniform>(handle.get_id()) {
+                            uniforms.insert(*name, uniform.value);
+                        }
+                    }
+
+                    let texture_name = if shading == Shading::Sky { "u_sky_texture" } else { "u_texture" };
+                    let texture = match uniforms.get(texture_name) {
+                        Some(UniformValue::Texture(id)) => asset_datas.get::<Image>(*id),
+                        _ => None,
+                    };
+                    let indices = match uniforms.get("u_indices") {
+                        Some(UniformValue::IndexedTexture(id)) => asset_datas.get::<IndexedImage>(*id),
+                        _ => None,
+                    };
+                    let palette = match uniforms.get("u_palette") {
+                        Some(UniformValue::Texture(id)) => asset_datas.get::<Image>(*id),
+                        _ => None,
+                    };
+
+                    let splat_textures = ["u_texture0", "u_texture1", "u_texture2", "u_texture3"].map(|name| {
+                        match uniforms.get(name) {
+                            Some(UniformValue::Texture(id)) => asset_datas.get::<Image>(*id),
+                            _ => None,
+                        }
+                    });
+
+                    let [ramp, matcap] = ["u_ramp", "u_matcap"].map(|name| {
+                        match uniforms.get(name) {
+                            Some(UniformValue::Texture(id)) => asset_datas.get::<Image>(*id),
+                            _ => None,
+                        }
+                    });
+
+                    let projection = if cmd.perspective {
+                        Camera::perspective_matrix(target.width, target.height)
+                    } else {
+                        Camera::orthographic_matrix(0.0, target.width as f32, target.height as f32, 0.0, -10.0, 10.0)
+                    };
+
+                    let render_state: &RenderState = &pass.render_state;
+                    let state = DrawState {
+                        shading,
+                        model,
+                        view: render_state.view,
+                        projection,
+                        resolution: Vec2::new(target.width as f32, target.height as f32),
+                        // the uniforms are ignored by the programs which don't declare them
+                        enable_lighting: render_state.enable_lighting
+                            && matches!(uniforms.get("u_enable_lighting"), Some(UniformValue::Bool(true))),
+                        enable_fog: render_state.enable_fog && uniforms.contains_key("u_enable_fog"),
+                        fog_start: render_state.fog_start,
+                        fog_end: render_state.fog_end,
+                        fog_color: render_state.fog_color,
+                        depth_test: material.depth_test,
+                        depth_write: material.writes_depth(),
+                        alpha_mode: material.alpha_mode,
+                        lod_fade: if uniforms.contains_key("u_lod_fade") { cmd.lod_fade } else { 0.0 },
+                        uniforms,
+                        texture,
+                        indices,
+                        palette,
+                        splat_textures,
+                        ramp,
+                        matcap,
+                    };
+
+                    gpu.stats.add_draw_call(
+                        mesh.primitive_type,
+                        mesh.vertices.len(),
+                        mesh.indices.as_ref().map_or(mesh.vertices.len(), |indices| indices.len())
+                    );
+
+                    self.draw_mesh(&mut target, &state, mesh);
+                }
+
+                (color_id, depth_id, target)
+            };
+
+            // the next passes can sample the result
+            let image = target.to_image();
+            if let Some(color) = gpu.assets.get_datas_mut().get_mut::<Image>(color_id) {
+                *color.get_data_mut() = image;
+            }
+            if let Some(depth) = gpu.assets.get_datas_mut().get_mut::<DepthBuffer>(depth_id) {
+                depth.set_data(target.depth);
+            }
+        }
+
+        let framebuffer = gpu.get_framebuffer()?;
+        let color_id = gpu.assets
+            .get_datas()
+            .get::<Framebuffer>(framebuffer.get_id())?
+            .get_color_target()
+            .get_id();
+        let image = gpu.assets.get_datas().get::<Image>(color_id)?.get_data().clone()?;
+
+        if gpu.capture.wants_frame() {
+            let delta_time = gpu.get_delta_time();
+            gpu.capture.on_frame(image.clone(), delta_time);
+        }
+
+        Some(image)
+    }
+
+    fn shading(&self, gpu: &GraphicsChip, material: &Material) -> Option<Shading> {
+        let programs = &gpu.globals.global_programs;
+        let program_id = material.program.get_id();
+
+        [
+            (&programs.gouraud, Shading::Gouraud),
+            (&programs.gouraud_textured, Shading::GouraudTextured),
+            (&programs.std_2d, Shading::Std2d),
+            (&programs.simple, Shading::Simple),
+            (&programs.palette, Shading::Palette),
+            (&programs.billboard, Shading::Billboard),
+            (&programs.sky, Shading::Sky),
+            (&programs.shadow, Shading::Shadow),
+            (&programs.terrain, Shading::Terrain),
+            (&programs.flat, Shading::Flat),
+            (&programs.unlit, Shading::Unlit),
+            (&programs.toon, Shading::Toon),
+            (&programs.outline, Shading::Outline),
+            (&programs.matcap, Shading::Matcap),
+        ]
+        .iter()
+        .find(|(program, _)| program.get_id() == program_id)
+        .map(|(_, shading)| *shading)
+    }
+
+    fn draw_mesh(&self, target: &mut Target, state: &DrawState, mesh: &Mesh) {
+        let vertices: Vec<ClipVertex> = mesh.vertices
+            .iter()
+            .map(|vertex| self.shade_vertex(state, vertex))
+            .collect();
+
+        let indices: Vec<usize> = match &mesh.indices {
+            Some(indices) => indices.iter().map(|index| *index as usize).collect(),
+            None => (0..vertices.len()).collect(),
+        };
+
+        match mesh.primitive_type {
+            PrimitiveType::Triangles => {
+                for triangle in indices.chunks_exact(3) {
+                    let corners = match (
+                        mesh.vertices.get(triangle[0]),
+                        mesh.vertices.get(triangle[1]),
+                        mesh.vertices.get(triangle[2])
+                    ) {
+                        (Some(a), Some(b), Some(c)) => [a, b, c],
+                        _ => continue,
+                    };
+
+                    match state.shading {
+                        // the vertices are lit with the normal of the face
+                        Shading::Flat => {
+                            let shaded = SoftwareRenderer::face_vertices(corners).map(|vertex| self.shade_vertex(state, &vertex));
+                            self.draw_triangle(target, state, shaded);
+                        },
+                        // the hull is seen from inside only, around the mesh
+                        Shading::Outline if SoftwareRenderer::faces_camera(state, corners) => continue,
+                        _ => self.draw_triangle(target, state, [vertices[triangle[0]], vertices[triangle[1]], vertices[triangle[2]]]),
+                    }
+                }
+            },
+            PrimitiveType::Lines => {
+                for line in indices.chunks_exact(2) {
+                    if let (Some(a), Some(b)) = (vertices.get(line[0]), vertices.get(line[1])) {
+                        self.draw_line(target, state, [*a, *b]);
+                    }
+                }
+            },
+            PrimitiveType::Points => {
+                for index in indices.iter() {
+                    if let Some(vertex) = vertices.get(*index) {
+                        self.draw_point(target, state, *vertex);
+                    }
+                }
+            },
+        }
+    }
+
+    // Vertex stage
+
+    fn shade_vertex(&self, state: &DrawState, vertex: &Vertex) -> ClipVertex {
+        let position = Vec3::from(vertex.position);
+        let normal = Vec3::from(vertex.normal);
+        let color = Vec4::from(vertex.color);
+        let uv = self.animate_uv(state, Vec2::from(vertex.uv));
+
+        match state.shading {
+            Shading::Gouraud | Shading::GouraudTextured | Shading::Palette | Shading::Flat => {
+                let world_vertex = state.model * position.extend(1.0);
+                let view_vertex = state.view * world_vertex;
+
+                let color = if state.enable_lighting {
+                    let normal_matrix = Mat3::from_mat4(state.model.inverse().transpose());
+                    let lit = SoftwareRenderer::light(color, normal_matrix * normal, world_vertex.xyz());
+                    lit.truncate().extend(1.0)
+                } else {
+                    color
+                };
+
+                ClipVertex {
+                    position: self.snap(state, state.projection * view_vertex),
+                    color,
+                    uv,
+                    fog_density: self.fog_density(state, view_vertex.length()),
+                    light: 1.0,
+                }
+            },
+            Shading::Billboard => {
+                let mut world_vertex = state.model * position.extend(1.0);
+                let view_vertex = if normal.z > 0.5 {
+                    // cylindrical: rotates around the world up axis only
+                    let camera_right = state.view.row(0).truncate();
+                    let right = Vec3::new(camera_right.x, 0.0, camera_right.z);
+                    let right = if right.length() > 0.0 { right.normalize() } else { Vec3::X };
+                    world_vertex += (right * normal.x + Vec3::Y * normal.y).extend(0.0);
+                    state.view * world_vertex
+                } else {
+                    // spherical: the quad always faces the camera
+                    state.view * world_vertex + Vec4::new(normal.x, normal.y, 0.0, 0.0)
+                };
+
+                let color = if state.enable_lighting {
+                    let quad_normal = Mat3::from_mat4(state.view).transpose() * Vec3::new(0.0, 0.0, -1.0);
+                    SoftwareRenderer::light(color, quad_normal, world_vertex.xyz())
+                } else {
+                    color
+                };
+
+                ClipVertex {
+                    position: self.snap(state, state.projection * view_vertex),
+                    color,
+                    uv,
+                    fog_density: self.fog_density(state, view_vertex.length()),
+                    light: 1.0,
+                }
+            },
+            Shading::Unlit => {
+                let view_vertex = state.view * state.model * position.extend(1.0);
+
+                ClipVertex {
+                    position: self.snap(state, state.projection * view_vertex),
+                    color,
+                    uv,
+                    fog_density: self.fog_density(state, view_vertex.length()),
+                    light: 1.0,
+                }
+            },
+            Shading::Toon => {
+                let world_vertex = state.model * position.extend(1.0);
+                let view_vertex = state.view * world_vertex;
+
+                // the intensity picks a band of the ramp for each pixel
+                let light = if state.enable_lighting {
+                    let normal_matrix = Mat3::from_mat4(state.model.inverse().transpose());
+                    SoftwareRenderer::light(Vec4::ONE, normal_matrix * normal, world_vertex.xyz()).x
+                } else {
+                    1.0
+                };
+
+                ClipVertex {
+                    position: self.snap(state, state.projection * view_vertex),
+                    color,
+                    uv,
+                    fog_density: self.fog_density(state, view_vertex.length()),
+                    light,
+                }
+            },
+            Shading::Matcap => {
+                let view_vertex = state.view * state.model * position.extend(1.0);
+
+                // looked up for each vertex, the top of the image facing up
+                let normal_matrix = Mat3::from_mat4((state.view * state.model).inverse().transpose());
+                let view_normal = (normal_matrix * normal).normalize_or_zero();
+                let matcap_uv = Vec2::new(view_normal.x * 0.5 + 0.5, 0.5 - view_normal.y * 0.5);
+
+                ClipVertex {
+                    position: self.snap(state, state.projection * view_vertex),
+                    color: color * self.sample(state.matcap, matcap_uv),
+                    uv,
+                    fog_density: self.fog_density(state, view_vertex.length()),
+                    light: 1.0,
+                }
+            },
+            Shading::Outline => {
+                let normal_matrix = Mat3::from_mat4(state.model.inverse().transpose());
+                let world_normal = (normal_matrix * normal).normalize_or_zero();
+                let world_vertex = state.model * position.extend(1.0) + (world_normal * state.float("u_outline_width")).extend(0.0);
+                let view_vertex = state.view * world_vertex;
+
+                ClipVertex {
+                    position: self.snap(state, state.projection * view_vertex),
+                    color: state.vec4("u_outline_color"),
+                    uv,
+                    fog_density: self.fog_density(state, view_vertex.length()),
+                    light: 1.0,
+                }
+            },
+            Shading::Std2d => ClipVertex {
+                position: state.projection * state.model * Vec4::new(position.x, position.y, 0.0, 1.0),
+                color,
+                uv,
+                fog_density: 0.0,
+                light: 1.0,
+            },
+            Shading::Simple => ClipVertex {
+                position: state.projection * state.view * state.model * position.extend(1.0),
+                color,
+                uv,
+                fog_density: 0.0,
+                light: 1.0,
+            },
+            Shading::Sky => ClipVertex {
+                // the quad covers the whole screen, behind everything
+                position: Vec4::new(position.x, position.y, 1.0, 1.0),
+                color,
+                uv: Vec2::new(position.x, position.y),
+                fog_density: 0.0,
+                light: 1.0,
+            },
+            Shading::Terrain => {
+                let world_vertex = state.model * position.extend(1.0);
+                let view_vertex = state.view * world_vertex;
+
+                // the color holds the weights of the splat textures, the light is white
+                let light = if state.enable_lighting {
+                    let normal_matrix = Mat3::from_mat4(state.model.inverse().transpose());
+                    SoftwareRenderer::light(Vec4::ONE, normal_matrix * normal, world_vertex.xyz()).x
+                } else {
+                    1.0
+                };
+
+                ClipVertex {
+                    position: self.snap(state, state.projection * view_vertex),
+                    color,
+                    uv,
+                    fog_density: self.fog_density(state, view_vertex.length()),
+                    light,
+                }
+            },
+            Shading::Shadow => {
+                let world_vertex = state.mat4("u_shadow_matrix") * state.model * position.extend(1.0);
+                let view_vertex = state.view * world_vertex;
+
+                ClipVertex {
+                    position: self.snap(state, state.projection * view_vertex),
+                    color,
+                    uv,
+                    fog_density: self.fog_density(state, (view_vertex.xyz() / view_vertex.w).length()),
+                    light: 1.0,
+                }
+            },
+        }
+    }
+
+    /// UV animation of the programs drawing textures.
+    fn animate_uv(&self, state: &DrawState, uv: Vec2) -> Vec2 {
+        let animates = matches!(
+            state.shading,
+            Shading::Gouraud | Shading::GouraudTextured | Shading::Palette | Shading::Billboard | Shading::Std2d | Shading::Toon | Shading::Matcap
+        );
+        if !animates || !state.bool("u_uv_animated") {
+            return uv;
+        }
+
+        UvAnimation::transform_uv(
+            uv,
+            state.vec2("u_uv_offset"),
+            state.vec2("u_uv_scale"),
+            state.float("u_uv_rotation"),
+            state.vec2("u_uv_scroll"),
+            state.float("u_time")
+        )
+    }
+
+    /// The corners with the normal of the face, on the side of their own normals.
+    fn face_vertices([a, b, c]: [&Vertex; 3]) -> [Vertex; 3] {
+        let (pa, pb, pc) = (Vec3::from(a.position), Vec3::from(b.position), Vec3::from(c.position));
+        let normals = Vec3::from(a.normal) + Vec3::from(b.normal) + Vec3::from(c.normal);
+
+        let mut face_normal = (pb - pa).cross(pc - pa).normalize_or_zero();
+        if face_normal.dot(normals) < 0.0 {
+            face_normal = -face_normal;
+        }
+
+        [a, b, c].map(|vertex| Vertex { normal: face_normal.to_array(), ..*vertex })
+    }
+
+    /// Whether the triangle of the outline hull faces the camera, whatever its winding.
+    fn faces_camera(state: &DrawState, corners: [&Vertex; 3]) -> bool {
+        let normal_matrix = Mat3::from_mat4(state.model.inverse().transpose());
+        let normal = normal_matrix * corners.iter().fold(Vec3::ZERO, |sum, vertex| sum + Vec3::from(vertex.normal));
+        let center = state.model.transform_point3(corners.iter().fold(Vec3::ZERO, |sum, vertex| sum + Vec3::from(vertex.position)) / 3.0);
+        let camera_position = state.view.inverse().w_axis.truncate();
+
+        normal.dot(camera_position - center) > 0.0
+    }
+
+    /// Ambient and diffuse lighting from the fixed light of the built-in programs.
+    fn light(color: Vec4, normal: Vec3, world_position: Vec3) -> Vec4 {
+        let ambient = 0.1;
+        let light = Vec3::new(1.0, 0.0, 0.0);
+        let lighting_dir = (light - world_position).normalize_or_zero();
+        let diffuse = lighting_dir.dot(normal.normalize_or_zero()).max(0.0);
+
+        (color.truncate() * (ambient + diffuse)).extend(color.w)
+    }
+
+    /// Polygon jittering: snaps the vertex to the target resolution.
+    fn snap(&self, state: &DrawState, mut vertex: Vec4) -> Vec4 {
+        if vertex.w == 0.0 {
+            return vertex;
+        }
+
+        let ndc = vertex.xy() / vertex.w;
+        let snapped = (state.resolution * ndc).floor() / state.resolution;
+        vertex.x = snapped.x * vertex.w;
+        vertex.y = snapped.y * vertex.w;
+        vertex
+    }
+
+    fn fog_density(&self, state: &DrawState, depth: f32) -> f32 {
+        if !state.enable_fog {
+            return 0.0;
+        }
+
+        if state.fog_end <= state.fog_start {
+            return if depth >= state.fog_end { 1.0 } else { 0.0 };
+        }
+
+        ((depth - state.fog_start) / (state.fog_end - state.fog_start)).clamp(0.0, 1.0)
+    }
+
+    // Clipping
+
+    /// Clips a polygon against the near and far planes.
+    fn clip_polygon(&self, polygon: Vec<ClipVertex>) -> Vec<ClipVertex> {
+        let near = |vertex: &ClipVertex| vertex.position.z + vertex.position.w;
+        let far = |vertex: &ClipVertex| vertex.position.w - vertex.position.z;
+
+        let mut polygon = polygon;
+        for distance in [&near as &dyn Fn(&ClipVertex) -> f32, &far] {
+            let mut clipped = Vec::with_capacity(polygon.len() + 2);
+            for (index, current) in polygon.iter().enumerate() {
+                let next = &polygon[(index + 1) % polygon.len()];
+                let (current_distance, next_distance) = (distance(current), distance(next));
+
+                if current_distance >= 0.0 {
+                    clipped.push(*current);
+                }
+                if (current_distance >= 0.0) != (next_distance >= 0.0) {
+                    let t = current_distance / (current_distance - next_distance);
+                    clipped.push(current.lerp(next, t));
+                }
+            }
+            polygon = clipped;
+        }
+
+        polygon
+    }
+
+    fn to_screen(&self, target: &Target, vertex: ClipVertex) -> ScreenVertex {
+        let inv_w = 1.0 / vertex.position.w;
+        let ndc = vertex.position.xyz() * inv_w;
+
+        ScreenVertex {
+            x: (ndc.x * 0.5 + 0.5) * target.width as f32,
+            y: (0.5 - ndc.y * 0.5) * target.height as f32,
+            depth: ndc.z * 0.5 + 0.5,
+            inv_w,
+            vertex,
+        }
+    }
+
+    // Rasterization
+
+    fn draw_triangle(&self, target: &mut Target, state: &DrawState, triangle: [ClipVertex; 3]) {
+        let polygon: Vec<ScreenVertex> = self
+            .clip_polygon(triangle.to_vec())
+            .into_iter()
+            .map(|vertex| self.to_screen(target, vertex))
+            .collect();
+
+        // the clipped polygon is convex, drawn as a fan
+        for index in 1..polygon.len().saturating_sub(1) {
+            self.fill_triangle(target, state, [polygon[0], polygon[index], polygon[index + 1]]);
+        }
+    }
+
+    fn fill_triangle(&self, target: &mut Target, state: &DrawState, [a, b, c]: [ScreenVertex; 3]) {
+        let edge = |p: &ScreenVertex, q: &ScreenVertex, x: f32, y: f32| (q.x - p.x) * (y - p.y) - (q.y - p.y) * (x - p.x);
+
+        let area = edge(&a, &b, c.x, c.y);
+        if area == 0.0 {
+            return;
+        }
+
+        let min_x = a.x.min(b.x).min(c.x).floor().max(0.0) as u32;
+        let min_y = a.y.min(b.y).min(c.y).floor().max(0.0) as u32;
+        let max_x = (a.x.max(b.x).max(c.x).ceil().max(0.0) as u32).min(target.width);
+        let max_y = (a.y.max(b.y).max(c.y).ceil().max(0.0) as u32).min(target.height);
+
+        // the shadow uvs are perspective correct, the other attributes are affine
+        let perspective_uv = state.shading == Shading::Shadow;
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let wa = edge(&b, &c, px, py) / area;
+                let wb = edge(&c, &a, px, py) / area;
+                let wc = edge(&a, &b, px, py) / area;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+
+                let depth = a.depth * wa + b.depth * wb + c.depth * wc;
+
+                let inv_w = a.inv_w * wa + b.inv_w * wb + c.inv_w * wc;
+                let correct = |va: f32, vb: f32, vc: f32| {
+                    (va * a.inv_w * wa + vb * b.inv_w * wb + vc * c.inv_w * wc) / inv_w
+                };
+
+                let uv = if perspective_uv {
+                    Vec2::new(
+                        correct(a.vertex.uv.x, b.vertex.uv.x, c.vertex.uv.x),
+                        correct(a.vertex.uv.y, b.vertex.uv.y, c.vertex.uv.y),
+                    )
+                } else {
+                    a.vertex.uv * wa + b.vertex.uv * wb + c.vertex.uv * wc
+                };
+
+                let fragment = Fragment {
+                    color: a.vertex.color * wa + b.vertex.color * wb + c.vertex.color * wc,
+                    uv,
+                    fog_density: correct(a.vertex.fog_density, b.vertex.fog_density, c.vertex.fog_density),
+                    light: a.vertex.light * wa + b.vertex.light * wb + c.vertex.light * wc,
+                };
+
+                self.write_fragment(target, state, x, y, depth, &fragment);
+            }
+        }
+    }
+
+    fn draw_line(&self, target: &mut Target, state: &DrawState, [start, end]: [ClipVertex; 2]) {
+        // a degenerate polygon clips like a segment
+        let clipped = self.clip_polygon(vec![start, end]);
+        let (start, end) = match (clipped.first(), clipped.last()) {
+            (Some(start), Some(end)) => (self.to_screen(target, *start), self.to_screen(target, *end)),
+            _ => return,
+        };
+
+        let steps = (end.x - start.x).abs().max((end.y - start.y).abs()).ceil().max(1.0) as u32;
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            let (x, y) = (start.x + (end.x - start.x) * t, start.y + (end.y - start.y) * t);
+            if x < 0.0 || y < 0.0 || x >= target.width as f32 || y >= target.height as f32 {
+                continue;
+            }
+
+            let vertex = start.vertex.lerp(&end.vertex, t);
+            let fragment = Fragment {
+                color: vertex.color,
+                uv: vertex.uv,
+                fog_density: vertex.fog_density,
+                light: vertex.light,
+            };
+            let depth = start.depth + (end.depth - start.depth) * t;
+
+            self.write_fragment(target, state, x as u32, y as u32, depth, &fragment);
+        }
+    }
+
+    fn draw_point(&self, target: &mut Target, state: &DrawState, vertex: ClipVertex) {
+        let position = vertex.position;
+        if position.z < -position.w || position.z > position.w {
+            return;
+        }
+
+        let point = self.to_screen(target, vertex);
+        if point.x < 0.0 || point.y < 0.0 || point.x >= target.width as f32 || point.y >= target.height as f32 {
+            return;
+        }
+
+        let fragment = Fragment {
+            color: vertex.color,
+            uv: vertex.uv,
+            fog_density: vertex.fog_density,
+            light: vertex.light,
+        };
+
+        self.write_fragment(target, state, point.x as u32, point.y as u32, point.depth, &fragment);
+    }
+
+    /// Depth test, fragment stage and alpha cutout or blending.
+    fn write_fragment(&self, target: &mut Target, state: &DrawState, x: u32, y: u32, depth: f32, fragment: &Fragment) {
+        let index = y as usize * target.width as usize + x as usize;
+
+        if state.depth_test && depth >= target.depth[index] {
+            return;
+        }
+
+        if SoftwareRenderer::lod_discarded(state, x, y) {
+            return;
+        }
+
+        let source = self.shade_fragment(state, fragment).clamp(Vec4::ZERO, Vec4::ONE);
+        match state.alpha_mode {
+            AlphaMode::Opaque => target.color[index] = source,
+            AlphaMode::Cutout(cutoff) => {
+                if source.w < cutoff {
+                    return;
+                }
+                target.color[index] = source;
+            },
+            AlphaMode::Blend => {
+                let destination = target.color[index];
+                target.color[index] = source * source.w + destination * (1.0 - source.w);
+            },
+        }
+
+        if state.depth_write {
+            target.depth[index] = depth;
+        }
+    }
+
+    // Fragment stage
+
+    fn shade_fragment(&self, state: &DrawState, fragment: &Fragment) -> Vec4 {
+        let frame_uv = UvAnimation::frame_uv(fragment.uv, state.vec4("u_uv_frame"));
+
+        match state.shading {
+            Shading::Gouraud | Shading::Flat | Shading::Unlit | Shading::Outline => fragment.color.lerp(state.fog_color, fragment.fog_density),
+            Shading::Toon => {
+                let band = self.sample(state.ramp, Vec2::new(fragment.light.clamp(0.0, 0.999), 0.5));
+                let texel = self.sample(state.texture, fragment.uv);
+                (fragment.color.truncate() * texel.truncate() * band.truncate())
+                    .extend(fragment.color.w * texel.w)
+                    .lerp(state.fog_color, fragment.fog_density)
+            },
+            Shading::Matcap => (fragment.color * self.sample(state.texture, fragment.uv)).lerp(state.fog_color, fragment.fog_density),
+            Shading::GouraudTextured | Shading::Billboard => {
+                let texel = self.sample(state.texture, frame_uv);
+                (fragment.color * texel).lerp(state.fog_color, fragment.fog_density)
+            },
+            Shading::Std2d => fragment.color * self.sample(state.texture, frame_uv),
+            Shading::Simple => fragment.color,
+            Shading::Palette => {
+                let texel = self.palette_color(state, frame_uv);
+                (fragment.color * texel).lerp(state.fog_color, fragment.fog_density)
+            },
+            Shading::Sky => self.sky_color(state, fragment.uv),
+            Shading::Terrain => {
+                let uv = fragment.uv * state.float("u_tiling");
+                let weights = fragment.color;
+                let total = weights.dot(Vec4::ONE).max(0.0001);
+                let texel = (0..4).fold(Vec3::ZERO, |texel, i| {
+                    texel + self.sample(state.splat_textures[i], uv).truncate() * weights[i]
+                }) / total;
+                (texel * fragment.light).extend(1.0).lerp(state.fog_color, fragment.fog_density)
+            },
+            Shading::Shadow => {
+                let mut color = state.vec4("u_shadow_color");
+                if state.bool("u_blob") {
+                    let distance = (fragment.uv * 2.0 - Vec2::ONE).length();
+                    color.w *= 1.0 - SoftwareRenderer::smoothstep(0.3, 1.0, distance);
+                }
+                // shadows vanish in the fog
+                color.w *= 1.0 - fragment.fog_density;
+                color
+            },
+        }
+    }
+
+    fn sky_color(&self, state: &DrawState, ndc: Vec2) -> Vec4 {
+        // view direction of the pixel, in world space
+        let view_dir = state.projection.inverse() * Vec4::new(ndc.x, ndc.y, 1.0, 1.0);
+        let dir = (Mat3::from_mat4(state.view).transpose() * (view_dir.xyz() / view_dir.w)).normalize_or_zero();
+
+        let mode = state.float("u_sky_mode");
+        let mut color = if mode < 0.5 {
+            let horizon = state.vec4("u_sky_horizon");
+            if dir.y > 0.0 {
+                horizon.lerp(state.vec4("u_sky_top"), dir.y)
+            } else {
+                horizon.lerp(state.vec4("u_sky_bottom"), -dir.y)
+            }
+        } else if mode < 1.5 {
+            // scrolls with the camera yaw
+            let u = dir.x.atan2(dir.z) / std::f32::consts::TAU + 0.5;
+            let v = 0.5 - dir.y.clamp(-1.0, 1.0).asin() / std::f32::consts::PI;
+            self.sample(state.texture, Vec2::new(u, v))
+        } else {
+            let a = dir.abs();
+            let (face, face_uv) = if a.x >= a.y && a.x >= a.z {
+                (if dir.x > 0.0 { 0.0 } else { 1.0 }, Vec2::new(if dir.x > 0.0 { -dir.z } else { dir.z }, -dir.y) / a.x)
+            } else if a.y >= a.z {
+                (if dir.y > 0.0 { 2.0 } else { 3.0 }, Vec2::new(dir.x, if dir.y > 0.0 { dir.z } else { -dir.z }) / a.y)
+            } else {
+                (if dir.z > 0.0 { 4.0 } else { 5.0 }, Vec2::new(if dir.z > 0.0 { dir.x } else { -dir.x }, -dir.y) / a.z)
+            };
+            let face_uv = (face_uv * 0.5 + 0.5).clamp(Vec2::ZERO, Vec2::ONE);
+            self.sample(state.texture, Vec2::new((face + face_uv.x) / 6.0, face_uv.y))
+        };
+
+        // the sky fades into the fog at the horizon
+        if state.enable_fog {
+            let fog_density = 1.0 - (dir.y.abs() / state.float("u_sky_fog_height").max(0.0001)).clamp(0.0, 1.0);
+            color = color.lerp(state.fog_color, fog_density);
+        }
+
+        color
+    }
+
+    /// LOD cross-fade: the outgoing level (positive) and the incoming one (negative) keep complementary pixels.
+    fn lod_discarded(state: &DrawState, x: u32, y: u32) -> bool {
+        const BAYER: [f32; 16] = [0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0];
+
+        let uses_fade = matches!(
+            state.shading,
+            Shading::Gouraud | Shading::GouraudTextured | Shading::Palette | Shading::Flat | Shading::Unlit | Shading::Toon | Shading::Matcap
+        );
+        if !uses_fade || state.lod_fade == 0.0 {
+            return false;
+        }
+
+        let threshold = (BAYER[((y & 3) * 4 + (x & 3)) as usize] + 0.5) / 16.0;
+        if state.lod_fade > 0.0 {
+            threshold < state.lod_fade
+        } else {
+            threshold >= -state.lod_fade
+        }
+    }
+
+    fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+        let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    // Textures
+
+    /// Samples an image, v = 0 being the top row.
+    fn sample(&self, image: Option<&Image>, uv: Vec2) -> Vec4 {
+        let image = match image {
+            Some(image) => image,
+            None => return Vec4::ZERO,
+        };
+
+        let (width, height) = image.get_dimensions();
+        if width == 0 || height == 0 {
+            return Vec4::ZERO;
+        }
+
+        let sampler = image.get_sampler();
+        let texel = |x: i64, y: i64| {
+            let x = SoftwareRenderer::wrap(x, width, sampler.wrap_u);
+            let y = SoftwareRenderer::wrap(y, height, sampler.wrap_v);
+            image
+                .get_pixel(x, y)
+                .map_or(Vec4::ZERO, |pixel| Vec4::new(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32, pixel[3] as f32) / 255.0)
+        };
+
+        let x = uv.x * width as f32;
+        let y = uv.y * height as f32;
+
+        match sampler.mag_filter {
+            FilterMode::Nearest => texel(x.floor() as i64, y.floor() as i64),
+            FilterMode::Linear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+
+                let top = texel(x0, y0).lerp(texel(x0 + 1, y0), tx);
+                let bottom = texel(x0, y0 + 1).lerp(texel(x0 + 1, y0 + 1), tx);
+                top.lerp(bottom, ty)
+            },
+        }
+    }
+
+    fn wrap(coord: i64, size: u32, mode: WrapMode) -> u32 {
+        let size = size as i64;
+        let wrapped = match mode {
+            WrapMode::Repeat => coord.rem_euclid(size),
+            WrapMode::Clamp => coord.clamp(0, size - 1),
+            WrapMode::Mirrored => {
+                let period = coord.rem_euclid(size * 2);
+                if period < size { period } else { size * 2 - 1 - period }
+            },
+        };
+        wrapped as u32
+    }
+
+    /// Palette lookup: entries are read from left to right and top to bottom.
+    fn palette_color(&self, state: &DrawState, uv: Vec2) -> Vec4 {
+        let (indices, palette) = match (state.indices, state.palette) {
+            (Some(indices), Some(palette)) => (indices, palette),
+            _ => return Vec4::ZERO,
+        };
+
+        let (width, height) = indices.get_dimensions();
+        if width == 0 || height == 0 {
+            return Vec4::ZERO;
+        }
+
+        let x = SoftwareRenderer::wrap((uv.x * width as f32).floor() as i64, width, WrapMode::Repeat);
+        let y = SoftwareRenderer::wrap((uv.y * height as f32).floor() as i64, height, WrapMode::Repeat);
+        let index = indices.get_index(x, y).unwrap_or(0) as u32;
+
+        let palette_width = palette.get_dimensions().0.max(1);
+        palette
+            .get_pixel(index % palette_width, index / palette_width)
+            .map_or(Vec4::ZERO, |pixel| Vec4::new(pixel[0] as f32, pixel[1] as f32, pixel[2] as f32, pixel[3] as f32) / 255.0)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Vec4 = Vec4::new(1.0, 0.0, 0.0, 1.0);
+    const GREEN: Vec4 = Vec4::new(0.0, 1.0, 0.0, 1.0);
+    const BLUE: Vec4 = Vec4::new(0.0, 0.0, 1.0, 1.0);
+
+    fn state<'a>(shading: Shading) -> DrawState<'a> {
+        DrawState {
+            shading,
+            model: Mat4::IDENTITY,
+            view: Mat4::IDENTITY,
+            projection: Mat4::IDENTITY,
+            resolution: Vec2::new(8.0, 8.0),
+            enable_lighting: false,
+            enable_fog: false,
+            fog_start: 0.0,
+            fog_end: 1.0,
+            fog_color: Vec4::ZERO,
+            depth_test: true,
+            depth_write: true,
+            alpha_mode: AlphaMode::Opaque,
+            lod_fade: 0.0,
+            uniforms: HashMap::new(),
+            texture: None,
+            indices: None,
+            palette: None,
+            splat_textures: [None; 4],
+            ramp: None,
+            matcap: None,
+        }
+    }
+
+    fn fragment(color: Vec4) -> Fragment {
+        Fragment {
+            color,
+            uv: Vec2::ZERO,
+            fog_density: 0.0,
+            light: 1.0,
+        }
+    }
+
+    fn clip_vertex(position: Vec4) -> ClipVertex {
+        ClipVertex {
+            position,
+            color: RED,
+            uv: Vec2::ZERO,
+            fog_density: 0.0,
+            light: 1.0,
+        }
+    }
+
+    fn assert_color(actual: Vec4, expected: Vec4) {
+        assert!(actual.abs_diff_eq(expected, 1e-5), "{} instead of {}", actual, expected);
+    }
+
+    /// A 2x2 image: red, green on the top row and blue, white on the bottom one.
+    fn image(filter: FilterMode, wrap: WrapMode) -> Image {
+        let mut data = RgbaImage::new(2, 2);
+        data.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        data.put_pixel(1, 0, image::Rgba([0, 255, 0, 255]));
+        data.put_pixel(0, 1, image::Rgba([0, 0, 255, 255]));
+        data.put_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+
+        let mut image = Image::from_data(data);
+        image.set_filter(filter, filter);
+        image.set_wrap(wrap, wrap);
+        image
+    }
+
+    #[test]
+    fn triangle_is_clipped_by_the_near_plane() {
+        let renderer = SoftwareRenderer {};
+
+        // the top vertex is in front of the near plane, which cuts the triangle at mid height
+        let triangle = [
+            clip_vertex(Vec4::new(0.0, 1.0, -3.0, 1.0)),
+            clip_vertex(Vec4::new(-1.0, -1.0, 1.0, 1.0)),
+            clip_vertex(Vec4::new(1.0, -1.0, 1.0, 1.0)),
+        ];
+
+        let polygon = renderer.clip_polygon(triangle.to_vec());
+        assert_eq!(polygon.len(), 4);
+        assert!(polygon.iter().all(|vertex| vertex.position.z >= -vertex.position.w - 1e-5));
+        assert!(polygon.iter().all(|vertex| vertex.position.y <= 1e-5));
+
+        let mut target = Target::new(8, 8, Vec4::ZERO);
+        renderer.draw_triangle(&mut target, &state(Shading::Simple), triangle);
+
+        let covered = |rows: std::ops::Range<usize>| {
+            rows.flat_map(|y| (0..8).map(move |x| y * 8 + x))
+                .filter(|index| target.color[*index] == RED)
+                .count()
+        };
+        assert_eq!(covered(0..4), 0);
+        assert!(covered(4..8) > 0);
+
+        // entirely in front of the near plane
+        let mut target = Target::new(8, 8, Vec4::ZERO);
+        let hidden = [
+            clip_vertex(Vec4::new(0.0, 1.0, -3.0, 1.0)),
+            clip_vertex(Vec4::new(-1.0, -1.0, -2.0, 1.0)),
+            clip_vertex(Vec4::new(1.0, -1.0, -2.0, 1.0)),
+        ];
+        renderer.draw_triangle(&mut target, &state(Shading::Simple), hidden);
+        assert!(target.color.iter().all(|color| *color == Vec4::ZERO));
+    }
+
+    #[test]
+    fn depth_test_keeps_the_closest_fragment() {
+        let renderer = SoftwareRenderer {};
+        let mut target = Target::new(1, 1, Vec4::ZERO);
+        let mut state = state(Shading::Simple);
+
+        renderer.write_fragment(&mut target, &state, 0, 0, 0.5, &fragment(RED));
+        renderer.write_fragment(&mut target, &state, 0, 0, 0.7, &fragment(GREEN));
+        assert_color(target.color[0], RED);
+        assert_eq!(target.depth[0], 0.5);
+
+        renderer.write_fragment(&mut target, &state, 0, 0, 0.3, &fragment(BLUE));
+        assert_color(target.color[0], BLUE);
+        assert_eq!(target.depth[0], 0.3);
+
+        // drawn on top, without touching the depth
+        state.depth_test = false;
+        state.depth_write = false;
+        renderer.write_fragment(&mut target, &state, 0, 0, 0.9, &fragment(GREEN));
+        assert_color(target.color[0], GREEN);
+        assert_eq!(target.depth[0], 0.3);
+    }
+
+    #[test]
+    fn nearest_sampling_wraps() {
+        let renderer = SoftwareRenderer {};
+        let sample = |wrap: WrapMode, u: f32| renderer.sample(Some(&image(FilterMode::Nearest, wrap)), Vec2::new(u, 0.25));
+
+        for wrap in [WrapMode::Repeat, WrapMode::Clamp, WrapMode::Mirrored] {
+            assert_color(sample(wrap, 0.25), RED);
+            assert_color(sample(wrap, 0.75), GREEN);
+        }
+
+        // half a texel before the image
+        assert_color(sample(WrapMode::Repeat, -0.25), GREEN);
+        assert_color(sample(WrapMode::Clamp, -0.25), RED);
+        assert_color(sample(WrapMode::Mirrored, -0.25), RED);
+
+        // one and a half texel before the image
+        assert_color(sample(WrapMode::Repeat, -0.75), RED);
+        assert_color(sample(WrapMode::Clamp, -0.75), RED);
+        assert_color(sample(WrapMode::Mirrored, -0.75), GREEN);
+
+        assert_color(renderer.sample(Some(&image(FilterMode::Nearest, WrapMode::Repeat)), Vec2::new(0.25, 0.75)), BLUE);
+        assert_color(renderer.sample(None, Vec2::ZERO), Vec4::ZERO);
+    }
+
+    #[test]
+    fn linear_sampling_wraps() {
+        let renderer = SoftwareRenderer {};
+        let sample = |wrap: WrapMode, u: f32| renderer.sample(Some(&image(FilterMode::Linear, wrap)), Vec2::new(u, 0.25));
+
+        // texel centers, and between the two texels of the top row
+        for wrap in [WrapMode::Repeat, WrapMode::Clamp, WrapMode::Mirrored] {
+            assert_color(sample(wrap, 0.25), RED);
+            assert_color(sample(wrap, 0.5), RED.lerp(GREEN, 0.5));
+        }
+
+        // the left edge blends with the last texel only when repeating
+        assert_color(sample(WrapMode::Repeat, 0.0), GREEN.lerp(RED, 0.5));
+        assert_color(sample(WrapMode::Clamp, 0.0), RED);
+        assert_color(sample(WrapMode::Mirrored, 0.0), RED);
+
+        // a texel before the image, the mirror shows the green texel again
+        assert_color(sample(WrapMode::Repeat, -0.25), GREEN);
+        assert_color(sample(WrapMode::Clamp, -0.25), RED);
+        assert_color(sample(WrapMode::Mirrored, -0.5), GREEN.lerp(RED, 0.5));
+        assert_color(sample(WrapMode::Mirrored, -0.75), GREEN);
+    }
+
+    #[test]
+    fn lod_fade_discards_complementary_pixels() {
+        let discarded = |shading: Shading, lod_fade: f32| {
+            let mut state = state(shading);
+            state.lod_fade = lod_fade;
+            (0..4)
+                .flat_map(|y| (0..4).map(move |x| (x, y)))
+                .filter(|(x, y)| SoftwareRenderer::lod_discarded(&state, *x, *y))
+                .collect::<Vec<_>>()
+        };
+
+        assert!(discarded(Shading::Gouraud, 0.0).is_empty());
+        assert_eq!(discarded(Shading::Gouraud, 0.25).len(), 4);
+        assert_eq!(discarded(Shading::Gouraud, 1.0).len(), 16);
+
+        // the ordered pattern spreads the pixels: one per 2x2 block at a quarter
+        let quarter = discarded(Shading::Gouraud, 0.25);
+        for block in [(0, 0), (2, 0), (0, 2), (2, 2)] {
+            assert_eq!(quarter.iter().filter(|(x, y)| x / 2 * 2 == block.0 && y / 2 * 2 == block.1).count(), 1);
+        }
+
+        // the incoming level fills the pixels left by the outgoing one
+        let outgoing = discarded(Shading::Gouraud, 0.5);
+        let incoming = discarded(Shading::Gouraud, -0.5);
+        assert_eq!(outgoing.len(), 8);
+        assert_eq!(incoming.len(), 8);
+        assert!(outgoing.iter().all(|pixel| !incoming.contains(pixel)));
+
+        // the pattern repeats every 4 pixels
+        let mut state = state(Shading::Gouraud);
+        state.lod_fade = 0.5;
+        for (x, y) in outgoing {
+            assert!(SoftwareRenderer::lod_discarded(&state, x + 4, y + 8));
+        }
+
+        // programs without the fade are never discarded
+        assert!(discarded(Shading::Simple, 0.5).is_empty());
+    }
+
+    #[test]
+    fn cutout_discards_transparent_fragments() {
+        let renderer = SoftwareRenderer {};
+        let mut target = Target::new(1, 1, BLUE);
+        let mut state = state(Shading::Simple);
+        state.alpha_mode = AlphaMode::Cutout(0.5);
+
+        renderer.write_fragment(&mut target, &state, 0, 0, 0.5, &fragment(RED.truncate().extend(0.4)));
+        assert_color(target.color[0], BLUE);
+        assert_eq!(target.depth[0], 1.0);
+
+        // kept fragments are opaque and write the depth
+        renderer.write_fragment(&mut target, &state, 0, 0, 0.5, &fragment(RED.truncate().extend(0.6)));
+        assert_color(target.color[0], RED.truncate().extend(0.6));
+        assert_eq!(target.depth[0], 0.5);
+    }
+
+    #[test]
+    fn blend_mixes_with_the_target() {
+        let renderer = SoftwareRenderer {};
+        let mut target = Target::new(1, 1, BLUE);
+        let mut state = state(Shading::Simple);
+        state.alpha_mode = AlphaMode::Blend;
+        state.depth_write = false;
+
+        renderer.write_fragment(&mut target, &state, 0, 0, 0.5, &fragment(RED.truncate().extend(0.25)));
+        assert_color(target.color[0], Vec4::new(0.25, 0.0, 0.75, 0.8125));
+        assert_eq!(target.depth[0], 1.0);
+
+        // the opaque mode replaces the color, alpha included
+        state.alpha_mode = AlphaMode::Opaque;
+        renderer.write_fragment(&mut target, &state, 0, 0, 0.5, &fragment(GREEN.truncate().extend(0.25)));
+        assert_color(target.color[0], GREEN.truncate().extend(0.25));
+    }
+}
@@ -1,12 +1,15 @@
-use std::ops::Deref;
+use std::ops::{Deref, DerefMut};
 
-use mlua::UserData;
+use mlua::{UserData, UserDataMethods, prelude::LuaValue};
 use slotmap::Key;
 use verdi_database::{ResourceId, Resource, Assets, Handle};
 
 use crate::{
-    mesh::MeshId, 
-    image::ImageId
+    mesh::{MeshId, Mesh},
+    image::ImageId,
+    uniform::{UniformHandle, Uniform, UniformValue},
+    atlas::AtlasFrame,
+    vertex::Vertex,
 };
 
 pub type SpriteId = ResourceId;
@@ -15,6 +18,7 @@ pub type SpriteId = ResourceId;
 pub struct Sprite {
     pub image_id: ImageId,
     pub quad_id: MeshId,
+    pub texture: UniformHandle,
     pub id: SpriteId,
 }
 
@@ -29,13 +33,44 @@ impl Resource for Sprite {
 }
 
 impl Sprite {
-    pub fn new(image_id: ImageId, quad_id: MeshId) -> Self {
+    pub fn new(image_id: ImageId, quad_id: MeshId, texture: UniformHandle) -> Self {
         Self {
             image_id,
             quad_id,
+            texture,
             id: SpriteId::null(),
         }
     }
+
+    /// Vertices of a quad of the size of the frame, in pixels, with the frame uvs.
+    pub fn quad_vertices(frame: &AtlasFrame) -> Vec<Vertex> {
+        let width = frame.region.width as f32;
+        let height = frame.region.height as f32;
+        let [u0, v0, u1, v1] = frame.uv;
+
+        vec![
+            Vertex {
+                position: [0.0, 0.0, 0.0],
+                uv: [u0, v0],
+                ..Default::default()
+            },
+            Vertex {
+                position: [0.0, height, 0.0],
+                uv: [u0, v1],
+                ..Default::default()
+            },
+            Vertex {
+                position: [width, 0.0, 0.0],
+                uv: [u1, v0],
+                ..Default::default()
+            },
+            Vertex {
+                position: [width, height, 0.0],
+                uv: [u1, v1],
+                ..Default::default()
+            },
+        ]
+    }
 }
 
 #[derive(Clone)]
@@ -49,10 +84,48 @@ impl Deref for SpriteHandle {
     }
 }
 
+impl DerefMut for SpriteHandle {
+    fn deref_mut(&mut self) -> &mut Handle {
+        &mut self.0
+    }
+}
+
 impl SpriteHandle {
     pub fn new(assets: Assets, id: SpriteId) -> Self {
         SpriteHandle(assets.new_handle(id))
     }
+
+    pub fn set_frame(&mut self, frame: &AtlasFrame) {
+        let sprite_id = self.get_id();
+        let mut datas = self.get_datas_mut();
+
+        let (quad_id, texture_id) = match datas.get_mut::<Sprite>(sprite_id) {
+            Some(sprite) => {
+                sprite.image_id = frame.image.get_id();
+                (sprite.quad_id, sprite.texture.get_id())
+            },
+            None => return,
+        };
+
+        if let Some(quad) = datas.get_mut::<Mesh>(quad_id) {
+            quad.vertices = Sprite::quad_vertices(frame);
+            quad.set_modified();
+        }
+
+        if let Some(texture) = datas.get_mut::<Uniform>(texture_id) {
+            texture.value = UniformValue::Texture(frame.image.get_id());
+        }
+    }
 }
 
-impl UserData for SpriteHandle {}
\ No newline at end of file
+impl UserData for SpriteHandle {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method_mut("setFrame", |_, sprite, frame: LuaValue| {
+            Ok({
+                if let Some(frame) = AtlasFrame::from_lua_value(&frame) {
+                    sprite.set_frame(&frame);
+                }
+            })
+        });
+    }
+}
@@ -0,0 +1,518 @@
+use std::ops::{Deref, DerefMut};
+
+use image::RgbaImage;
+use mlua::{UserData, UserDataMethods};
+use slotmap::Key;
+use verdi_database::{ResourceId, Resource, Assets, Handle};
+use verdi_math::{Vec2, Vec3, Vec4, prelude::{TransformHandle, LuaVec3}};
+
+use crate::{
+    mesh::{Mesh, MeshHandle},
+    material::MaterialId,
+    image::{Image, ImageHandle},
+    uniform::{Uniform, UniformHandle, UniformValue},
+    sampler::WrapMode,
+    vertex::Vertex,
+    bounds::Bounds,
+};
+
+pub type TerrainId = ResourceId;
+
+/// Cells on each side of a chunk.
+pub const DEFAULT_TERRAIN_CHUNK_SIZE: u32 = 32;
+
+/// Vertices, indices and bounds of a chunk mesh.
+type ChunkGeometry = (Vec<Vertex>, Vec<u32>, Option<Bounds>);
+
+struct TerrainChunk {
+    mesh: MeshHandle,
+    dirty: bool,
+}
+
+/// The shading of the terrain.
+pub struct TerrainMaterials {
+    /// Lit vertex colors.
+    pub colors: MaterialId,
+    /// Up to 4 textures blended by the vertex colors.
+    pub splat: MaterialId,
+    pub splat_textures: [UniformHandle; 4],
+    pub tiling: UniformHandle,
+}
+
+/// A grid of heights centered on the origin, drawn with chunked meshes.
+/// The positions are in the terrain space: x and z on the grid, y up.
+pub struct Terrain {
+    /// Row by row, from -z to +z.
+    heights: Vec<f32>,
+    /// Vertex colors, or weights of the splat textures.
+    colors: Vec<Vec4>,
+    columns: u32,
+    rows: u32,
+    size: Vec2,
+    chunk_size: u32,
+    chunks: Vec<TerrainChunk>,
+    materials: TerrainMaterials,
+    splat: bool,
+    pub transform: TransformHandle,
+    pub id: TerrainId,
+}
+
+impl Resource for Terrain {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Terrain {
+    /// Reads the heights from the luminance of an image, one sample per pixel.
+    pub fn from_heightmap(image: &RgbaImage, size: f32, height: f32, chunk_size: u32, materials: TerrainMaterials, transform: TransformHandle) -> Self {
+        let columns = image.width().max(2);
+        let rows = image.height().max(2);
+
+        let mut heights = Vec::with_capacity((columns * rows) as usize);
+        for y in 0..rows {
+            for x in 0..columns {
+                let x = x.min(image.width().saturating_sub(1));
+                let y = y.min(image.height().saturating_sub(1));
+                let elevation = image.get_pixel_checked(x, y).map_or(0.0, |pixel| {
+                    let luminance = 0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32;
+                    height * luminance / 255.0
+                });
+                heights.push(elevation);
+            }
+        }
+
+        // the cells are square
+        let depth = size * (rows - 1) as f32 / (columns - 1) as f32;
+
+        Self {
+            heights,
+            colors: vec![Vec4::ONE; (columns * rows) as usize],
+            columns,
+            rows,
+            size: Vec2::new(size, depth),
+            chunk_size: chunk_size.max(1),
+            chunks: Vec::new(),
+            materials,
+            splat: false,
+            transform,
+            id: TerrainId::null(),
+        }
+    }
+
+    /// Width along x and depth along z.
+    pub fn get_size(&self) -> Vec2 {
+        self.size
+    }
+
+    fn cell_size(&self) -> Vec2 {
+        Vec2::new(self.size.x / (self.columns - 1) as f32, self.size.y / (self.rows - 1) as f32)
+    }
+
+    fn chunk_columns(&self) -> u32 {
+        (self.columns - 1).div_ceil(self.chunk_size)
+    }
+
+    pub fn get_chunk_count(&self) -> usize {
+        (self.chunk_columns() * (self.rows - 1).div_ceil(self.chunk_size)) as usize
+    }
+
+    pub fn add_chunk(&mut self, mesh: MeshHandle) {
+        self.chunks.push(TerrainChunk { mesh, dirty: false });
+    }
+
+    /// The material used by the chunks.
+    pub fn get_material(&self) -> MaterialId {
+        if self.splat {
+            self.materials.splat
+        } else {
+            self.materials.colors
+        }
+    }
+
+    fn height(&self, column: u32, row: u32) -> f32 {
+        self.heights[(row * self.columns + column) as usize]
+    }
+
+    fn sample_position(&self, column: u32, row: u32) -> Vec3 {
+        let cell = self.cell_size();
+        Vec3::new(
+            column as f32 * cell.x - self.size.x / 2.0,
+            self.height(column, row),
+            row as f32 * cell.y - self.size.y / 2.0,
+        )
+    }
+
+    /// Smooth normal of a sample, from central differences.
+    fn sample_normal(&self, column: u32, row: u32) -> Vec3 {
+        let cell = self.cell_size();
+        let (left, right) = (column.saturating_sub(1), (column + 1).min(self.columns - 1));
+        let (back, front) = (row.saturating_sub(1), (row + 1).min(self.rows - 1));
+
+        let dx = (self.height(right, row) - self.height(left, row)) / ((right - left) as f32 * cell.x);
+        let dz = (self.height(column, front) - self.height(column, back)) / ((front - back) as f32 * cell.y);
+        Vec3::new(-dx, 1.0, -dz).normalize_or_zero()
+    }
+
+    /// Samples covered by a chunk, bounds included.
+    fn chunk_samples(&self, chunk: usize) -> (u32, u32, u32, u32) {
+        let chunk_x = chunk as u32 % self.chunk_columns();
+        let chunk_y = chunk as u32 / self.chunk_columns();
+        (
+            chunk_x * self.chunk_size,
+            chunk_y * self.chunk_size,
+            ((chunk_x + 1) * self.chunk_size).min(self.columns - 1),
+            ((chunk_y + 1) * self.chunk_size).min(self.rows - 1),
+        )
+    }
+
+    /// Vertices, indices and bounds of the mesh of a chunk.
+    pub fn chunk_geometry(&self, chunk: usize) -> ChunkGeometry {
+        let (first_column, first_row, last_column, last_row) = self.chunk_samples(chunk);
+        let stride = last_column - first_column + 1;
+
+        let mut vertices = Vec::new();
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                vertices.push(
+                    Vertex {
+                        position: self.sample_position(column, row).to_array(),
+                        normal: self.sample_normal(column, row).to_array(),
+                        color: self.colors[(row * self.columns + column) as usize].to_array(),
+                        uv: [column as f32 / (self.columns - 1) as f32, row as f32 / (self.rows - 1) as f32],
+                    }
+                );
+            }
+        }
+
+        let mut indices = Vec::new();
+        for row in 0..last_row - first_row {
+            for column in 0..last_column - first_column {
+                let i0 = row * stride + column;
+                let i1 = i0 + 1;
+                let i2 = i0 + stride;
+                let i3 = i2 + 1;
+                indices.extend_from_slice(&[i0, i2, i1, i1, i2, i3]);
+            }
+        }
+
+        let bounds = Bounds::from_points(vertices.iter().map(|vertex| Vec3::from(vertex.position)));
+        (vertices, indices, bounds)
+    }
+
+    /// Height and normal of the surface under a point, following the triangles of the meshes.
+    /// None outside of the terrain.
+    pub fn get_surface(&self, x: f32, z: f32) -> Option<(f32, Vec3)> {
+        let cell = self.cell_size();
+        let fx = (x + self.size.x / 2.0) / cell.x;
+        let fz = (z + self.size.y / 2.0) / cell.y;
+        if !(0.0..=(self.columns - 1) as f32).contains(&fx) || !(0.0..=(self.rows - 1) as f32).contains(&fz) {
+            return None;
+        }
+
+        let column = (fx as u32).min(self.columns - 2);
+        let row = (fz as u32).min(self.rows - 2);
+        let (tx, tz) = (fx - column as f32, fz - row as f32);
+
+        let h00 = self.height(column, row);
+        let h10 = self.height(column + 1, row);
+        let h01 = self.height(column, row + 1);
+        let h11 = self.height(column + 1, row + 1);
+
+        // the cells are split along the diagonal from (1, 0) to (0, 1)
+        let (height, normal) = if tx + tz <= 1.0 {
+            (
+                h00 + (h10 - h00) * tx + (h01 - h00) * tz,
+                Vec3::new(-(h10 - h00) * cell.y, cell.x * cell.y, -(h01 - h00) * cell.x),
+            )
+        } else {
+            (
+                h11 + (h01 - h11) * (1.0 - tx) + (h10 - h11) * (1.0 - tz),
+                Vec3::new((h01 - h11) * cell.y, cell.x * cell.y, (h10 - h11) * cell.x),
+            )
+        };
+
+        Some((height, normal.normalize_or_zero()))
+    }
+
+    /// Samples within a radius of a point, with their distance. The nearest sample when the radius is 0.
+    fn samples_around(&self, x: f32, z: f32, radius: f32) -> Vec<(u32, u32, f32)> {
+        let cell = self.cell_size();
+        let fx = (x + self.size.x / 2.0) / cell.x;
+        let fz = (z + self.size.y / 2.0) / cell.y;
+
+        if radius <= 0.0 {
+            let column = fx.round();
+            let row = fz.round();
+            if column < 0.0 || row < 0.0 || column > (self.columns - 1) as f32 || row > (self.rows - 1) as f32 {
+                return Vec::new();
+            }
+            return vec![(column as u32, row as u32, 0.0)];
+        }
+
+        let first_column = ((x - radius + self.size.x / 2.0) / cell.x).ceil().max(0.0) as u32;
+        let last_column = (((x + radius + self.size.x / 2.0) / cell.x).floor().max(-1.0) as i64).min(self.columns as i64 - 1);
+        let first_row = ((z - radius + self.size.y / 2.0) / cell.y).ceil().max(0.0) as u32;
+        let last_row = (((z + radius + self.size.y / 2.0) / cell.y).floor().max(-1.0) as i64).min(self.rows as i64 - 1);
+
+        let mut samples = Vec::new();
+        for row in first_row as i64..=last_row {
+            for column in first_column as i64..=last_column {
+                let position = self.sample_position(column as u32, row as u32);
+                let distance = Vec2::new(position.x - x, position.z - z).length();
+                if distance <= radius {
+                    samples.push((column as u32, row as u32, distance));
+                }
+            }
+        }
+        samples
+    }
+
+    /// The chunks using a sample are rebuilt, with the ones using its neighbours for the normals.
+    fn mark_dirty(&mut self, column: u32, row: u32) {
+        let (left, right) = (column.saturating_sub(1), column + 1);
+        let (back, front) = (row.saturating_sub(1), row + 1);
+        for chunk in 0..self.chunks.len() {
+            let (first_column, first_row, last_column, last_row) = self.chunk_samples(chunk);
+            if first_column <= right && last_column >= left && first_row <= front && last_row >= back {
+                self.chunks[chunk].dirty = true;
+            }
+        }
+    }
+
+    pub fn set_height(&mut self, x: f32, z: f32, height: f32) {
+        for (column, row, _) in self.samples_around(x, z, 0.0) {
+            self.heights[(row * self.columns + column) as usize] = height;
+            self.mark_dirty(column, row);
+        }
+    }
+
+    /// Raises the heights around a point, less and less until the radius.
+    pub fn raise(&mut self, x: f32, z: f32, amount: f32, radius: f32) {
+        for (column, row, distance) in self.samples_around(x, z, radius) {
+            let falloff = if radius > 0.0 { 1.0 - distance / radius } else { 1.0 };
+            self.heights[(row * self.columns + column) as usize] += amount * falloff;
+            self.mark_dirty(column, row);
+        }
+    }
+
+    pub fn set_color(&mut self, x: f32, z: f32, color: Vec4) {
+        for (column, row, _) in self.samples_around(x, z, 0.0) {
+            self.colors[(row * self.columns + column) as usize] = color;
+            self.mark_dirty(column, row);
+        }
+    }
+
+    /// Colors all the samples from an image stretched over the terrain.
+    pub fn set_color_map(&mut self, image: &RgbaImage) {
+        if image.width() == 0 || image.height() == 0 {
+            return;
+        }
+
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                let x = column * (image.width() - 1) / (self.columns - 1);
+                let y = row * (image.height() - 1) / (self.rows - 1);
+                let pixel = image.get_pixel(x, y);
+                self.colors[(row * self.columns + column) as usize] = Vec4::new(
+                    pixel[0] as f32 / 255.0,
+                    pixel[1] as f32 / 255.0,
+                    pixel[2] as f32 / 255.0,
+                    pixel[3] as f32 / 255.0,
+                );
+            }
+        }
+
+        for chunk in self.chunks.iter_mut() {
+            chunk.dirty = true;
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TerrainHandle(Handle);
+
+impl Deref for TerrainHandle {
+    type Target = Handle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for TerrainHandle {
+    fn deref_mut(&mut self) -> &mut Handle {
+        &mut self.0
+    }
+}
+
+impl TerrainHandle {
+    pub fn new(assets: Assets, id: TerrainId) -> Self {
+        TerrainHandle(assets.new_handle(id))
+    }
+
+    /// Gives a mutable access to the terrain.
+    pub fn edit<R, F: FnOnce(&mut Terrain) -> R>(&mut self, func: F) -> Option<R> {
+        let terrain_id = self.get_id();
+        self.get_datas_mut()
+            .get_mut::<Terrain>(terrain_id)
+            .map(func)
+    }
+
+    /// Rebuilds the meshes of the chunks whose samples changed.
+    pub fn build_chunks(&mut self) {
+        let rebuilt: Vec<(usize, MeshHandle, ChunkGeometry)> = {
+            let datas = self.get_datas();
+            let terrain = match datas.get::<Terrain>(self.get_id()) {
+                Some(terrain) => terrain,
+                None => return,
+            };
+
+            terrain.chunks
+                .iter()
+                .enumerate()
+                .filter(|(_, chunk)| chunk.dirty)
+                .map(|(index, chunk)| (index, chunk.mesh.clone(), terrain.chunk_geometry(index)))
+                .collect()
+        };
+
+        for (index, mesh, (vertices, indices, bounds)) in rebuilt {
+            let mesh_id = mesh.get_id();
+            if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+                mesh.vertices = vertices;
+                mesh.indices = Some(indices);
+                mesh.bounds = bounds;
+                mesh.set_modified();
+            }
+
+            self.edit(|terrain| terrain.chunks[index].dirty = false);
+        }
+    }
+
+    /// Meshes of the chunks, up to date, with the transform of the terrain.
+    pub fn get_chunks(&mut self) -> (Vec<MeshHandle>, Option<TransformHandle>) {
+        self.build_chunks();
+
+        let datas = self.get_datas();
+        match datas.get::<Terrain>(self.get_id()) {
+            Some(terrain) => (
+                terrain.chunks.iter().map(|chunk| chunk.mesh.clone()).collect(),
+                Some(terrain.transform.clone())
+            ),
+            None => (Vec::new(), None),
+        }
+    }
+
+    /// Blends up to 4 textures with the vertex colors, the red channel being the weight of the first one.
+    /// Without textures, the vertex colors are drawn again.
+    pub fn set_splat_textures(&mut self, textures: &[ImageHandle]) {
+        let (uniforms, material) = match self.edit(|terrain| {
+            terrain.splat = !textures.is_empty();
+            (terrain.materials.splat_textures.clone(), terrain.get_material())
+        }) {
+            Some(splat) => splat,
+            None => return,
+        };
+
+        if let Some(first) = textures.first() {
+            for (index, uniform) in uniforms.iter().enumerate() {
+                // the missing textures have no weight, the first one stands for them
+                let mut texture = textures.get(index).unwrap_or(first).clone();
+                texture.set_wrap(WrapMode::Repeat, WrapMode::Repeat);
+                if let Some(uniform) = self.get_datas_mut().get_mut::<Uniform>(uniform.get_id()) {
+                    uniform.value = UniformValue::Texture(texture.get_id());
+                }
+            }
+        }
+
+        let meshes: Vec<MeshHandle> = self.get_chunks().0;
+        for mesh in meshes {
+            if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh.get_id()) {
+                mesh.material = material;
+            }
+        }
+    }
+
+    /// How many times the splat textures repeat over the terrain.
+    pub fn set_texture_tiling(&mut self, tiling: f32) {
+        let uniform = self.edit(|terrain| terrain.materials.tiling.clone());
+        if let Some(uniform) = uniform {
+            if let Some(uniform) = self.get_datas_mut().get_mut::<Uniform>(uniform.get_id()) {
+                uniform.value = UniformValue::Float(tiling);
+            }
+        }
+    }
+
+    pub fn set_color_map(&mut self, image: &ImageHandle) {
+        let data = self.get_datas()
+            .get::<Image>(image.get_id())
+            .and_then(|image| image.get_data().clone());
+
+        if let Some(data) = data {
+            self.edit(|terrain| terrain.set_color_map(&data));
+        }
+    }
+}
+
+// the positions are given in the terrain space
+impl UserData for TerrainHandle {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("getSize", |_, terrain, ()| {
+            let size = terrain.get_datas().get::<Terrain>(terrain.get_id()).map_or(Vec2::ZERO, |terrain| terrain.get_size());
+            Ok((size.x, size.y))
+        });
+
+        methods.add_method("getTransform", |_, terrain, ()| {
+            Ok(terrain.get_datas().get::<Terrain>(terrain.get_id()).map(|terrain| terrain.transform.clone()))
+        });
+
+        methods.add_method("getHeight", |_, terrain, (x, z): (f32, f32)| {
+            Ok(
+                terrain.get_datas()
+                    .get::<Terrain>(terrain.get_id())
+                    .and_then(|terrain| terrain.get_surface(x, z))
+                    .map(|(height, _)| height)
+            )
+        });
+
+        methods.add_method("getNormal", |_, terrain, (x, z): (f32, f32)| {
+            Ok(
+                terrain.get_datas()
+                    .get::<Terrain>(terrain.get_id())
+                    .and_then(|terrain| terrain.get_surface(x, z))
+                    .map(|(_, normal)| LuaVec3(normal))
+            )
+        });
+
+        methods.add_method_mut("setHeight", |_, terrain, (x, z, height): (f32, f32, f32)| {
+            terrain.edit(|terrain| terrain.set_height(x, z, height));
+            Ok(())
+        });
+
+        methods.add_method_mut("raise", |_, terrain, (x, z, amount, radius): (f32, f32, f32, Option<f32>)| {
+            terrain.edit(|terrain| terrain.raise(x, z, amount, radius.unwrap_or(0.0)));
+            Ok(())
+        });
+
+        methods.add_method_mut("setColor", |_, terrain, (x, z, r, g, b, a): (f32, f32, f32, f32, f32, Option<f32>)| {
+            terrain.edit(|terrain| terrain.set_color(x, z, Vec4::new(r, g, b, a.unwrap_or(1.0))));
+            Ok(())
+        });
+
+        methods.add_method_mut("setColorMap", |_, terrain, image: ImageHandle| {
+            Ok(terrain.set_color_map(&image))
+        });
+
+        methods.add_method_mut("setSplatTextures", |_, terrain, textures: mlua::Variadic<ImageHandle>| {
+            Ok(terrain.set_splat_textures(&textures.iter().take(4).cloned().collect::<Vec<ImageHandle>>()))
+        });
+
+        methods.add_method_mut("setTextureTiling", |_, terrain, tiling: f32| {
+            Ok(terrain.set_texture_tiling(tiling))
+        });
+    }
+}
@@ -0,0 +1,561 @@
+use std::{path::{Path, PathBuf}, collections::HashMap, io::Read, str::FromStr};
+
+use image::ImageError;
+use roxmltree::Node;
+use serde_json::Value;
+use verdi_math::Vec2;
+
+use thiserror::Error;
+
+use crate::{
+    atlas::Region,
+    tilemap::{Property, Properties, MapObject, ObjectLayer},
+};
+
+#[derive(Error, Debug)]
+pub enum TiledError {
+    #[error("Reading tiled map failed")]
+    IoError(#[from] std::io::Error),
+    #[error("Tiled JSON parsing failed")]
+    JsonError(#[from] serde_json::Error),
+    #[error("Tiled TMX parsing failed")]
+    XmlError(#[from] roxmltree::Error),
+    #[error("Tiled map is missing {0}")]
+    MissingField(&'static str),
+    #[error("Tiled map uses unsupported {0}")]
+    Unsupported(&'static str),
+    #[error("Tile data decoding failed")]
+    DecodeError,
+    #[error("Image loading error")]
+    ImageError(#[from] ImageError),
+}
+
+// the high bits of the tile gids are the flip flags, which are ignored
+const GID_MASK: u32 = 0x0FFF_FFFF;
+
+/// A tileset cut in a grid of tiles.
+pub struct TiledTileset {
+    pub image_path: PathBuf,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub tile_count: u32,
+    pub spacing: u32,
+    pub margin: u32,
+    /// Properties by tile index in the tileset.
+    pub tile_properties: HashMap<u32, Properties>,
+}
+
+impl TiledTileset {
+    fn new(image_path: PathBuf, image_size: (u32, u32), tile_size: (u32, u32), spacing: u32, margin: u32, columns: Option<u32>, tile_count: Option<u32>) -> Self {
+        let tile_width = tile_size.0.max(1);
+        let tile_height = tile_size.1.max(1);
+        let grid = |image_size: u32, tile_size: u32| (image_size.saturating_sub(2 * margin) + spacing) / (tile_size + spacing);
+
+        let columns = columns
+            .filter(|columns| *columns > 0)
+            .unwrap_or_else(|| grid(image_size.0, tile_width));
+        let tile_count = tile_count.unwrap_or_else(|| columns * grid(image_size.1, tile_height));
+
+        Self {
+            image_path,
+            tile_width,
+            tile_height,
+            columns,
+            tile_count,
+            spacing,
+            margin,
+            tile_properties: HashMap::new(),
+        }
+    }
+
+    /// Areas of the tiles in the tileset image, by tile index.
+    pub fn regions(&self) -> Vec<Region> {
+        (0..self.tile_count)
+            .map(|index| Region {
+                x: self.margin + (index % self.columns.max(1)) * (self.tile_width + self.spacing),
+                y: self.margin + (index / self.columns.max(1)) * (self.tile_height + self.spacing),
+                width: self.tile_width,
+                height: self.tile_height,
+            })
+            .collect()
+    }
+}
+
+pub struct TiledLayer {
+    pub name: String,
+    /// Tile ids, row by row.
+    pub tiles: Vec<u32>,
+    pub offset: Vec2,
+    pub parallax: Vec2,
+    pub visible: bool,
+    pub properties: Properties,
+}
+
+/// A map exported by the Tiled editor.
+/// The tiles of the tilesets are numbered from 1 following each other, as the frames of a single atlas.
+pub struct TiledMap {
+    pub cols: u32,
+    pub rows: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// Sorted by first gid.
+    pub tilesets: Vec<TiledTileset>,
+    pub layers: Vec<TiledLayer>,
+    pub object_layers: Vec<ObjectLayer>,
+    /// Properties by tile id.
+    pub tile_properties: HashMap<u32, Properties>,
+    // first gid and first tile id of each tileset
+    gids: Vec<(u32, u32)>,
+}
+
+impl TiledMap {
+    fn new(cols: u32, rows: u32, tile_width: u32, tile_height: u32, mut tilesets: Vec<(u32, TiledTileset)>) -> Self {
+        tilesets.sort_by_key(|(first_gid, _)| *first_gid);
+
+        let mut gids = Vec::new();
+        let mut tile_properties = HashMap::new();
+        let mut first_id = 1;
+        for (first_gid, tileset) in tilesets.iter_mut() {
+            gids.push((*first_gid, first_id));
+            for (index, properties) in tileset.tile_properties.drain() {
+                tile_properties.insert(first_id + index, properties);
+            }
+            first_id += tileset.tile_count;
+        }
+
+        Self {
+            cols,
+            rows,
+            tile_width,
+            tile_height,
+            tilesets: tilesets.into_iter().map(|(_, tileset)| tileset).collect(),
+            layers: Vec::new(),
+            object_layers: Vec::new(),
+            tile_properties,
+            gids,
+        }
+    }
+
+    /// Converts a gid of the map file to a tile id, 0 being an empty cell.
+    fn tile_id(&self, gid: u32) -> u32 {
+        let gid = gid & GID_MASK;
+        if gid == 0 {
+            return 0;
+        }
+
+        match self.gids.iter().rev().find(|(first_gid, _)| gid >= *first_gid) {
+            Some((first_gid, first_id)) => first_id + gid - first_gid,
+            None => 0,
+        }
+    }
+}
+
+/// Offset, parallax and visibility inherited from the group layers.
+#[derive(Copy, Clone)]
+struct LayerParent {
+    offset: Vec2,
+    parallax: Vec2,
+    visible: bool,
+}
+
+impl Default for LayerParent {
+    fn default() -> Self {
+        Self {
+            offset: Vec2::ZERO,
+            parallax: Vec2::ONE,
+            visible: true,
+        }
+    }
+}
+
+pub struct TiledLoader;
+
+impl TiledLoader {
+    /// Loads a map saved as JSON (.tmj, .json) or as TMX. The infinite maps and the image collection tilesets aren't supported.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<TiledMap, TiledError> {
+        let path = path.as_ref();
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("tmx") | Some("xml") => TiledLoader::load_tmx(path),
+            _ => TiledLoader::load_json(path),
+        }
+    }
+
+    fn folder(path: &Path) -> PathBuf {
+        path.parent().unwrap_or(Path::new("")).to_path_buf()
+    }
+
+    fn load_external_tileset(path: &Path) -> Result<TiledTileset, TiledError> {
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("tsx") | Some("xml") => {
+                let document = roxmltree::Document::parse(&text)?;
+                TiledLoader::read_tmx_tileset(document.root_element(), &TiledLoader::folder(path))
+            },
+            _ => TiledLoader::read_json_tileset(&serde_json::from_str(&text)?, &TiledLoader::folder(path)),
+        }
+    }
+
+    /// Reads the tiles of a layer, stored little endian in base64, and optionally compressed.
+    fn decode_base64(data: &str, compression: &str) -> Result<Vec<u32>, TiledError> {
+        let bytes = base64::decode(data.trim()).map_err(|_| TiledError::DecodeError)?;
+        let bytes = match compression {
+            "" => bytes,
+            "zlib" => {
+                let mut decoded = Vec::new();
+                flate2::read::ZlibDecoder::new(bytes.as_slice()).read_to_end(&mut decoded)?;
+                decoded
+            },
+            "gzip" => {
+                let mut decoded = Vec::new();
+                flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut decoded)?;
+                decoded
+            },
+            _ => return Err(TiledError::Unsupported("tile data compression")),
+        };
+
+        Ok(
+            bytes
+                .chunks_exact(4)
+                .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+                .collect()
+        )
+    }
+
+    fn decode_csv(data: &str) -> Result<Vec<u32>, TiledError> {
+        data
+            .split(',')
+            .map(|gid| gid.trim())
+            .filter(|gid| !gid.is_empty())
+            .map(|gid| gid.parse().map_err(|_| TiledError::DecodeError))
+            .collect()
+    }
+
+    // JSON format
+
+    fn load_json(path: &Path) -> Result<TiledMap, TiledError> {
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let folder = TiledLoader::folder(path);
+
+        if json["infinite"].as_bool() == Some(true) {
+            return Err(TiledError::Unsupported("infinite maps"));
+        }
+
+        let mut tilesets = Vec::new();
+        for tileset in json["tilesets"].as_array().ok_or(TiledError::MissingField("tilesets"))? {
+            let first_gid = tileset["firstgid"].as_u64().ok_or(TiledError::MissingField("firstgid"))? as u32;
+            let tileset = match tileset["source"].as_str() {
+                Some(source) => TiledLoader::load_external_tileset(&folder.join(source))?,
+                None => TiledLoader::read_json_tileset(tileset, &folder)?,
+            };
+            tilesets.push((first_gid, tileset));
+        }
+
+        let read_size = |name: &'static str| json[name]
+            .as_u64()
+            .map(|size| size as u32)
+            .ok_or(TiledError::MissingField(name));
+
+        let mut map = TiledMap::new(
+            read_size("width")?,
+            read_size("height")?,
+            read_size("tilewidth")?,
+            read_size("tileheight")?,
+            tilesets
+        );
+
+        TiledLoader::read_json_layers(&json["layers"], &mut map, LayerParent::default())?;
+
+        Ok(map)
+    }
+
+    fn read_json_tileset(json: &Value, folder: &Path) -> Result<TiledTileset, TiledError> {
+        let image = json["image"]
+            .as_str()
+            .ok_or(TiledError::Unsupported("image collection tilesets"))?;
+        let read = |name: &str| json[name].as_u64().map(|value| value as u32);
+
+        let mut tileset = TiledTileset::new(
+            folder.join(image),
+            (read("imagewidth").unwrap_or(0), read("imageheight").unwrap_or(0)),
+            (
+                read("tilewidth").ok_or(TiledError::MissingField("tilewidth"))?,
+                read("tileheight").ok_or(TiledError::MissingField("tileheight"))?
+            ),
+            read("spacing").unwrap_or(0),
+            read("margin").unwrap_or(0),
+            read("columns"),
+            read("tilecount"),
+        );
+
+        for tile in json["tiles"].as_array().into_iter().flatten() {
+            if let Some(index) = tile["id"].as_u64() {
+                let properties = TiledLoader::read_json_properties(&tile["properties"]);
+                if !properties.is_empty() {
+                    tileset.tile_properties.insert(index as u32, properties);
+                }
+            }
+        }
+
+        Ok(tileset)
+    }
+
+    fn read_json_properties(json: &Value) -> Properties {
+        let mut properties = Properties::new();
+        for property in json.as_array().into_iter().flatten() {
+            let name = match property["name"].as_str() {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+
+            let value = match &property["value"] {
+                Value::Bool(value) => Property::Bool(*value),
+                Value::Number(value) if property["type"].as_str() == Some("float") => Property::Float(value.as_f64().unwrap_or(0.0)),
+                Value::Number(value) => match value.as_i64() {
+                    Some(value) => Property::Int(value),
+                    None => Property::Float(value.as_f64().unwrap_or(0.0)),
+                },
+                Value::String(value) => Property::String(value.clone()),
+                _ => continue,
+            };
+
+            properties.insert(name, value);
+        }
+        properties
+    }
+
+    fn read_json_layers(json: &Value, map: &mut TiledMap, parent: LayerParent) -> Result<(), TiledError> {
+        for layer in json.as_array().into_iter().flatten() {
+            let read = |name: &str, default: f32| layer[name].as_f64().map_or(default, |value| value as f32);
+            let current = LayerParent {
+                offset: parent.offset + Vec2::new(read("offsetx", 0.0), read("offsety", 0.0)),
+                parallax: parent.parallax * Vec2::new(read("parallaxx", 1.0), read("parallaxy", 1.0)),
+                visible: parent.visible && layer["visible"].as_bool().unwrap_or(true),
+            };
+            let name = layer["name"].as_str().unwrap_or_default().to_string();
+            let properties = TiledLoader::read_json_properties(&layer["properties"]);
+
+            match layer["type"].as_str() {
+                Some("tilelayer") => {
+                    let gids = match &layer["data"] {
+                        Value::Array(gids) => gids.iter().map(|gid| gid.as_u64().unwrap_or(0) as u32).collect(),
+                        Value::String(data) => TiledLoader::decode_base64(data, layer["compression"].as_str().unwrap_or_default())?,
+                        _ => return Err(TiledError::MissingField("data")),
+                    };
+
+                    let tiles = gids.into_iter().map(|gid| map.tile_id(gid)).collect();
+                    map.layers.push(
+                        TiledLayer {
+                            name,
+                            tiles,
+                            offset: current.offset,
+                            parallax: current.parallax,
+                            visible: current.visible,
+                            properties,
+                        }
+                    );
+                },
+                Some("objectgroup") => {
+                    let mut objects = Vec::new();
+                    for object in layer["objects"].as_array().into_iter().flatten() {
+                        let read_f32 = |name: &str| object[name].as_f64().unwrap_or(0.0) as f32;
+                        // the type was renamed class in Tiled 1.9
+                        let kind = object["type"].as_str().or_else(|| object["class"].as_str()).unwrap_or_default();
+                        objects.push(
+                            MapObject {
+                                id: object["id"].as_u64().unwrap_or(0) as u32,
+                                name: object["name"].as_str().unwrap_or_default().to_string(),
+                                kind: kind.to_string(),
+                                x: read_f32("x") + current.offset.x,
+                                y: read_f32("y") + current.offset.y,
+                                width: read_f32("width"),
+                                height: read_f32("height"),
+                                rotation: read_f32("rotation"),
+                                tile: map.tile_id(object["gid"].as_u64().unwrap_or(0) as u32),
+                                properties: TiledLoader::read_json_properties(&object["properties"]),
+                            }
+                        );
+                    }
+                    map.object_layers.push(ObjectLayer { name, objects, properties });
+                },
+                Some("group") => TiledLoader::read_json_layers(&layer["layers"], map, current)?,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    // TMX format
+
+    fn attribute<T: FromStr>(node: Node, name: &str) -> Option<T> {
+        node.attribute(name)?.parse().ok()
+    }
+
+    fn load_tmx(path: &Path) -> Result<TiledMap, TiledError> {
+        let text = std::fs::read_to_string(path)?;
+        let document = roxmltree::Document::parse(&text)?;
+        let root = document.root_element();
+        let folder = TiledLoader::folder(path);
+
+        if TiledLoader::attribute::<u32>(root, "infinite") == Some(1) {
+            return Err(TiledError::Unsupported("infinite maps"));
+        }
+
+        let mut tilesets = Vec::new();
+        for tileset in root.children().filter(|node| node.has_tag_name("tileset")) {
+            let first_gid = TiledLoader::attribute(tileset, "firstgid").ok_or(TiledError::MissingField("firstgid"))?;
+            let tileset = match tileset.attribute("source") {
+                Some(source) => TiledLoader::load_external_tileset(&folder.join(source))?,
+                None => TiledLoader::read_tmx_tileset(tileset, &folder)?,
+            };
+            tilesets.push((first_gid, tileset));
+        }
+
+        let read_size = |name: &'static str| TiledLoader::attribute(root, name).ok_or(TiledError::MissingField(name));
+
+        let mut map = TiledMap::new(
+            read_size("width")?,
+            read_size("height")?,
+            read_size("tilewidth")?,
+            read_size("tileheight")?,
+            tilesets
+        );
+
+        TiledLoader::read_tmx_layers(root, &mut map, LayerParent::default())?;
+
+        Ok(map)
+    }
+
+    fn read_tmx_tileset(node: Node, folder: &Path) -> Result<TiledTileset, TiledError> {
+        let image = node
+            .children()
+            .find(|child| child.has_tag_name("image"))
+            .ok_or(TiledError::Unsupported("image collection tilesets"))?;
+        let source = image.attribute("source").ok_or(TiledError::MissingField("image.source"))?;
+
+        let mut tileset = TiledTileset::new(
+            folder.join(source),
+            (TiledLoader::attribute(image, "width").unwrap_or(0), TiledLoader::attribute(image, "height").unwrap_or(0)),
+            (
+                TiledLoader::attribute(node, "tilewidth").ok_or(TiledError::MissingField("tilewidth"))?,
+                TiledLoader::attribute(node, "tileheight").ok_or(TiledError::MissingField("tileheight"))?
+            ),
+            TiledLoader::attribute(node, "spacing").unwrap_or(0),
+            TiledLoader::attribute(node, "margin").unwrap_or(0),
+            TiledLoader::attribute(node, "columns"),
+            TiledLoader::attribute(node, "tilecount"),
+        );
+
+        for tile in node.children().filter(|child| child.has_tag_name("tile")) {
+            if let Some(index) = TiledLoader::attribute(tile, "id") {
+                let properties = TiledLoader::read_tmx_properties(tile);
+                if !properties.is_empty() {
+                    tileset.tile_properties.insert(index, properties);
+                }
+            }
+        }
+
+        Ok(tileset)
+    }
+
+    fn read_tmx_properties(node: Node) -> Properties {
+        let mut properties = Properties::new();
+        let nodes = node
+            .children()
+            .filter(|child| child.has_tag_name("properties"))
+            .flat_map(|child| child.children())
+            .filter(|child| child.has_tag_name("property"));
+
+        for property in nodes {
+            let name = match property.attribute("name") {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            // multiline strings are stored as text
+            let text = property.attribute("value").or_else(|| property.text()).unwrap_or_default();
+
+            let value = match property.attribute("type").unwrap_or("string") {
+                "bool" => Property::Bool(text == "true"),
+                "int" | "object" => Property::Int(text.parse().unwrap_or(0)),
+                "float" => Property::Float(text.parse().unwrap_or(0.0)),
+                "class" => continue,
+                _ => Property::String(text.to_string()),
+            };
+
+            properties.insert(name, value);
+        }
+        properties
+    }
+
+    fn read_tmx_layers(node: Node, map: &mut TiledMap, parent: LayerParent) -> Result<(), TiledError> {
+        for layer in node.children().filter(|child| child.is_element()) {
+            let read = |name: &str, default: f32| TiledLoader::attribute(layer, name).unwrap_or(default);
+            let current = LayerParent {
+                offset: parent.offset + Vec2::new(read("offsetx", 0.0), read("offsety", 0.0)),
+                parallax: parent.parallax * Vec2::new(read("parallaxx", 1.0), read("parallaxy", 1.0)),
+                visible: parent.visible && TiledLoader::attribute::<u32>(layer, "visible") != Some(0),
+            };
+            let name = layer.attribute("name").unwrap_or_default().to_string();
+            let properties = TiledLoader::read_tmx_properties(layer);
+
+            match layer.tag_name().name() {
+                "layer" => {
+                    let data = layer
+                        .children()
+                        .find(|child| child.has_tag_name("data"))
+                        .ok_or(TiledError::MissingField("data"))?;
+
+                    let gids = match data.attribute("encoding") {
+                        Some("csv") => TiledLoader::decode_csv(data.text().unwrap_or_default())?,
+                        Some("base64") => TiledLoader::decode_base64(data.text().unwrap_or_default(), data.attribute("compression").unwrap_or_default())?,
+                        Some(_) => return Err(TiledError::Unsupported("tile data encoding")),
+                        None => data
+                            .children()
+                            .filter(|child| child.has_tag_name("tile"))
+                            .map(|tile| TiledLoader::attribute(tile, "gid").unwrap_or(0))
+                            .collect(),
+                    };
+
+                    let tiles = gids.into_iter().map(|gid| map.tile_id(gid)).collect();
+                    map.layers.push(
+                        TiledLayer {
+                            name,
+                            tiles,
+                            offset: current.offset,
+                            parallax: current.parallax,
+                            visible: current.visible,
+                            properties,
+                        }
+                    );
+                },
+                "objectgroup" => {
+                    let mut objects = Vec::new();
+                    for object in layer.children().filter(|child| child.has_tag_name("object")) {
+                        let read_f32 = |name: &str| TiledLoader::attribute(object, name).unwrap_or(0.0);
+                        let kind = object.attribute("type").or_else(|| object.attribute("class")).unwrap_or_default();
+                        objects.push(
+                            MapObject {
+                                id: TiledLoader::attribute(object, "id").unwrap_or(0),
+                                name: object.attribute("name").unwrap_or_default().to_string(),
+                                kind: kind.to_string(),
+                                x: read_f32("x") + current.offset.x,
+                                y: read_f32("y") + current.offset.y,
+                                width: read_f32("width"),
+                                height: read_f32("height"),
+                                rotation: read_f32("rotation"),
+                                tile: map.tile_id(TiledLoader::attribute(object, "gid").unwrap_or(0)),
+                                properties: TiledLoader::read_tmx_properties(object),
+                            }
+                        );
+                    }
+                    map.object_layers.push(ObjectLayer { name, objects, properties });
+                },
+                "group" => TiledLoader::read_tmx_layers(layer, map, current)?,
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,530 @@
+use std::{ops::{Deref, DerefMut}, collections::HashMap};
+
+use mlua::{UserData, UserDataMethods, Lua, Table, prelude::LuaValue};
+use slotmap::Key;
+use verdi_database::{ResourceId, Resource, Assets, Handle};
+use verdi_math::{Vec2, Vec3, prelude::{TransformHandle, Transform}};
+
+use crate::{
+    mesh::{Mesh, MeshHandle, PrimitiveType},
+    material::MaterialId,
+    atlas::{Atlas, AtlasHandle},
+    image::Image,
+    vertex::Vertex,
+};
+
+pub type TilemapId = ResourceId;
+
+/// Width and height of the chunks, in tiles.
+pub const CHUNK_SIZE: u32 = 16;
+
+/// Value of a custom property set in the map editor.
+#[derive(Clone, PartialEq)]
+pub enum Property {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+pub type Properties = HashMap<String, Property>;
+
+pub fn properties_to_table<'lua>(lua: &'lua Lua, properties: &Properties) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    for (name, property) in properties {
+        match property {
+            Property::Bool(value) => table.set(name.as_str(), *value)?,
+            Property::Int(value) => table.set(name.as_str(), *value)?,
+            Property::Float(value) => table.set(name.as_str(), *value)?,
+            Property::String(value) => table.set(name.as_str(), value.as_str())?,
+        }
+    }
+    Ok(table)
+}
+
+/// An object placed on an object layer: spawn points, triggers, collisions...
+#[derive(Clone)]
+pub struct MapObject {
+    pub id: u32,
+    pub name: String,
+    pub kind: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub rotation: f32,
+    /// Tile drawn by the object, 0 for none.
+    pub tile: u32,
+    pub properties: Properties,
+}
+
+impl MapObject {
+    pub fn to_table<'lua>(&self, lua: &'lua Lua) -> mlua::Result<Table<'lua>> {
+        let table = lua.create_table()?;
+        table.set("id", self.id)?;
+        table.set("name", self.name.as_str())?;
+        table.set("type", self.kind.as_str())?;
+        table.set("x", self.x)?;
+        table.set("y", self.y)?;
+        table.set("width", self.width)?;
+        table.set("height", self.height)?;
+        table.set("rotation", self.rotation)?;
+        if self.tile > 0 {
+            table.set("tile", self.tile)?;
+        }
+        table.set("properties", properties_to_table(lua, &self.properties)?)?;
+        Ok(table)
+    }
+}
+
+pub struct ObjectLayer {
+    pub name: String,
+    pub objects: Vec<MapObject>,
+    pub properties: Properties,
+}
+
+/// A square of tiles drawn with a single mesh.
+struct Chunk {
+    mesh: Option<MeshHandle>,
+    tile_count: usize,
+    dirty: bool,
+}
+
+/// A grid of tiles. The tile ids are the atlas frames starting at 1, 0 being an empty cell.
+pub struct TileLayer {
+    pub name: String,
+    tiles: Vec<u32>,
+    chunks: Vec<Chunk>,
+    /// How much the layer follows the scrolling, 1 moving with the map, 0 staying in place.
+    pub parallax: Vec2,
+    /// Position of the layer, in pixels.
+    pub offset: Vec2,
+    pub visible: bool,
+    pub properties: Properties,
+    transform: TransformHandle,
+}
+
+/// Layers of tiles taken from an atlas, drawn in pixels like the sprites.
+pub struct Tilemap {
+    atlas: AtlasHandle,
+    material: MaterialId,
+    // where the layer transforms are created
+    transforms: Assets,
+    tile_width: u32,
+    tile_height: u32,
+    cols: u32,
+    rows: u32,
+    layers: Vec<TileLayer>,
+    pub object_layers: Vec<ObjectLayer>,
+    /// Properties of the tiles, by tile id.
+    pub tile_properties: HashMap<u32, Properties>,
+    pub id: TilemapId,
+}
+
+impl Resource for Tilemap {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+impl Tilemap {
+    pub fn new(atlas: AtlasHandle, material: MaterialId, transforms: Assets, tile_width: u32, tile_height: u32, cols: u32, rows: u32) -> Self {
+        Self {
+            atlas,
+            material,
+            transforms,
+            tile_width: tile_width.max(1),
+            tile_height: tile_height.max(1),
+            cols,
+            rows,
+            layers: Vec::new(),
+            object_layers: Vec::new(),
+            tile_properties: HashMap::new(),
+            id: TilemapId::null(),
+        }
+    }
+
+    fn chunk_cols(&self) -> u32 {
+        self.cols.div_ceil(CHUNK_SIZE)
+    }
+
+    fn chunk_rows(&self) -> u32 {
+        self.rows.div_ceil(CHUNK_SIZE)
+    }
+
+    /// Adds an empty layer drawn above the others, and returns its index.
+    pub fn add_layer(&mut self, name: String) -> usize {
+        let transform = TransformHandle::new(
+            self.transforms.clone(),
+            self.transforms.add(Box::new(Transform::new()))
+        );
+
+        let chunks = (0..self.chunk_cols() * self.chunk_rows())
+            .map(|_| Chunk { mesh: None, tile_count: 0, dirty: false })
+            .collect();
+
+        self.layers.push(
+            TileLayer {
+                name,
+                tiles: vec![0; (self.cols * self.rows) as usize],
+                chunks,
+                parallax: Vec2::ONE,
+                offset: Vec2::ZERO,
+                visible: true,
+                properties: Properties::new(),
+                transform,
+            }
+        );
+
+        self.layers.len() - 1
+    }
+
+    pub fn get_layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn get_layer(&self, layer: usize) -> Option<&TileLayer> {
+        self.layers.get(layer)
+    }
+
+    pub fn get_layer_mut(&mut self, layer: usize) -> Option<&mut TileLayer> {
+        self.layers.get_mut(layer)
+    }
+
+    pub fn find_layer(&self, name: &str) -> Option<usize> {
+        self.layers.iter().position(|layer| layer.name == name)
+    }
+
+    pub fn get_size(&self) -> (u32, u32) {
+        (self.cols, self.rows)
+    }
+
+    pub fn get_tile_size(&self) -> (u32, u32) {
+        (self.tile_width, self.tile_height)
+    }
+
+    pub fn get_tile(&self, layer: usize, x: u32, y: u32) -> u32 {
+        if x >= self.cols || y >= self.rows {
+            return 0;
+        }
+
+        self.layers
+            .get(layer)
+            .map_or(0, |layer| layer.tiles[(y * self.cols + x) as usize])
+    }
+
+    /// Changes a tile, the chunk containing it is rebuilt when the map is drawn.
+    pub fn set_tile(&mut self, layer: usize, x: u32, y: u32, tile: u32) {
+        if x >= self.cols || y >= self.rows {
+            return;
+        }
+
+        let chunk_index = ((y / CHUNK_SIZE) * self.chunk_cols() + x / CHUNK_SIZE) as usize;
+        let cell = (y * self.cols + x) as usize;
+        if let Some(layer) = self.layers.get_mut(layer) {
+            if layer.tiles[cell] != tile {
+                layer.tiles[cell] = tile;
+                layer.chunks[chunk_index].dirty = true;
+            }
+        }
+    }
+
+    /// Vertices and indices of the tiles of a chunk, with the atlas frames uvs.
+    fn chunk_vertices(&self, layer: &TileLayer, chunk_x: u32, chunk_y: u32, atlas: &Atlas, image_size: (u32, u32)) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let (image_width, image_height) = (image_size.0.max(1) as f32, image_size.1.max(1) as f32);
+
+        let x_range = chunk_x * CHUNK_SIZE..((chunk_x + 1) * CHUNK_SIZE).min(self.cols);
+        for y in chunk_y * CHUNK_SIZE..((chunk_y + 1) * CHUNK_SIZE).min(self.rows) {
+            for x in x_range.clone() {
+                let tile = layer.tiles[(y * self.cols + x) as usize];
+                let region = match tile.checked_sub(1).and_then(|index| atlas.get_region(index as usize)) {
+                    Some(region) => region,
+                    None => continue,
+                };
+
+                let u0 = region.x as f32 / image_width;
+                let v0 = region.y as f32 / image_height;
+                let u1 = (region.x + region.width) as f32 / image_width;
+                let v1 = (region.y + region.height) as f32 / image_height;
+
+                // the tiles are bottom aligned on their cell, as in the map editors
+                let left = (x * self.tile_width) as f32;
+                let bottom = ((y + 1) * self.tile_height) as f32;
+                let right = left + region.width as f32;
+                let top = bottom - region.height as f32;
+
+                let first = vertices.len() as u32;
+                vertices.push(Vertex { position: [left, top, 0.0], uv: [u0, v0], ..Default::default() });
+                vertices.push(Vertex { position: [left, bottom, 0.0], uv: [u0, v1], ..Default::default() });
+                vertices.push(Vertex { position: [right, top, 0.0], uv: [u1, v0], ..Default::default() });
+                vertices.push(Vertex { position: [right, bottom, 0.0], uv: [u1, v1], ..Default::default() });
+                indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 1, first + 3]);
+            }
+        }
+
+        (vertices, indices)
+    }
+}
+
+#[derive(Clone)]
+pub struct TilemapHandle(Handle);
+
+impl Deref for TilemapHandle {
+    type Target = Handle;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for TilemapHandle {
+    fn deref_mut(&mut self) -> &mut Handle {
+        &mut self.0
+    }
+}
+
+impl TilemapHandle {
+    pub fn new(assets: Assets, id: TilemapId) -> Self {
+        TilemapHandle(assets.new_handle(id))
+    }
+
+    /// Gives a mutable access to the map.
+    pub fn edit<R, F: FnOnce(&mut Tilemap) -> R>(&mut self, func: F) -> Option<R> {
+        let tilemap_id = self.get_id();
+        self.get_datas_mut()
+            .get_mut::<Tilemap>(tilemap_id)
+            .map(func)
+    }
+
+    /// Rebuilds the meshes of the chunks whose tiles changed.
+    pub fn build_chunks(&mut self) {
+        let tilemap_id = self.get_id();
+
+        // vertices of the dirty chunks, computed while the map is only read
+        let mut rebuilt = Vec::new();
+        let material = {
+            let datas = self.get_datas();
+            let tilemap = match datas.get::<Tilemap>(tilemap_id) {
+                Some(tilemap) => tilemap,
+                None => return,
+            };
+            let atlas = match datas.get::<Atlas>(tilemap.atlas.get_id()) {
+                Some(atlas) => atlas,
+                None => return,
+            };
+            let image_size = datas
+                .get::<Image>(atlas.get_image().get_id())
+                .map_or((1, 1), |image| image.get_dimensions());
+
+            for (layer_index, layer) in tilemap.layers.iter().enumerate() {
+                for (chunk_index, chunk) in layer.chunks.iter().enumerate() {
+                    if chunk.dirty {
+                        let chunk_x = chunk_index as u32 % tilemap.chunk_cols();
+                        let chunk_y = chunk_index as u32 / tilemap.chunk_cols();
+                        let (vertices, indices) = tilemap.chunk_vertices(layer, chunk_x, chunk_y, atlas, image_size);
+                        rebuilt.push((layer_index, chunk_index, chunk.mesh.clone(), vertices, indices));
+                    }
+                }
+            }
+
+            tilemap.material
+        };
+
+        for (layer_index, chunk_index, mesh, vertices, indices) in rebuilt {
+            let tile_count = indices.len() / 6;
+            let mesh = match mesh {
+                Some(mesh) => {
+                    let mesh_id = mesh.get_id();
+                    if let Some(mesh) = self.get_datas_mut().get_mut::<Mesh>(mesh_id) {
+                        mesh.vertices = vertices;
+                        mesh.indices = Some(indices);
+                        mesh.set_modified();
+                    }
+                    mesh
+                },
+                None => {
+                    // the mesh of a chunk is created the first time it has tiles
+                    if tile_count == 0 {
+                        continue;
+                    }
+                    let mesh_id = self.get_assets_mut().add(
+                        Box::new(Mesh::new(vertices, Some(indices), PrimitiveType::Triangles, material))
+                    );
+                    MeshHandle::new(self.get_assets().clone(), mesh_id)
+                },
+            };
+
+            self.edit(|tilemap| {
+                let chunk = &mut tilemap.layers[layer_index].chunks[chunk_index];
+                chunk.mesh = Some(mesh);
+                chunk.tile_count = tile_count;
+                chunk.dirty = false;
+            });
+        }
+    }
+
+    /// Meshes to draw for a scrolling position, with the transform of their layer.
+    /// Chunks outside of the viewport are skipped.
+    pub fn get_visible_chunks(&mut self, scroll: Vec2, viewport: (u32, u32)) -> Vec<(MeshHandle, TransformHandle)> {
+        self.build_chunks();
+
+        let mut draws = Vec::new();
+        let datas = self.get_datas();
+        let tilemap = match datas.get::<Tilemap>(self.get_id()) {
+            Some(tilemap) => tilemap,
+            None => return draws,
+        };
+
+        let chunk_width = (CHUNK_SIZE * tilemap.tile_width) as f32;
+        let chunk_height = (CHUNK_SIZE * tilemap.tile_height) as f32;
+        for layer in tilemap.layers.iter().filter(|layer| layer.visible) {
+            let position = layer.offset - scroll * layer.parallax;
+            let mut transform = layer.transform.clone();
+            let transform_id = transform.get_id();
+            if let Some(transform) = transform.get_datas_mut().get_mut::<Transform>(transform_id) {
+                transform.set_position(Vec3::new(position.x.round(), position.y.round(), 0.0));
+            }
+
+            for (chunk_index, chunk) in layer.chunks.iter().enumerate() {
+                let mesh = match &chunk.mesh {
+                    Some(mesh) if chunk.tile_count > 0 => mesh,
+                    _ => continue,
+                };
+
+                // tiles larger than the grid overflow on the chunks above and on the right
+                let left = position.x + (chunk_index as u32 % tilemap.chunk_cols()) as f32 * chunk_width;
+                let top = position.y + (chunk_index as u32 / tilemap.chunk_cols()) as f32 * chunk_height;
+                if left > viewport.0 as f32 || left + 2.0 * chunk_width < 0.0 || top - chunk_height > viewport.1 as f32 || top + chunk_height < 0.0 {
+                    continue;
+                }
+
+                draws.push((mesh.clone(), layer.transform.clone()));
+            }
+        }
+
+        draws
+    }
+
+    /// Finds a layer by index starting at 1, or by name. The first layer by default.
+    pub fn layer_index(&self, key: &Option<LuaValue>) -> Option<usize> {
+        match key {
+            None | Some(LuaValue::Nil) => Some(0),
+            Some(LuaValue::Integer(index)) if *index > 0 => Some(*index as usize - 1),
+            Some(LuaValue::Number(index)) if *index >= 1.0 => Some(*index as usize - 1),
+            Some(LuaValue::String(name)) => {
+                let name = name.to_str().ok()?;
+                self.get_datas().get::<Tilemap>(self.get_id())?.find_layer(name)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn get_atlas(&self) -> Option<AtlasHandle> {
+        self.get_datas()
+            .get::<Tilemap>(self.get_id())
+            .map(|tilemap| tilemap.atlas.clone())
+    }
+}
+
+impl UserData for TilemapHandle {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("getAtlas", |_, map, ()| {
+            Ok(map.get_atlas())
+        });
+
+        methods.add_method("getSize", |_, map, ()| {
+            Ok(map.get_datas().get::<Tilemap>(map.get_id()).map_or((0, 0), |tilemap| tilemap.get_size()))
+        });
+
+        methods.add_method("getTileSize", |_, map, ()| {
+            Ok(map.get_datas().get::<Tilemap>(map.get_id()).map_or((0, 0), |tilemap| tilemap.get_tile_size()))
+        });
+
+        // tiles are placed from the top left cell at (0, 0)
+        methods.add_method("getTile", |_, map, (x, y, layer): (u32, u32, Option<LuaValue>)| {
+            Ok(match map.layer_index(&layer) {
+                Some(layer) => map.get_datas().get::<Tilemap>(map.get_id()).map_or(0, |tilemap| tilemap.get_tile(layer, x, y)),
+                None => 0,
+            })
+        });
+
+        methods.add_method_mut("setTile", |_, map, (x, y, tile, layer): (u32, u32, u32, Option<LuaValue>)| {
+            if let Some(layer) = map.layer_index(&layer) {
+                map.edit(|tilemap| tilemap.set_tile(layer, x, y, tile));
+            }
+            Ok(())
+        });
+
+        methods.add_method_mut("addLayer", |_, map, name: Option<String>| {
+            Ok(map.edit(|tilemap| tilemap.add_layer(name.unwrap_or_default()) + 1))
+        });
+
+        methods.add_method("getLayerCount", |_, map, ()| {
+            Ok(map.get_datas().get::<Tilemap>(map.get_id()).map_or(0, |tilemap| tilemap.get_layer_count()))
+        });
+
+        methods.add_method_mut("setLayerParallax", |_, map, (layer, x, y): (LuaValue, f32, Option<f32>)| {
+            if let Some(layer) = map.layer_index(&Some(layer)) {
+                map.edit(|tilemap| tilemap.get_layer_mut(layer).map(|layer| layer.parallax = Vec2::new(x, y.unwrap_or(x))));
+            }
+            Ok(())
+        });
+
+        methods.add_method_mut("setLayerOffset", |_, map, (layer, x, y): (LuaValue, f32, f32)| {
+            if let Some(layer) = map.layer_index(&Some(layer)) {
+                map.edit(|tilemap| tilemap.get_layer_mut(layer).map(|layer| layer.offset = Vec2::new(x, y)));
+            }
+            Ok(())
+        });
+
+        methods.add_method_mut("setLayerVisible", |_, map, (layer, visible): (LuaValue, bool)| {
+            if let Some(layer) = map.layer_index(&Some(layer)) {
+                map.edit(|tilemap| tilemap.get_layer_mut(layer).map(|layer| layer.visible = visible));
+            }
+            Ok(())
+        });
+
+        methods.add_method("getLayerProperties", |lua, map, layer: LuaValue| {
+            let datas = map.get_datas();
+            let properties = map
+                .layer_index(&Some(layer))
+                .and_then(|layer| datas.get::<Tilemap>(map.get_id())?.get_layer(layer))
+                .map(|layer| &layer.properties);
+
+            match properties {
+                Some(properties) => Ok(Some(properties_to_table(lua, properties)?)),
+                None => Ok(None),
+            }
+        });
+
+        methods.add_method("getTileProperties", |lua, map, tile: u32| {
+            let datas = map.get_datas();
+            match datas.get::<Tilemap>(map.get_id()).and_then(|tilemap| tilemap.tile_properties.get(&tile)) {
+                Some(properties) => Ok(Some(properties_to_table(lua, properties)?)),
+                None => Ok(None),
+            }
+        });
+
+        // the objects of a layer given by name, or of all the object layers
+        methods.add_method("getObjects", |lua, map, layer: Option<String>| {
+            let objects = lua.create_table()?;
+            if let Some(tilemap) = map.get_datas().get::<Tilemap>(map.get_id()) {
+                let layers = tilemap
+                    .object_layers
+                    .iter()
+                    .filter(|object_layer| layer.as_ref().is_none_or(|name| &object_layer.name == name));
+
+                for object in layers.flat_map(|object_layer| object_layer.objects.iter()) {
+                    objects.push(object.to_table(lua)?)?;
+                }
+            }
+            Ok(objects)
+        });
+    }
+}
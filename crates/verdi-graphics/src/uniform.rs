@@ -4,7 +4,7 @@ use mlua::UserData;
 use verdi_database::{Resource, ResourceId, Assets, Handle};
 use verdi_math::{Vec2, Mat4, Vec3, Vec4};
 
-use crate::{gpu_image::GpuImage, gpu_assets::GpuAssets, image::ImageId};
+use crate::{gpu_image::GpuImage, gpu_assets::GpuAssets, image::ImageId, indexed_image::{IndexedImageId, GpuIndexedImage}, depth_buffer::{DepthBufferId, GpuDepthBuffer}};
 
 pub type UniformId = ResourceId;
 
@@ -18,6 +18,9 @@ pub enum UniformValue {
     Vec4(Vec4),
     Mat4(Mat4),
     Texture(ImageId),
+    IndexedTexture(IndexedImageId),
+    /// A sampled depth buffer.
+    DepthTexture(DepthBufferId),
 }
 
 impl UniformValue {
@@ -30,6 +33,22 @@ impl UniformValue {
             UniformValue::Vec4(value) =>  value.get_gl_value(gpu_assets),
             UniformValue::Mat4(value) =>  value.get_gl_value(gpu_assets),
             UniformValue::Texture(value) =>  value.get_gl_value(gpu_assets),
+            UniformValue::IndexedTexture(value) => {
+                let gpu_image = gpu_assets.get::<GpuIndexedImage>(*value).expect("Gpu Indexed Image not Found");
+                glium::uniforms::UniformValue::Texture2d(&gpu_image.get_gl_texture(), Some(*gpu_image.get_gl_sampler()))
+            },
+            UniformValue::DepthTexture(value) => {
+                let gl_texture = gpu_assets
+                    .get::<GpuDepthBuffer>(*value)
+                    .and_then(|gpu_depth| gpu_depth.get_gl_texture())
+                    .expect("Gpu Depth Texture not Found");
+                let sampler = glium::uniforms::SamplerBehavior {
+                    minify_filter: glium::uniforms::MinifySamplerFilter::Nearest,
+                    magnify_filter: glium::uniforms::MagnifySamplerFilter::Nearest,
+                    ..Default::default()
+                };
+                glium::uniforms::UniformValue::DepthTexture2d(gl_texture, Some(sampler))
+            },
         }
     }
 }
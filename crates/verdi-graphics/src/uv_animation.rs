@@ -0,0 +1,82 @@
+use verdi_database::Assets;
+use verdi_math::{Vec2, Vec4};
+
+use crate::uniform::{Uniform, UniformHandle, UniformValue};
+
+/// Animation of the texture coordinates of a material.
+/// The transform and the scrolling are evaluated by the programs from the global time,
+/// the frame of the flipbook is selected once per frame for the drawn materials.
+#[derive(Clone)]
+pub struct UvAnimation {
+    pub animated: UniformHandle,
+    pub offset: UniformHandle,
+    pub scale: UniformHandle,
+    pub rotation: UniformHandle,
+    pub scroll: UniformHandle,
+    pub frame: UniformHandle,
+    /// (u0, v0, u1, v1) of each frame of the flipbook, played in loop.
+    pub frames: Vec<[f32; 4]>,
+    pub frame_rate: f32,
+}
+
+impl UvAnimation {
+    pub fn new(assets: &mut Assets) -> Self {
+        let mut new_uniform = |value| UniformHandle::new(
+            assets.clone(),
+            assets.add(Box::new(Uniform::new(value)))
+        );
+
+        Self {
+            animated: new_uniform(UniformValue::Bool(true)),
+            offset: new_uniform(UniformValue::Vec2(Vec2::ZERO)),
+            scale: new_uniform(UniformValue::Vec2(Vec2::ONE)),
+            rotation: new_uniform(UniformValue::Float(0.0)),
+            scroll: new_uniform(UniformValue::Vec2(Vec2::ZERO)),
+            frame: new_uniform(UniformValue::Vec4(Vec4::ZERO)),
+            frames: Vec::new(),
+            frame_rate: 0.0,
+        }
+    }
+
+    /// The uniforms to add to the material.
+    pub fn get_uniforms(&self) -> [(&'static str, UniformHandle); 6] {
+        [
+            ("u_uv_animated", self.animated.clone()),
+            ("u_uv_offset", self.offset.clone()),
+            ("u_uv_scale", self.scale.clone()),
+            ("u_uv_rotation", self.rotation.clone()),
+            ("u_uv_scroll", self.scroll.clone()),
+            ("u_uv_frame", self.frame.clone()),
+        ]
+    }
+
+    /// The flipbook frame shown at the given time, as (u, v, width, height).
+    /// An empty frame means there is no flipbook.
+    pub fn frame_at(&self, time: f32) -> Vec4 {
+        if self.frames.is_empty() {
+            return Vec4::ZERO;
+        }
+
+        let index = (time * self.frame_rate.max(0.0)).floor().max(0.0) as usize % self.frames.len();
+        let [u0, v0, u1, v1] = self.frames[index];
+        Vec4::new(u0, v0, u1 - u0, v1 - v0)
+    }
+
+    /// Texture coordinates transformed as by the programs:
+    /// scaled and rotated around the center, then offset and scrolled over time.
+    pub fn transform_uv(uv: Vec2, offset: Vec2, scale: Vec2, rotation: f32, scroll: Vec2, time: f32) -> Vec2 {
+        let (sin, cos) = rotation.sin_cos();
+        let centered = (uv - Vec2::splat(0.5)) * scale;
+        let rotated = Vec2::new(centered.x * cos - centered.y * sin, centered.x * sin + centered.y * cos);
+        rotated + Vec2::splat(0.5) + offset + scroll * time
+    }
+
+    /// Maps the texture coordinates into the flipbook frame, repeating inside it.
+    pub fn frame_uv(uv: Vec2, frame: Vec4) -> Vec2 {
+        if frame.z == 0.0 || frame.w == 0.0 {
+            return uv;
+        }
+
+        Vec2::new(frame.x, frame.y) + (uv - uv.floor()) * Vec2::new(frame.z, frame.w)
+    }
+}
@@ -14,6 +14,10 @@ impl Math {
         }
     }
 
+    pub fn get_assets(&self) -> &Assets {
+        &self.assets
+    }
+
     pub fn new_transform(&mut self) -> TransformHandle {
         TransformHandle::new(
             self.assets.clone(),
@@ -4,4 +4,9 @@ function verdi.boot()
     graphics.camera = graphics.newCamera(cam_transform)
 
     if verdi.start then verdi.start() end
-end
\ No newline at end of file
+end
+
+-- default report of the budgets exceeded, replaced by the game
+function verdi.onBudgetOverflow(overflow)
+    print(overflow.message)
+end
@@ -1,4 +1,11 @@
 function verdi.run(deltaTime, pass) 
+    -- the budgets exceeded by the last frame, reported when they start overflowing
+    if verdi.onBudgetOverflow then
+        for _, overflow in ipairs(graphics.getBudgetOverflows()) do
+            if overflow.started then verdi.onBudgetOverflow(overflow) end
+        end
+    end
+
     if verdi.update then verdi.update(deltaTime) end
     if verdi.draw then verdi.draw(pass) end
-end
\ No newline at end of file
+end
@@ -7,8 +7,11 @@ use verdi_ecs::prelude::{WorldHandle, World, BindWorld};
 use verdi_graphics::prelude::{
     GraphicsChip, 
     Renderer, 
+    SoftwareRenderer,
     BindGraphicsChip, 
     PassHandle,
+    BindDebugDraw,
+    RgbaImage,
 };
 use verdi_input::prelude::{Inputs, BindInputs, MouseButton, Key};
 use verdi_math::prelude::{BindMath, Math};
@@ -30,6 +33,10 @@ pub enum SystemError {
     FolderError,
     #[error("Cannot evaluate lua code")]
     LuaError(#[from] mlua::Error),
+    #[error("The game has no framebuffer")]
+    NoFramebuffer,
+    #[error("{0}")]
+    BudgetError(String),
 }
 
 #[derive(PartialEq)]
@@ -136,9 +143,106 @@ impl System {
         self.gpu.borrow_mut().frame_ends();
     }
 
+    /// Saves the game framebuffer in the captures folder.
+    pub fn capture_screenshot(&self) {
+        if let Some(path) = System::capture_path("png") {
+            self.gpu.borrow_mut().capture_screenshot(&path);
+        }
+    }
+
+    /// Starts or stops recording a GIF in the captures folder, with the capture palette of the game.
+    pub fn toggle_recording(&self) {
+        let mut gpu = self.gpu.borrow_mut();
+        if gpu.capture.is_recording() {
+            gpu.stop_recording();
+        }
+        else if let Some(path) = System::capture_path("gif") {
+            gpu.start_recording(&path, 10.0, None);
+        }
+    }
+
+    /// Shows or hides the render stats over the game.
+    pub fn toggle_stats_overlay(&self) {
+        let mut gpu = self.gpu.borrow_mut();
+        let show_stats = !gpu.show_stats;
+        gpu.set_stats_overlay(show_stats);
+    }
+
+    fn capture_path(extension: &str) -> Option<String> {
+        if let Err(e) = std::fs::create_dir_all("captures") {
+            println!("{}", e);
+            return None;
+        }
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_millis())
+            .unwrap_or(0);
+
+        Some(format!("captures/capture_{}.{}", timestamp, extension))
+    }
+
     pub fn get_scripts(&self) -> Rc<RefCell<Scripts>> {
         self.scripts.clone()
     }
+
+    /// Fails when the last frame went over a budget whose overflows are errors.
+    fn check_budgets(&self) -> Result<(), SystemError> {
+        let error = self.gpu.borrow().budgets.get_error();
+        match error {
+            Some(error) => Err(SystemError::BudgetError(error)),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the game callbacks for one frame, drawing in a new pass on the game framebuffer.
+    fn run(&mut self, delta_time: f32) -> Result<(), mlua::Error> {
+        // the chip can't stay borrowed while the time advances
+        let framebuffer = self.gpu.borrow().get_framebuffer();
+        if let Some(framebuffer) = framebuffer {
+            self.gpu.borrow_mut().advance_time(delta_time);
+
+            let pass = PassHandle {
+                graph: self.gpu.borrow().render_graph.clone(),
+                id: self.gpu.borrow().render_graph.borrow_mut().create_pass(framebuffer),
+            };
+
+            LuaContext::call_run(&self.lua, delta_time, pass)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs a game folder without any window or GPU, for a number of frames of fixed duration.
+    /// The frames are rasterized on the CPU, and the last one is returned.
+    pub fn run_headless<P: AsRef<Path>>(path: P, frames: u32, delta_time: f32) -> Result<RgbaImage, SystemError> {
+        let mut system = System::new()?;
+        let mut software_renderer = SoftwareRenderer {};
+
+        system.load_scripts(path)?;
+        system.boot()?;
+        system.state = SystemState::Running;
+
+        let mut image = None;
+        for _ in 0..frames {
+            system.frame_starts();
+            system.run(delta_time)?;
+            system.check_budgets()?;
+
+            let mut gpu = system.gpu.borrow_mut();
+            gpu.prepare_frame();
+            image = software_renderer.render(&mut gpu);
+            drop(gpu);
+
+            system.frame_ends();
+        }
+
+        // the last frame has no next one to report its overflows
+        system.check_budgets()?;
+        system.on_shutdown();
+
+        image.ok_or(SystemError::NoFramebuffer)
+    }
 }
 
 impl EventHandler for System {
@@ -148,6 +252,7 @@ impl EventHandler for System {
 
         BindWorld::bind(&self.lua, self.world.clone())?;
         BindGraphicsChip::bind(&self.lua, self.gpu.clone())?;
+        BindDebugDraw::bind(&self.lua, self.gpu.clone())?;
         BindInputs::bind(&self.lua, self.inputs.clone())?;
         BindMath::bind(&self.lua, self.math.clone())?;
         BindAudio::bind(&self.lua, self.audio.clone())?;
@@ -168,22 +273,18 @@ impl EventHandler for System {
         
         self.scripts.as_ref().borrow_mut().hot_reload(&self.lua)?;
 
-        if let Some(framebuffer) = self.gpu.borrow().get_framebuffer() {
-            let pass = PassHandle {
-                graph: self.gpu.borrow().render_graph.clone(),
-                id: self.gpu.borrow().render_graph.borrow_mut().create_pass(framebuffer),
-            };
-    
-            // callbacks
-            if let Err(err) = LuaContext::call_run(&self.lua, delta_time, pass) {
-                let current_error = err.to_string();
-                if self.last_error != current_error {
-                    println!("{}", err);
-                    self.last_error = current_error;
-                }
+        // callbacks
+        if let Err(err) = self.run(delta_time) {
+            let current_error = err.to_string();
+            if self.last_error != current_error {
+                println!("{}", err);
+                self.last_error = current_error;
             }
         }
-        
+
+        // the game is stopped once the overflows of the last frame are reported to it
+        self.check_budgets()?;
+
         Ok(())
     }
 
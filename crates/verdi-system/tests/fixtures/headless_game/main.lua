@@ -0,0 +1,40 @@
+-- A lit cube turning behind a translucent sphere, rendered by the headless runner.
+local cube
+local cube_transform
+local sphere
+local sphere_transform
+local angle = 0
+
+function verdi.start()
+    graphics.setClearColor(0.1, 0.1, 0.2, 1)
+
+    local cube_material = graphics.newMaterial()
+    cube_material:setProgram(graphics.getProgram("toon"))
+    cube = graphics.newCube()
+    cube:setMaterial(cube_material)
+    cube_transform = math.newTransform()
+    cube_transform:setPosition(math.vec3(0.3, 0, 3))
+
+    local tint = graphics.newImageData(1, 1)
+    tint:setPixel(0, 0, 1, 0.5, 0.2, 0.5)
+
+    local sphere_material = graphics.newMaterial()
+    sphere_material:setTexture(tint)
+    sphere_material:setAlphaMode("blend")
+    sphere = graphics.newSphere(12, 16)
+    sphere:setMaterial(sphere_material)
+    sphere_transform = math.newTransform()
+    sphere_transform:setPosition(math.vec3(-0.4, 0, 2))
+    sphere_transform:setScale(0.8, 0.8, 0.8)
+end
+
+function verdi.update(deltaTime)
+    angle = angle + deltaTime
+    cube_transform:setRotation(0.6 + angle, 0, 1, 0)
+end
+
+function verdi.draw(pass)
+    pass:enableLighting(true)
+    pass:drawMesh(cube, cube_transform)
+    pass:drawMesh(sphere, sphere_transform)
+end
@@ -0,0 +1,38 @@
+use std::path::Path;
+
+use verdi_system::prelude::System;
+
+const FIXTURE: &str = "crates/verdi-system/tests/fixtures/headless_game";
+const GOLDEN: &str = "crates/verdi-system/tests/fixtures/headless_game.png";
+
+/// Renders the fixture game on the CPU and compares the last frame with the checked-in image.
+/// Set `VERDI_BLESS=1` to replace the image after an intended change.
+#[test]
+fn headless_game_matches_golden_image() {
+    // the shaders and the internal scripts are loaded from the workspace root
+    std::env::set_current_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("../..")).unwrap();
+
+    let image = System::run_headless(FIXTURE, 10, 1.0 / 30.0).unwrap();
+
+    if std::env::var_os("VERDI_BLESS").is_some() {
+        image.save(GOLDEN).unwrap();
+        return;
+    }
+
+    let golden = image::open(GOLDEN).unwrap().to_rgba8();
+    assert_eq!(image.dimensions(), golden.dimensions());
+
+    // small rounding differences between platforms are accepted
+    let mismatches = image
+        .as_raw()
+        .iter()
+        .zip(golden.as_raw())
+        .filter(|(actual, expected)| actual.abs_diff(**expected) > 2)
+        .count();
+
+    if mismatches > 0 {
+        let output = std::env::temp_dir().join("headless_game.png");
+        image.save(&output).unwrap();
+        panic!("{} channels differ from {}, the frame was saved to {}", mismatches, GOLDEN, output.display());
+    }
+}